@@ -0,0 +1,18 @@
+//! Runs the reference echo server on `127.0.0.1:3000`.
+//!
+//! ```sh
+//! cargo run --example dev_server --features dev-server
+//! curl -X POST localhost:3000/echo -H 'Content-Type: application/json' -H 'Accept: application/cbor' -d '{"message":"hi"}'
+//! ```
+
+#[tokio::main]
+async fn main() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
+        .await
+        .unwrap();
+
+    println!("Listening on http://{}", listener.local_addr().unwrap());
+    axum::serve(listener, axum_content_negotiation::dev_server::router())
+        .await
+        .unwrap();
+}
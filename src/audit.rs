@@ -0,0 +1,190 @@
+//! Sampling of the fields sent on request payloads, to learn which fields clients actually use before deprecating them.
+//!
+//! Only the names and types of the top-level fields are recorded, never their values.
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use axum::body::Bytes;
+
+use crate::{encoding::DecodeOptions, value::Value, Encoding};
+
+/// Type of a field observed on a request payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum FieldType {
+    /// `null`, or an undefined CBOR value
+    Null,
+    /// `true` or `false`
+    Bool,
+    /// Number without a fractional part
+    Integer,
+    /// Number with a fractional part
+    Float,
+    /// Text string
+    Text,
+    /// Byte string, such as on CBOR payloads
+    Bytes,
+    /// Array or sequence
+    Array,
+    /// Object or map
+    Map,
+}
+
+impl FieldType {
+    fn of(value: &Value) -> Self {
+        match value {
+            Value::Null => Self::Null,
+            Value::Bool(_) => Self::Bool,
+            Value::Integer(_) => Self::Integer,
+            Value::Float(_) => Self::Float,
+            Value::Text(_) => Self::Text,
+            Value::Bytes(_) => Self::Bytes,
+            Value::Array(_) => Self::Array,
+            Value::Map(_) => Self::Map,
+        }
+    }
+}
+
+impl fmt::Display for FieldType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Null => "null",
+            Self::Bool => "bool",
+            Self::Integer => "integer",
+            Self::Float => "float",
+            Self::Text => "text",
+            Self::Bytes => "bytes",
+            Self::Array => "array",
+            Self::Map => "map",
+        })
+    }
+}
+
+/// Top-level fields of a sampled request payload, provided to [NegotiateLayer::audit_schemas](crate::NegotiateLayer::audit_schemas).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SchemaSample {
+    /// Route of the request, such as `/users/{id}`, when it was matched by a router
+    pub route: Option<String>,
+    /// Encoding of the payload
+    pub encoding: Encoding,
+    /// Name and type of each top-level field, in the order of the payload
+    pub fields: Vec<(String, FieldType)>,
+}
+
+type Record = dyn Fn(SchemaSample) + Send + Sync;
+
+/// Callback receiving the fields of one out of every few decoded request payloads.
+pub(crate) struct SchemaAuditor {
+    every: u64,
+    seen: AtomicU64,
+    record: Box<Record>,
+}
+
+impl fmt::Debug for SchemaAuditor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SchemaAuditor")
+            .field("every", &self.every)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SchemaAuditor {
+    pub(crate) fn new(
+        every: u32,
+        record: impl Fn(SchemaSample) + Send + Sync + 'static,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            every: u64::from(every.max(1)),
+            seen: AtomicU64::new(0),
+            record: Box::new(record),
+        })
+    }
+
+    /// Record the fields of the body when it is the one sampled, and it is a map with text keys.
+    ///
+    /// The body is decoded once more as schemaless, so payloads not sampled cost only a counter increment.
+    pub(crate) fn sample(
+        &self,
+        route: Option<&str>,
+        encoding: Encoding,
+        body: &Bytes,
+        options: &DecodeOptions,
+    ) {
+        if !self
+            .seen
+            .fetch_add(1, Ordering::Relaxed)
+            .is_multiple_of(self.every)
+        {
+            return;
+        }
+        let value = match encoding.decode::<Value>(body.clone(), options) {
+            Ok(value) => value,
+            Err(e) => {
+                tracing::debug!(error = %e, %encoding, "skipped auditing undecodable payload");
+                return;
+            }
+        };
+        let Some(fields) = fields(&value) else {
+            return;
+        };
+        (self.record)(SchemaSample {
+            route: route.map(str::to_string),
+            encoding,
+            fields,
+        });
+    }
+}
+
+/// Name and type of the members of a map, if it has only text keys.
+fn fields(value: &Value) -> Option<Vec<(String, FieldType)>> {
+    let Value::Map(entries) = value else {
+        return None;
+    };
+    entries
+        .iter()
+        .map(|(key, value)| match key {
+            Value::Text(name) => Some((name.clone(), FieldType::of(value))),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_record_field_types_without_values() {
+        let value = Value::Map(vec![
+            (Value::Text("id".to_string()), Value::Integer(7)),
+            (
+                Value::Text("email".to_string()),
+                Value::Text("secret".to_string()),
+            ),
+            (Value::Text("tags".to_string()), Value::Array(vec![])),
+            (Value::Text("legacy".to_string()), Value::Null),
+        ]);
+
+        assert_eq!(
+            fields(&value),
+            Some(vec![
+                ("id".to_string(), FieldType::Integer),
+                ("email".to_string(), FieldType::Text),
+                ("tags".to_string(), FieldType::Array),
+                ("legacy".to_string(), FieldType::Null),
+            ])
+        );
+        assert_eq!(fields(&Value::Array(vec![])), None);
+        assert_eq!(
+            fields(&Value::Map(vec![(Value::Integer(1), Value::Null)])),
+            None
+        );
+    }
+}
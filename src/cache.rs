@@ -0,0 +1,113 @@
+//! Freshness of negotiated responses, so edge caches and in-process caches of pre-serialized representations agree on it.
+
+use std::time::{Duration, SystemTime};
+
+use axum::{
+    http::HeaderValue,
+    response::{IntoResponse, Response},
+};
+
+/// Used as a [Response](axum::response::IntoResponse) for representations served from an in-process cache, such as a
+/// `NegotiateBytes` blob, informing an `Age` header with the time since it was stored.
+///
+/// Edge caches then subtract it from the `max-age` configured with [NegotiateLayer::max_age](crate::NegotiateLayer::max_age),
+/// instead of keeping a stale representation for a full period.
+///
+/// ```rust
+/// use std::time::SystemTime;
+///
+/// use axum_content_negotiation::{Negotiate, NegotiateCached};
+///
+/// async fn catalog() -> NegotiateCached<Negotiate<Vec<String>>> {
+///     let (catalog, stored_at) = (vec!["book".to_string()], SystemTime::now());
+///     NegotiateCached::new(Negotiate(catalog), stored_at)
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct NegotiateCached<R> {
+    response: R,
+    stored: Stored,
+}
+
+impl<R> NegotiateCached<R> {
+    /// Serve the response, such as [Negotiate](crate::Negotiate) or `NegotiateBytes`, cached at `stored_at`.
+    pub fn new(response: R, stored_at: SystemTime) -> Self {
+        Self {
+            response,
+            stored: Stored::At(stored_at),
+        }
+    }
+
+    /// Serve the response cached `age` ago, for caches tracking the age themselves, such as on platforms without a system clock
+    /// like `wasm32-unknown-unknown`.
+    pub fn with_age(response: R, age: Duration) -> Self {
+        Self {
+            response,
+            stored: Stored::Aged(age),
+        }
+    }
+}
+
+/// [NegotiateCached] implements [IntoResponse] if the cached response does.
+impl<R> IntoResponse for NegotiateCached<R>
+where
+    R: IntoResponse,
+{
+    fn into_response(self) -> Response {
+        let mut response = self.response.into_response();
+        response.extensions_mut().insert(self.stored);
+        response
+    }
+}
+
+/// When a cached response was stored, waiting for the layer to inform its `Age`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Stored {
+    At(SystemTime),
+    Aged(Duration),
+}
+
+impl Stored {
+    /// `Age` of the response, in whole seconds, which is zero for clocks going backwards.
+    ///
+    /// The clock is only read for responses informing when they were stored.
+    pub(crate) fn age(self, now: impl FnOnce() -> SystemTime) -> HeaderValue {
+        let age = match self {
+            Stored::At(stored_at) => now().duration_since(stored_at).unwrap_or_default(),
+            Stored::Aged(age) => age,
+        };
+        HeaderValue::from(age.as_secs())
+    }
+}
+
+/// `Cache-Control` of fresh negotiated responses.
+///
+/// It is not marked `public`, so shared caches still don't store the responses to requests with an `Authorization`.
+pub(crate) fn cache_control(max_age: Duration) -> HeaderValue {
+    HeaderValue::from_str(&format!("max-age={}", max_age.as_secs()))
+        .expect("digits are valid header values")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_inform_age_in_seconds() {
+        let stored_at = SystemTime::UNIX_EPOCH + Duration::from_secs(100);
+
+        assert_eq!(
+            Stored::At(stored_at).age(|| stored_at + Duration::from_millis(42_500)),
+            "42"
+        );
+        assert_eq!(
+            Stored::At(stored_at).age(|| stored_at - Duration::from_secs(1)),
+            "0"
+        );
+        assert_eq!(
+            Stored::Aged(Duration::from_secs(7)).age(|| unreachable!("no clock is read")),
+            "7"
+        );
+        assert_eq!(cache_control(Duration::from_secs(60)), "max-age=60");
+    }
+}
@@ -0,0 +1,194 @@
+//! Coalescing of concurrent identical `GET` requests onto a single handler execution.
+//!
+//! The first request of a representation runs the handler, while the identical ones arriving before it finishes wait for
+//! its serialized response, which the layer owns as bytes, and answer a copy of it.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    body::{Body, Bytes},
+    http::{
+        header::{AUTHORIZATION, CACHE_CONTROL, COOKIE, IF_NONE_MATCH, SET_COOKIE},
+        HeaderMap, HeaderValue, Method, StatusCode, Version,
+    },
+    response::{IntoResponse, Response},
+};
+use tokio::sync::watch;
+
+use crate::NegotiationOutcome;
+
+/// Response shared with the requests coalesced onto the one which ran the handler.
+#[derive(Debug)]
+struct Shared {
+    status: StatusCode,
+    version: Version,
+    headers: HeaderMap,
+    outcome: NegotiationOutcome,
+    body: Bytes,
+}
+
+type Slot = watch::Receiver<Option<Arc<Shared>>>;
+
+/// What makes two requests identical: the target, the negotiated representation and the credentials of the client,
+/// so responses are never shared across users.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct Key {
+    uri: String,
+    media_type: HeaderValue,
+    authorization: Option<HeaderValue>,
+    cookie: Option<HeaderValue>,
+    if_none_match: Option<HeaderValue>,
+}
+
+impl Key {
+    /// Key of the request, if it is a `GET` that can be coalesced.
+    pub(crate) fn of(
+        method: &Method,
+        uri: String,
+        headers: &HeaderMap,
+        media_type: &HeaderValue,
+    ) -> Option<Self> {
+        (method == Method::GET).then(|| Self {
+            uri,
+            media_type: media_type.clone(),
+            authorization: headers.get(AUTHORIZATION).cloned(),
+            cookie: headers.get(COOKIE).cloned(),
+            if_none_match: headers.get(IF_NONE_MATCH).cloned(),
+        })
+    }
+}
+
+/// Requests running the handler, by key, for the identical ones to wait for them.
+#[derive(Debug, Default)]
+pub(crate) struct Coalescer {
+    in_flight: Mutex<InFlight>,
+}
+
+#[derive(Debug, Default)]
+struct InFlight {
+    /// Slots by key, along with the generation of their leader
+    slots: HashMap<Key, (u64, Slot)>,
+    generation: u64,
+}
+
+/// Part a request plays on its key.
+pub(crate) enum Role {
+    /// Runs the handler, sharing the response once it is serialized
+    Leader(Leader),
+    /// Waits for the response of the leader
+    Follower(Follower),
+}
+
+impl Coalescer {
+    /// Join the requests of the key, leading them if none is running the handler.
+    pub(crate) fn join(self: &Arc<Self>, key: Key) -> Role {
+        let mut in_flight = self.in_flight.lock().expect("coalescer lock poisoned");
+        if let Some((_, slot)) = in_flight.slots.get(&key) {
+            return Role::Follower(Follower(slot.clone()));
+        }
+        in_flight.generation += 1;
+        let generation = in_flight.generation;
+        let (sender, slot) = watch::channel(None);
+        in_flight.slots.insert(key.clone(), (generation, slot));
+        Role::Leader(Leader {
+            coalescer: self.clone(),
+            key,
+            generation,
+            sender,
+        })
+    }
+}
+
+/// Request running the handler for its key, releasing the key when dropped so later requests run the handler again.
+pub(crate) struct Leader {
+    coalescer: Arc<Coalescer>,
+    key: Key,
+    generation: u64,
+    sender: watch::Sender<Option<Arc<Shared>>>,
+}
+
+impl Leader {
+    /// Share the response with the waiting requests, if it was serialized by the layer.
+    ///
+    /// Other responses, such as streams passed through or responses private to the client, are answered only to the leader,
+    /// and the waiting requests run the handler themselves.
+    pub(crate) async fn share(self, response: Response) -> Response {
+        let Some(outcome) = response.extensions().get::<NegotiationOutcome>().cloned() else {
+            return response;
+        };
+        if is_private(response.headers()) {
+            return response;
+        }
+        let (parts, body) = response.into_parts();
+        let Ok(body) = axum::body::to_bytes(body, usize::MAX).await else {
+            tracing::error!("failed to buffer the serialized response body to share it");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        };
+
+        self.release();
+        let _ = self.sender.send(Some(Arc::new(Shared {
+            status: parts.status,
+            version: parts.version,
+            headers: parts.headers.clone(),
+            outcome,
+            body: body.clone(),
+        })));
+        Response::from_parts(parts, Body::from(body))
+    }
+
+    /// Remove the slot of the key, unless a later leader already replaced it.
+    fn release(&self) {
+        let mut in_flight = self
+            .coalescer
+            .in_flight
+            .lock()
+            .expect("coalescer lock poisoned");
+        if matches!(in_flight.slots.get(&self.key), Some((generation, _)) if *generation == self.generation)
+        {
+            in_flight.slots.remove(&self.key);
+        }
+    }
+}
+
+impl Drop for Leader {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+/// Check if the response is private to the client, such as starting a session with `Set-Cookie`, even when the request had no credentials.
+fn is_private(headers: &HeaderMap) -> bool {
+    headers.contains_key(SET_COOKIE)
+        || headers
+            .get_all(CACHE_CONTROL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|directive| directive.split('=').next().unwrap_or_default().trim())
+            .any(|directive| {
+                directive.eq_ignore_ascii_case("private")
+                    || directive.eq_ignore_ascii_case("no-store")
+            })
+}
+
+/// Request waiting for the response of the leader of its key.
+pub(crate) struct Follower(Slot);
+
+impl Follower {
+    /// Wait for the response of the leader, or `None` when it didn't share one and the request should run the handler itself.
+    pub(crate) async fn wait(mut self) -> Option<Response> {
+        let shared = match self.0.wait_for(Option::is_some).await {
+            Ok(shared) => shared.clone()?,
+            Err(_) => return None,
+        };
+        let mut response = Response::new(Body::from(shared.body.clone()));
+        *response.status_mut() = shared.status;
+        *response.version_mut() = shared.version;
+        *response.headers_mut() = shared.headers.clone();
+        response.extensions_mut().insert(shared.outcome.clone());
+        Some(response)
+    }
+}
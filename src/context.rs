@@ -0,0 +1,159 @@
+//! Serialization of response payloads depending on a context provided by the [NegotiateLayer](crate::NegotiateLayer).
+
+use std::{any::type_name, marker::PhantomData, sync::Arc};
+
+use axum::{
+    http::{Extensions, StatusCode},
+    response::{IntoResponse, Response},
+    Extension,
+};
+
+use crate::{encoding::CodecError, Encoding};
+
+/// Serialize a value using a context, such as a currency or locale configured for the deployment.
+///
+/// The context is provided with [NegotiateLayer::serialization_context](crate::NegotiateLayer::serialization_context),
+/// and the value is returned from handlers wrapped on a [NegotiateWith].
+///
+/// ```rust
+/// use axum_content_negotiation::SerializeWith;
+/// use serde::Serializer;
+///
+/// #[derive(Clone)]
+/// struct Currency(&'static str);
+///
+/// struct Price(u64);
+///
+/// impl SerializeWith<Currency> for Price {
+///     fn serialize_with<S: Serializer>(&self, context: &Currency, serializer: S) -> Result<S::Ok, S::Error> {
+///         serializer.collect_str(&format_args!("{} {}", self.0, context.0))
+///     }
+/// }
+/// ```
+pub trait SerializeWith<Ctx> {
+    /// Serialize the value using the context.
+    fn serialize_with<S>(&self, context: &Ctx, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer;
+}
+
+/// Response serialized on the negotiated format using the context configured on the [NegotiateLayer](crate::NegotiateLayer).
+///
+/// If the layer has no context of type `Ctx`, the response is a 500 Internal Server Error.
+///
+/// ```rust
+/// # use axum_content_negotiation::SerializeWith;
+/// # #[derive(Clone)]
+/// # struct Currency(&'static str);
+/// # struct Price(u64);
+/// # impl SerializeWith<Currency> for Price {
+/// #     fn serialize_with<S: serde::Serializer>(&self, context: &Currency, serializer: S) -> Result<S::Ok, S::Error> {
+/// #         serializer.collect_str(&format_args!("{} {}", self.0, context.0))
+/// #     }
+/// # }
+/// use axum::{routing::get, Router};
+/// use axum_content_negotiation::{NegotiateLayer, NegotiateWith};
+///
+/// async fn handler() -> NegotiateWith<Currency, Price> {
+///     NegotiateWith::new(Price(42))
+/// }
+///
+/// let router: Router = Router::new()
+///     .route("/", get(handler))
+///     .layer(NegotiateLayer::new().serialization_context(Currency("EUR")));
+/// ```
+pub struct NegotiateWith<Ctx, T> {
+    value: T,
+    context: PhantomData<fn(&Ctx)>,
+}
+
+impl<Ctx, T> NegotiateWith<Ctx, T> {
+    /// Wrap the value to be serialized with the layer context.
+    pub fn new(value: T) -> Self {
+        Self {
+            value,
+            context: PhantomData,
+        }
+    }
+
+    /// Unwrap the value.
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<Ctx, T: std::fmt::Debug> std::fmt::Debug for NegotiateWith<Ctx, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("NegotiateWith").field(&self.value).finish()
+    }
+}
+
+/// [NegotiateWith] implements [IntoResponse] if the internal content is serializable with the context.
+///
+/// Just like [Negotiate](crate::Negotiate), it is converted to the right response by the [NegotiateLayer](crate::NegotiateLayer).
+impl<Ctx, T> IntoResponse for NegotiateWith<Ctx, T>
+where
+    Ctx: Send + Sync + 'static,
+    T: SerializeWith<Ctx> + Send + Sync + 'static,
+{
+    fn into_response(self) -> Response {
+        let payload: Arc<dyn ContextualPayload> = Arc::new(self);
+        (
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Extension(ContextualNegotiate(payload)),
+            "Misconfigured service layer",
+        )
+            .into_response()
+    }
+}
+
+/// Payload waiting for the layer context in order to be serialized, stored type-erased as a response extension.
+#[derive(Clone)]
+pub(crate) struct ContextualNegotiate(pub(crate) Arc<dyn ContextualPayload>);
+
+pub(crate) trait ContextualPayload: Send + Sync {
+    /// Serialize the payload using the matching context out of the configured ones.
+    fn encode(&self, encoding: Encoding, contexts: &Extensions) -> Result<Vec<u8>, CodecError>;
+
+    /// Whether the payload can be encoded, such as by a dedicated response type for encodings serde can't produce.
+    fn encodes(&self, encoding: Encoding) -> bool {
+        encoding.encodes()
+    }
+}
+
+impl<Ctx, T> ContextualPayload for NegotiateWith<Ctx, T>
+where
+    Ctx: Send + Sync + 'static,
+    T: SerializeWith<Ctx> + Send + Sync,
+{
+    fn encode(&self, encoding: Encoding, contexts: &Extensions) -> Result<Vec<u8>, CodecError> {
+        let context = contexts.get::<Ctx>().ok_or_else(|| {
+            format!(
+                "missing serialization context {} on the layer",
+                type_name::<Ctx>()
+            )
+        })?;
+        encoding.encode(&WithContext {
+            value: &self.value,
+            context,
+        })
+    }
+}
+
+/// Adapter serializing a value along with its context.
+struct WithContext<'a, Ctx, T> {
+    value: &'a T,
+    context: &'a Ctx,
+}
+
+impl<Ctx, T> serde::Serialize for WithContext<'_, Ctx, T>
+where
+    T: SerializeWith<Ctx>,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.value.serialize_with(self.context, serializer)
+    }
+}
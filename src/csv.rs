@@ -0,0 +1,1063 @@
+//! Serialization of sequences of records as `text/csv`, and deserialization of `text/csv` request bodies.
+//!
+//! Only flat payloads can be represented: the top level must be a sequence, and every row a struct, map, tuple or scalar
+//! with scalar fields. The header is taken from the field names of the first row when it is a struct or a map,
+//! and every later row must have the same fields, in the same order.
+
+use std::fmt::{self, Display};
+
+use serde::{
+    de::{self, value::SeqDeserializer, IntoDeserializer},
+    ser::{self, Impossible, Serialize},
+};
+
+/// Error produced when the payload can't be represented as CSV.
+#[derive(Debug)]
+pub(crate) struct Error(String);
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+fn unsupported<T>(what: &str) -> Result<T, Error> {
+    Err(Error(format!("{what} can't be represented as CSV")))
+}
+
+/// Serialize a sequence of records into a CSV document.
+pub(crate) fn to_vec(payload: &dyn erased_serde::Serialize) -> Result<Vec<u8>, Error> {
+    let mut document = Document::default();
+    payload.serialize(&mut document)?;
+    Ok(document.output.into_bytes())
+}
+
+#[derive(Default)]
+struct Document {
+    output: String,
+    rows: usize,
+    /// Field names of the first row, which later rows must match
+    header: Option<Vec<String>>,
+}
+
+impl Document {
+    fn write_line(&mut self, fields: &[String]) {
+        self.output.push_str(&fields.join(","));
+        self.output.push('\n');
+    }
+}
+
+/// Quote a field when it contains a delimiter, a quote or a line break.
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Prefix text starting like a formula with a quote, so spreadsheets opening the document show it instead of evaluating it.
+///
+/// Only text fields are neutralized, so negative numbers are still read as numbers.
+fn neutralize(text: &str) -> String {
+    if text.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{text}")
+    } else {
+        text.to_string()
+    }
+}
+
+macro_rules! top_level_unsupported {
+    ($($method:ident($($arg:ty),*)),* $(,)?) => {
+        $(
+            fn $method(self, $(_: $arg),*) -> Result<(), Error> {
+                unsupported("a payload that is not a sequence")
+            }
+        )*
+    };
+}
+
+impl ser::Serializer for &mut Document {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Impossible<(), Error>;
+    type SerializeTupleVariant = Impossible<(), Error>;
+    type SerializeMap = Impossible<(), Error>;
+    type SerializeStruct = Impossible<(), Error>;
+    type SerializeStructVariant = Impossible<(), Error>;
+
+    top_level_unsupported!(
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str),
+        serialize_bytes(&[u8]),
+        serialize_none(),
+        serialize_unit(),
+        serialize_unit_struct(&'static str),
+        serialize_unit_variant(&'static str, u32, &'static str),
+    );
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<(), Error> {
+        unsupported("a payload that is not a sequence")
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self, Error> {
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Self, Error> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        unsupported("a payload that is not a sequence")
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        unsupported("a payload that is not a sequence")
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        unsupported("a payload that is not a sequence")
+    }
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self::SerializeStruct, Error> {
+        unsupported("a payload that is not a sequence")
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        unsupported("a payload that is not a sequence")
+    }
+}
+
+impl ser::SerializeSeq for &mut Document {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        value.serialize(Row::new(self))
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTuple for &mut Document {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// A single row of the document, writing a header before it when it is the first row and has named fields.
+struct Row<'a> {
+    document: &'a mut Document,
+    names: Vec<String>,
+    values: Vec<String>,
+    named: bool,
+}
+
+impl<'a> Row<'a> {
+    fn new(document: &'a mut Document) -> Self {
+        Self {
+            document,
+            names: Vec::new(),
+            values: Vec::new(),
+            named: false,
+        }
+    }
+
+    fn named(mut self) -> Self {
+        self.named = true;
+        self
+    }
+
+    fn finish(self) -> Result<(), Error> {
+        let names = self.named.then_some(self.names);
+        if self.document.rows == 0 {
+            if let Some(names) = &names {
+                self.document.write_line(names);
+            }
+            self.document.header = names;
+        } else if names != self.document.header {
+            return Err(Error(format!(
+                "row {} has fields {names:?}, which don't match the header {:?}",
+                self.document.rows + 1,
+                self.document.header
+            )));
+        }
+        self.document.write_line(&self.values);
+        self.document.rows += 1;
+        Ok(())
+    }
+
+    fn single(mut self, value: String) -> Result<(), Error> {
+        self.values.push(escape(&value));
+        self.finish()
+    }
+}
+
+macro_rules! row_scalar {
+    ($($method:ident($arg:ty)),* $(,)?) => {
+        $(
+            fn $method(self, value: $arg) -> Result<(), Error> {
+                let field = Field.$method(value)?;
+                self.single(field)
+            }
+        )*
+    };
+}
+
+impl<'a> ser::Serializer for Row<'a> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Impossible<(), Error>;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Impossible<(), Error>;
+
+    row_scalar!(
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str),
+        serialize_bytes(&[u8]),
+        serialize_unit_struct(&'static str),
+    );
+
+    fn serialize_none(self) -> Result<(), Error> {
+        self.single(String::new())
+    }
+
+    fn serialize_unit(self) -> Result<(), Error> {
+        self.single(String::new())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+    ) -> Result<(), Error> {
+        self.single(variant.to_string())
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self, Error> {
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Self, Error> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(self, _: &'static str, _: usize) -> Result<Self, Error> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        unsupported("a tuple variant row")
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self, Error> {
+        Ok(self.named())
+    }
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self, Error> {
+        Ok(self.named())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        unsupported("a struct variant row")
+    }
+}
+
+impl ser::SerializeSeq for Row<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.values.push(escape(&value.serialize(Field)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for Row<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for Row<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeMap for Row<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), Error> {
+        self.names.push(escape(&key.serialize(Field)?));
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for Row<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.names.push(escape(key));
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+/// A single scalar field, serialized as its unescaped text.
+struct Field;
+
+impl ser::Serializer for Field {
+    type Ok = String;
+    type Error = Error;
+    type SerializeSeq = Impossible<String, Error>;
+    type SerializeTuple = Impossible<String, Error>;
+    type SerializeTupleStruct = Impossible<String, Error>;
+    type SerializeTupleVariant = Impossible<String, Error>;
+    type SerializeMap = Impossible<String, Error>;
+    type SerializeStruct = Impossible<String, Error>;
+    type SerializeStructVariant = Impossible<String, Error>;
+
+    fn serialize_bool(self, value: bool) -> Result<String, Error> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_i8(self, value: i8) -> Result<String, Error> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_i16(self, value: i16) -> Result<String, Error> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_i32(self, value: i32) -> Result<String, Error> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_i64(self, value: i64) -> Result<String, Error> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_u8(self, value: u8) -> Result<String, Error> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_u16(self, value: u16) -> Result<String, Error> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_u32(self, value: u32) -> Result<String, Error> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_u64(self, value: u64) -> Result<String, Error> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_f32(self, value: f32) -> Result<String, Error> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_f64(self, value: f64) -> Result<String, Error> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_char(self, value: char) -> Result<String, Error> {
+        Ok(neutralize(&value.to_string()))
+    }
+
+    fn serialize_str(self, value: &str) -> Result<String, Error> {
+        Ok(neutralize(value))
+    }
+
+    fn serialize_bytes(self, _: &[u8]) -> Result<String, Error> {
+        unsupported("a bytes field")
+    }
+
+    fn serialize_none(self) -> Result<String, Error> {
+        Ok(String::new())
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<String, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<String, Error> {
+        Ok(String::new())
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<String, Error> {
+        Ok(String::new())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+    ) -> Result<String, Error> {
+        Ok(variant.to_string())
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<String, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        value: &T,
+    ) -> Result<String, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        unsupported("a nested sequence field")
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, Error> {
+        unsupported("a nested tuple field")
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        unsupported("a nested tuple field")
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        unsupported("a nested tuple field")
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        unsupported("a nested map field")
+    }
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self::SerializeStruct, Error> {
+        unsupported("a nested struct field")
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        unsupported("a nested struct field")
+    }
+}
+
+/// Options to read `text/csv` request bodies, such as spreadsheets exported with another delimiter.
+///
+/// ```rust
+/// use axum_content_negotiation::{CsvOptions, NegotiateLayer};
+///
+/// let layer = NegotiateLayer::new().csv_options(CsvOptions::new().delimiter(b';'));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CsvOptions {
+    delimiter: u8,
+    has_headers: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            has_headers: true,
+        }
+    }
+}
+
+impl CsvOptions {
+    /// Comma-separated records, with the field names on the first line.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Byte separating the fields of a record. Defaults to `,`.
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Whether the first line holds the field names, matched against the fields of each row.
+    ///
+    /// Defaults to `true`. Without headers, the fields of each row are deserialized by their position instead.
+    pub fn has_headers(mut self, has_headers: bool) -> Self {
+        self.has_headers = has_headers;
+        self
+    }
+}
+
+/// Deserialize a CSV document as a sequence of records.
+pub(crate) fn decode_seed<D, T>(seed: D, body: &[u8], options: &CsvOptions) -> Result<T, Error>
+where
+    D: for<'de> de::DeserializeSeed<'de, Value = T>,
+{
+    let document = std::str::from_utf8(body).map_err(|e| Error(e.to_string()))?;
+    let mut records = parse(document, options.delimiter)?.into_iter();
+    let headers = match options.has_headers {
+        true => Some(records.next().unwrap_or_default()),
+        false => None,
+    };
+
+    let rows = records
+        .enumerate()
+        .map(|(line, values)| match &headers {
+            Some(headers) if headers.len() != values.len() => Err(Error(format!(
+                "record {} has {} fields, but the header has {}",
+                line + 1,
+                values.len(),
+                headers.len()
+            ))),
+            _ => Ok(Record {
+                headers: headers.as_deref(),
+                values,
+            }),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    seed.deserialize(SeqDeserializer::new(rows.into_iter()))
+}
+
+/// Split the document into records, unquoting their fields. Empty lines are skipped.
+fn parse(document: &str, delimiter: u8) -> Result<Vec<Vec<String>>, Error> {
+    let delimiter = char::from(delimiter);
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = document.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            c if quoted => field.push(c),
+            c if c == delimiter => record.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                if !record.is_empty() || !field.is_empty() {
+                    record.push(std::mem::take(&mut field));
+                    records.push(std::mem::take(&mut record));
+                }
+            }
+            c => field.push(c),
+        }
+    }
+
+    if quoted {
+        return Err(Error("unterminated quoted field".to_string()));
+    }
+    if !record.is_empty() || !field.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// A single record of the document, read by field name when the document has headers, or by position otherwise.
+struct Record<'a> {
+    headers: Option<&'a [String]>,
+    values: Vec<String>,
+}
+
+impl<'de, 'a> IntoDeserializer<'de, Error> for Record<'a> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl Record<'_> {
+    /// The only field of the record, for rows deserialized as a scalar.
+    fn single(self) -> Result<Cell, Error> {
+        match <[String; 1]>::try_from(self.values) {
+            Ok([value]) => Ok(Cell(value)),
+            Err(values) => Err(Error(format!(
+                "expected a single field, but the record has {}",
+                values.len()
+            ))),
+        }
+    }
+}
+
+macro_rules! record_scalar {
+    ($($method:ident),* $(,)?) => {
+        $(
+            fn $method<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                self.single()?.$method(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Record<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.headers {
+            Some(headers) => {
+                let fields = headers
+                    .iter()
+                    .map(String::as_str)
+                    .zip(self.values.into_iter().map(Cell));
+                let mut map = de::value::MapDeserializer::new(fields);
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+            None => {
+                let mut seq = SeqDeserializer::new(self.values.into_iter().map(Cell));
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+        }
+    }
+
+    record_scalar!(
+        deserialize_bool,
+        deserialize_i8,
+        deserialize_i16,
+        deserialize_i32,
+        deserialize_i64,
+        deserialize_u8,
+        deserialize_u16,
+        deserialize_u32,
+        deserialize_u64,
+        deserialize_f32,
+        deserialize_f64,
+        deserialize_char,
+        deserialize_str,
+        deserialize_string,
+    );
+
+    fn deserialize_option<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: de::Visitor<'de>>(
+        self,
+        _: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: de::Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.single()?.deserialize_enum(name, variants, visitor)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+/// A single field of a record, parsed as the scalar requested by the target type.
+struct Cell(String);
+
+impl<'de> IntoDeserializer<'de, Error> for Cell {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+macro_rules! field_parse {
+    ($($method:ident => $visit:ident),* $(,)?) => {
+        $(
+            fn $method<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                match self.0.trim().parse() {
+                    Ok(value) => visitor.$visit(value),
+                    Err(_) => Err(Error(format!("invalid field {:?}", self.0))),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Cell {
+    type Error = Error;
+
+    fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_string(self.0)
+    }
+
+    field_parse!(
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    );
+
+    /// Empty fields are missing values.
+    fn deserialize_option<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0.is_empty() {
+            true => visitor.visit_none(),
+            false => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_unit<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0.is_empty() {
+            true => visitor.visit_unit(),
+            false => Err(Error(format!("invalid field {:?}", self.0))),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: de::Visitor<'de>>(
+        self,
+        _: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: de::Visitor<'de>>(
+        self,
+        _: &'static str,
+        _: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_enum(self.0.into_deserializer())
+    }
+
+    serde::forward_to_deserialize_any! {
+        str string bytes byte_buf unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode_seed, to_vec, CsvOptions};
+
+    #[test]
+    fn test_write_records_with_header() {
+        #[derive(serde::Serialize)]
+        struct Row {
+            name: &'static str,
+            count: u32,
+            note: Option<&'static str>,
+        }
+
+        let rows = vec![
+            Row {
+                name: "plain",
+                count: 1,
+                note: None,
+            },
+            Row {
+                name: "needs, \"quotes\"",
+                count: 2,
+                note: Some("multi\nline"),
+            },
+        ];
+
+        assert_eq!(
+            String::from_utf8(to_vec(&rows).unwrap()).unwrap(),
+            "name,count,note\nplain,1,\n\"needs, \"\"quotes\"\"\",2,\"multi\nline\"\n"
+        );
+    }
+
+    #[test]
+    fn test_neutralize_text_starting_like_formulas() {
+        #[derive(serde::Serialize)]
+        struct Row {
+            name: &'static str,
+            balance: i32,
+        }
+
+        let rows = vec![
+            Row {
+                name: "=HYPERLINK(\"http://example.com\")",
+                balance: -1,
+            },
+            Row {
+                name: "@SUM(A1)",
+                balance: 2,
+            },
+        ];
+
+        assert_eq!(
+            String::from_utf8(to_vec(&rows).unwrap()).unwrap(),
+            "name,balance\n\"'=HYPERLINK(\"\"http://example.com\"\")\",-1\n'@SUM(A1),2\n"
+        );
+    }
+
+    #[test]
+    fn test_reject_payloads_that_are_not_flat_sequences() {
+        #[derive(serde::Serialize)]
+        struct Nested {
+            values: Vec<u32>,
+        }
+
+        assert!(to_vec(&Nested { values: vec![1] }).is_err());
+        assert!(to_vec(&vec![Nested { values: vec![1] }]).is_err());
+        assert_eq!(to_vec(&vec![(1, "a"), (2, "b")]).unwrap(), b"1,a\n2,b\n");
+    }
+
+    #[test]
+    fn test_reject_rows_with_fields_other_than_the_header() {
+        use std::collections::BTreeMap;
+
+        let rows = vec![
+            BTreeMap::from([("name", "a"), ("note", "first")]),
+            BTreeMap::from([("count", "2"), ("name", "b")]),
+        ];
+        assert!(to_vec(&rows).is_err());
+
+        let rows = vec![
+            BTreeMap::from([("name", "a"), ("note", "first")]),
+            BTreeMap::from([("name", "b"), ("note", "second")]),
+        ];
+        assert_eq!(to_vec(&rows).unwrap(), b"name,note\na,first\nb,second\n");
+    }
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct Import {
+        name: String,
+        count: u32,
+        note: Option<String>,
+    }
+
+    fn import<T: serde::de::DeserializeOwned>(
+        body: &str,
+        options: CsvOptions,
+    ) -> Result<T, super::Error> {
+        decode_seed(std::marker::PhantomData, body.as_bytes(), &options)
+    }
+
+    #[test]
+    fn test_read_records_by_header() {
+        let rows: Vec<Import> = import(
+            "count,name,note\r\n1,plain,\r\n\n2,\"needs, \"\"quotes\"\"\",\"multi\nline\"\n",
+            CsvOptions::new(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            rows,
+            vec![
+                Import {
+                    name: "plain".to_string(),
+                    count: 1,
+                    note: None,
+                },
+                Import {
+                    name: "needs, \"quotes\"".to_string(),
+                    count: 2,
+                    note: Some("multi\nline".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_records_with_options() {
+        let rows: Vec<(u32, String)> = import(
+            "1;a\n2;b",
+            CsvOptions::new().delimiter(b';').has_headers(false),
+        )
+        .unwrap();
+        assert_eq!(rows, vec![(1, "a".to_string()), (2, "b".to_string())]);
+
+        let values: Vec<u32> = import("1\n2\n", CsvOptions::new().has_headers(false)).unwrap();
+        assert_eq!(values, vec![1, 2]);
+
+        assert!(import::<Vec<Import>>("name,count\nmissing", CsvOptions::new()).is_err());
+        assert!(import::<Vec<Import>>("name,count\nplain,many", CsvOptions::new()).is_err());
+        assert!(import::<Vec<Import>>("name,count\n\"open,1", CsvOptions::new()).is_err());
+    }
+}
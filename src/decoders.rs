@@ -0,0 +1,103 @@
+//! Request body decoders registered at runtime, for media types unknown when compiling the service.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, PoisonError, RwLock},
+};
+
+use axum::body::Bytes;
+
+use crate::{encoding::CodecError, Encoding};
+
+pub(crate) type Translate = dyn Fn(Bytes) -> Result<Bytes, CodecError> + Send + Sync;
+
+/// Decoders of request bodies for extra media types, shared between clones so plugins can register them while the service runs.
+///
+/// Each decoder translates its bodies into one of the compiled [Encoding]s, which then deserializes them as usual,
+/// so the interface with dynamically loaded code stays plain bytes.
+///
+/// ```rust
+/// use axum::{routing::post, Router};
+/// use axum_content_negotiation::{BodyDecoders, Encoding, Negotiate, NegotiateLayer};
+///
+/// async fn handler(Negotiate(value): Negotiate<u32>) -> Negotiate<u32> {
+///     Negotiate(value)
+/// }
+///
+/// let decoders = BodyDecoders::new();
+/// let router: Router = Router::new()
+///     .route("/", post(handler))
+///     .layer(NegotiateLayer::new().body_decoders(decoders.clone()));
+///
+/// // Later on, such as when loading a plugin
+/// # #[cfg(any(feature = "simd-json", feature = "json"))]
+/// decoders.register("application/x-plugin+json", Encoding::Json, |body| Ok(body));
+/// ```
+#[derive(Clone, Default)]
+pub struct BodyDecoders(Arc<RwLock<HashMap<String, Decoder>>>);
+
+#[derive(Clone)]
+pub(crate) struct Decoder {
+    pub(crate) encoding: Encoding,
+    pub(crate) translate: Arc<Translate>,
+}
+
+impl BodyDecoders {
+    /// Create an empty set of decoders.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decode request bodies of `media_type` by translating them into `encoding`, replacing any previous decoder of the media type.
+    ///
+    /// Media types decoded by the [NegotiateLayer](crate::NegotiateLayer), such as `application/json`, keep being decoded by it.
+    pub fn register<F>(&self, media_type: &str, encoding: Encoding, translate: F)
+    where
+        F: Fn(Bytes) -> Result<Bytes, Box<dyn std::error::Error + Send + Sync>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        let decoder = Decoder {
+            encoding,
+            translate: Arc::new(translate),
+        };
+        self.0
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(media_type.to_ascii_lowercase(), decoder);
+    }
+
+    /// Stop decoding request bodies of `media_type`, returning whether it had a decoder.
+    pub fn unregister(&self, media_type: &str) -> bool {
+        self.0
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&media_type.to_ascii_lowercase())
+            .is_some()
+    }
+
+    /// Media types with a registered decoder.
+    pub(crate) fn media_types(&self) -> Vec<String> {
+        let decoders = self.0.read().unwrap_or_else(PoisonError::into_inner);
+        decoders.keys().cloned().collect()
+    }
+
+    /// Find the decoder of the media type informed on a `Content-Type`, ignoring its parameters.
+    pub(crate) fn get(&self, content_type: &str) -> Option<Decoder> {
+        let media_type = content_type.split(';').next().unwrap_or_default().trim();
+        self.0
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&media_type.to_ascii_lowercase())
+            .cloned()
+    }
+}
+
+impl fmt::Debug for BodyDecoders {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let decoders = self.0.read().unwrap_or_else(PoisonError::into_inner);
+        f.debug_set().entries(decoders.keys()).finish()
+    }
+}
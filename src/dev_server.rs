@@ -0,0 +1,209 @@
+//! Reference server that echoes negotiated payloads, meant to test client serializers against.
+//!
+//! Every request body is decoded using the `Content-Type` header and encoded back using the `Accept` header,
+//! so a client can, for example, send `application/json` and ask for `application/cbor` to check both sides of its implementation.
+//!
+//! ```rust,no_run
+//! # async fn run() {
+//! let listener = tokio::net::TcpListener::bind("127.0.0.1:3000").await.unwrap();
+//! axum::serve(listener, axum_content_negotiation::dev_server::router()).await.unwrap();
+//! # }
+//! ```
+//!
+//! Client teams can also contract-test against [Stubs], serving example payloads on the paths of the real backend.
+
+use axum::{
+    response::IntoResponse,
+    routing::{get, post},
+    Router,
+};
+
+use crate::{value::Value, Negotiate, NegotiateLayer};
+
+/// Build a [Router] echoing back any payload sent to `/echo` using all the enabled encodings.
+pub fn router() -> Router {
+    Router::new()
+        .route("/echo", post(echo).put(echo))
+        .layer(NegotiateLayer::new())
+}
+
+async fn echo(Negotiate(payload): Negotiate<Value>) -> impl IntoResponse {
+    Negotiate(payload)
+}
+
+/// Stub server answering `GET` requests with example payloads, negotiated on all the enabled encodings.
+///
+/// ```rust,no_run
+/// use axum_content_negotiation::dev_server::Stubs;
+///
+/// #[derive(Clone, serde::Serialize)]
+/// struct User {
+///     id: u32,
+///     name: &'static str,
+/// }
+///
+/// # async fn run() {
+/// let router = Stubs::new()
+///     .example("/users/1", User { id: 1, name: "Ada" })
+///     .example("/users", vec![User { id: 1, name: "Ada" }])
+///     .router();
+///
+/// let listener = tokio::net::TcpListener::bind("127.0.0.1:3000").await.unwrap();
+/// axum::serve(listener, router).await.unwrap();
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct Stubs(Router);
+
+impl Stubs {
+    /// Create a stub server without examples, answering 404 Not Found to every path.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serve the example on `path`, which uses the [Router] syntax, such as `/users/{id}` to answer every user with it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the path is invalid or already has an example, like [Router::route].
+    pub fn example<T>(self, path: &str, example: T) -> Self
+    where
+        T: serde::Serialize + Clone + Send + Sync + 'static,
+    {
+        Self(
+            self.0
+                .route(path, get(move || async move { Negotiate(example) })),
+        )
+    }
+
+    /// Build the [Router], negotiating the examples with a default [NegotiateLayer].
+    pub fn router(self) -> Router {
+        self.0.layer(NegotiateLayer::new())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        body::Body,
+        http::{
+            header::{ACCEPT, CONTENT_TYPE},
+            Request,
+        },
+    };
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    use super::router;
+
+    #[cfg(any(feature = "simd-json", feature = "json"))]
+    #[tokio::test]
+    async fn test_echo_payload_back() {
+        let response = router()
+            .oneshot(
+                Request::builder()
+                    .uri("/echo")
+                    .method("POST")
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(ACCEPT, "application/json")
+                    .body(Body::from(r#"{"message":"test","values":[1,-2,3.5,null]}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            response.into_body().collect().await.unwrap().to_bytes(),
+            r#"{"message":"test","values":[1,-2,3.5,null]}"#
+        );
+    }
+
+    #[cfg(all(any(feature = "simd-json", feature = "json"), feature = "cbor"))]
+    #[tokio::test]
+    async fn test_serve_stubs_in_every_encoding() {
+        let router = super::Stubs::new()
+            .example("/users/{id}", vec![1, 2])
+            .router();
+
+        for (accept, expected) in [
+            ("application/json", &b"[1,2]"[..]),
+            ("application/cbor", &[0x82, 0x01, 0x02][..]),
+        ] {
+            let response = router
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .uri("/users/42")
+                        .header(ACCEPT, accept)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), 200);
+            assert_eq!(response.headers().get(CONTENT_TYPE).unwrap(), accept);
+            assert_eq!(
+                response.into_body().collect().await.unwrap().to_bytes(),
+                expected
+            );
+        }
+    }
+
+    #[cfg(all(any(feature = "simd-json", feature = "json"), feature = "cbor"))]
+    #[tokio::test]
+    async fn test_echo_payload_across_encodings() {
+        use cbor4ii::core::{enc::Encode, utils::BufWriter, Value};
+
+        let response = router()
+            .oneshot(
+                Request::builder()
+                    .uri("/echo")
+                    .method("PUT")
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(ACCEPT, "application/cbor")
+                    .body(Body::from(r#"{"message":"test"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.into_body().collect().await.unwrap().to_bytes(), {
+            let mut writer = BufWriter::new(Vec::new());
+            Value::Map(vec![(
+                Value::Text("message".to_string()),
+                Value::Text("test".to_string()),
+            )])
+            .encode(&mut writer)
+            .unwrap();
+            writer.into_inner()
+        });
+    }
+
+    #[cfg(feature = "cbor")]
+    #[tokio::test]
+    async fn test_reject_truncated_payloads_declaring_huge_lengths() {
+        // An array of 2^36 elements, and a map of 2^28 entries, without any element
+        for body in [
+            &[0x9b, 0, 0, 0, 0x10, 0, 0, 0, 0][..],
+            &[0xba, 0x10, 0, 0, 0][..],
+        ] {
+            let response = router()
+                .oneshot(
+                    Request::builder()
+                        .uri("/echo")
+                        .method("POST")
+                        .header(CONTENT_TYPE, "application/cbor")
+                        .header(ACCEPT, "application/cbor")
+                        .body(Body::from(body))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), 400);
+        }
+    }
+}
@@ -0,0 +1,160 @@
+//! Rewriting of the `Accept` header of requests sent upstream, for services proxying and transcoding payloads.
+
+use std::{
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use axum::http::{header::ACCEPT, HeaderValue, Request};
+use tower::{Layer, Service};
+
+use crate::{
+    media_type::{parse_accept, split_unquoted, Matcher, MediaRange},
+    Config, Encoding,
+};
+
+/// Rewrites the `Accept` header of outgoing requests to the media types the [NegotiateLayer](crate::NegotiateLayer) can decode,
+/// built by [NegotiateLayer::downgrade_accept](crate::NegotiateLayer::downgrade_accept).
+///
+/// Wrap the client used to call upstream services with it, so their responses use a format the service can transcode
+/// for its own clients. Wildcards are expanded into the supported media types, and requests accepting none of them accept all of them.
+#[derive(Debug, Clone)]
+pub struct AcceptDowngradeLayer {
+    config: Arc<Config>,
+}
+
+impl AcceptDowngradeLayer {
+    pub(crate) fn new(config: Arc<Config>) -> Self {
+        Self { config }
+    }
+}
+
+impl<S> Layer<S> for AcceptDowngradeLayer {
+    type Service = AcceptDowngrade<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AcceptDowngrade {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+/// Rewrites the `Accept` header of outgoing requests, created by the [AcceptDowngradeLayer].
+#[derive(Debug, Clone)]
+pub struct AcceptDowngrade<S> {
+    inner: S,
+    config: Arc<Config>,
+}
+
+impl<S, B> Service<Request<B>> for AcceptDowngrade<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<B>) -> Self::Future {
+        let accept = request
+            .headers()
+            .get(ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .unwrap_or_default();
+        let downgraded = downgrade(&self.config.matcher, accept);
+        if let Ok(downgraded) = HeaderValue::try_from(downgraded) {
+            request.headers_mut().insert(ACCEPT, downgraded);
+        }
+        self.inner.call(request)
+    }
+}
+
+/// Keep the media ranges of an `Accept` header matching a decodable encoding, expanding wildcards into their media types.
+fn downgrade(matcher: &Matcher, accept: &str) -> String {
+    let decodable = || {
+        Encoding::ALL
+            .iter()
+            .filter(|encoding| encoding.decodes())
+            .map(|encoding| encoding.media_type())
+    };
+
+    let mut acceptable = false;
+    let mut ranges: Vec<String> = Vec::new();
+    let mut push = |range: String, media_type: &str| {
+        let listed = ranges.iter().any(|listed| {
+            listed
+                .split(';')
+                .next()
+                .is_some_and(|listed| listed.trim().eq_ignore_ascii_case(media_type))
+        });
+        if !listed {
+            ranges.push(range);
+        }
+    };
+
+    for raw in split_unquoted(accept, ',').into_iter().map(str::trim) {
+        let Some(MediaRange {
+            media_type,
+            quality,
+            ..
+        }) = parse_accept(raw).next()
+        else {
+            continue;
+        };
+
+        if let Some(kind) = media_type.strip_suffix("/*") {
+            if quality <= 0.0 {
+                continue;
+            }
+            for expanded in decodable().filter(|expanded| {
+                kind == "*"
+                    || expanded
+                        .split_once('/')
+                        .is_some_and(|(expanded, _)| expanded.eq_ignore_ascii_case(kind))
+            }) {
+                let range = if quality < 1.0 {
+                    format!("{expanded};q={quality}")
+                } else {
+                    expanded.to_string()
+                };
+                push(range, expanded);
+                acceptable = true;
+            }
+        } else if matcher
+            .select(media_type)
+            .is_some_and(|selection| selection.encoding.decodes())
+        {
+            push(raw.to_string(), media_type);
+            acceptable |= quality > 0.0;
+        }
+    }
+
+    if !acceptable {
+        return decodable().collect::<Vec<_>>().join(", ");
+    }
+    ranges.join(", ")
+}
+
+#[cfg(all(test, any(feature = "simd-json", feature = "json"), feature = "cbor"))]
+mod test {
+    use super::downgrade;
+    use crate::Config;
+
+    #[test]
+    fn test_downgrade_accept_to_decodable_media_types() {
+        let matcher = Config::default().matcher;
+
+        assert_eq!(
+            downgrade(&matcher, "application/xml, application/cbor;q=0.9"),
+            "application/cbor;q=0.9"
+        );
+        assert!(downgrade(&matcher, "application/json, */*;q=0.5")
+            .starts_with("application/json, application/cbor;q=0.5"));
+        assert_eq!(downgrade(&matcher, "text/html"), downgrade(&matcher, ""));
+        assert!(downgrade(&matcher, "").contains("application/json"));
+    }
+}
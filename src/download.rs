@@ -0,0 +1,133 @@
+//! File downloads of negotiated payloads, named after the negotiated format.
+
+use std::fmt::Write;
+
+use axum::{
+    http::HeaderValue,
+    response::{IntoResponse, Response},
+};
+
+use crate::{Encoding, Negotiate};
+
+/// Used as a [Response](axum::response::IntoResponse), like [Negotiate], but downloaded as an attachment,
+/// such as by the export buttons of admin interfaces.
+///
+/// The [NegotiateLayer](crate::NegotiateLayer) informs a `Content-Disposition` with the file extension of the negotiated encoding,
+/// such as `report.json` or `report.cbor`.
+///
+/// ```rust
+/// use axum_content_negotiation::NegotiateDownload;
+///
+/// async fn export() -> NegotiateDownload<Vec<u32>> {
+///     NegotiateDownload::new("report", vec![1, 2, 3])
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct NegotiateDownload<T> {
+    filename_stem: String,
+    value: T,
+}
+
+impl<T> NegotiateDownload<T> {
+    /// Download the value in a file named `filename_stem`, followed by the extension of the negotiated encoding, if it has one.
+    pub fn new(filename_stem: impl Into<String>, value: T) -> Self {
+        Self {
+            filename_stem: filename_stem.into(),
+            value,
+        }
+    }
+}
+
+/// [NegotiateDownload] implements [IntoResponse] if the internal content is serializable.
+impl<T> IntoResponse for NegotiateDownload<T>
+where
+    T: serde::Serialize + Send + Sync + 'static,
+{
+    fn into_response(self) -> Response {
+        let mut response = Negotiate(self.value).into_response();
+        response
+            .extensions_mut()
+            .insert(DownloadName(self.filename_stem));
+        response
+    }
+}
+
+/// Filename stem of a download, waiting for the negotiated encoding to build its `Content-Disposition`.
+#[derive(Debug, Clone)]
+pub(crate) struct DownloadName(String);
+
+impl DownloadName {
+    /// `Content-Disposition` of the download once serialized with the encoding.
+    pub(crate) fn content_disposition(&self, encoding: Encoding) -> Option<HeaderValue> {
+        let filename = match encoding.extension() {
+            Some(extension) => format!("{}.{extension}", self.0),
+            None => self.0.clone(),
+        };
+
+        let mut disposition = String::from("attachment; filename=\"");
+        for c in filename.chars() {
+            match c {
+                '"' | '\\' => {
+                    disposition.push('\\');
+                    disposition.push(c);
+                }
+                ' '..='~' => disposition.push(c),
+                _ => disposition.push('_'),
+            }
+        }
+        disposition.push('"');
+
+        // Non-ASCII names are kept by clients supporting the extended parameter of RFC 6266
+        if !filename.bytes().all(|b| (b' '..=b'~').contains(&b)) {
+            disposition.push_str("; filename*=UTF-8''");
+            for b in filename.bytes() {
+                if b.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&b) {
+                    disposition.push(b as char);
+                } else {
+                    let _ = write!(disposition, "%{b:02X}");
+                }
+            }
+        }
+
+        HeaderValue::try_from(disposition).ok()
+    }
+}
+
+#[cfg(all(test, any(feature = "simd-json", feature = "json")))]
+mod test {
+    use super::DownloadName;
+    use crate::Encoding;
+
+    #[test]
+    fn test_escape_filenames() {
+        let disposition = |stem: &str| {
+            DownloadName(stem.to_string())
+                .content_disposition(Encoding::Json)
+                .unwrap()
+        };
+
+        assert_eq!(
+            disposition("report"),
+            "attachment; filename=\"report.json\""
+        );
+        assert_eq!(
+            disposition("say \"hi\""),
+            "attachment; filename=\"say \\\"hi\\\".json\""
+        );
+        assert_eq!(
+            disposition("relatório"),
+            "attachment; filename=\"relat_rio.json\"; filename*=UTF-8''relat%C3%B3rio.json"
+        );
+    }
+
+    #[cfg(feature = "cbor-seq")]
+    #[test]
+    fn test_leave_formats_without_extension_unsuffixed() {
+        assert_eq!(
+            DownloadName("report".to_string())
+                .content_disposition(Encoding::CborSeq)
+                .unwrap(),
+            "attachment; filename=\"report\""
+        );
+    }
+}
@@ -0,0 +1,721 @@
+//! Serialization formats known by the crate, and how to encode/decode each of them.
+
+use axum::body::Bytes;
+
+/// Error produced while encoding or decoding a payload with one of the enabled formats.
+pub(crate) type CodecError = Box<dyn std::error::Error + Send + Sync>;
+
+/// How request bodies are read, for the encodings with options on the [NegotiateLayer](crate::NegotiateLayer).
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DecodeOptions {
+    #[cfg(feature = "csv")]
+    pub(crate) csv: crate::csv::CsvOptions,
+    /// Check `application/geo+json` payloads are GeoJSON objects, on requests and responses
+    #[cfg(feature = "geojson")]
+    pub(crate) validate_geojson: bool,
+}
+
+/// Serialization formats enabled by the crate features.
+///
+/// The variants available depend on which features are enabled, so matching on it should always include a wildcard arm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Encoding {
+    /// `application/json`, enabled by either the `json` or `simd-json` features.
+    #[cfg(any(feature = "simd-json", feature = "json"))]
+    Json,
+    /// `application/cbor`, enabled by the `cbor` feature.
+    #[cfg(feature = "cbor")]
+    Cbor,
+    /// `text/csv`, enabled by the `csv` feature.
+    ///
+    /// Only responses that are sequences of flat records can be encoded, while the other responses are answered with the next acceptable encoding.
+    /// Request bodies are decoded as sequences of records,
+    /// read as configured by [NegotiateLayer::csv_options](crate::NegotiateLayer::csv_options).
+    #[cfg(feature = "csv")]
+    Csv,
+    /// `application/x-ndjson`, enabled by the `ndjson` feature.
+    ///
+    /// Sequences are encoded as one JSON document per line, and any other payload as a single line.
+    /// Only responses can be encoded.
+    #[cfg(feature = "ndjson")]
+    NdJson,
+    /// `application/cbor-seq`, enabled by the `cbor-seq` feature.
+    ///
+    /// Sequences are encoded as consecutive CBOR items without an enclosing array, and any other payload as a single item.
+    /// Request bodies are decoded as a sequence of all their items.
+    #[cfg(feature = "cbor-seq")]
+    CborSeq,
+    /// `application/flatbuffers`, enabled by the `flatbuffers` feature.
+    ///
+    /// Only [NegotiateFlatBuffer](crate::NegotiateFlatBuffer) responses can be encoded, as FlatBuffers doesn't use serde,
+    /// while the other responses are answered with the next acceptable encoding.
+    #[cfg(feature = "flatbuffers")]
+    FlatBuffers,
+    /// `multipart/form-data`, enabled by the `multipart` feature.
+    ///
+    /// Only request bodies can be decoded, from their text fields, skipping files. It is never negotiated for responses.
+    #[cfg(feature = "multipart")]
+    Multipart,
+    /// `text/plain`, enabled by the `plain-text` feature.
+    ///
+    /// Only [NegotiatePlainText](crate::NegotiatePlainText) responses can be encoded, from their human-readable form,
+    /// while the other responses are answered with the next acceptable encoding.
+    #[cfg(feature = "plain-text")]
+    PlainText,
+    /// `application/hal+json`, enabled by the `hal` feature.
+    ///
+    /// Encoded as JSON, with the [HalLinks](crate::HalLinks) of [NegotiateHal](crate::NegotiateHal) responses injected as their `_links`.
+    #[cfg(feature = "hal")]
+    Hal,
+    /// `application/octet-stream`, enabled by the `octet-stream` feature.
+    ///
+    /// Only [NegotiateBytes](crate::NegotiateBytes) responses can be encoded, passing their bytes through as is,
+    /// while the other responses are answered with the next acceptable encoding.
+    /// Request bodies are decoded as a byte string.
+    #[cfg(feature = "octet-stream")]
+    OctetStream,
+    /// `application/atom+xml`, enabled by the `feed` feature.
+    ///
+    /// Only [NegotiateFeed](crate::NegotiateFeed) responses can be encoded, from their [Feed](crate::Feed),
+    /// while the other responses are answered with the next acceptable encoding.
+    #[cfg(feature = "feed")]
+    Atom,
+    /// `application/rss+xml`, enabled by the `feed` feature.
+    ///
+    /// Only [NegotiateFeed](crate::NegotiateFeed) responses can be encoded, from their [Feed](crate::Feed),
+    /// while the other responses are answered with the next acceptable encoding.
+    #[cfg(feature = "feed")]
+    Rss,
+    /// `application/javascript`, enabled by the `jsonp` feature.
+    ///
+    /// Only negotiated on layers configured with [NegotiateLayer::jsonp](crate::NegotiateLayer::jsonp),
+    /// encoded as JSON wrapped in the callback named on the query string. Only responses can be encoded.
+    #[cfg(feature = "jsonp")]
+    Jsonp,
+    /// `text/html`, enabled by the `debug-html` feature.
+    ///
+    /// Responses are rendered as nested HTML tables and lists for browsing endpoints during development. Only responses can be encoded.
+    #[cfg(feature = "debug-html")]
+    Html,
+    /// `application/vnd.openxmlformats-officedocument.spreadsheetml.sheet`, enabled by the `xlsx` feature.
+    ///
+    /// Only responses that are sequences of flat records can be encoded, as a workbook with a single worksheet,
+    /// while the other responses are answered with the next acceptable encoding.
+    #[cfg(feature = "xlsx")]
+    Xlsx,
+    /// `application/geo+json`, enabled by the `geojson` feature.
+    ///
+    /// Encoded and decoded as JSON, checking the payloads are GeoJSON objects on layers configured with
+    /// [NegotiateLayer::validate_geojson](crate::NegotiateLayer::validate_geojson).
+    #[cfg(feature = "geojson")]
+    GeoJson,
+}
+
+impl Encoding {
+    /// All the encodings enabled by the crate features.
+    pub const ALL: &'static [Self] = &[
+        #[cfg(any(feature = "simd-json", feature = "json"))]
+        Self::Json,
+        #[cfg(feature = "cbor")]
+        Self::Cbor,
+        #[cfg(feature = "csv")]
+        Self::Csv,
+        #[cfg(feature = "ndjson")]
+        Self::NdJson,
+        #[cfg(feature = "cbor-seq")]
+        Self::CborSeq,
+        #[cfg(feature = "flatbuffers")]
+        Self::FlatBuffers,
+        #[cfg(feature = "multipart")]
+        Self::Multipart,
+        #[cfg(feature = "plain-text")]
+        Self::PlainText,
+        #[cfg(feature = "hal")]
+        Self::Hal,
+        #[cfg(feature = "octet-stream")]
+        Self::OctetStream,
+        #[cfg(feature = "feed")]
+        Self::Atom,
+        #[cfg(feature = "feed")]
+        Self::Rss,
+        #[cfg(feature = "jsonp")]
+        Self::Jsonp,
+        #[cfg(feature = "debug-html")]
+        Self::Html,
+        #[cfg(feature = "xlsx")]
+        Self::Xlsx,
+        #[cfg(feature = "geojson")]
+        Self::GeoJson,
+    ];
+
+    /// Encoding used when the request does not inform which format to use and the [NegotiateLayer](crate::NegotiateLayer) is not configured otherwise.
+    ///
+    /// It is defined by the `default-*` features. When more than one is enabled, `default-json` takes precedence.
+    pub const fn compiled_default() -> Option<Self> {
+        #[cfg(all(feature = "default-json", any(feature = "simd-json", feature = "json")))]
+        return Some(Self::Json);
+
+        #[cfg(all(
+            feature = "default-cbor",
+            feature = "cbor",
+            not(all(feature = "default-json", any(feature = "simd-json", feature = "json")))
+        ))]
+        return Some(Self::Cbor);
+
+        #[allow(unreachable_code)]
+        None
+    }
+
+    /// Media type used on the `Content-Type` header for this encoding.
+    pub const fn media_type(self) -> &'static str {
+        match self {
+            #[cfg(any(feature = "simd-json", feature = "json"))]
+            Self::Json => "application/json",
+            #[cfg(feature = "cbor")]
+            Self::Cbor => "application/cbor",
+            #[cfg(feature = "csv")]
+            Self::Csv => "text/csv",
+            #[cfg(feature = "ndjson")]
+            Self::NdJson => "application/x-ndjson",
+            #[cfg(feature = "cbor-seq")]
+            Self::CborSeq => "application/cbor-seq",
+            #[cfg(feature = "flatbuffers")]
+            Self::FlatBuffers => "application/flatbuffers",
+            #[cfg(feature = "multipart")]
+            Self::Multipart => "multipart/form-data",
+            #[cfg(feature = "plain-text")]
+            Self::PlainText => "text/plain",
+            #[cfg(feature = "hal")]
+            Self::Hal => "application/hal+json",
+            #[cfg(feature = "octet-stream")]
+            Self::OctetStream => "application/octet-stream",
+            #[cfg(feature = "feed")]
+            Self::Atom => "application/atom+xml",
+            #[cfg(feature = "feed")]
+            Self::Rss => "application/rss+xml",
+            #[cfg(feature = "jsonp")]
+            Self::Jsonp => "application/javascript",
+            #[cfg(feature = "debug-html")]
+            Self::Html => "text/html",
+            #[cfg(feature = "xlsx")]
+            Self::Xlsx => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+            #[cfg(feature = "geojson")]
+            Self::GeoJson => "application/geo+json",
+        }
+    }
+
+    /// Structured syntax suffix of the encoding, used by vendor media types such as `application/vnd.example+json`.
+    pub const fn suffix(self) -> &'static str {
+        match self {
+            #[cfg(any(feature = "simd-json", feature = "json"))]
+            Self::Json => "json",
+            #[cfg(feature = "cbor")]
+            Self::Cbor => "cbor",
+            #[cfg(feature = "csv")]
+            Self::Csv => "csv",
+            #[cfg(feature = "ndjson")]
+            Self::NdJson => "ndjson",
+            #[cfg(feature = "cbor-seq")]
+            Self::CborSeq => "cbor-seq",
+            #[cfg(feature = "flatbuffers")]
+            Self::FlatBuffers => "flatbuffers",
+            #[cfg(feature = "multipart")]
+            Self::Multipart => "form-data",
+            #[cfg(feature = "plain-text")]
+            Self::PlainText => "plain",
+            #[cfg(feature = "hal")]
+            Self::Hal => "json",
+            #[cfg(feature = "octet-stream")]
+            Self::OctetStream => "octet-stream",
+            #[cfg(feature = "feed")]
+            Self::Atom => "xml",
+            #[cfg(feature = "feed")]
+            Self::Rss => "xml",
+            #[cfg(feature = "jsonp")]
+            Self::Jsonp => "javascript",
+            #[cfg(feature = "debug-html")]
+            Self::Html => "html",
+            #[cfg(feature = "xlsx")]
+            Self::Xlsx => "xlsx",
+            #[cfg(feature = "geojson")]
+            Self::GeoJson => "json",
+        }
+    }
+
+    /// Whether request bodies in this encoding can be decoded.
+    pub(crate) const fn decodes(self) -> bool {
+        match self {
+            #[cfg(feature = "ndjson")]
+            Self::NdJson => false,
+            #[cfg(feature = "flatbuffers")]
+            Self::FlatBuffers => false,
+            #[cfg(feature = "plain-text")]
+            Self::PlainText => false,
+            #[cfg(feature = "feed")]
+            Self::Atom | Self::Rss => false,
+            #[cfg(feature = "jsonp")]
+            Self::Jsonp => false,
+            #[cfg(feature = "debug-html")]
+            Self::Html => false,
+            #[cfg(feature = "xlsx")]
+            Self::Xlsx => false,
+            #[allow(unreachable_patterns)]
+            _ => true,
+        }
+    }
+
+    /// Whether responses can be encoded, by serde or by a dedicated response type, so the encoding can be negotiated on `Accept` headers.
+    pub(crate) const fn answers(self) -> bool {
+        match self {
+            #[cfg(feature = "multipart")]
+            Self::Multipart => false,
+            #[allow(unreachable_patterns)]
+            _ => true,
+        }
+    }
+
+    /// Whether serde payloads, such as [Negotiate](crate::Negotiate) responses, can be encoded.
+    pub(crate) const fn encodes(self) -> bool {
+        match self {
+            #[cfg(feature = "flatbuffers")]
+            Self::FlatBuffers => false,
+            #[cfg(feature = "multipart")]
+            Self::Multipart => false,
+            #[cfg(feature = "plain-text")]
+            Self::PlainText => false,
+            #[cfg(feature = "octet-stream")]
+            Self::OctetStream => false,
+            #[cfg(feature = "feed")]
+            Self::Atom | Self::Rss => false,
+            #[allow(unreachable_patterns)]
+            _ => true,
+        }
+    }
+
+    /// Whether only some serde payloads can be encoded, such as sequences of flat records,
+    /// so failing to encode one means it can't be represented rather than being broken.
+    pub(crate) const fn shaped(self) -> bool {
+        match self {
+            #[cfg(feature = "csv")]
+            Self::Csv => true,
+            #[cfg(feature = "xlsx")]
+            Self::Xlsx => true,
+            #[allow(unreachable_patterns)]
+            _ => false,
+        }
+    }
+
+    /// File extension of documents in this encoding, without the leading dot.
+    ///
+    /// Formats without a registered extension, such as CBOR sequences and multipart documents, have none,
+    /// rather than borrowing the extension of another format.
+    pub const fn extension(self) -> Option<&'static str> {
+        match self {
+            #[cfg(any(feature = "simd-json", feature = "json"))]
+            Self::Json => Some("json"),
+            #[cfg(feature = "cbor")]
+            Self::Cbor => Some("cbor"),
+            #[cfg(feature = "csv")]
+            Self::Csv => Some("csv"),
+            #[cfg(feature = "ndjson")]
+            Self::NdJson => Some("ndjson"),
+            #[cfg(feature = "cbor-seq")]
+            Self::CborSeq => None,
+            #[cfg(feature = "flatbuffers")]
+            Self::FlatBuffers => Some("bin"),
+            #[cfg(feature = "multipart")]
+            Self::Multipart => None,
+            #[cfg(feature = "plain-text")]
+            Self::PlainText => Some("txt"),
+            #[cfg(feature = "hal")]
+            Self::Hal => Some("json"),
+            #[cfg(feature = "octet-stream")]
+            Self::OctetStream => Some("bin"),
+            #[cfg(feature = "feed")]
+            Self::Atom => Some("atom"),
+            #[cfg(feature = "feed")]
+            Self::Rss => Some("rss"),
+            #[cfg(feature = "jsonp")]
+            Self::Jsonp => Some("js"),
+            #[cfg(feature = "debug-html")]
+            Self::Html => Some("html"),
+            #[cfg(feature = "xlsx")]
+            Self::Xlsx => Some("xlsx"),
+            #[cfg(feature = "geojson")]
+            Self::GeoJson => Some("geojson"),
+        }
+    }
+
+    /// Find the encoding matching exactly the provided media type.
+    pub fn from_media_type(media_type: &[u8]) -> Option<Self> {
+        match media_type {
+            #[cfg(any(feature = "simd-json", feature = "json"))]
+            b"application/json" => Some(Self::Json),
+            #[cfg(feature = "cbor")]
+            b"application/cbor" => Some(Self::Cbor),
+            #[cfg(feature = "csv")]
+            b"text/csv" => Some(Self::Csv),
+            #[cfg(feature = "ndjson")]
+            b"application/x-ndjson" => Some(Self::NdJson),
+            #[cfg(feature = "cbor-seq")]
+            b"application/cbor-seq" => Some(Self::CborSeq),
+            #[cfg(feature = "flatbuffers")]
+            b"application/flatbuffers" => Some(Self::FlatBuffers),
+            #[cfg(feature = "multipart")]
+            b"multipart/form-data" => Some(Self::Multipart),
+            #[cfg(feature = "plain-text")]
+            b"text/plain" => Some(Self::PlainText),
+            #[cfg(feature = "hal")]
+            b"application/hal+json" => Some(Self::Hal),
+            #[cfg(feature = "octet-stream")]
+            b"application/octet-stream" => Some(Self::OctetStream),
+            #[cfg(feature = "feed")]
+            b"application/atom+xml" => Some(Self::Atom),
+            #[cfg(feature = "feed")]
+            b"application/rss+xml" => Some(Self::Rss),
+            #[cfg(feature = "jsonp")]
+            b"application/javascript" => Some(Self::Jsonp),
+            #[cfg(feature = "debug-html")]
+            b"text/html" => Some(Self::Html),
+            #[cfg(feature = "xlsx")]
+            b"application/vnd.openxmlformats-officedocument.spreadsheetml.sheet" => {
+                Some(Self::Xlsx)
+            }
+            #[cfg(feature = "geojson")]
+            b"application/geo+json" => Some(Self::GeoJson),
+            _ => None,
+        }
+    }
+
+    /// Serialize the value into a body of this encoding, such as on clients sharing the codecs of the service.
+    ///
+    /// Fails for the encodings requiring a dedicated response type, such as `application/octet-stream`, or unable to represent the value.
+    pub fn to_vec<T>(self, value: &T) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>>
+    where
+        T: serde::Serialize,
+    {
+        self.encode(value)
+    }
+
+    /// Deserialize a body of this encoding, such as on clients reading the responses of the service.
+    ///
+    /// Bodies are read with the default options, such as comma-separated CSV records with a header line.
+    pub fn from_slice<T>(self, body: &[u8]) -> Result<T, Box<dyn std::error::Error + Send + Sync>>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        self.decode(Bytes::copy_from_slice(body), &DecodeOptions::default())
+    }
+
+    /// Deserialize the body into the target type, reading it as configured on the layer.
+    pub(crate) fn decode<T>(self, body: Bytes, options: &DecodeOptions) -> Result<T, CodecError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        self.decode_seed(std::marker::PhantomData::<T>, body, options)
+    }
+
+    /// Deserialize the body using a stateful seed.
+    pub(crate) fn decode_seed<D, T>(
+        self,
+        seed: D,
+        body: Bytes,
+        options: &DecodeOptions,
+    ) -> Result<T, CodecError>
+    where
+        D: for<'de> serde::de::DeserializeSeed<'de, Value = T>,
+    {
+        // Only some encodings are read with options
+        let _ = options;
+        match self {
+            #[cfg(feature = "simd-json")]
+            Self::Json => {
+                let mut body = body.to_vec();
+                let mut deserializer = simd_json::Deserializer::from_slice(&mut body)?;
+                Ok(seed.deserialize(&mut deserializer)?)
+            }
+            #[cfg(feature = "json")]
+            Self::Json => {
+                let mut deserializer = serde_json::Deserializer::from_slice(&body);
+                let value = seed.deserialize(&mut deserializer)?;
+                deserializer.end()?;
+                Ok(value)
+            }
+            #[cfg(feature = "cbor")]
+            Self::Cbor => {
+                let mut deserializer = cbor4ii::serde::Deserializer::new(
+                    cbor4ii::core::utils::SliceReader::new(&body),
+                );
+                Ok(seed.deserialize(&mut deserializer)?)
+            }
+            #[cfg(feature = "csv")]
+            Self::Csv => Ok(crate::csv::decode_seed(seed, &body, &options.csv)?),
+            #[cfg(feature = "ndjson")]
+            Self::NdJson => {
+                let _ = (seed, body);
+                Err("NDJSON request bodies are not supported".into())
+            }
+            #[cfg(feature = "cbor-seq")]
+            Self::CborSeq => crate::sequence::decode_cbor_items(seed, &body),
+            #[cfg(feature = "flatbuffers")]
+            Self::FlatBuffers => {
+                let _ = (seed, body);
+                Err("FlatBuffers request bodies are not supported".into())
+            }
+            #[cfg(feature = "multipart")]
+            Self::Multipart => crate::multipart::decode_seed(seed, &body),
+            #[cfg(feature = "plain-text")]
+            Self::PlainText => {
+                let _ = (seed, body);
+                Err("text/plain request bodies are not supported".into())
+            }
+            #[cfg(feature = "hal")]
+            Self::Hal => Self::Json.decode_seed(seed, body, options),
+            #[cfg(feature = "octet-stream")]
+            Self::OctetStream => Ok(seed.deserialize(serde::de::value::BytesDeserializer::<
+                serde::de::value::Error,
+            >::new(&body))?),
+            #[cfg(feature = "feed")]
+            Self::Atom | Self::Rss => {
+                let _ = (seed, body);
+                Err("feed request bodies are not supported".into())
+            }
+            #[cfg(feature = "jsonp")]
+            Self::Jsonp => {
+                let _ = (seed, body);
+                Err("application/javascript request bodies are not supported".into())
+            }
+            #[cfg(feature = "debug-html")]
+            Self::Html => {
+                let _ = (seed, body);
+                Err("text/html request bodies are not supported".into())
+            }
+            #[cfg(feature = "xlsx")]
+            Self::Xlsx => {
+                let _ = (seed, body);
+                Err("XLSX request bodies are not supported".into())
+            }
+            #[cfg(feature = "geojson")]
+            Self::GeoJson => {
+                if options.validate_geojson {
+                    crate::geojson::validate(&body)?;
+                }
+                Self::Json.decode_seed(seed, body, options)
+            }
+        }
+    }
+
+    /// Serialize the type-erased payload into the format body.
+    pub(crate) fn encode(
+        self,
+        payload: &dyn erased_serde::Serialize,
+    ) -> Result<Vec<u8>, CodecError> {
+        match self {
+            #[cfg(any(feature = "simd-json", feature = "json"))]
+            Self::Json => encode_json(payload),
+            #[cfg(feature = "cbor")]
+            Self::Cbor => encode_cbor(payload),
+            #[cfg(feature = "csv")]
+            Self::Csv => Ok(crate::csv::to_vec(payload)?),
+            #[cfg(feature = "ndjson")]
+            Self::NdJson => {
+                let mut body = Vec::new();
+                let is_sequence = crate::sequence::for_each_item(payload, |item| {
+                    body.append(&mut encode_json(item)?);
+                    body.push(b'\n');
+                    Ok(())
+                })?;
+                if !is_sequence {
+                    body = encode_json(payload)?;
+                    body.push(b'\n');
+                }
+                Ok(body)
+            }
+            #[cfg(feature = "cbor-seq")]
+            Self::CborSeq => {
+                let mut body = Vec::new();
+                let is_sequence = crate::sequence::for_each_item(payload, |item| {
+                    body.append(&mut encode_cbor(item)?);
+                    Ok(())
+                })?;
+                if !is_sequence {
+                    body = encode_cbor(payload)?;
+                }
+                Ok(body)
+            }
+            #[cfg(feature = "flatbuffers")]
+            Self::FlatBuffers => {
+                let _ = payload;
+                Err("FlatBuffers responses require a NegotiateFlatBuffer payload".into())
+            }
+            #[cfg(feature = "multipart")]
+            Self::Multipart => {
+                let _ = payload;
+                Err("multipart/form-data responses are not supported".into())
+            }
+            #[cfg(feature = "plain-text")]
+            Self::PlainText => {
+                let _ = payload;
+                Err("text/plain responses require a NegotiatePlainText payload".into())
+            }
+            #[cfg(feature = "hal")]
+            Self::Hal => encode_json(payload),
+            #[cfg(feature = "octet-stream")]
+            Self::OctetStream => {
+                let _ = payload;
+                Err("application/octet-stream responses require a NegotiateBytes payload".into())
+            }
+            #[cfg(feature = "feed")]
+            Self::Atom | Self::Rss => {
+                let _ = payload;
+                Err("feed responses require a NegotiateFeed payload".into())
+            }
+            #[cfg(feature = "jsonp")]
+            Self::Jsonp => encode_json(payload),
+            #[cfg(feature = "debug-html")]
+            Self::Html => crate::html::render(payload),
+            #[cfg(feature = "xlsx")]
+            Self::Xlsx => crate::xlsx::to_vec(payload),
+            #[cfg(feature = "geojson")]
+            Self::GeoJson => encode_json(payload),
+        }
+    }
+
+    /// Serialize the type-erased payload into a caller-provided buffer, returning the length written.
+    ///
+    /// Returns `None` when the body doesn't fit the buffer or the encoding isn't supported, in which case [Encoding::encode] should be used.
+    pub(crate) fn encode_inline(
+        self,
+        payload: &dyn erased_serde::Serialize,
+        buffer: &mut [u8],
+    ) -> Option<usize> {
+        let capacity = buffer.len();
+        match self {
+            #[cfg(any(feature = "simd-json", feature = "json"))]
+            Self::Json => {
+                let mut writer = buffer;
+                {
+                    let mut serializer = serde_json::Serializer::new(&mut writer);
+                    let mut serializer = <dyn erased_serde::Serializer>::erase(&mut serializer);
+                    payload.erased_serialize(&mut serializer).ok()?;
+                }
+                Some(capacity - writer.len())
+            }
+            #[cfg(feature = "cbor")]
+            Self::Cbor => {
+                let mut writer = SliceWriter(buffer);
+                {
+                    let mut serializer = cbor4ii::serde::Serializer::new(&mut writer);
+                    let mut serializer = <dyn erased_serde::Serializer>::erase(&mut serializer);
+                    payload.erased_serialize(&mut serializer).ok()?;
+                }
+                Some(capacity - writer.0.len())
+            }
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
+    }
+}
+
+/// CBOR writer filling a fixed buffer, failing once it is full.
+#[cfg(feature = "cbor")]
+struct SliceWriter<'a>(&'a mut [u8]);
+
+#[cfg(feature = "cbor")]
+impl cbor4ii::core::enc::Write for SliceWriter<'_> {
+    type Error = std::io::Error;
+
+    fn push(&mut self, input: &[u8]) -> Result<(), Self::Error> {
+        std::io::Write::write_all(&mut self.0, input)
+    }
+}
+
+/// Serialize the payload as a single JSON document.
+#[cfg(any(feature = "simd-json", feature = "json", feature = "ndjson"))]
+fn encode_json(payload: &dyn erased_serde::Serialize) -> Result<Vec<u8>, CodecError> {
+    let mut body = Vec::new();
+    {
+        let mut serializer = serde_json::Serializer::new(&mut body);
+        let mut serializer = <dyn erased_serde::Serializer>::erase(&mut serializer);
+        payload.erased_serialize(&mut serializer)?;
+    }
+    Ok(body)
+}
+
+/// Layout of CBOR responses on the wire.
+#[cfg(feature = "cbor")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CborProfile {
+    /// Layout chosen by the serde backend, such as indefinite lengths for sequences of unknown size.
+    #[default]
+    Backend,
+    /// Layout pinned by the crate, so responses stay byte-stable across upgrades of the crate and its backend:
+    /// definite lengths, map keys sorted by their encoded bytes, floats always in 64 bits and tags kept as serialized.
+    ///
+    /// It follows the deterministic encoding of RFC 8949, except for the shortest float forms.
+    Deterministic,
+}
+
+/// Re-encode a CBOR item with the [CborProfile::Deterministic] layout.
+#[cfg(feature = "cbor")]
+pub(crate) fn deterministic_cbor(body: &[u8]) -> Result<Vec<u8>, CodecError> {
+    use cbor4ii::core::{
+        dec::Decode,
+        enc::Encode,
+        utils::{BufWriter, SliceReader},
+        Value,
+    };
+
+    fn encode(value: &Value) -> Result<Vec<u8>, CodecError> {
+        let mut writer = BufWriter::new(Vec::new());
+        value
+            .encode(&mut writer)
+            .map_err(|e| format!("failed to encode CBOR: {e:?}"))?;
+        Ok(writer.into_inner())
+    }
+
+    fn sort_keys(value: Value) -> Result<Value, CodecError> {
+        Ok(match value {
+            Value::Array(items) => {
+                Value::Array(items.into_iter().map(sort_keys).collect::<Result<_, _>>()?)
+            }
+            Value::Map(entries) => {
+                let mut entries = entries
+                    .into_iter()
+                    .map(|(key, value)| {
+                        let key = sort_keys(key)?;
+                        Ok((encode(&key)?, key, sort_keys(value)?))
+                    })
+                    .collect::<Result<Vec<_>, CodecError>>()?;
+                entries.sort_by(|(a, ..), (b, ..)| a.cmp(b));
+                Value::Map(entries.into_iter().map(|(_, k, v)| (k, v)).collect())
+            }
+            Value::Tag(tag, value) => Value::Tag(tag, Box::new(sort_keys(*value)?)),
+            value => value,
+        })
+    }
+
+    let mut reader = SliceReader::new(body);
+    let value = Value::decode(&mut reader).map_err(|e| format!("failed to decode CBOR: {e:?}"))?;
+    encode(&sort_keys(value)?)
+}
+
+/// Serialize the payload as a single CBOR item.
+#[cfg(feature = "cbor")]
+fn encode_cbor(payload: &dyn erased_serde::Serialize) -> Result<Vec<u8>, CodecError> {
+    let mut body = cbor4ii::core::utils::BufWriter::new(Vec::new());
+    {
+        let mut serializer = cbor4ii::serde::Serializer::new(&mut body);
+        let mut serializer = <dyn erased_serde::Serializer>::erase(&mut serializer);
+        payload.erased_serialize(&mut serializer)?;
+    }
+    Ok(body.into_inner())
+}
+
+impl std::fmt::Display for Encoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.media_type())
+    }
+}
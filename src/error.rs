@@ -0,0 +1,275 @@
+//! Rejections produced by the crate, and how their bodies are built.
+
+use std::{fmt, sync::Arc};
+
+use axum::{
+    http::{
+        header::{ACCEPT, CONTENT_TYPE},
+        HeaderMap, HeaderName, HeaderValue, Method, StatusCode,
+    },
+    response::{IntoResponse, Response},
+};
+
+use crate::{problem, Encoding, ErasedNegotiate, ProblemDetails};
+
+/// Header used to correlate error bodies with the request that caused them.
+static REQUEST_ID: &str = "x-request-id";
+
+/// Why a media type informed by the request could not be used, ordered from the least to the most specific.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Mismatch {
+    /// The media type is missing or syntactically invalid
+    Invalid,
+    /// The media type is valid, but not supported by the crate
+    Unsupported,
+    /// The media type is supported by the crate, but disabled on this service
+    Disabled,
+}
+
+/// Failures detected by the crate while negotiating, decoding or encoding payloads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Rejection {
+    /// The request `Content-Type` can't be used
+    UnsupportedContentType(Mismatch),
+    /// The request has a body without a `Content-Type`, and the layer requires one
+    MissingContentType,
+    /// None of the media types on the `Accept` header can be used
+    NotAcceptable(Mismatch),
+    /// The `Accept` header is present but empty, and configured to be rejected
+    EmptyAccept,
+    /// The request body could not be read
+    BodyRead(StatusCode),
+    /// The request body exceeds the configured size limit
+    RequestTooLarge,
+    /// The request body was not received within the configured timeout
+    #[cfg(feature = "tokio")]
+    BodyTimeout,
+    /// The request body could not be deserialized on the informed format
+    MalformedBody,
+    /// The response payload could not be serialized on the negotiated format
+    SerializationFailed,
+    /// The serialized response payload exceeds the configured size limit
+    ResponseTooLarge,
+    /// The response has a body that bypassed the negotiation, on a strict layer
+    Unnegotiated,
+    /// JSONP was negotiated without a valid callback on the query string
+    #[cfg(feature = "jsonp")]
+    InvalidCallback,
+}
+
+impl Rejection {
+    fn status(self) -> StatusCode {
+        match self {
+            Self::UnsupportedContentType(_) | Self::MissingContentType => {
+                StatusCode::UNSUPPORTED_MEDIA_TYPE
+            }
+            Self::NotAcceptable(_) => StatusCode::NOT_ACCEPTABLE,
+            Self::BodyRead(status) => status,
+            Self::RequestTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            #[cfg(feature = "tokio")]
+            Self::BodyTimeout => StatusCode::REQUEST_TIMEOUT,
+            Self::EmptyAccept | Self::MalformedBody => StatusCode::BAD_REQUEST,
+            #[cfg(feature = "jsonp")]
+            Self::InvalidCallback => StatusCode::BAD_REQUEST,
+            Self::SerializationFailed | Self::Unnegotiated => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::ResponseTooLarge => StatusCode::INSUFFICIENT_STORAGE,
+        }
+    }
+
+    fn code(self) -> &'static str {
+        match self {
+            Self::UnsupportedContentType(Mismatch::Invalid) => "invalid_content_type",
+            Self::UnsupportedContentType(Mismatch::Unsupported) => "unsupported_content_type",
+            Self::UnsupportedContentType(Mismatch::Disabled) => "disabled_content_type",
+            Self::MissingContentType => "missing_content_type",
+            Self::NotAcceptable(Mismatch::Invalid) => "invalid_accept",
+            Self::NotAcceptable(Mismatch::Unsupported) => "not_acceptable",
+            Self::NotAcceptable(Mismatch::Disabled) => "disabled_accept",
+            Self::EmptyAccept => "empty_accept",
+            Self::BodyRead(_) => "body_read_failed",
+            Self::RequestTooLarge => "request_too_large",
+            #[cfg(feature = "tokio")]
+            Self::BodyTimeout => "body_read_timeout",
+            Self::MalformedBody => "malformed_body",
+            Self::SerializationFailed => "serialization_failed",
+            Self::ResponseTooLarge => "response_too_large",
+            Self::Unnegotiated => "unnegotiated_response",
+            #[cfg(feature = "jsonp")]
+            Self::InvalidCallback => "invalid_jsonp_callback",
+        }
+    }
+
+    fn message(self) -> &'static str {
+        match self {
+            Self::UnsupportedContentType(mismatch) | Self::NotAcceptable(mismatch) => {
+                match mismatch {
+                    Mismatch::Invalid => "Invalid content type on request",
+                    Mismatch::Unsupported => "Unsupported content type on request",
+                    Mismatch::Disabled => "Content type disabled on this service",
+                }
+            }
+            Self::MissingContentType => "Missing content type on request",
+            Self::EmptyAccept => "Empty accept header on request",
+            Self::BodyRead(_) => "Failed to read request body",
+            Self::RequestTooLarge => "Request body exceeds the size limit",
+            #[cfg(feature = "tokio")]
+            Self::BodyTimeout => "Timed out reading request body",
+            Self::MalformedBody => "Malformed request body",
+            Self::SerializationFailed => "Failed to serialize response",
+            Self::ResponseTooLarge => "Serialized response exceeds the size limit",
+            Self::Unnegotiated => "Response bypassed content negotiation",
+            #[cfg(feature = "jsonp")]
+            Self::InvalidCallback => "Missing or invalid JSONP callback",
+        }
+    }
+}
+
+/// Details about a rejection produced by the crate, provided to the [NegotiateLayer::error_body](crate::NegotiateLayer::error_body) template.
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub struct ErrorDetails<'a> {
+    /// Status code of the response
+    pub status: StatusCode,
+    /// Stable machine-readable identifier of the failure, such as `malformed_body`
+    pub code: &'static str,
+    /// Human-readable description of the failure
+    pub message: &'static str,
+    /// Value of the `x-request-id` request header, if present
+    pub request_id: Option<&'a str>,
+    /// Media types supported by the service, when the `Content-Type` or `Accept` of the request was rejected, empty otherwise
+    pub supported: &'a [&'static str],
+}
+
+/// Closure building the serializable error body for the crate rejections, and the media type informed for each encoding.
+#[derive(Clone)]
+pub(crate) struct ErrorTemplate {
+    pub(crate) build: Arc<dyn Fn(&ErrorDetails<'_>) -> ErasedNegotiate + Send + Sync>,
+    pub(crate) media_type: fn(Encoding) -> &'static str,
+}
+
+impl ErrorTemplate {
+    /// Problem details of RFC 9457, informing the rejection code and request identifier as extension members.
+    pub(crate) fn problem_details() -> Self {
+        Self {
+            build: Arc::new(|details| {
+                let mut problem = ProblemDetails::new(details.status)
+                    .detail(details.message)
+                    .extension("code", details.code);
+                if let Some(request_id) = details.request_id {
+                    problem = problem.extension("request_id", request_id.to_string());
+                }
+                if !details.supported.is_empty() {
+                    problem = problem.extension("supported", details.supported.to_vec());
+                }
+                problem.into()
+            }),
+            media_type: problem::media_type,
+        }
+    }
+}
+
+impl fmt::Debug for ErrorTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ErrorTemplate")
+    }
+}
+
+/// Read the request identifier used on error bodies.
+pub(crate) fn request_id(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(REQUEST_ID)
+        .and_then(|id| id.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Marks the responses of the crate rejections, which strict layers let through without a [Negotiate](crate::Negotiate) payload.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Rejected;
+
+/// Header advertising the media types accepted on the body of a request with this method.
+///
+/// `Accept-Post` and `Accept-Patch` describe the bodies of their own methods, while RFC 9110 suggests `Accept` for the others.
+pub(crate) fn accept_header(method: &Method) -> HeaderName {
+    match *method {
+        Method::POST => HeaderName::from_static("accept-post"),
+        Method::PATCH => HeaderName::from_static("accept-patch"),
+        _ => ACCEPT,
+    }
+}
+
+/// Build the response for a rejection.
+///
+/// Without a template, the body is the plain text message, followed by the supported media types if any.
+/// With a template, the body is serialized using the negotiated encoding when available, or the first enabled encoding otherwise.
+/// On 415 Unsupported Media Type, the supported media types are also advertised on the `advertise` header, if any.
+pub(crate) fn respond(
+    template: Option<&ErrorTemplate>,
+    rejection: Rejection,
+    encoding: Option<Encoding>,
+    request_id: Option<&str>,
+    supported: &[&'static str],
+    advertise: Option<HeaderName>,
+) -> Response {
+    let mut response = build(template, rejection, encoding, request_id, supported);
+    if let Some(header) = advertise.filter(|_| {
+        rejection.status() == StatusCode::UNSUPPORTED_MEDIA_TYPE && !supported.is_empty()
+    }) {
+        if let Ok(accept) = HeaderValue::from_str(&supported.join(", ")) {
+            response.headers_mut().insert(header, accept);
+        }
+    }
+    response.extensions_mut().insert(Rejected);
+    response
+}
+
+fn build(
+    template: Option<&ErrorTemplate>,
+    rejection: Rejection,
+    encoding: Option<Encoding>,
+    request_id: Option<&str>,
+    supported: &[&'static str],
+) -> Response {
+    let status = rejection.status();
+    let plain = || {
+        if supported.is_empty() {
+            (status, rejection.message().to_string())
+        } else {
+            let supported = supported.join(", ");
+            (
+                status,
+                format!(
+                    "{}\nSupported media types: {supported}",
+                    rejection.message()
+                ),
+            )
+        }
+    };
+
+    let Some(ErrorTemplate { build, media_type }) = template else {
+        return plain().into_response();
+    };
+    let Some(encoding) = encoding.or_else(|| Encoding::ALL.first().copied()) else {
+        return plain().into_response();
+    };
+
+    let ErasedNegotiate(body) = build(&ErrorDetails {
+        status,
+        code: rejection.code(),
+        message: rejection.message(),
+        request_id,
+        supported,
+    });
+
+    match encoding.encode(body.as_ref()) {
+        Ok(body) => (
+            status,
+            [(CONTENT_TYPE, HeaderValue::from_static(media_type(encoding)))],
+            body,
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!(error = %e, %encoding, "failed to serialize error body");
+            plain().into_response()
+        }
+    }
+}
@@ -0,0 +1,167 @@
+//! Application error codes mapped to HTTP statuses by the layer, so every service answers them consistently, in the spirit of `grpc-status`.
+
+use std::fmt;
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::ser::SerializeMap;
+
+use crate::{ErasedNegotiate, Negotiate};
+
+/// Table of application error codes and the HTTP status answered for each of them, configured with
+/// [NegotiateLayer::error_codes](crate::NegotiateLayer::error_codes).
+///
+/// ```rust
+/// use axum::http::StatusCode;
+/// use axum_content_negotiation::ErrorCodes;
+///
+/// let codes = ErrorCodes::new()
+///     .code("not_found", StatusCode::NOT_FOUND)
+///     .code("out_of_credit", StatusCode::PAYMENT_REQUIRED)
+///     .unknown(StatusCode::BAD_REQUEST);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorCodes {
+    codes: Vec<(String, StatusCode)>,
+    unknown: StatusCode,
+}
+
+impl Default for ErrorCodes {
+    fn default() -> Self {
+        Self {
+            codes: Vec::new(),
+            unknown: StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl ErrorCodes {
+    /// Empty table, answering 500 Internal Server Error for every code.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer the status for errors with the code, replacing a previous mapping of the same code.
+    pub fn code(mut self, code: impl Into<String>, status: StatusCode) -> Self {
+        let code = code.into();
+        self.codes.retain(|(existing, _)| *existing != code);
+        self.codes.push((code, status));
+        self
+    }
+
+    /// Status answered for the codes missing from the table, 500 Internal Server Error by default.
+    pub fn unknown(mut self, status: StatusCode) -> Self {
+        self.unknown = status;
+        self
+    }
+
+    /// Status answered for errors with the code.
+    pub fn status(&self, code: &str) -> StatusCode {
+        self.codes
+            .iter()
+            .find(|(existing, _)| existing == code)
+            .map_or(self.unknown, |(_, status)| *status)
+    }
+}
+
+/// Used as a [Response](axum::response::IntoResponse) for application errors, answered with the status their code is mapped to
+/// by the [ErrorCodes] of the [NegotiateLayer](crate::NegotiateLayer).
+///
+/// It is serialized like [Negotiate] in every encoding, with `code` and `message` members, and `details` when any is added.
+///
+/// ```rust
+/// use axum_content_negotiation::ApiError;
+///
+/// async fn handler() -> ApiError {
+///     ApiError::new("out_of_credit", "Your balance is too low").detail("balance", 30)
+/// }
+/// ```
+#[derive(Clone)]
+pub struct ApiError {
+    code: String,
+    message: String,
+    details: Vec<(String, ErasedNegotiate)>,
+}
+
+impl ApiError {
+    /// Error with a machine-readable code, mapped to a status by the layer, and a human-readable message.
+    pub fn new(code: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            code: code.into(),
+            message: message.into(),
+            details: Vec::new(),
+        }
+    }
+
+    /// Extra detail of the error, such as the invalid field of a request.
+    pub fn detail<T>(mut self, name: impl Into<String>, value: T) -> Self
+    where
+        T: serde::Serialize + Send + Sync + 'static,
+    {
+        self.details.push((name.into(), value.into()));
+        self
+    }
+
+    /// Machine-readable code of the error.
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+}
+
+impl fmt::Debug for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ApiError")
+            .field("code", &self.code)
+            .field("message", &self.message)
+            .field(
+                "details",
+                &self
+                    .details
+                    .iter()
+                    .map(|(name, _)| name)
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+/// Serializes the details of an [ApiError] as a map.
+struct Details<'a>(&'a [(String, ErasedNegotiate)]);
+
+impl serde::Serialize for Details<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (name, ErasedNegotiate(value)) in self.0 {
+            map.serialize_entry(name, value.as_ref())?;
+        }
+        map.end()
+    }
+}
+
+impl serde::Serialize for ApiError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("code", &self.code)?;
+        map.serialize_entry("message", &self.message)?;
+        if !self.details.is_empty() {
+            map.serialize_entry("details", &Details(&self.details))?;
+        }
+        map.end()
+    }
+}
+
+/// [ApiError] implements [IntoResponse], answering the status mapped to its code once negotiated.
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let code = ErrorCode(self.code.clone());
+        let mut response = Negotiate(self).into_response();
+        response.extensions_mut().insert(code);
+        response
+    }
+}
+
+/// Marks [ApiError] responses, waiting for the layer to apply the status of their code.
+#[derive(Debug, Clone)]
+pub(crate) struct ErrorCode(pub(crate) String);
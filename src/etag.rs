@@ -0,0 +1,110 @@
+//! Entity tags of negotiated responses, and the conditional requests validating them.
+
+use axum::http::HeaderValue;
+
+/// How the [NegotiateLayer](crate::NegotiateLayer) derives the `ETag` of negotiated responses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ETagPolicy {
+    /// Strong validators unique to each representation, hashing the serialized body and its media type.
+    Strong,
+    /// Weak validators shared by every representation of the same payload, such as its JSON and CBOR bodies.
+    ///
+    /// They hash a canonical form of the payload decoded back from the body, regardless of the negotiated encoding.
+    Weak,
+}
+
+impl ETagPolicy {
+    /// `ETag` of the hashed bytes, which are the response body for strong validators and the canonical body for weak ones.
+    pub(crate) fn etag(self, hashed: &[u8], media_type: &HeaderValue) -> HeaderValue {
+        let etag = match self {
+            Self::Strong => format!("\"{:016x}\"", fnv1a([hashed, media_type.as_bytes()])),
+            Self::Weak => format!("W/\"{:016x}\"", fnv1a([hashed])),
+        };
+        HeaderValue::try_from(etag).expect("hex digits are valid header values")
+    }
+}
+
+/// Check if an `If-None-Match` header matches the `ETag` of the response, using the weak comparison of RFC 9110.
+pub(crate) fn is_not_modified(if_none_match: &HeaderValue, etag: &HeaderValue) -> bool {
+    fn opaque(tag: &str) -> &str {
+        tag.trim().trim_start_matches("W/")
+    }
+
+    let Ok(if_none_match) = if_none_match.to_str() else {
+        return false;
+    };
+    let Ok(etag) = etag.to_str() else {
+        return false;
+    };
+
+    if_none_match.trim() == "*"
+        || if_none_match
+            .split(',')
+            .any(|candidate| opaque(candidate) == opaque(etag))
+}
+
+/// 64-bit FNV-1a, stable across Rust releases unlike the standard library hashers.
+fn fnv1a<const N: usize>(chunks: [&[u8]; N]) -> u64 {
+    chunks
+        .iter()
+        .flat_map(|chunk| chunk.iter())
+        .fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::HeaderValue;
+
+    use super::{fnv1a, is_not_modified, ETagPolicy};
+
+    #[test]
+    fn test_hash_with_reference_fnv1a_values() {
+        assert_eq!(fnv1a([b""]), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a([b"a"]), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a([b"foobar"]), 0x8594_4171_f739_67e8);
+        assert_eq!(fnv1a([b"foo", b"bar"]), fnv1a([b"foobar"]));
+    }
+
+    #[test]
+    fn test_tag_representations_or_payloads() {
+        let json = HeaderValue::from_static("application/json");
+        let cbor = HeaderValue::from_static("application/cbor");
+        let body = br#"{"message":"Hello"}"#;
+
+        assert_eq!(ETagPolicy::Strong.etag(body, &json), "\"4013bb184ad715b9\"");
+        assert_ne!(
+            ETagPolicy::Strong.etag(body, &json),
+            ETagPolicy::Strong.etag(body, &cbor)
+        );
+        assert_eq!(ETagPolicy::Weak.etag(body, &json), "W/\"9a7e38f3060863c8\"");
+        assert_eq!(
+            ETagPolicy::Weak.etag(body, &json),
+            ETagPolicy::Weak.etag(body, &cbor)
+        );
+    }
+
+    #[test]
+    fn test_match_if_none_match_weakly() {
+        let etag = HeaderValue::from_static("\"abc\"");
+        let weak = HeaderValue::from_static("W/\"abc\"");
+
+        for if_none_match in [
+            "\"abc\"",
+            "W/\"abc\"",
+            " * ",
+            "\"other\", W/\"abc\"",
+            "\"other\",\"abc\"",
+        ] {
+            let if_none_match = HeaderValue::from_static(if_none_match);
+            assert!(is_not_modified(&if_none_match, &etag), "{if_none_match:?}");
+            assert!(is_not_modified(&if_none_match, &weak), "{if_none_match:?}");
+        }
+        for if_none_match in ["\"other\"", "\"abc", "\"other\", W/\"abcd\"", ""] {
+            let if_none_match = HeaderValue::from_static(if_none_match);
+            assert!(!is_not_modified(&if_none_match, &etag), "{if_none_match:?}");
+        }
+    }
+}
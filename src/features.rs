@@ -0,0 +1,89 @@
+//! Report of the codecs and defaults compiled in, for downstream CI to check the chosen feature set.
+
+use crate::Encoding;
+
+/// What an enabled encoding supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct EncodingSupport {
+    /// Encoding enabled by the crate features
+    pub encoding: Encoding,
+    /// Whether request bodies are decoded, by [Negotiate](crate::Negotiate) and the other extractors
+    pub decodes_requests: bool,
+    /// Whether serde payloads, such as [Negotiate](crate::Negotiate) responses, are encoded
+    pub encodes_responses: bool,
+}
+
+/// Codecs and defaults compiled in by the crate features, returned by [feature_matrix].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct FeatureMatrix {
+    /// Every enabled encoding, in the order of [Encoding::ALL]
+    pub encodings: Vec<EncodingSupport>,
+    /// `default-*` features enabled, even if their codec is not
+    pub default_features: Vec<&'static str>,
+    /// Encoding used by default, from [Encoding::compiled_default]
+    pub default_encoding: Option<Encoding>,
+}
+
+impl FeatureMatrix {
+    /// Inconsistencies of the feature set, such as a default pointing at a disabled codec.
+    ///
+    /// Empty when the features are coherent.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if self.encodings.is_empty() {
+            problems.push("no encoding is enabled".to_string());
+        }
+        if !self
+            .encodings
+            .iter()
+            .any(|support| support.decodes_requests)
+        {
+            problems.push("no enabled encoding decodes request bodies".to_string());
+        }
+        for feature in &self.default_features {
+            let enabled = match *feature {
+                "default-json" => cfg!(any(feature = "simd-json", feature = "json")),
+                "default-cbor" => cfg!(feature = "cbor"),
+                _ => true,
+            };
+            if !enabled {
+                let codec = feature.trim_start_matches("default-");
+                problems.push(format!("{feature} is enabled without the {codec} codec"));
+            }
+        }
+
+        problems
+    }
+}
+
+/// Report the codecs and defaults compiled in.
+///
+/// ```rust
+/// let matrix = axum_content_negotiation::feature_matrix();
+/// assert!(matrix.problems().is_empty(), "{:?}", matrix.problems());
+/// ```
+pub fn feature_matrix() -> FeatureMatrix {
+    let default_features = [
+        ("default-json", cfg!(feature = "default-json")),
+        ("default-cbor", cfg!(feature = "default-cbor")),
+    ];
+
+    FeatureMatrix {
+        encodings: Encoding::ALL
+            .iter()
+            .map(|&encoding| EncodingSupport {
+                encoding,
+                decodes_requests: encoding.decodes(),
+                encodes_responses: encoding.encodes(),
+            })
+            .collect(),
+        default_features: default_features
+            .into_iter()
+            .filter_map(|(feature, enabled)| enabled.then_some(feature))
+            .collect(),
+        default_encoding: Encoding::compiled_default(),
+    }
+}
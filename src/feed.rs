@@ -0,0 +1,376 @@
+//! Atom and RSS feeds for list endpoints, so feed readers and apps are served by the same handler.
+
+use std::{
+    fmt::Write,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    http::{Extensions, StatusCode},
+    response::{IntoResponse, Response},
+    Extension,
+};
+
+use crate::{
+    context::{ContextualNegotiate, ContextualPayload},
+    encoding::CodecError,
+    Encoding,
+};
+
+/// Entry of a feed, informed as an Atom `<entry>` or an RSS `<item>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedEntry {
+    id: String,
+    title: String,
+    updated: SystemTime,
+    link: Option<String>,
+    summary: Option<String>,
+}
+
+impl FeedEntry {
+    /// Entry with a permanent unique `id`, such as its URL or a `tag:` URI, last updated at `updated`.
+    pub fn new(id: impl Into<String>, title: impl Into<String>, updated: SystemTime) -> Self {
+        Self {
+            id: id.into(),
+            title: title.into(),
+            updated,
+            link: None,
+            summary: None,
+        }
+    }
+
+    /// Page of the entry opened by feed readers.
+    pub fn link(mut self, link: impl Into<String>) -> Self {
+        self.link = Some(link.into());
+        self
+    }
+
+    /// Short plain text description of the entry.
+    pub fn summary(mut self, summary: impl Into<String>) -> Self {
+        self.summary = Some(summary.into());
+        self
+    }
+}
+
+/// Metadata and entries of a feed, built by [Feed::feed].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedChannel {
+    link: String,
+    title: String,
+    description: Option<String>,
+    updated: Option<SystemTime>,
+    entries: Vec<FeedEntry>,
+}
+
+impl FeedChannel {
+    /// Feed at `link`, also used as its Atom `<id>`, named `title`.
+    pub fn new(link: impl Into<String>, title: impl Into<String>) -> Self {
+        Self {
+            link: link.into(),
+            title: title.into(),
+            description: None,
+            updated: None,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Plain text description of the feed, informed as the Atom `<subtitle>` and the RSS `<description>`.
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Last update of the feed, when it differs from its most recent entry.
+    pub fn updated(mut self, updated: SystemTime) -> Self {
+        self.updated = Some(updated);
+        self
+    }
+
+    /// Add an entry to the feed, in the order they are listed.
+    pub fn entry(mut self, entry: FeedEntry) -> Self {
+        self.entries.push(entry);
+        self
+    }
+
+    /// Add the entries to the feed, in the order they are listed.
+    pub fn entries(mut self, entries: impl IntoIterator<Item = FeedEntry>) -> Self {
+        self.entries.extend(entries);
+        self
+    }
+
+    /// Last update of the feed, falling back to its most recent entry, or the epoch of feeds without entries.
+    fn last_updated(&self) -> SystemTime {
+        self.updated
+            .or_else(|| self.entries.iter().map(|entry| entry.updated).max())
+            .unwrap_or(UNIX_EPOCH)
+    }
+
+    fn to_atom(&self) -> String {
+        let mut xml = String::from(r#"<?xml version="1.0" encoding="utf-8"?>"#);
+        xml.push_str(r#"<feed xmlns="http://www.w3.org/2005/Atom">"#);
+        element(&mut xml, "id", &self.link);
+        element(&mut xml, "title", &self.title);
+        if let Some(description) = &self.description {
+            element(&mut xml, "subtitle", description);
+        }
+        element(&mut xml, "updated", &rfc3339(self.last_updated()));
+        link(&mut xml, &self.link);
+        for entry in &self.entries {
+            xml.push_str("<entry>");
+            element(&mut xml, "id", &entry.id);
+            element(&mut xml, "title", &entry.title);
+            element(&mut xml, "updated", &rfc3339(entry.updated));
+            if let Some(href) = &entry.link {
+                link(&mut xml, href);
+            }
+            if let Some(summary) = &entry.summary {
+                element(&mut xml, "summary", summary);
+            }
+            xml.push_str("</entry>");
+        }
+        xml.push_str("</feed>");
+        xml
+    }
+
+    fn to_rss(&self) -> String {
+        let mut xml = String::from(r#"<?xml version="1.0" encoding="utf-8"?>"#);
+        xml.push_str(r#"<rss version="2.0"><channel>"#);
+        element(&mut xml, "title", &self.title);
+        element(&mut xml, "link", &self.link);
+        element(
+            &mut xml,
+            "description",
+            self.description.as_deref().unwrap_or(&self.title),
+        );
+        element(&mut xml, "lastBuildDate", &rfc822(self.last_updated()));
+        for entry in &self.entries {
+            xml.push_str("<item>");
+            element(&mut xml, "title", &entry.title);
+            if let Some(link) = &entry.link {
+                element(&mut xml, "link", link);
+            }
+            if let Some(summary) = &entry.summary {
+                element(&mut xml, "description", summary);
+            }
+            xml.push_str(r#"<guid isPermaLink="false">"#);
+            escape(&mut xml, &entry.id);
+            xml.push_str("</guid>");
+            element(&mut xml, "pubDate", &rfc822(entry.updated));
+            xml.push_str("</item>");
+        }
+        xml.push_str("</channel></rss>");
+        xml
+    }
+}
+
+fn element(xml: &mut String, name: &str, text: &str) {
+    let _ = write!(xml, "<{name}>");
+    escape(xml, text);
+    let _ = write!(xml, "</{name}>");
+}
+
+fn link(xml: &mut String, href: &str) {
+    xml.push_str(r#"<link href=""#);
+    escape(xml, href);
+    xml.push_str(r#""/>"#);
+}
+
+fn escape(xml: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => xml.push_str("&amp;"),
+            '<' => xml.push_str("&lt;"),
+            '>' => xml.push_str("&gt;"),
+            '"' => xml.push_str("&quot;"),
+            '\'' => xml.push_str("&apos;"),
+            c => xml.push(c),
+        }
+    }
+}
+
+/// Calendar date and time in UTC of the instant, truncated to seconds, along with the day of the week from Monday as 0.
+fn civil(time: SystemTime) -> (i64, u32, u32, u64, u64, u64, usize) {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs();
+    let (days, secs) = (secs / 86_400, secs % 86_400);
+    let weekday = ((days + 3) % 7) as usize;
+
+    // Days to civil date, from http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        weekday,
+    )
+}
+
+/// Timestamp used by Atom, such as `2024-01-02T03:04:05Z`.
+fn rfc3339(time: SystemTime) -> String {
+    let (year, month, day, hour, minute, second, _) = civil(time);
+    format!("{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}Z")
+}
+
+/// Timestamp used by RSS, such as `Tue, 02 Jan 2024 03:04:05 GMT`.
+fn rfc822(time: SystemTime) -> String {
+    const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let (year, month, day, hour, minute, second, weekday) = civil(time);
+    format!(
+        "{}, {day:02} {} {year:04} {hour:02}:{minute:02}:{second:02} GMT",
+        WEEKDAYS[weekday],
+        MONTHS[month as usize - 1]
+    )
+}
+
+/// Feed of a list, answered on `application/atom+xml` and `application/rss+xml` responses.
+///
+/// ```rust
+/// use std::time::SystemTime;
+/// use axum_content_negotiation::{Feed, FeedChannel, FeedEntry};
+///
+/// #[derive(serde::Serialize)]
+/// struct Post {
+///     slug: String,
+///     title: String,
+/// }
+///
+/// #[derive(serde::Serialize)]
+/// struct Posts(Vec<Post>);
+///
+/// impl Feed for Posts {
+///     fn feed(&self) -> FeedChannel {
+///         FeedChannel::new("https://example.com/posts", "Posts").entries(self.0.iter().map(|post| {
+///             let url = format!("https://example.com/posts/{}", post.slug);
+///             FeedEntry::new(&url, &post.title, SystemTime::UNIX_EPOCH).link(url)
+///         }))
+///     }
+/// }
+/// ```
+pub trait Feed {
+    /// Metadata and entries of the feed.
+    fn feed(&self) -> FeedChannel;
+}
+
+/// Used as a [Response](axum::response::IntoResponse), like [Negotiate](crate::Negotiate),
+/// but answering [Encoding::Atom] and [Encoding::Rss] with the [Feed] of the payload. Other encodings serialize it with serde only.
+#[derive(Debug, Clone)]
+pub struct NegotiateFeed<T>(
+    /// The stored content to be serialized
+    pub T,
+);
+
+/// [NegotiateFeed] implements [IntoResponse] if the internal content is serializable and provides a [Feed].
+///
+/// Just like [Negotiate](crate::Negotiate), it is converted to the right response by the [NegotiateLayer](crate::NegotiateLayer).
+impl<T> IntoResponse for NegotiateFeed<T>
+where
+    T: serde::Serialize + Feed + Send + Sync + 'static,
+{
+    fn into_response(self) -> Response {
+        let payload: Arc<dyn ContextualPayload> = Arc::new(self);
+        (
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Extension(ContextualNegotiate(payload)),
+            "Misconfigured service layer",
+        )
+            .into_response()
+    }
+}
+
+impl<T> ContextualPayload for NegotiateFeed<T>
+where
+    T: serde::Serialize + Feed + Send + Sync,
+{
+    fn encode(&self, encoding: Encoding, _: &Extensions) -> Result<Vec<u8>, CodecError> {
+        match encoding {
+            Encoding::Atom => Ok(self.0.feed().to_atom().into_bytes()),
+            Encoding::Rss => Ok(self.0.feed().to_rss().into_bytes()),
+            _ => encoding.encode(&self.0),
+        }
+    }
+
+    fn encodes(&self, encoding: Encoding) -> bool {
+        matches!(encoding, Encoding::Atom | Encoding::Rss) || encoding.encodes()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn channel() -> FeedChannel {
+        let updated = UNIX_EPOCH + Duration::from_secs(1_704_164_645);
+        FeedChannel::new("https://example.com/posts", "Posts & News")
+            .entry(
+                FeedEntry::new("tag:example.com,2024:1", "<First>", updated)
+                    .link("https://example.com/posts/1")
+                    .summary("Hello"),
+            )
+            .entry(FeedEntry::new(
+                "tag:example.com,2024:2",
+                "Second",
+                UNIX_EPOCH,
+            ))
+    }
+
+    #[test]
+    fn test_format_timestamps() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_704_164_645);
+        assert_eq!(rfc3339(time), "2024-01-02T03:04:05Z");
+        assert_eq!(rfc822(time), "Tue, 02 Jan 2024 03:04:05 GMT");
+        assert_eq!(rfc822(UNIX_EPOCH), "Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(
+            rfc3339(UNIX_EPOCH + Duration::from_secs(951_782_400)),
+            "2000-02-29T00:00:00Z"
+        );
+    }
+
+    #[test]
+    fn test_render_atom_and_rss() {
+        assert_eq!(
+            channel().to_atom(),
+            concat!(
+                r#"<?xml version="1.0" encoding="utf-8"?><feed xmlns="http://www.w3.org/2005/Atom">"#,
+                "<id>https://example.com/posts</id><title>Posts &amp; News</title>",
+                r#"<updated>2024-01-02T03:04:05Z</updated><link href="https://example.com/posts"/>"#,
+                "<entry><id>tag:example.com,2024:1</id><title>&lt;First&gt;</title>",
+                r#"<updated>2024-01-02T03:04:05Z</updated><link href="https://example.com/posts/1"/>"#,
+                "<summary>Hello</summary></entry>",
+                "<entry><id>tag:example.com,2024:2</id><title>Second</title>",
+                "<updated>1970-01-01T00:00:00Z</updated></entry></feed>",
+            )
+        );
+        assert_eq!(
+            channel().to_rss(),
+            concat!(
+                r#"<?xml version="1.0" encoding="utf-8"?><rss version="2.0"><channel>"#,
+                "<title>Posts &amp; News</title><link>https://example.com/posts</link>",
+                "<description>Posts &amp; News</description>",
+                "<lastBuildDate>Tue, 02 Jan 2024 03:04:05 GMT</lastBuildDate>",
+                "<item><title>&lt;First&gt;</title><link>https://example.com/posts/1</link>",
+                r#"<description>Hello</description><guid isPermaLink="false">tag:example.com,2024:1</guid>"#,
+                "<pubDate>Tue, 02 Jan 2024 03:04:05 GMT</pubDate></item>",
+                r#"<item><title>Second</title><guid isPermaLink="false">tag:example.com,2024:2</guid>"#,
+                "<pubDate>Thu, 01 Jan 1970 00:00:00 GMT</pubDate></item></channel></rss>",
+            )
+        );
+    }
+}
@@ -0,0 +1,78 @@
+//! FlatBuffers responses for types providing their own buffer, as FlatBuffers doesn't use serde.
+
+use std::sync::Arc;
+
+use axum::{
+    http::{Extensions, StatusCode},
+    response::{IntoResponse, Response},
+    Extension,
+};
+
+use crate::{
+    context::{ContextualNegotiate, ContextualPayload},
+    encoding::CodecError,
+    Encoding,
+};
+
+/// Build the FlatBuffers representation of a value, usually with the code generated by `flatc`.
+///
+/// ```rust
+/// use axum_content_negotiation::ToFlatBuffer;
+///
+/// struct Ping;
+///
+/// impl ToFlatBuffer for Ping {
+///     fn to_flatbuffer(&self) -> Vec<u8> {
+///         // let mut builder = flatbuffers::FlatBufferBuilder::new();
+///         // ...
+///         // builder.finished_data().to_vec()
+///         Vec::new()
+///     }
+/// }
+/// ```
+pub trait ToFlatBuffer {
+    /// Finished FlatBuffers bytes of the value.
+    fn to_flatbuffer(&self) -> Vec<u8>;
+}
+
+/// Used as a [Response](axum::response::IntoResponse), like [Negotiate](crate::Negotiate),
+/// but answering [Encoding::FlatBuffers] with [ToFlatBuffer] and the other encodings with serde.
+#[derive(Debug, Clone)]
+pub struct NegotiateFlatBuffer<T>(
+    /// The stored content to be serialized
+    pub T,
+);
+
+/// [NegotiateFlatBuffer] implements [IntoResponse] if the internal content is serializable by both serde and [ToFlatBuffer].
+///
+/// Just like [Negotiate](crate::Negotiate), it is converted to the right response by the [NegotiateLayer](crate::NegotiateLayer).
+impl<T> IntoResponse for NegotiateFlatBuffer<T>
+where
+    T: serde::Serialize + ToFlatBuffer + Send + Sync + 'static,
+{
+    fn into_response(self) -> Response {
+        let payload: Arc<dyn ContextualPayload> = Arc::new(self);
+        (
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Extension(ContextualNegotiate(payload)),
+            "Misconfigured service layer",
+        )
+            .into_response()
+    }
+}
+
+impl<T> ContextualPayload for NegotiateFlatBuffer<T>
+where
+    T: serde::Serialize + ToFlatBuffer + Send + Sync,
+{
+    fn encode(&self, encoding: Encoding, _: &Extensions) -> Result<Vec<u8>, CodecError> {
+        match encoding {
+            Encoding::FlatBuffers => Ok(self.0.to_flatbuffer()),
+            _ => encoding.encode(&self.0),
+        }
+    }
+
+    fn encodes(&self, encoding: Encoding) -> bool {
+        encoding == Encoding::FlatBuffers || encoding.encodes()
+    }
+}
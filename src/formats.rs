@@ -0,0 +1,200 @@
+//! Formats of a route declared at the type level, such as `NegotiatedRoute<Formats![Json, Cbor]>`.
+//!
+//! Each enabled encoding has a marker type, so declaring a format whose feature is disabled fails to compile,
+//! and the declared formats can be read back as constants by documentation or code generation.
+//!
+//! ```rust
+//! # #[cfg(all(feature = "cbor", any(feature = "simd-json", feature = "json")))] {
+//! use axum::{routing::post, Router};
+//! use axum_content_negotiation::{
+//!     formats::{Cbor, Json},
+//!     Encoding, Formats, NegotiatedRoute,
+//! };
+//!
+//! type SyncRoute = NegotiatedRoute<Formats![Cbor]>;
+//! type OrdersRoute = NegotiatedRoute<Formats![Json, Cbor]>;
+//!
+//! assert_eq!(SyncRoute::ENCODINGS, &[Encoding::Cbor]);
+//! assert_eq!(OrdersRoute::ENCODINGS, &[Encoding::Json, Encoding::Cbor]);
+//!
+//! let router: Router<()> = Router::new()
+//!     .route("/internal/sync", post(|| async {}))
+//!     .route_layer(SyncRoute::new())
+//!     .route("/orders", post(|| async {}))
+//!     .layer(OrdersRoute::new());
+//! # }
+//! ```
+
+use std::{fmt, marker::PhantomData};
+
+use crate::{Encoding, NegotiateLayer, NegotiateService, NegotiationMetadata};
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Marker type of an enabled encoding, used on [Formats!](crate::Formats).
+pub trait Format: sealed::Sealed {
+    /// Encoding the marker stands for
+    const ENCODING: Encoding;
+}
+
+/// List of [Format] markers, as a tuple built by the [Formats!](crate::Formats) macro.
+pub trait FormatList: sealed::Sealed {
+    /// Encodings of the list, in the declared order
+    const ENCODINGS: &'static [Encoding];
+}
+
+macro_rules! formats {
+    ($($(#[$cfg:meta])* $marker:ident => $encoding:ident $(: $note:literal)?),* $(,)?) => {
+        $(
+            #[doc = concat!("Marker of [Encoding::", stringify!($encoding), "].")]
+            $(
+                #[doc = ""]
+                #[doc = $note]
+            )?
+            $(#[$cfg])*
+            #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+            pub struct $marker;
+
+            $(#[$cfg])*
+            impl sealed::Sealed for $marker {}
+
+            $(#[$cfg])*
+            impl Format for $marker {
+                const ENCODING: Encoding = Encoding::$encoding;
+            }
+        )*
+    };
+}
+
+formats!(
+    #[cfg(any(feature = "simd-json", feature = "json"))]
+    Json => Json,
+    #[cfg(feature = "cbor")]
+    Cbor => Cbor,
+    #[cfg(feature = "csv")]
+    Csv => Csv,
+    #[cfg(feature = "ndjson")]
+    NdJson => NdJson,
+    #[cfg(feature = "cbor-seq")]
+    CborSeq => CborSeq,
+    #[cfg(feature = "flatbuffers")]
+    FlatBuffers => FlatBuffers,
+    #[cfg(feature = "multipart")]
+    Multipart => Multipart: "Only request bodies are decoded from `multipart/form-data`, so routes declaring it still answer their other formats.",
+    #[cfg(feature = "plain-text")]
+    PlainText => PlainText,
+    #[cfg(feature = "hal")]
+    Hal => Hal,
+    #[cfg(feature = "octet-stream")]
+    OctetStream => OctetStream,
+    #[cfg(feature = "feed")]
+    Atom => Atom,
+    #[cfg(feature = "feed")]
+    Rss => Rss,
+    #[cfg(feature = "jsonp")]
+    Jsonp => Jsonp,
+    #[cfg(feature = "debug-html")]
+    Html => Html,
+    #[cfg(feature = "xlsx")]
+    Xlsx => Xlsx,
+    #[cfg(feature = "geojson")]
+    GeoJson => GeoJson,
+);
+
+macro_rules! format_lists {
+    ($(($($format:ident),+)),* $(,)?) => {
+        $(
+            impl<$($format: Format),+> sealed::Sealed for ($($format,)+) {}
+
+            impl<$($format: Format),+> FormatList for ($($format,)+) {
+                const ENCODINGS: &'static [Encoding] = &[$($format::ENCODING),+];
+            }
+        )*
+    };
+}
+
+format_lists!(
+    (A),
+    (A, B),
+    (A, B, C),
+    (A, B, C, D),
+    (A, B, C, D, E),
+    (A, B, C, D, E, F),
+    (A, B, C, D, E, F, G),
+    (A, B, C, D, E, F, G, H),
+);
+
+/// List of formats negotiated by a route, such as `Formats![Json, Cbor]`, naming the markers of [formats](crate::formats).
+///
+/// Up to eight formats can be listed.
+#[macro_export]
+macro_rules! Formats {
+    ($($format:ty),+ $(,)?) => {
+        ($($format,)+)
+    };
+}
+
+/// [NegotiateLayer] restricted to the formats declared by its type, such as `NegotiatedRoute<Formats![Json, Cbor]>`.
+///
+/// Like [NegotiateLayer::only_encodings], it is usually applied with a [route_layer](axum::Router::route_layer),
+/// while the formats stay available to documentation and code generation as [NegotiatedRoute::ENCODINGS].
+pub struct NegotiatedRoute<F> {
+    layer: NegotiateLayer,
+    formats: PhantomData<fn() -> F>,
+}
+
+impl<F: FormatList> NegotiatedRoute<F> {
+    /// Encodings negotiated by the route, in the declared order.
+    pub const ENCODINGS: &'static [Encoding] = F::ENCODINGS;
+
+    /// Negotiate the declared formats with the default configuration of a [NegotiateLayer].
+    pub fn new() -> Self {
+        Self::from_layer(NegotiateLayer::new())
+    }
+
+    /// Negotiate the declared formats with the configuration of the layer, replacing its [NegotiateLayer::only_encodings].
+    pub fn from_layer(layer: NegotiateLayer) -> Self {
+        Self {
+            layer: layer.only_encodings(F::ENCODINGS.iter().copied()),
+            formats: PhantomData,
+        }
+    }
+
+    /// Describe the media types negotiated by the route, like [NegotiateLayer::metadata].
+    pub fn metadata(&self) -> NegotiationMetadata {
+        self.layer.metadata()
+    }
+}
+
+impl<F: FormatList> Default for NegotiatedRoute<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<F> Clone for NegotiatedRoute<F> {
+    fn clone(&self) -> Self {
+        Self {
+            layer: self.layer.clone(),
+            formats: PhantomData,
+        }
+    }
+}
+
+impl<F: FormatList> fmt::Debug for NegotiatedRoute<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NegotiatedRoute")
+            .field("encodings", &F::ENCODINGS)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<F, S> tower::Layer<S> for NegotiatedRoute<F> {
+    type Service = NegotiateService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        tower::Layer::layer(&self.layer, inner)
+    }
+}
@@ -0,0 +1,118 @@
+//! Structural validation of `application/geo+json` payloads, following the objects of RFC 7946.
+//!
+//! Only the shape of the objects is checked, such as a `Feature` having a `geometry` and `properties`,
+//! not the values of the coordinates.
+
+use axum::body::Bytes;
+
+use crate::{encoding::CodecError, value::Value};
+
+const GEOMETRIES: &[&str] = &[
+    "Point",
+    "MultiPoint",
+    "LineString",
+    "MultiLineString",
+    "Polygon",
+    "MultiPolygon",
+];
+
+/// Check the JSON body is a GeoJSON object: a geometry, a `Feature` or a `FeatureCollection`.
+pub(crate) fn validate(body: &Bytes) -> Result<(), CodecError> {
+    let value: Value = crate::Encoding::Json.decode(body.clone(), &Default::default())?;
+    validate_object(&value).map_err(|e| format!("invalid GeoJSON: {e}").into())
+}
+
+fn validate_object(value: &Value) -> Result<(), String> {
+    match kind(value)? {
+        "Feature" => validate_feature(value),
+        "FeatureCollection" => match member(value, "features") {
+            Some(Value::Array(features)) => {
+                features
+                    .iter()
+                    .try_for_each(|feature| match kind(feature)? {
+                        "Feature" => validate_feature(feature),
+                        other => Err(format!("expected a Feature on the features, found {other}")),
+                    })
+            }
+            _ => Err("a FeatureCollection requires a features array".to_string()),
+        },
+        _ => validate_geometry(value),
+    }
+}
+
+fn validate_feature(feature: &Value) -> Result<(), String> {
+    match member(feature, "geometry") {
+        Some(Value::Null) => {}
+        Some(geometry) => validate_geometry(geometry)?,
+        None => return Err("a Feature requires a geometry, even if null".to_string()),
+    }
+    match member(feature, "properties") {
+        Some(Value::Null | Value::Map(_)) => Ok(()),
+        _ => Err("a Feature requires a properties object, even if null".to_string()),
+    }
+}
+
+fn validate_geometry(geometry: &Value) -> Result<(), String> {
+    match kind(geometry)? {
+        "GeometryCollection" => match member(geometry, "geometries") {
+            Some(Value::Array(geometries)) => geometries.iter().try_for_each(validate_geometry),
+            _ => Err("a GeometryCollection requires a geometries array".to_string()),
+        },
+        kind if GEOMETRIES.contains(&kind) => match member(geometry, "coordinates") {
+            Some(Value::Array(_)) => Ok(()),
+            _ => Err(format!("a {kind} requires a coordinates array")),
+        },
+        other => Err(format!("unknown GeoJSON type {other}")),
+    }
+}
+
+fn kind(value: &Value) -> Result<&str, String> {
+    match member(value, "type") {
+        Some(Value::Text(kind)) => Ok(kind),
+        _ => Err("expected an object with a type member".to_string()),
+    }
+}
+
+fn member<'a>(value: &'a Value, name: &str) -> Option<&'a Value> {
+    let Value::Map(entries) = value else {
+        return None;
+    };
+    entries
+        .iter()
+        .find(|(key, _)| matches!(key, Value::Text(key) if key == name))
+        .map(|(_, value)| value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_validate_geojson_objects() {
+        let validate = |body: &'static [u8]| validate(&Bytes::from_static(body));
+
+        assert!(validate(br#"{"type":"Point","coordinates":[1.0,2.0]}"#).is_ok());
+        assert!(validate(
+            br#"{"type":"FeatureCollection","features":[
+                {"type":"Feature","geometry":null,"properties":null},
+                {"type":"Feature","properties":{"name":"park"},"geometry":
+                    {"type":"GeometryCollection","geometries":[{"type":"LineString","coordinates":[]}]}}
+            ]}"#
+        )
+        .is_ok());
+
+        for invalid in [
+            &br#"[1, 2]"#[..],
+            br#"{"type":"Circle","coordinates":[]}"#,
+            br#"{"type":"Point"}"#,
+            br#"{"type":"Feature","geometry":null}"#,
+            br#"{"type":"FeatureCollection","features":[{"type":"Point","coordinates":[]}]}"#,
+        ] {
+            assert!(
+                validate(invalid).is_err(),
+                "{}",
+                String::from_utf8_lossy(invalid)
+            );
+        }
+    }
+}
@@ -0,0 +1,207 @@
+//! HAL hypermedia responses, injecting the `_links` of the payload when negotiated as `application/hal+json`.
+
+use std::sync::Arc;
+
+use axum::{
+    http::{Extensions, StatusCode},
+    response::{IntoResponse, Response},
+    Extension,
+};
+
+use crate::{
+    context::{ContextualNegotiate, ContextualPayload},
+    encoding::CodecError,
+    Encoding,
+};
+
+/// Link of a HAL resource, informed on its `_links` under the relation type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HalLink {
+    rel: String,
+    href: String,
+    templated: bool,
+    title: Option<String>,
+}
+
+impl HalLink {
+    /// Link to `href` with the relation type `rel`, such as `self` or `next`.
+    pub fn new(rel: impl Into<String>, href: impl Into<String>) -> Self {
+        Self {
+            rel: rel.into(),
+            href: href.into(),
+            templated: false,
+            title: None,
+        }
+    }
+
+    /// Mark the `href` as a URI template, such as `/orders{?page}`.
+    pub fn templated(mut self) -> Self {
+        self.templated = true;
+        self
+    }
+
+    /// Human-readable label of the link.
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+}
+
+/// Serializes links as the `_links` of HAL, grouping the ones sharing a relation type into arrays in the order they were listed.
+struct Links<'a>(&'a [HalLink]);
+
+impl serde::Serialize for Links<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut rels: Vec<&str> = Vec::new();
+        for link in self.0 {
+            if !rels.contains(&link.rel.as_str()) {
+                rels.push(&link.rel);
+            }
+        }
+
+        let mut map = serializer.serialize_map(Some(rels.len()))?;
+        for rel in rels {
+            let links: Vec<_> = self.0.iter().filter(|link| link.rel == rel).collect();
+            match links.as_slice() {
+                [link] => map.serialize_entry(rel, link)?,
+                links => map.serialize_entry(rel, links)?,
+            }
+        }
+        map.end()
+    }
+}
+
+impl serde::Serialize for HalLink {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("href", &self.href)?;
+        if self.templated {
+            map.serialize_entry("templated", &true)?;
+        }
+        if let Some(title) = &self.title {
+            map.serialize_entry("title", title)?;
+        }
+        map.end()
+    }
+}
+
+/// Links of a resource, injected as its `_links` on `application/hal+json` responses.
+///
+/// ```rust
+/// use axum_content_negotiation::{HalLink, HalLinks};
+///
+/// #[derive(serde::Serialize)]
+/// struct Order {
+///     id: u32,
+/// }
+///
+/// impl HalLinks for Order {
+///     fn links(&self) -> Vec<HalLink> {
+///         vec![
+///             HalLink::new("self", format!("/orders/{}", self.id)),
+///             HalLink::new("items", format!("/orders/{}/items", self.id)),
+///         ]
+///     }
+/// }
+/// ```
+pub trait HalLinks {
+    /// Links of the resource. Links sharing a relation type are informed as an array.
+    fn links(&self) -> Vec<HalLink>;
+}
+
+/// Used as a [Response](axum::response::IntoResponse), like [Negotiate](crate::Negotiate),
+/// but injecting the [HalLinks] of the payload when answering [Encoding::Hal]. Other encodings serialize it with serde only.
+#[derive(Debug, Clone)]
+pub struct NegotiateHal<T>(
+    /// The stored content to be serialized
+    pub T,
+);
+
+/// [NegotiateHal] implements [IntoResponse] if the internal content is serializable and provides [HalLinks].
+///
+/// Just like [Negotiate](crate::Negotiate), it is converted to the right response by the [NegotiateLayer](crate::NegotiateLayer).
+impl<T> IntoResponse for NegotiateHal<T>
+where
+    T: serde::Serialize + HalLinks + Send + Sync + 'static,
+{
+    fn into_response(self) -> Response {
+        let payload: Arc<dyn ContextualPayload> = Arc::new(self);
+        (
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Extension(ContextualNegotiate(payload)),
+            "Misconfigured service layer",
+        )
+            .into_response()
+    }
+}
+
+impl<T> ContextualPayload for NegotiateHal<T>
+where
+    T: serde::Serialize + HalLinks + Send + Sync,
+{
+    fn encode(&self, encoding: Encoding, _: &Extensions) -> Result<Vec<u8>, CodecError> {
+        if encoding != Encoding::Hal {
+            return encoding.encode(&self.0);
+        }
+
+        // Splicing the links into the serialized object keeps the order of its fields
+        let mut body = encoding.encode(&self.0)?;
+        if body.first() != Some(&b'{') || body.last() != Some(&b'}') {
+            return Err("HAL resources must serialize as objects".into());
+        }
+        let links = encoding.encode(&Links(&self.0.links()))?;
+        body.pop();
+        if body.len() > 1 {
+            body.push(b',');
+        }
+        body.extend_from_slice(br#""_links":"#);
+        body.extend_from_slice(&links);
+        body.push(b'}');
+        Ok(body)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(serde::Serialize)]
+    struct Order {
+        id: u32,
+    }
+
+    impl HalLinks for Order {
+        fn links(&self) -> Vec<HalLink> {
+            vec![
+                HalLink::new("self", "/orders/1"),
+                HalLink::new("item", "/items/1"),
+                HalLink::new("item", "/items/2").title("Second"),
+                HalLink::new("find", "/orders{?id}").templated(),
+            ]
+        }
+    }
+
+    #[test]
+    fn test_inject_links_only_on_hal() {
+        let hal = NegotiateHal(Order { id: 1 })
+            .encode(Encoding::Hal, &Extensions::new())
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(hal).unwrap(),
+            concat!(
+                r#"{"id":1,"_links":{"self":{"href":"/orders/1"},"#,
+                r#""item":[{"href":"/items/1"},{"href":"/items/2","title":"Second"}],"#,
+                r#""find":{"href":"/orders{?id}","templated":true}}}"#,
+            )
+        );
+
+        let json = NegotiateHal(Order { id: 1 })
+            .encode(Encoding::Json, &Extensions::new())
+            .unwrap();
+        assert_eq!(String::from_utf8(json).unwrap(), r#"{"id":1}"#);
+    }
+}
@@ -0,0 +1,161 @@
+//! Health and readiness documents served on the negotiated format.
+//!
+//! Platform probes and fleet tooling usually hit the same endpoints asking for different formats,
+//! so the status documents are [Negotiate] responses, converted by the [NegotiateLayer](crate::NegotiateLayer).
+//!
+//! ```rust
+//! use axum::{routing::get, Router};
+//! use axum_content_negotiation::{
+//!     health::{self, Health, Status},
+//!     NegotiateLayer,
+//! };
+//!
+//! async fn readiness() -> Health {
+//!     Health::new().check("database", Status::Pass)
+//! }
+//!
+//! let router: Router = Router::new()
+//!     .route("/health", get(health::liveness))
+//!     .route("/ready", get(readiness))
+//!     .layer(NegotiateLayer::new());
+//! ```
+
+use std::collections::BTreeMap;
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+
+use crate::Negotiate;
+
+/// Status of the service, or of one of its checks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    /// Healthy
+    #[default]
+    Pass,
+    /// Healthy, with some concerns
+    Warn,
+    /// Unhealthy
+    Fail,
+}
+
+/// Status document, responding 200 OK when passing or warning and 503 Service Unavailable when failing.
+///
+/// The overall status is the worst status of its checks.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct Health {
+    status: Status,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    checks: BTreeMap<String, Status>,
+}
+
+impl Health {
+    /// Create a passing status document without any checks.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the status of a named check, such as a database or a downstream dependency.
+    pub fn check(mut self, name: impl Into<String>, status: Status) -> Self {
+        self.status = self.status.max(status);
+        self.checks.insert(name.into(), status);
+        self
+    }
+
+    /// Overall status of the document.
+    pub fn status(&self) -> Status {
+        self.status
+    }
+}
+
+impl IntoResponse for Health {
+    fn into_response(self) -> Response {
+        let status = match self.status {
+            Status::Pass | Status::Warn => StatusCode::OK,
+            Status::Fail => StatusCode::SERVICE_UNAVAILABLE,
+        };
+        let mut response = Negotiate(self).into_response();
+        // The layer only replaces the placeholder status, so failing documents keep theirs
+        if status != StatusCode::OK {
+            *response.status_mut() = status;
+        }
+        response
+    }
+}
+
+/// Liveness handler, always answering a passing status document.
+pub async fn liveness() -> Health {
+    Health::new()
+}
+
+#[cfg(all(test, any(feature = "simd-json", feature = "json")))]
+mod test {
+    use axum::{
+        body::Body,
+        http::{header::ACCEPT, Request},
+        routing::get,
+        Router,
+    };
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    use super::{liveness, Health, Status};
+    use crate::NegotiateLayer;
+
+    #[tokio::test]
+    async fn test_liveness_passes() {
+        let app = Router::new()
+            .route("/health", get(liveness))
+            .layer(NegotiateLayer::new());
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/health")
+                    .header(ACCEPT, "application/json")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            response.into_body().collect().await.unwrap().to_bytes(),
+            r#"{"status":"pass"}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_readiness_fails_with_any_failing_check() {
+        async fn readiness() -> Health {
+            Health::new()
+                .check("database", Status::Pass)
+                .check("cache", Status::Fail)
+        }
+
+        let app = Router::new()
+            .route("/ready", get(readiness))
+            .layer(NegotiateLayer::new());
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/ready")
+                    .header(ACCEPT, "application/json")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 503);
+        assert_eq!(
+            response.into_body().collect().await.unwrap().to_bytes(),
+            r#"{"status":"fail","checks":{"cache":"fail","database":"pass"}}"#
+        );
+    }
+}
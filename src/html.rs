@@ -0,0 +1,166 @@
+//! HTML rendering of payloads for browsing endpoints during development, as nested tables and lists.
+
+use std::fmt::Write;
+
+use crate::{encoding::CodecError, value::Value, Encoding};
+
+const STYLE: &str = "body{font-family:sans-serif}table{border-collapse:collapse}\
+th,td{border:1px solid #ccc;padding:2px 6px;text-align:left;vertical-align:top}\
+th{background:#f4f4f4}em{color:#888}";
+
+/// Render the payload as an HTML page, reading it back through a self-describing codec.
+pub(crate) fn render(payload: &dyn erased_serde::Serialize) -> Result<Vec<u8>, CodecError> {
+    #[cfg(feature = "cbor")]
+    let codec = Encoding::Cbor;
+    #[cfg(not(feature = "cbor"))]
+    let codec = Encoding::Json;
+    let value: Value = codec.decode(codec.encode(payload)?.into(), &Default::default())?;
+
+    let mut html = String::from("<!DOCTYPE html><html><head><meta charset=\"utf-8\">");
+    let _ = write!(html, "<style>{STYLE}</style></head><body>");
+    write_value(&mut html, &value);
+    html.push_str("</body></html>");
+    Ok(html.into_bytes())
+}
+
+fn write_value(html: &mut String, value: &Value) {
+    match value {
+        Value::Null => html.push_str("<em>null</em>"),
+        Value::Bool(v) => {
+            let _ = write!(html, "{v}");
+        }
+        Value::Integer(v) => {
+            let _ = write!(html, "{v}");
+        }
+        Value::Float(v) => {
+            let _ = write!(html, "{v}");
+        }
+        Value::Text(v) => escape(html, v),
+        Value::Bytes(v) => {
+            html.push_str("<code>");
+            for byte in v {
+                let _ = write!(html, "{byte:02x}");
+            }
+            html.push_str("</code>");
+        }
+        Value::Array(values) if values.is_empty() => html.push_str("<em>empty</em>"),
+        Value::Array(values) => match columns(values) {
+            Some(columns) => {
+                html.push_str("<table><tr>");
+                for column in &columns {
+                    html.push_str("<th>");
+                    write_value(html, column);
+                    html.push_str("</th>");
+                }
+                html.push_str("</tr>");
+                for row in values {
+                    html.push_str("<tr>");
+                    for column in &columns {
+                        html.push_str("<td>");
+                        if let Some(value) = field(row, column) {
+                            write_value(html, value);
+                        }
+                        html.push_str("</td>");
+                    }
+                    html.push_str("</tr>");
+                }
+                html.push_str("</table>");
+            }
+            None => {
+                html.push_str("<ol start=\"0\">");
+                for value in values {
+                    html.push_str("<li>");
+                    write_value(html, value);
+                    html.push_str("</li>");
+                }
+                html.push_str("</ol>");
+            }
+        },
+        Value::Map(entries) if entries.is_empty() => html.push_str("<em>empty</em>"),
+        Value::Map(entries) => {
+            html.push_str("<table>");
+            for (key, value) in entries {
+                html.push_str("<tr><th>");
+                write_value(html, key);
+                html.push_str("</th><td>");
+                write_value(html, value);
+                html.push_str("</td></tr>");
+            }
+            html.push_str("</table>");
+        }
+    }
+}
+
+/// Keys of a sequence of maps in the order they first appear, so records are rendered as the rows of a single table.
+fn columns(values: &[Value]) -> Option<Vec<&Value>> {
+    let mut columns: Vec<&Value> = Vec::new();
+    for value in values {
+        let Value::Map(entries) = value else {
+            return None;
+        };
+        for (key, _) in entries {
+            if !columns.contains(&key) {
+                columns.push(key);
+            }
+        }
+    }
+    Some(columns)
+}
+
+fn field<'a>(row: &'a Value, column: &Value) -> Option<&'a Value> {
+    let Value::Map(entries) = row else {
+        return None;
+    };
+    entries
+        .iter()
+        .find(|(key, _)| key == column)
+        .map(|(_, value)| value)
+}
+
+fn escape(html: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            c => html.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render_records_as_a_table() {
+        let mut html = String::new();
+        write_value(
+            &mut html,
+            &Value::Map(vec![
+                (Value::Text("name".into()), Value::Text("<b>".into())),
+                (
+                    Value::Text("rows".into()),
+                    Value::Array(vec![
+                        Value::Map(vec![(Value::Text("id".into()), Value::Integer(1))]),
+                        Value::Map(vec![(Value::Text("ok".into()), Value::Bool(true))]),
+                    ]),
+                ),
+                (
+                    Value::Text("tags".into()),
+                    Value::Array(vec![Value::Null, Value::Float(1.5)]),
+                ),
+            ]),
+        );
+        assert_eq!(
+            html,
+            concat!(
+                "<table><tr><th>name</th><td>&lt;b&gt;</td></tr>",
+                "<tr><th>rows</th><td><table><tr><th>id</th><th>ok</th></tr>",
+                "<tr><td>1</td><td></td></tr><tr><td></td><td>true</td></tr></table></td></tr>",
+                "<tr><th>tags</th><td><ol start=\"0\"><li><em>null</em></li><li>1.5</li></ol></td></tr></table>",
+            )
+        );
+    }
+}
@@ -0,0 +1,80 @@
+//! JSONP responses for legacy clients unable to use CORS, wrapping JSON bodies in the callback named on the query string.
+
+use axum::body::Bytes;
+
+/// Find the callback named by the query parameter, if it is a valid JavaScript function reference such as `jQuery_123` or `widgets.render`.
+///
+/// The value is not percent-decoded, as valid callbacks never need it.
+pub(crate) fn callback<'a>(query: Option<&'a str>, parameter: &str) -> Option<&'a str> {
+    let (_, callback) = query?
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(name, _)| *name == parameter)?;
+    let is_identifier = |part: &str| {
+        part.bytes()
+            .next()
+            .is_some_and(|b| b.is_ascii_alphabetic() || b == b'_' || b == b'$')
+            && part
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'$')
+    };
+    (callback.len() <= 128 && callback.split('.').all(is_identifier)).then_some(callback)
+}
+
+/// Wrap the JSON body in a call to the callback.
+///
+/// The leading comment prevents the body from being sniffed as another content type,
+/// and line separators, valid in JSON strings but not in older JavaScript engines, are escaped.
+pub(crate) fn wrap(callback: &str, json: &[u8]) -> Bytes {
+    let mut body = Vec::with_capacity(json.len() + callback.len() + 8);
+    body.extend_from_slice(b"/**/");
+    body.extend_from_slice(callback.as_bytes());
+    body.push(b'(');
+    let mut rest = json;
+    while let Some(at) = rest
+        .windows(3)
+        .position(|w| w == "\u{2028}".as_bytes() || w == "\u{2029}".as_bytes())
+    {
+        body.extend_from_slice(&rest[..at]);
+        body.extend_from_slice(if rest[at + 2] == 0xA8 {
+            br"\u2028"
+        } else {
+            br"\u2029"
+        });
+        rest = &rest[at + 3..];
+    }
+    body.extend_from_slice(rest);
+    body.extend_from_slice(b");");
+    body.into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read_valid_callbacks_only() {
+        assert_eq!(
+            callback(Some("a=1&cb=jQuery_1$2"), "cb"),
+            Some("jQuery_1$2")
+        );
+        assert_eq!(
+            callback(Some("cb=widgets.render"), "cb"),
+            Some("widgets.render")
+        );
+        assert_eq!(callback(Some("callback=render"), "cb"), None);
+        assert_eq!(callback(Some("cb=alert(1)"), "cb"), None);
+        assert_eq!(callback(Some("cb=1render"), "cb"), None);
+        assert_eq!(callback(Some("cb=widgets..render"), "cb"), None);
+        assert_eq!(callback(Some("cb="), "cb"), None);
+        assert_eq!(callback(None, "cb"), None);
+    }
+
+    #[test]
+    fn test_wrap_and_escape_line_separators() {
+        assert_eq!(
+            wrap("render", "{\"text\":\"a\u{2028}b\u{2029}\"}".as_bytes()),
+            r#"/**/render({"text":"a\u2028b\u2029"});"#
+        );
+    }
+}
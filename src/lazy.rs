@@ -0,0 +1,100 @@
+//! Request bodies deserialized on first access, for handlers which may answer before needing them.
+
+use std::{fmt, sync::OnceLock};
+
+use axum::{
+    extract::{FromRequest, Request},
+    response::Response,
+};
+
+use crate::{Encoding, RequestBody};
+
+/// Extractor reading the request body like [Negotiate](crate::Negotiate), but only deserializing it on the first call to [LazyNegotiate::get].
+///
+/// Unsupported media types are still rejected before running the handler, while malformed bodies are only detected when accessed,
+/// so handlers checking authorization or rate limits first don't pay for decoding rejected requests.
+///
+/// ```rust
+/// use axum::{http::StatusCode, response::{IntoResponse, Response}};
+/// use axum_content_negotiation::{LazyNegotiate, Negotiate};
+///
+/// #[derive(serde::Deserialize, serde::Serialize)]
+/// struct Order {
+///     id: u32,
+/// }
+///
+/// async fn handler(allowed: bool, order: LazyNegotiate<Order>) -> Result<Response, Response> {
+///     if !allowed {
+///         return Ok(StatusCode::FORBIDDEN.into_response());
+///     }
+///     let order = order.into_inner()?;
+///     Ok(Negotiate(order).into_response())
+/// }
+/// ```
+pub struct LazyNegotiate<T> {
+    body: RequestBody,
+    decoded: OnceLock<T>,
+}
+
+// Failures are answered with the rejection responses of the extractors
+#[allow(clippy::result_large_err)]
+impl<T> LazyNegotiate<T>
+where
+    T: serde::de::DeserializeOwned,
+{
+    /// Deserialize the body on the first call, answering the same rejection as [Negotiate](crate::Negotiate) when it is malformed.
+    pub fn get(&self) -> Result<&T, Response> {
+        if let Some(decoded) = self.decoded.get() {
+            return Ok(decoded);
+        }
+        let decoded = self.decode()?;
+        Ok(self.decoded.get_or_init(|| decoded))
+    }
+
+    /// Take the deserialized body, decoding it if [LazyNegotiate::get] was not called yet.
+    pub fn into_inner(mut self) -> Result<T, Response> {
+        match self.decoded.take() {
+            Some(decoded) => Ok(decoded),
+            None => self.decode(),
+        }
+    }
+
+    fn decode(&self) -> Result<T, Response> {
+        self.body
+            .clone()
+            .decode(|encoding, body, options| encoding.decode(body, options))
+    }
+
+    /// Encoding informed by the request `Content-Type`.
+    pub fn encoding(&self) -> Encoding {
+        self.body.encoding
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for LazyNegotiate<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LazyNegotiate")
+            .field("encoding", &self.body.encoding)
+            .field("body_len", &self.body.body.len())
+            .field("decoded", &self.decoded.get())
+            .finish()
+    }
+}
+
+/// [LazyNegotiate] implements [FromRequest] if the target type is deserializable.
+///
+/// The body is read, and its `Content-Type` checked, with the same rejections as [Negotiate](crate::Negotiate).
+impl<T, S> FromRequest<S> for LazyNegotiate<T>
+where
+    T: serde::de::DeserializeOwned + Send + Sync + 'static,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self {
+            body: crate::read_request(req, state).await?,
+            decoded: OnceLock::new(),
+        })
+    }
+}
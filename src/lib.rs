@@ -2,6 +2,7 @@
 
 use std::{
     future::Future,
+    marker::PhantomData,
     pin::Pin,
     sync::Arc,
     task::{Context, Poll},
@@ -11,33 +12,157 @@ use axum::{
     body::Bytes,
     extract::{FromRequest, Request},
     http::{
-        header::{HeaderValue, ACCEPT, CONTENT_LENGTH, CONTENT_TYPE},
-        StatusCode,
+        header::{
+            HeaderValue, ACCEPT, AGE, CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_ENCODING,
+            CONTENT_LENGTH, CONTENT_TYPE, ETAG, IF_NONE_MATCH, LINK, VARY,
+        },
+        HeaderMap, HeaderName, Method, StatusCode,
     },
     response::{IntoResponse, Response},
     Extension,
 };
 use tower::Service;
 
+pub mod audit;
+mod cache;
+#[cfg(feature = "tokio")]
+mod coalesce;
+mod context;
+#[cfg(feature = "csv")]
+mod csv;
+mod decoders;
+#[cfg(feature = "dev-server")]
+pub mod dev_server;
+mod downgrade;
+mod download;
+mod encoding;
+mod error;
+mod error_codes;
+mod etag;
+mod features;
+#[cfg(feature = "feed")]
+mod feed;
+#[cfg(feature = "flatbuffers")]
+mod flatbuffers;
+pub mod formats;
+#[cfg(feature = "geojson")]
+mod geojson;
+#[cfg(feature = "hal")]
+mod hal;
+pub mod health;
+#[cfg(feature = "debug-html")]
+mod html;
+#[cfg(feature = "jsonp")]
+mod jsonp;
+mod lazy;
+mod limit;
+#[cfg(feature = "tokio")]
+mod long_poll;
+mod media_type;
+mod metadata;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "multipart")]
+mod multipart;
+#[cfg(feature = "octet-stream")]
+mod octet_stream;
+#[cfg(feature = "json-patch")]
+mod patch;
+mod path_extension;
+mod payload_hash;
+#[cfg(feature = "plain-text")]
+mod plain_text;
+mod problem;
+mod quirks;
+mod repr;
+mod seed;
+#[cfg(any(feature = "ndjson", feature = "cbor-seq"))]
+mod sequence;
+#[cfg(feature = "test-util")]
+pub mod test_util;
+mod value;
+#[cfg(feature = "xlsx")]
+mod xlsx;
+
+pub use cache::NegotiateCached;
+pub use context::{NegotiateWith, SerializeWith};
+#[cfg(feature = "csv")]
+pub use csv::CsvOptions;
+pub use decoders::BodyDecoders;
+pub use downgrade::{AcceptDowngrade, AcceptDowngradeLayer};
+pub use download::NegotiateDownload;
+#[cfg(feature = "cbor")]
+pub use encoding::CborProfile;
+pub use encoding::Encoding;
+pub use error::ErrorDetails;
+use error::{Mismatch, Rejection};
+pub use error_codes::{ApiError, ErrorCodes};
+pub use etag::ETagPolicy;
+pub use features::{feature_matrix, EncodingSupport, FeatureMatrix};
+#[cfg(feature = "feed")]
+pub use feed::{Feed, FeedChannel, FeedEntry, NegotiateFeed};
+#[cfg(feature = "flatbuffers")]
+pub use flatbuffers::{NegotiateFlatBuffer, ToFlatBuffer};
+pub use formats::NegotiatedRoute;
+#[cfg(feature = "hal")]
+pub use hal::{HalLink, HalLinks, NegotiateHal};
+pub use lazy::LazyNegotiate;
+pub use limit::RequestBodyLimit;
+#[cfg(feature = "tokio")]
+pub use long_poll::LongPoll;
+pub use media_type::{canonicalize_accept, SuffixFamily};
+use media_type::{Matcher, Selection};
+pub use metadata::NegotiationMetadata;
+#[cfg(feature = "octet-stream")]
+pub use octet_stream::NegotiateBytes;
+#[cfg(feature = "json-patch")]
+pub use patch::{JsonPatch, PatchError, PatchOperation};
+pub use path_extension::{PathExtension, PathExtensionLayer};
+pub use payload_hash::PayloadHash;
+#[cfg(feature = "plain-text")]
+pub use plain_text::{NegotiatePlainText, ToPlainText};
+pub use problem::ProblemDetails;
+pub use quirks::ClientQuirks;
+pub use repr::{DeserializeFor, NegotiateFor, SerializeFor};
+pub use seed::{BodySeed, NegotiateSeed};
+
 #[cfg(all(feature = "json", feature = "simd-json"))]
 compile_error!("json and simd-json features are mutually exclusive");
-#[cfg(all(feature = "default-json", feature = "default-cbor"))]
-compile_error!("default-json and default-cbor features are mutually exclusive");
 
-#[cfg(feature = "default-json")]
-/// Default to application/json if the request does not have any accept header or uses */* when json is enabled
-static DEFAULT_CONTENT_TYPE_VALUE: &str = "application/json";
+#[cfg(all(feature = "hal", not(any(feature = "simd-json", feature = "json"))))]
+compile_error!("hal feature requires the json or simd-json feature");
+
+#[cfg(all(feature = "jsonp", not(any(feature = "simd-json", feature = "json"))))]
+compile_error!("jsonp feature requires the json or simd-json feature");
 
-#[cfg(feature = "default-cbor")]
-/// Default to application/cbor if the request does not have any accept header or uses */* when json is not enabled
-static DEFAULT_CONTENT_TYPE_VALUE: &str = "application/cbor";
+#[cfg(all(feature = "geojson", not(any(feature = "simd-json", feature = "json"))))]
+compile_error!("geojson feature requires the json or simd-json feature");
 
-#[cfg(not(any(feature = "default-json", feature = "default-cbor")))]
-compile_error!("A default-* feature must be enabled for fallback encoding");
+#[cfg(all(
+    feature = "debug-html",
+    not(any(feature = "simd-json", feature = "json", feature = "cbor"))
+))]
+compile_error!("debug-html feature requires the json, simd-json or cbor feature");
 
-static DEFAULT_CONTENT_TYPE: HeaderValue = HeaderValue::from_static(DEFAULT_CONTENT_TYPE_VALUE);
+#[cfg(all(
+    feature = "xlsx",
+    not(any(feature = "simd-json", feature = "json", feature = "cbor"))
+))]
+compile_error!("xlsx feature requires the json, simd-json or cbor feature");
 
-static MALFORMED_RESPONSE: (StatusCode, &str) = (StatusCode::BAD_REQUEST, "Malformed request body");
+#[cfg(all(
+    feature = "json-patch",
+    not(any(feature = "simd-json", feature = "json"))
+))]
+compile_error!("json-patch feature requires the json or simd-json feature");
+
+#[cfg(all(feature = "metrics", target_arch = "wasm32", target_os = "unknown"))]
+compile_error!(
+    "metrics feature requires a system clock, which wasm32-unknown-unknown does not have"
+);
+
+#[cfg(all(feature = "dev-server", target_arch = "wasm32"))]
+compile_error!("dev-server feature requires a native target");
 
 /// Used either as an [Extract](axum::extract::FromRequest) or [Response](axum::response::IntoResponse) to negotiate the serialization format used.
 ///
@@ -75,77 +200,313 @@ pub struct Negotiate<T>(
 ///
 /// It will attempt to deserialize the request body based on the `Content-Type` header.
 /// If the `Content-Type` header is not supported, it will return a 415 Unsupported Media Type response without running the handler.
+///
+/// If the request extensions already contain a `Negotiate<T>`, such as one placed by an internal dispatcher or a test harness,
+/// it is used as is and the body is not decoded at all.
+///
+/// ```rust
+/// use axum::{extract::FromRequest, http::Request};
+/// use axum_content_negotiation::Negotiate;
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let mut request = Request::new(axum::body::Body::empty());
+/// request.extensions_mut().insert(Negotiate(42u32));
+///
+/// let Ok(Negotiate(value)) = Negotiate::<u32>::from_request(request, &()).await else {
+///     panic!("value is already decoded");
+/// };
+/// assert_eq!(value, 42);
+/// # }
+/// ```
 impl<T, S> FromRequest<S> for Negotiate<T>
 where
-    T: serde::de::DeserializeOwned,
+    T: serde::de::DeserializeOwned + Send + Sync + 'static,
     S: Send + Sync,
 {
     type Rejection = Response;
 
-    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
-        let accept = req
-            .headers()
-            .get(CONTENT_TYPE)
-            .unwrap_or(&DEFAULT_CONTENT_TYPE);
-
-        match accept.as_bytes() {
-            #[cfg(feature = "simd-json")]
-            b"application/json" => {
-                let mut body = Bytes::from_request(req, state)
-                    .await
-                    .map_err(|e| {
-                        tracing::error!(error = %e, "failed to ready request body as bytes");
-                        e.into_response()
-                    })?
-                    .to_vec();
-
-                let body = simd_json::from_slice(&mut body).map_err(|e| {
-                    tracing::error!(error = %e, "failed to deserialize request body as json");
-                    MALFORMED_RESPONSE.into_response()
-                })?;
-
-                Ok(Self(body))
-            }
-            #[cfg(feature = "json")]
-            b"application/json" => {
-                let body = Bytes::from_request(req, state).await.map_err(|e| {
-                    tracing::error!(error = %e, "failed to ready request body as bytes");
-                    e.into_response()
-                })?;
+    async fn from_request(mut req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(decoded) = take_decoded(&mut req) {
+            return Ok(decoded);
+        }
 
-                let body = serde_json::from_slice(&body).map_err(|e| {
-                    tracing::error!(error = %e, "failed to deserialize request body as json");
-                    MALFORMED_RESPONSE.into_response()
-                })?;
+        decode_request(req, state, |encoding, body, options| {
+            encoding.decode(body, options)
+        })
+        .await
+        .map(Self)
+    }
+}
 
-                Ok(Self(body))
-            }
+/// Take the value already decoded on the request extensions, such as by an internal dispatcher or a test harness.
+///
+/// Extensions are looked up by type, so only `Send + Sync + 'static` values can be found there.
+fn take_decoded<T>(req: &mut Request) -> Option<Negotiate<T>>
+where
+    T: Send + Sync + 'static,
+{
+    req.extensions_mut().remove::<Negotiate<T>>()
+}
 
-            #[cfg(feature = "cbor")]
-            b"application/cbor" => {
-                let body = Bytes::from_request(req, state).await.map_err(|e| {
-                    tracing::error!(error = %e, "failed to ready request body as bytes");
-                    e.into_response()
-                })?;
+/// Decode the request body using the format informed on the `Content-Type` header, answering failures with the configured rejections.
+async fn decode_request<T, S>(
+    req: Request,
+    state: &S,
+    decode: impl FnOnce(Encoding, Bytes, &encoding::DecodeOptions) -> Result<T, encoding::CodecError>,
+) -> Result<T, Response>
+where
+    S: Send + Sync,
+{
+    read_request(req, state).await?.decode(decode)
+}
+
+/// Request body read in the format informed on the `Content-Type` header, waiting to be decoded.
+#[derive(Clone)]
+struct RequestBody {
+    config: Arc<Config>,
+    /// Encoding accepted by the client, used to answer rejections
+    accepted: Option<Encoding>,
+    request_id: Option<String>,
+    encoding: Encoding,
+    body: Bytes,
+    /// Permit to decode a large body, released once it is decoded
+    permit: limit::DecodePermit,
+    /// Fail to decode, as injected by a test
+    #[cfg(feature = "test-util")]
+    fail_decode: bool,
+}
 
-                let body = cbor4ii::serde::from_slice(&body).map_err(|e| {
-                    tracing::error!(error = %e, "failed to deserialize request body as json");
-                    MALFORMED_RESPONSE.into_response()
-                })?;
+#[allow(clippy::result_large_err)]
+impl RequestBody {
+    /// Decode the body, answering failures with the malformed body rejection.
+    fn decode<T>(
+        self,
+        decode: impl FnOnce(
+            Encoding,
+            Bytes,
+            &encoding::DecodeOptions,
+        ) -> Result<T, encoding::CodecError>,
+    ) -> Result<T, Response> {
+        let _permit = self.permit;
+        let encoding = self.encoding;
+        #[cfg(feature = "test-util")]
+        let decode = |encoding, body, options: &_| match self.fail_decode {
+            true => Err("decode failure injected by test".into()),
+            false => decode(encoding, body, options),
+        };
+        let decoded = || decode(encoding, self.body, &self.config.decode_options);
+        #[cfg(feature = "metrics")]
+        let decoded = metrics::time(
+            self.config.codec_recorder.as_ref(),
+            encoding,
+            metrics::CodecOperation::Decode,
+            decoded,
+        );
+        #[cfg(not(feature = "metrics"))]
+        let decoded = decoded();
 
-                Ok(Self(body))
-            }
+        decoded.map_err(|e| {
+            tracing::error!(error = %e, %encoding, "failed to deserialize request body");
+            self.config.reject(
+                Rejection::MalformedBody,
+                self.accepted,
+                self.request_id.as_deref(),
+            )
+        })
+    }
+}
 
-            _ => {
-                tracing::error!("unsupported accept header: {:?}", accept);
-                return Err((
-                    StatusCode::NOT_ACCEPTABLE,
-                    "Invalid content type on request",
-                )
-                    .into_response());
+/// Check if the request is known to have no body, such as without `Content-Length` and `Transfer-Encoding` headers.
+fn has_empty_body(req: &Request) -> bool {
+    use axum::body::HttpBody;
+
+    req.body().size_hint().exact() == Some(0)
+}
+
+/// Read the request body after checking its `Content-Type` header, translating it with the runtime decoders when needed.
+async fn read_request<S>(req: Request, state: &S) -> Result<RequestBody, Response>
+where
+    S: Send + Sync,
+{
+    let config = Config::from_extensions(req.extensions());
+    // Rejections are answered using the format the client accepts
+    let accepted = req
+        .headers()
+        .negotiate(&config)
+        .ok()
+        .map(|selection| selection.encoding);
+    let request_id = error::request_id(req.headers());
+    let method = req.method().clone();
+    let reject =
+        |rejection| config.reject_body(rejection, accepted, request_id.as_deref(), &method);
+    #[cfg(feature = "test-util")]
+    let injected = req
+        .extensions()
+        .get::<test_util::InjectedFailure>()
+        .copied();
+    #[cfg(feature = "test-util")]
+    if injected == Some(test_util::InjectedFailure::UnsupportedContentType) {
+        return Err(reject(Rejection::UnsupportedContentType(
+            Mismatch::Unsupported,
+        )));
+    }
+
+    let (encoding, translate) = request_encoding(&req, &config).map_err(reject)?;
+
+    let route = config.schema_auditor.as_ref().and_then(|_| {
+        req.extensions()
+            .get::<axum::extract::MatchedPath>()
+            .map(|path| path.as_str().to_string())
+    });
+
+    let (body, permit) = read_body(req, state, &config, &reject).await?;
+
+    let body = match translate {
+        Some(translate) => translate(body).map_err(|e| {
+            tracing::error!(error = %e, %encoding, "failed to translate request body");
+            reject(Rejection::MalformedBody)
+        })?,
+        None => body,
+    };
+
+    if let Some(auditor) = &config.schema_auditor {
+        auditor.sample(route.as_deref(), encoding, &body, &config.decode_options);
+    }
+
+    Ok(RequestBody {
+        config,
+        accepted,
+        request_id,
+        encoding,
+        body,
+        permit,
+        #[cfg(feature = "test-util")]
+        fail_decode: injected == Some(test_util::InjectedFailure::Decode),
+    })
+}
+
+/// Select the encoding of the request body from its `Content-Type` header, along with the runtime decoder translating it, if any.
+fn request_encoding(
+    req: &Request,
+    config: &Config,
+) -> Result<(Encoding, Option<Arc<decoders::Translate>>), Rejection> {
+    // Runtime decoders translate the bodies of media types the matcher doesn't know, or can't decode, such as `text/plain`
+    let mut translate = None;
+    let encoding = match req.headers().get(CONTENT_TYPE) {
+        Some(content_type) => match content_type.to_str() {
+            Ok(content_type) => {
+                let (media_type, parameters) = media_type::parse_content_type(content_type);
+                // Bodies are always read as UTF-8, so other charsets would be decoded into garbage
+                if !media_type::is_utf8(&parameters) {
+                    tracing::error!(?parameters, "unsupported charset on content-type header");
+                    return Err(Rejection::UnsupportedContentType(Mismatch::Unsupported));
+                }
+                config
+                    .matcher
+                    .select(media_type)
+                    .map(|selection| selection.encoding)
+                    .filter(|&encoding| encoding.decodes() && config.negotiable(encoding))
+                    .or_else(|| {
+                        let decoder = config.body_decoders.as_ref()?.get(media_type)?;
+                        translate = Some(decoder.translate);
+                        Some(decoder.encoding)
+                    })
+                    .ok_or_else(|| config.matcher.mismatch(media_type))
             }
+            Err(_) => Err(Mismatch::Invalid),
+        },
+        None if config.require_content_type && !has_empty_body(req) => {
+            tracing::error!("missing content-type header on request with a body");
+            return Err(Rejection::MissingContentType);
+        }
+        None => config
+            .matcher
+            .default_excluding(&config.disabled_encodings(), None)
+            .ok_or(Mismatch::Invalid),
+    };
+
+    let encoding = match encoding {
+        Ok(encoding) => encoding,
+        Err(mismatch) => {
+            tracing::error!(
+                ?mismatch,
+                "unsupported content-type header: {:?}",
+                req.headers().get(CONTENT_TYPE)
+            );
+            return Err(Rejection::UnsupportedContentType(mismatch));
+        }
+    };
+
+    Ok((encoding, translate))
+}
+
+/// Read the whole request body, within the size limit and timeout configured on the layer.
+///
+/// Large bodies are returned with the permit of [NegotiateLayer::limit_large_decodes], to be held until they are decoded.
+async fn read_body<S>(
+    req: Request,
+    state: &S,
+    config: &Config,
+    reject: &impl Fn(Rejection) -> Response,
+) -> Result<(Bytes, limit::DecodePermit), Response>
+where
+    S: Send + Sync,
+{
+    // Declared sizes are rejected before waiting for the body, and chunked uploads are counted while reading it
+    if let Some(limit) = config.max_request_size {
+        if let Some(length) = limit::content_length(req.headers()).filter(|&length| length > limit)
+        {
+            tracing::error!(length, limit, "declared request body is too large");
+            return Err(reject(Rejection::RequestTooLarge));
         }
     }
+
+    #[cfg(feature = "tokio")]
+    let permit = match &config.decode_guard {
+        Some(guard) if guard.is_large(req.headers(), req.body()) => guard.acquire().await,
+        _ => limit::DecodePermit::default(),
+    };
+    #[cfg(not(feature = "tokio"))]
+    let permit = limit::DecodePermit::default();
+
+    let body = async {
+        let limit = match config.max_request_size {
+            Some(limit) => limit,
+            None => {
+                return Bytes::from_request(req, state).await.map_err(|e| {
+                    tracing::error!(error = %e, "failed to ready request body as bytes");
+                    match config.error_template {
+                        Some(_) => reject(Rejection::BodyRead(e.status())),
+                        None => e.into_response(),
+                    }
+                })
+            }
+        };
+        limit::read_body(req.into_body(), limit)
+            .await
+            .map_err(|e| match e {
+                limit::ReadError::TooLarge => {
+                    tracing::error!(limit, "request body is too large");
+                    reject(Rejection::RequestTooLarge)
+                }
+                limit::ReadError::Body(e) => {
+                    tracing::error!(error = %e, "failed to ready request body as bytes");
+                    reject(Rejection::BodyRead(StatusCode::BAD_REQUEST))
+                }
+            })
+    };
+    #[cfg(feature = "tokio")]
+    let body = match config.body_timeout {
+        Some(timeout) => tokio::time::timeout(timeout, body).await.map_err(|_| {
+            tracing::error!(?timeout, "timed out reading request body");
+            reject(Rejection::BodyTimeout)
+        })?,
+        None => body.await,
+    };
+    #[cfg(not(feature = "tokio"))]
+    let body = body.await;
+    body.map(|body| (body, permit))
 }
 
 /// Internal Negotiate object without the type parameter explicitly, in order to be able retrieve it as an extension on the [Layer](tower::Layer) response processing.
@@ -181,435 +542,6913 @@ where
     }
 }
 
-/// Layer responsible to convert a [Negotiate] response into the right serialization format based on the `Accept` header.
+/// Runtime configuration shared between the [NegotiateLayer] and the [Negotiate] extractor.
 ///
-/// If the `Accept` header is not supported, it will return a 406 Not Acceptable response without running the handler.
-#[derive(Clone)]
-pub struct NegotiateLayer;
-
-impl<S> tower::Layer<S> for NegotiateLayer {
-    type Service = NegotiateService<S>;
-
-    fn layer(&self, inner: S) -> Self::Service {
-        NegotiateService(inner)
-    }
-}
-
-trait AcceptExt {
-    fn negotiate(&self) -> Option<&'static str>;
+/// The [NegotiateService] stores it on the request extensions, so extractors running under the layer observe the same settings.
+#[derive(Debug, Clone)]
+struct Config {
+    /// Encoding selection rules, including the encoding used when the request has no `Content-Type`/`Accept` header, or accepts `*/*`
+    matcher: Matcher,
+    /// Limit concurrent decoding of large request bodies
+    #[cfg(feature = "tokio")]
+    decode_guard: Option<limit::DecodeGuard>,
+    /// Maximum time to receive the whole request body
+    #[cfg(feature = "tokio")]
+    body_timeout: Option<std::time::Duration>,
+    /// Share the response of concurrent identical `GET` requests
+    #[cfg(feature = "tokio")]
+    coalescer: Option<Arc<coalesce::Coalescer>>,
+    /// Receive the time spent on each codec
+    #[cfg(feature = "metrics")]
+    codec_recorder: Option<metrics::Recorder>,
+    /// Receive the top-level fields of sampled request payloads
+    schema_auditor: Option<Arc<audit::SchemaAuditor>>,
+    /// Build serializable bodies for the crate rejections
+    error_template: Option<error::ErrorTemplate>,
+    /// Statuses answered for the codes of [ApiError] responses
+    error_codes: Option<ErrorCodes>,
+    /// How to treat empty `Accept` headers
+    empty_accept: EmptyAccept,
+    /// Reject request bodies without a `Content-Type` instead of decoding them with the default encoding
+    require_content_type: bool,
+    /// Answer the default encoding instead of 406 Not Acceptable when nothing on the `Accept` header is supported
+    lenient: bool,
+    /// Query parameter naming the response format, over the `Accept` header
+    format_parameter: Option<String>,
+    /// Advertise the other representations of negotiated responses
+    link_alternates: bool,
+    /// Append `Vary: Accept` to negotiated responses
+    vary_accept: bool,
+    /// Freshness informed on the `Cache-Control` of successful negotiated responses
+    max_age: Option<std::time::Duration>,
+    /// Maximum size of request bodies, replacing the limit of axum
+    max_request_size: Option<usize>,
+    /// Maximum size of serialized response bodies
+    max_response_size: Option<usize>,
+    /// Encodings negotiable with this layer, among the enabled ones, or all of them
+    allowed_encodings: Option<Vec<Encoding>>,
+    /// Encodings tried in order when the negotiated one fails to serialize the response
+    fallback_encodings: Vec<Encoding>,
+    /// Size of the stack buffer tried first to serialize responses, disabled when zero
+    inline_response_size: usize,
+    /// Decoders registered at runtime for the request bodies of extra media types
+    body_decoders: Option<BodyDecoders>,
+    /// Layout of CBOR responses on the wire
+    #[cfg(feature = "cbor")]
+    cbor_profile: CborProfile,
+    /// How request bodies are read, for the encodings with options
+    decode_options: encoding::DecodeOptions,
+    /// How to derive the `ETag` of negotiated responses, if any
+    etag: Option<ETagPolicy>,
+    /// Read the `Content-Type` parameters of requests, such as a schema version
+    parameter_hook: Option<media_type::ParameterHook>,
+    /// Hash the canonical form of request payloads into their extensions
+    payload_hash: Option<payload_hash::PayloadHasher>,
+    /// Reject responses bypassing the negotiation, except for these media types
+    strict: Option<Vec<String>>,
+    /// Encodings never answered to some clients, regardless of their `Accept` header
+    client_quirks: Option<ClientQuirks>,
+    /// Query parameter naming the callback of JSONP responses, which are only negotiated when set
+    #[cfg(feature = "jsonp")]
+    jsonp_callback: Option<String>,
+    /// Contexts available to [NegotiateWith] responses, by type
+    contexts: axum::http::Extensions,
 }
 
-impl AcceptExt for axum::http::HeaderMap {
-    /// Basic implementation without q= values
-    fn negotiate(&self) -> Option<&'static str> {
-        let accept = self.get(ACCEPT).unwrap_or(&DEFAULT_CONTENT_TYPE);
-
-        match accept.as_bytes() {
-            #[cfg(any(feature = "simd-json", feature = "json"))]
-            b"application/json" => Some("application/json"),
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            matcher: Matcher {
+                default_encoding: Encoding::compiled_default(),
+                aliases: media_type::ALIASES
+                    .iter()
+                    .map(|(alias, encoding)| (alias.to_string(), *encoding))
+                    .collect(),
+                media_types: Vec::new(),
+                families: Encoding::ALL
+                    .iter()
+                    .map(|encoding| (*encoding, SuffixFamily::none()))
+                    .collect(),
+                preference: Vec::new(),
+            },
+            #[cfg(feature = "tokio")]
+            decode_guard: None,
+            #[cfg(feature = "tokio")]
+            body_timeout: None,
+            #[cfg(feature = "tokio")]
+            coalescer: None,
+            #[cfg(feature = "metrics")]
+            codec_recorder: None,
+            schema_auditor: None,
+            error_template: None,
+            error_codes: None,
+            empty_accept: EmptyAccept::default(),
+            lenient: false,
+            format_parameter: None,
+            require_content_type: false,
+            link_alternates: false,
+            vary_accept: true,
+            max_age: None,
+            max_request_size: None,
+            max_response_size: None,
+            allowed_encodings: None,
+            fallback_encodings: Vec::new(),
+            inline_response_size: 0,
+            body_decoders: None,
             #[cfg(feature = "cbor")]
-            b"application/cbor" => Some("application/cbor"),
-            b"*/*" => Some(DEFAULT_CONTENT_TYPE_VALUE),
-            _ => None,
+            cbor_profile: CborProfile::Backend,
+            decode_options: encoding::DecodeOptions::default(),
+            etag: None,
+            parameter_hook: None,
+            payload_hash: None,
+            strict: None,
+            client_quirks: None,
+            #[cfg(feature = "jsonp")]
+            jsonp_callback: None,
+            contexts: axum::http::Extensions::new(),
         }
     }
 }
 
-/// Serialize the stored [Extension] struct defined by a [Negotiate] into the right serialization format based on the `Accept` header.
-#[derive(Clone)]
-pub struct NegotiateService<S>(S);
+impl Config {
+    /// Retrieve the configuration installed by a [NegotiateService], falling back to the compile-time behavior.
+    fn from_extensions(extensions: &axum::http::Extensions) -> Arc<Config> {
+        extensions.get::<Arc<Config>>().cloned().unwrap_or_default()
+    }
 
-impl<T> Service<Request> for NegotiateService<T>
-where
-    T: Service<Request>,
-    T::Response: IntoResponse,
-    T::Future: Send + 'static,
-{
-    type Response = axum::response::Response;
-    type Error = T::Error;
-    type Future =
-        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+    /// Build the response for a rejection, using the error template when configured.
+    fn reject(
+        &self,
+        rejection: Rejection,
+        encoding: Option<Encoding>,
+        request_id: Option<&str>,
+    ) -> Response {
+        let supported = self.supported_media_types(rejection);
+        self.respond(rejection, encoding, request_id, &supported, None)
+    }
 
-    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.0.poll_ready(cx)
+    /// Build the response for a rejection of the request body, advertising the decoded media types on the header of its `method`.
+    fn reject_body(
+        &self,
+        rejection: Rejection,
+        encoding: Option<Encoding>,
+        request_id: Option<&str>,
+        method: &Method,
+    ) -> Response {
+        let supported = self.supported_media_types(rejection);
+        self.respond(
+            rejection,
+            encoding,
+            request_id,
+            &supported,
+            Some(error::accept_header(method)),
+        )
     }
 
-    fn call(&mut self, request: Request) -> Self::Future {
-        let accept = request.headers().negotiate();
+    /// Build the response for a rejection listing the `supported` media types, also advertised on the `advertise` header on 415 Unsupported Media Type.
+    fn respond(
+        &self,
+        rejection: Rejection,
+        encoding: Option<Encoding>,
+        request_id: Option<&str>,
+        supported: &[&'static str],
+        advertise: Option<HeaderName>,
+    ) -> Response {
+        // Error bodies are not wrapped in a callback, so they are informed as plain JSON
+        #[cfg(feature = "jsonp")]
+        let encoding = encoding.map(|encoding| match encoding {
+            Encoding::Jsonp => Encoding::Json,
+            encoding => encoding,
+        });
+        error::respond(
+            self.error_template.as_ref(),
+            rejection,
+            encoding.or(self.matcher.default_encoding),
+            request_id,
+            supported,
+            advertise,
+        )
+    }
 
-        let Some(encoding) = accept else {
-            return Box::pin(async move {
-                let response: Response = (
-                    StatusCode::NOT_ACCEPTABLE,
-                    "Invalid content type on request",
-                )
-                    .into_response();
-                Ok(response)
-            });
+    /// Check if the encoding can be negotiated with this layer, as some of the enabled ones can be disabled by its configuration.
+    fn negotiable(&self, encoding: Encoding) -> bool {
+        #[cfg(feature = "jsonp")]
+        if encoding == Encoding::Jsonp && self.jsonp_callback.is_none() {
+            return false;
+        }
+        self.allowed_encodings
+            .as_ref()
+            .is_none_or(|allowed| allowed.contains(&encoding))
+    }
+
+    /// Enabled encodings which can't be negotiated with this layer.
+    fn disabled_encodings(&self) -> Vec<Encoding> {
+        Encoding::ALL
+            .iter()
+            .copied()
+            .filter(|&encoding| !self.negotiable(encoding))
+            .collect()
+    }
+
+    /// Media types the client can use instead of the ones rejected, informed on the `Content-Type` or `Accept` of the request.
+    fn supported_media_types(&self, rejection: Rejection) -> Vec<&'static str> {
+        let supported: fn(Encoding) -> bool = match rejection {
+            Rejection::UnsupportedContentType(_) | Rejection::MissingContentType => {
+                Encoding::decodes
+            }
+            Rejection::NotAcceptable(_) | Rejection::EmptyAccept => Encoding::encodes,
+            _ => return Vec::new(),
         };
+        Encoding::ALL
+            .iter()
+            .copied()
+            .filter(|&encoding| supported(encoding) && self.negotiable(encoding))
+            .map(Encoding::media_type)
+            .collect()
+    }
 
-        let future = self.0.call(request);
+    /// Check if a strict layer should reject a response without a payload, which has a body with a media type not allowed to bypass the negotiation.
+    ///
+    /// Rejections of the crate and responses negotiated by inner layers are let through.
+    fn bypasses_negotiation(
+        &self,
+        parts: &axum::http::response::Parts,
+        body: &axum::body::Body,
+    ) -> bool {
+        use axum::body::HttpBody;
 
-        Box::pin(async move {
-            let inner_service = future.await?;
-            let response: Response = inner_service.into_response();
-            let data = response.extensions().get::<ErasedNegotiate>();
+        let Some(allowed) = &self.strict else {
+            return false;
+        };
+        if parts.extensions.get::<error::Rejected>().is_some()
+            || parts.extensions.get::<NegotiationOutcome>().is_some()
+            || body.size_hint().exact() == Some(0)
+        {
+            return false;
+        }
+        let Some(content_type) = parts.headers.get(CONTENT_TYPE) else {
+            return false;
+        };
+        let media_type = content_type
+            .to_str()
+            .map(|content_type| media_type::parse_content_type(content_type).0)
+            .unwrap_or_default();
+        !allowed
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(media_type))
+    }
 
-            let Some(ErasedNegotiate(payload)) = data else {
-                return Ok(response);
-            };
+    /// Serialize the [Negotiate] payload of a response using the negotiated selection, keeping its status and headers.
+    ///
+    /// Payloads which can't be encoded into the selection, such as a [Negotiate] asked for `text/plain`, are negotiated again
+    /// with the request headers, when available, without the encodings they can't use. Responses without a payload are returned untouched.
+    #[allow(clippy::too_many_arguments)]
+    fn encode_response(
+        &self,
+        response: Response,
+        selection: Selection,
+        headers: Option<&HeaderMap>,
+        request_id: Option<&str>,
+        location: Option<&str>,
+        if_none_match: Option<&HeaderValue>,
+        callback: Option<&str>,
+    ) -> Response {
+        let (mut parts, body) = response.into_parts();
+        // Taking the payload out lets the innermost layer decide, such as one configured for a single route
+        let erased = parts.extensions.remove::<ErasedNegotiate>();
+        let contextual = parts.extensions.remove::<context::ContextualNegotiate>();
+        if erased.is_none() && contextual.is_none() {
+            if let Some(outcome) = parts.extensions.get::<NegotiationOutcome>() {
+                tracing::debug!(
+                    encoding = %outcome.encoding,
+                    "response already negotiated by an inner layer, passing it through"
+                );
+                return Response::from_parts(parts, body);
+            }
+            if self.bypasses_negotiation(&parts, &body) {
+                tracing::error!(
+                    content_type = ?parts.headers.get(CONTENT_TYPE),
+                    "response bypassed content negotiation"
+                );
+                return self.reject(
+                    Rejection::Unnegotiated,
+                    Some(selection.encoding),
+                    request_id,
+                );
+            }
+            return Response::from_parts(parts, body);
+        }
+        let download = parts.extensions.remove::<download::DownloadName>();
+        let problem = parts.extensions.remove::<problem::Problem>();
+        let error_code = parts.extensions.remove::<error_codes::ErrorCode>();
+        let stored = parts.extensions.remove::<cache::Stored>();
 
-            let body = match encoding {
-                #[cfg(any(feature = "simd-json", feature = "json"))]
-                "application/json" => {
-                    let mut body = Vec::new();
-                    {
-                        let mut serializer = serde_json::Serializer::new(&mut body);
-                        let mut serializer = <dyn erased_serde::Serializer>::erase(&mut serializer);
-                        if let Err(e) = payload.erased_serialize(&mut serializer) {
-                            tracing::error!(error = %e, "failed to deserialize request body as json");
-
-                            let response: Response = (
-                                StatusCode::INTERNAL_SERVER_ERROR,
-                                "Failed to serialize response",
-                            )
-                                .into_response();
-                            return Ok(response);
-                        };
+        let not_acceptable = || {
+            let rejection = Rejection::NotAcceptable(Mismatch::Unsupported);
+            self.reject(rejection, None, request_id)
+        };
+        let mut unrepresentable: Vec<Encoding> = Encoding::ALL
+            .iter()
+            .copied()
+            .filter(|&encoding| {
+                // The callback of JSONP is only read from the query string when it was negotiated upfront
+                #[cfg(feature = "jsonp")]
+                if encoding == Encoding::Jsonp && callback.is_none() {
+                    return true;
+                }
+                match (&erased, &contextual) {
+                    (None, Some(context::ContextualNegotiate(payload))) => {
+                        !payload.encodes(encoding)
                     }
-                    body
+                    _ => !encoding.encodes(),
                 }
-                #[cfg(feature = "cbor")]
-                "application/cbor" => {
-                    let mut body = cbor4ii::core::utils::BufWriter::new(Vec::new());
-                    {
-                        let mut serializer = cbor4ii::serde::Serializer::new(&mut body);
-                        let mut serializer = <dyn erased_serde::Serializer>::erase(&mut serializer);
-                        if let Err(e) = payload.erased_serialize(&mut serializer) {
-                            tracing::error!(error = %e, "failed to deserialize request body as cbor");
-
-                            let response: Response = (
-                                StatusCode::INTERNAL_SERVER_ERROR,
-                                "Failed to serialize response",
-                            )
-                                .into_response();
-                            return Ok(response);
-                        }
+            })
+            .collect();
+        let selection = if unrepresentable.contains(&selection.encoding) {
+            let renegotiated = headers
+                .and_then(|headers| headers.negotiate_excluding(self, &unrepresentable).ok());
+            match renegotiated {
+                Some(renegotiated) => renegotiated,
+                None => {
+                    tracing::debug!(
+                        encoding = %selection.encoding,
+                        "response payload can't be encoded into any acceptable encoding"
+                    );
+                    return not_acceptable();
+                }
+            }
+        } else {
+            selection
+        };
+
+        let encode = |encoding: Encoding| {
+            let body = match (&erased, &contextual) {
+                (Some(ErasedNegotiate(payload)), _) => {
+                    match self.encode_inline(encoding, payload.as_ref()) {
+                        Some(body) => Ok(body),
+                        None => encoding.encode(payload.as_ref()).map(Bytes::from),
                     }
-                    body.into_inner()
                 }
-                _ => vec![],
+                (None, Some(context::ContextualNegotiate(payload))) => {
+                    payload.encode(encoding, &self.contexts).map(Bytes::from)
+                }
+                (None, None) => unreachable!("checked for a payload above"),
             };
+            #[cfg(feature = "geojson")]
+            if encoding == Encoding::GeoJson && self.decode_options.validate_geojson {
+                return body.and_then(|body| geojson::validate(&body).map(|()| body));
+            }
+            #[cfg(feature = "cbor")]
+            if encoding == Encoding::Cbor && self.cbor_profile == CborProfile::Deterministic {
+                return body.and_then(|body| encoding::deterministic_cbor(&body).map(Bytes::from));
+            }
+            body
+        };
+
+        let mut selection = selection;
+        let (
+            body,
+            negotiated,
+            Selection {
+                encoding,
+                media_type,
+            },
+        ) = 'negotiation: loop {
+            let negotiated = selection.encoding;
+            let fallbacks = self
+                .fallback_encodings
+                .iter()
+                .filter(|&&fallback| fallback != negotiated)
+                .map(|&fallback| Selection::from(fallback));
+            let mut candidates = std::iter::once(selection).chain(fallbacks).peekable();
+            loop {
+                let candidate = candidates
+                    .next()
+                    .expect("the negotiated encoding is always tried");
+                let encoding = candidate.encoding;
+                let encoded = || encode(encoding);
+                #[cfg(feature = "metrics")]
+                let encoded = metrics::time(
+                    self.codec_recorder.as_ref(),
+                    encoding,
+                    metrics::CodecOperation::Encode,
+                    encoded,
+                );
+                #[cfg(not(feature = "metrics"))]
+                let encoded = encoded();
+
+                match encoded {
+                    Ok(body) => break 'negotiation (body, negotiated, candidate),
+                    // Only some payloads fit these encodings, such as sequences of records, so the others negotiate again
+                    Err(e) if encoding == negotiated && encoding.shaped() => {
+                        tracing::debug!(error = %e, %encoding, "response payload can't be represented in the negotiated encoding");
+                        unrepresentable.push(encoding);
+                        if let Some(renegotiated) = headers.and_then(|headers| {
+                            headers.negotiate_excluding(self, &unrepresentable).ok()
+                        }) {
+                            selection = renegotiated;
+                            continue 'negotiation;
+                        }
+                        if candidates.peek().is_none() {
+                            return not_acceptable();
+                        }
+                    }
+                    Err(e) if candidates.peek().is_some() => {
+                        tracing::warn!(error = %e, %encoding, "failed to serialize response body, trying the next fallback encoding");
+                    }
+                    Err(e) => {
+                        tracing::error!(error = %e, %encoding, "failed to serialize response body");
+                        return self.reject(
+                            Rejection::SerializationFailed,
+                            Some(encoding),
+                            request_id,
+                        );
+                    }
+                }
+            }
+        };
+        #[cfg(feature = "jsonp")]
+        let body = match callback.filter(|_| encoding == Encoding::Jsonp) {
+            Some(callback) => {
+                parts.headers.insert(
+                    axum::http::header::X_CONTENT_TYPE_OPTIONS,
+                    HeaderValue::from_static("nosniff"),
+                );
+                jsonp::wrap(callback, &body)
+            }
+            None => body,
+        };
+        #[cfg(not(feature = "jsonp"))]
+        let _ = callback;
+
+        if let Some(limit) = self.max_response_size.filter(|&limit| body.len() > limit) {
+            tracing::error!(size = body.len(), limit, %encoding, "serialized response body is too large");
+            return self.reject(Rejection::ResponseTooLarge, Some(encoding), request_id);
+        }
+
+        if parts.status == StatusCode::UNSUPPORTED_MEDIA_TYPE {
+            parts.status = StatusCode::OK;
+        }
+        if let Some(error_codes::ErrorCode(code)) = error_code {
+            parts.status = self
+                .error_codes
+                .as_ref()
+                .map_or(StatusCode::INTERNAL_SERVER_ERROR, |codes| {
+                    codes.status(&code)
+                });
+        }
+        let media_type = match problem {
+            Some(problem::Problem(status)) => {
+                parts.status = status;
+                HeaderValue::from_static(problem::media_type(encoding))
+            }
+            None => media_type,
+        };
 
-            let (mut parts, _) = response.into_parts();
-            if parts.status == StatusCode::UNSUPPORTED_MEDIA_TYPE {
-                parts.status = StatusCode::OK;
+        // The serialized body replaces the placeholder of the handler, so a compression applied to it by inner layers no longer holds
+        parts.headers.remove(CONTENT_ENCODING);
+        if self.vary_accept {
+            vary_on_accept(&mut parts.headers);
+        }
+        if let Some(max_age) = self.max_age.filter(|_| parts.status.is_success()) {
+            if !parts.headers.contains_key(CACHE_CONTROL) {
+                parts
+                    .headers
+                    .insert(CACHE_CONTROL, cache::cache_control(max_age));
             }
+        }
+        if let Some(stored) = stored {
             parts
                 .headers
-                .insert(CONTENT_TYPE, HeaderValue::from_static(encoding));
-            parts.headers.remove(CONTENT_LENGTH);
+                .insert(AGE, stored.age(std::time::SystemTime::now));
+        }
 
-            Ok(Response::from_parts(parts, body.into()))
-        })
-    }
-}
+        let etag = self
+            .etag
+            .filter(|_| parts.status.is_success() && !parts.headers.contains_key(ETAG))
+            .map(|policy| {
+                let canonical = match policy {
+                    ETagPolicy::Weak => self.canonical_body(encoding, &body),
+                    ETagPolicy::Strong => None,
+                };
+                policy.etag(canonical.as_deref().unwrap_or(&body), &media_type)
+            });
+        if let Some(etag) = etag {
+            if if_none_match.is_some_and(|tags| etag::is_not_modified(tags, &etag)) {
+                parts.status = StatusCode::NOT_MODIFIED;
+                parts.headers.remove(CONTENT_LENGTH);
+                parts.headers.insert(ETAG, etag);
+                return Response::from_parts(parts, axum::body::Body::empty());
+            }
+            parts.headers.insert(ETAG, etag);
+        }
+        parts.extensions.insert(NegotiationOutcome {
+            encoding,
+            media_type: media_type.clone(),
+            fallback: encoding != negotiated,
+        });
+        parts.headers.insert(CONTENT_TYPE, media_type);
+        if let Some(disposition) = download.and_then(|name| name.content_disposition(encoding)) {
+            parts.headers.insert(CONTENT_DISPOSITION, disposition);
+        }
+        // Outer layers, such as compression, rely on an accurate length of the new body
+        parts
+            .headers
+            .insert(CONTENT_LENGTH, HeaderValue::from(body.len()));
 
-#[cfg(test)]
-mod test {
-    use crate::Negotiate;
+        if let Some(location) = location {
+            let alternates = Encoding::ALL
+                .iter()
+                .filter(|&&other| other != encoding && other.encodes() && self.negotiable(other));
+            for alternate in alternates {
+                let link = format!(
+                    "<{location}>; rel=\"alternate\"; type=\"{}\"",
+                    alternate.media_type()
+                );
+                if let Ok(link) = HeaderValue::try_from(link) {
+                    parts.headers.append(LINK, link);
+                }
+            }
+        }
 
-    use axum::{
-        body::Body,
-        http::{
-            header::{ACCEPT, CONTENT_LENGTH, CONTENT_TYPE},
-            Request, StatusCode,
-        },
-        response::IntoResponse,
-        routing::post,
-        Router,
-    };
-    use http_body_util::BodyExt;
-    use tower::ServiceExt;
+        Response::from_parts(parts, body.into())
+    }
 
-    use crate::NegotiateLayer;
+    /// Serialize small payloads on the stack, so only the final body is allocated.
+    fn encode_inline(
+        &self,
+        encoding: Encoding,
+        payload: &dyn erased_serde::Serialize,
+    ) -> Option<Bytes> {
+        if self.inline_response_size == 0 {
+            return None;
+        }
+        let mut buffer = [0; INLINE_RESPONSE_CAPACITY];
+        let len = encoding.encode_inline(payload, &mut buffer[..self.inline_response_size])?;
+        Some(Bytes::copy_from_slice(&buffer[..len]))
+    }
 
-    #[derive(Debug, serde::Serialize, serde::Deserialize)]
-    struct Example {
-        message: String,
+    /// Form of the serialized body independent of its encoding, so the JSON and CBOR bodies of a payload share weak validators.
+    ///
+    /// Bodies of encodings which can't be decoded back, such as HTML tables, have none and are hashed as sent.
+    fn canonical_body(&self, encoding: Encoding, body: &Bytes) -> Option<Vec<u8>> {
+        if !encoding.decodes() {
+            return None;
+        }
+        let value = encoding
+            .decode::<value::Value>(body.clone(), &self.decode_options)
+            .ok()?;
+        let mut canonical = Vec::new();
+        payload_hash::write_canonical(&value, &mut canonical);
+        Some(canonical)
     }
+}
 
-    fn content_length(headers: &axum::http::HeaderMap) -> usize {
-        headers
-            .get(CONTENT_LENGTH)
-            .map(|v| v.to_str().unwrap().parse::<usize>().unwrap())
-            .unwrap()
+/// Add `Accept` to the `Vary` header, merging the ones set by inner layers, such as `SetResponseHeaderLayer`, into a single header.
+///
+/// Outer layers, such as `CompressionLayer`, then find one header to extend.
+fn vary_on_accept(headers: &mut HeaderMap) {
+    if varies_on_accept(headers) {
+        return;
     }
+    let mut merged = Vec::new();
+    for value in headers.get_all(VARY) {
+        merged.extend_from_slice(value.as_bytes());
+        merged.extend_from_slice(b", ");
+    }
+    merged.extend_from_slice(b"accept");
+    if let Ok(merged) = HeaderValue::from_bytes(&merged) {
+        headers.insert(VARY, merged);
+    }
+}
 
-    mod general {
-        use super::*;
+/// Check if the `Vary` headers already list `Accept`, or `*` for responses varying on everything.
+fn varies_on_accept(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(VARY)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|name| name == "*" || name.eq_ignore_ascii_case("accept"))
+}
 
-        mod input {
-            use super::*;
+/// Largest buffer used to serialize small responses on the stack.
+const INLINE_RESPONSE_CAPACITY: usize = 1024;
 
-            #[tokio::test]
-            async fn test_does_not_process_handler_if_content_type_is_not_supported() {
-                #[axum::debug_handler]
-                async fn handler(_: Negotiate<Example>) -> impl IntoResponse {
-                    unimplemented!("This should not be called");
-                    #[allow(unreachable_code)]
-                    ()
-                }
+/// Layer responsible to convert a [Negotiate] response into the right serialization format based on the `Accept` header.
+///
+/// If the `Accept` header is not supported, it will return a 406 Not Acceptable response without running the handler.
+///
+/// When layers are nested, such as a [route_layer](axum::Router::route_layer) under a router-wide layer, the innermost one serializes the response
+/// and the outer ones pass it through untouched, so applying the layer twice by accident is harmless.
+///
+/// ## Example
+///
+/// ```rust
+/// use axum_content_negotiation::NegotiateLayer;
+///
+/// let layer = NegotiateLayer::new();
+/// ```
+#[derive(Clone)]
+pub struct NegotiateLayer<C = DirectCall> {
+    config: Arc<Config>,
+    call: PhantomData<C>,
+}
 
-                let app = Router::new()
-                    .route("/", post(handler))
-                    .layer(NegotiateLayer);
+/// Marker of a [NegotiateLayer] calling the wrapped service as soon as the request is negotiated, so any service can be wrapped.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DirectCall;
 
-                let response = app
-                    .oneshot(
-                        Request::builder()
-                            .uri("/")
-                            .header(CONTENT_TYPE, "non-supported")
-                            .method("POST")
-                            .body(Body::from("really-cool-format"))
-                            .unwrap(),
-                    )
-                    .await
+/// Marker of a [NegotiateLayer] awaiting before calling the wrapped service, such as to hash the request payload
+/// or to wait for a coalesced response, so the wrapped service must be `Clone + Send + 'static`.
+///
+/// Routers and handlers of `axum` already are, while custom services may need to derive [Clone],
+/// or to be wrapped with `tower::util::BoxCloneService`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DeferredCall;
+
+impl Default for NegotiateLayer {
+    fn default() -> Self {
+        Self {
+            config: Arc::default(),
+            call: PhantomData,
+        }
+    }
+}
+
+impl NegotiateLayer {
+    /// Create a layer using the encodings and defaults defined by the enabled features.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<C> NegotiateLayer<C> {
+    /// Keep the configuration, awaiting before calling the wrapped service.
+    fn deferred(self) -> NegotiateLayer<DeferredCall> {
+        NegotiateLayer {
+            config: self.config,
+            call: PhantomData,
+        }
+    }
+
+    /// Encodings this layer can negotiate, among the ones enabled by the features, as restricted by its configuration,
+    /// such as with [NegotiateLayer::only_encodings].
+    pub fn supported_encodings(&self) -> Vec<Encoding> {
+        Encoding::ALL
+            .iter()
+            .copied()
+            .filter(|&encoding| self.config.negotiable(encoding))
+            .collect()
+    }
+
+    /// Media types this layer can negotiate, useful to assert at startup that the compiled features and the configuration of the layer
+    /// match the API contract.
+    ///
+    /// ```rust,no_run
+    /// use axum_content_negotiation::NegotiateLayer;
+    ///
+    /// let layer = NegotiateLayer::new();
+    /// assert!(
+    ///     layer.supported_media_types().any(|media_type| media_type == "application/json"),
+    ///     "JSON support must be compiled in"
+    /// );
+    /// ```
+    pub fn supported_media_types(&self) -> impl Iterator<Item = &'static str> {
+        self.supported_encodings()
+            .into_iter()
+            .map(Encoding::media_type)
+    }
+
+    /// Describe the media types negotiated by this layer, such as to document the route it is applied on.
+    ///
+    /// ```rust
+    /// use std::collections::BTreeMap;
+    ///
+    /// use axum_content_negotiation::NegotiateLayer;
+    ///
+    /// let orders = NegotiateLayer::new();
+    /// let documentation = BTreeMap::from([("/orders", orders.metadata())]);
+    /// // Serve it with Negotiate(documentation), or merge it into an OpenAPI document
+    /// ```
+    pub fn metadata(&self) -> NegotiationMetadata {
+        NegotiationMetadata::new(&self.config)
+    }
+
+    /// Media type used when the request has no `Content-Type`/`Accept` header, or accepts `*/*`.
+    ///
+    /// Returns [None] when there is no default encoding.
+    pub fn default_media_type(&self) -> Option<&'static str> {
+        self.config
+            .matcher
+            .default_encoding
+            .map(Encoding::media_type)
+    }
+
+    /// Encoding used when the request has no `Content-Type`/`Accept` header, or accepts `*/*`.
+    ///
+    /// Defaults to [Encoding::compiled_default], which is defined by the `default-*` features.
+    pub fn default_encoding(mut self, encoding: Encoding) -> Self {
+        Arc::make_mut(&mut self.config).matcher.default_encoding = Some(encoding);
+        self
+    }
+
+    /// Never guess the encoding, requiring requests to inform explicit `Content-Type` and `Accept` headers.
+    ///
+    /// Requests without them, or accepting only `*/*`, are rejected instead of using a default encoding.
+    /// Subtype wildcards such as `application/*` still answer the first enabled encoding of the type, following [NegotiateLayer::prefer_encodings].
+    /// This is also the behavior when no `default-*` feature is enabled.
+    pub fn no_default_encoding(mut self) -> Self {
+        Arc::make_mut(&mut self.config).matcher.default_encoding = None;
+        self
+    }
+
+    /// Negotiate an alternative spelling of a media type as the encoding, such as `text/json` for JSON.
+    ///
+    /// Responses always use the canonical media type of the encoding.
+    /// A few common aliases are registered by default, like `text/json` and `application/x-cbor`.
+    pub fn media_type_alias(mut self, alias: impl Into<String>, encoding: Encoding) -> Self {
+        let alias = alias.into();
+        let aliases = &mut Arc::make_mut(&mut self.config).matcher.aliases;
+        aliases.retain(|(existing, _)| !existing.eq_ignore_ascii_case(&alias));
+        aliases.push((alias, encoding));
+        self
+    }
+
+    /// Negotiate a media type of the API as a first-class type encoded with the codec of the encoding,
+    /// such as `application/vnd.example.order.v2+cbor`, echoing it on the response `Content-Type`.
+    ///
+    /// Unlike [NegotiateLayer::suffix_family], the media type doesn't need the suffix of the encoding,
+    /// and unlike [NegotiateLayer::media_type_alias], responses are answered with it instead of the canonical media type.
+    /// Media types are compared case-insensitively, and registering one again binds it to the new encoding.
+    ///
+    /// ```rust
+    /// # #[cfg(feature = "cbor")] {
+    /// use axum_content_negotiation::{Encoding, NegotiateLayer};
+    ///
+    /// let layer = NegotiateLayer::new()
+    ///     .register_media_type("application/vnd.example.order.v2+cbor", Encoding::Cbor)
+    ///     .register_media_type("application/vnd.example.order.v2", Encoding::Cbor);
+    /// # }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the media type is not a valid `type/subtype`, without parameters.
+    pub fn register_media_type(
+        mut self,
+        media_type: impl Into<String>,
+        encoding: Encoding,
+    ) -> Self {
+        let media_type = media_type.into().to_ascii_lowercase();
+        assert!(
+            media_type::is_valid(&media_type),
+            "invalid media type {media_type:?}"
+        );
+        let media_types = &mut Arc::make_mut(&mut self.config).matcher.media_types;
+        media_types.retain(|(existing, _)| *existing != media_type);
+        media_types.push((media_type, encoding));
+        self
+    }
+
+    /// Configure which vendor media types with the encoding suffix are negotiated with this encoding.
+    ///
+    /// By default no vendor type is negotiated, only the base media type of each encoding.
+    ///
+    /// ```rust
+    /// # #[cfg(any(feature = "simd-json", feature = "json"))] {
+    /// use axum_content_negotiation::{Encoding, NegotiateLayer, SuffixFamily};
+    ///
+    /// let layer = NegotiateLayer::new().suffix_family(
+    ///     Encoding::Json,
+    ///     SuffixFamily::only(["application/vnd.example.order+json"]),
+    /// );
+    /// # }
+    /// ```
+    pub fn suffix_family(mut self, encoding: Encoding, family: SuffixFamily) -> Self {
+        let families = &mut Arc::make_mut(&mut self.config).matcher.families;
+        families.retain(|(claimed, _)| *claimed != encoding);
+        families.push((encoding, family));
+        self
+    }
+
+    /// Negotiate every vendor media type with the `+json` or `+cbor` structured syntax suffix, such as `application/vnd.example.thing+json`,
+    /// using the codec of the suffix for requests and responses, and echoing the vendor type as the response `Content-Type`.
+    ///
+    /// Shorthand for [NegotiateLayer::suffix_family] with [SuffixFamily::any] on the enabled JSON and CBOR encodings.
+    pub fn vendor_media_types(self) -> Self {
+        let encodings = [
+            #[cfg(any(feature = "simd-json", feature = "json"))]
+            Encoding::Json,
+            #[cfg(feature = "cbor")]
+            Encoding::Cbor,
+        ];
+        encodings.into_iter().fold(self, |layer, encoding| {
+            layer.suffix_family(encoding, SuffixFamily::any())
+        })
+    }
+
+    /// Limit how many request bodies of at least `threshold` bytes are buffered and deserialized concurrently by the [Negotiate] extractor.
+    ///
+    /// Requests over the limit wait for a permit before reading the body, protecting memory under bursty uploads of big payloads.
+    /// The permit is held until the body is deserialized, or until a [LazyNegotiate] is dropped.
+    /// Requests without a body never wait, while bodies without a `Content-Length` header, such as chunked uploads,
+    /// are considered large. HTTP/2 requests without a `Content-Length` always count as large, as their size is only known once received.
+    ///
+    /// Requires the `tokio` feature.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `permits` is zero, as large bodies would wait forever for a permit.
+    #[cfg(feature = "tokio")]
+    pub fn limit_large_decodes(mut self, threshold: usize, permits: usize) -> Self {
+        assert!(permits > 0, "large decodes need at least one permit");
+        Arc::make_mut(&mut self.config).decode_guard =
+            Some(limit::DecodeGuard::new(threshold, permits));
+        self
+    }
+
+    /// Limit how long the [Negotiate] extractor waits to receive the whole request body, answering 408 Request Timeout once it elapses.
+    ///
+    /// Slow uploads are dropped instead of pinning handler tasks indefinitely.
+    ///
+    /// Requires the `tokio` feature.
+    #[cfg(feature = "tokio")]
+    pub fn body_read_timeout(mut self, timeout: std::time::Duration) -> Self {
+        Arc::make_mut(&mut self.config).body_timeout = Some(timeout);
+        self
+    }
+
+    /// Run the handler once for concurrent identical `GET` requests, answering all of them with a copy of its serialized response.
+    ///
+    /// Requests are identical when they target the same URI with the same negotiated media type, `Authorization`, `Cookie` and `If-None-Match`,
+    /// so routes whose responses depend on other headers should not be coalesced. Only responses serialized by the layer are shared,
+    /// while the waiting requests run the handler themselves after responses passed through, such as streams,
+    /// or private to the client, with a `Set-Cookie` or a `Cache-Control` of `private` or `no-store`.
+    ///
+    /// Requires the `tokio` feature.
+    ///
+    /// Waiting requests call the wrapped service later, so it must be `Clone + Send + 'static`, as explained on [DeferredCall].
+    #[cfg(feature = "tokio")]
+    pub fn coalesce_gets(mut self) -> NegotiateLayer<DeferredCall> {
+        Arc::make_mut(&mut self.config).coalescer = Some(Arc::default());
+        self.deferred()
+    }
+
+    /// Record the time spent deserializing request bodies and serializing responses, separately per encoding.
+    ///
+    /// The callback runs after every codec use, usually forwarding the measurement to a metrics or OpenTelemetry histogram.
+    ///
+    /// Requires the `metrics` feature.
+    ///
+    /// ```rust
+    /// use axum_content_negotiation::{metrics::CodecTiming, NegotiateLayer};
+    ///
+    /// let layer = NegotiateLayer::new().record_codec_time(|timing: CodecTiming| {
+    ///     tracing::info!(encoding = %timing.encoding, operation = ?timing.operation, elapsed = ?timing.elapsed);
+    /// });
+    /// ```
+    #[cfg(feature = "metrics")]
+    pub fn record_codec_time<F>(mut self, record: F) -> Self
+    where
+        F: Fn(metrics::CodecTiming) + Send + Sync + 'static,
+    {
+        Arc::make_mut(&mut self.config).codec_recorder = Some(metrics::Recorder(Arc::new(record)));
+        self
+    }
+
+    /// Record the names and types of the top-level fields of one out of every `every` decoded request payloads, per route.
+    ///
+    /// Values are never recorded, so the samples tell which fields clients still send, such as before deprecating one,
+    /// without leaking their data. Sampled payloads are decoded once more, and payloads which are not maps with text keys are skipped.
+    /// The route is the one matched by axum, such as `/users/{id}`, so it is available when the layer wraps a [Router](axum::Router).
+    ///
+    /// ```rust
+    /// use axum_content_negotiation::{audit::SchemaSample, NegotiateLayer};
+    ///
+    /// let layer = NegotiateLayer::new().audit_schemas(100, |sample: SchemaSample| {
+    ///     for (name, kind) in sample.fields {
+    ///         tracing::info!(route = ?sample.route, encoding = %sample.encoding, field = name, %kind);
+    ///     }
+    /// });
+    /// ```
+    pub fn audit_schemas<F>(mut self, every: u32, record: F) -> Self
+    where
+        F: Fn(audit::SchemaSample) + Send + Sync + 'static,
+    {
+        Arc::make_mut(&mut self.config).schema_auditor =
+            Some(audit::SchemaAuditor::new(every, record));
+        self
+    }
+
+    /// How to treat a request with an empty or whitespace-only `Accept` header.
+    ///
+    /// Defaults to [EmptyAccept::Default], using the default encoding as if the header was missing.
+    pub fn empty_accept(mut self, behavior: EmptyAccept) -> Self {
+        Arc::make_mut(&mut self.config).empty_accept = behavior;
+        self
+    }
+
+    /// Reject requests with a body but no `Content-Type` header with 415 Unsupported Media Type, instead of decoding them with the default encoding.
+    ///
+    /// Guessing the format of a body lets it be read differently than intermediaries like WAFs inspected it, so APIs wary of content confusion should require it.
+    /// Requests without a body, such as most `GET` requests, are not affected.
+    pub fn require_content_type(mut self) -> Self {
+        Arc::make_mut(&mut self.config).require_content_type = true;
+        self
+    }
+
+    /// Answer the default encoding when the `Accept` header lists nothing supported, instead of rejecting the request with 406 Not Acceptable.
+    ///
+    /// RFC 9110 allows disregarding the header this way, which public-facing APIs may prefer over refusing badly-behaved clients.
+    /// Requests are still rejected when there is no default encoding, and clients can tell the format they got from the `Content-Type`.
+    pub fn lenient(mut self) -> Self {
+        Arc::make_mut(&mut self.config).lenient = true;
+        self
+    }
+
+    /// Advertise the other representations of negotiated responses with `Link: <url>; rel="alternate"; type="..."` headers.
+    ///
+    /// Clients can use them to prefetch the formats they typically request next, and proxies supporting it can forward them as 103 Early Hints.
+    /// Servers can't send HTTP/2 push or informational responses through [Service], so the headers are only on the final response.
+    /// Only the formats this layer can answer are listed, skipping the ones disabled by its configuration, such as [NegotiateLayer::only_encodings].
+    pub fn link_alternates(mut self) -> Self {
+        Arc::make_mut(&mut self.config).link_alternates = true;
+        self
+    }
+
+    /// Don't append `Vary: Accept` to negotiated responses, such as for services behind a cache keyed on the URL alone.
+    ///
+    /// By default the header is added, unless the response already varies on `Accept` or `*`.
+    pub fn no_vary_accept(mut self) -> Self {
+        Arc::make_mut(&mut self.config).vary_accept = false;
+        self
+    }
+
+    /// Inform `Cache-Control: max-age=...` on successful negotiated responses, unless the handler set its own `Cache-Control`.
+    ///
+    /// Responses are not marked `public`, so shared caches don't store the ones answered to requests with an `Authorization`,
+    /// unless the handler informs its own `Cache-Control`.
+    /// Use a [route_layer](axum::Router::route_layer) for routes with their own freshness, and [NegotiateCached] for responses
+    /// served from an in-process cache, so edge caches account for the time they were already kept.
+    pub fn max_age(mut self, max_age: std::time::Duration) -> Self {
+        Arc::make_mut(&mut self.config).max_age = Some(max_age);
+        self
+    }
+
+    /// Provide a context used to serialize [NegotiateWith] responses, such as the currency or locale of the deployment.
+    ///
+    /// Contexts are looked up by type, so different types can be provided by calling this multiple times.
+    pub fn serialization_context<Ctx>(mut self, context: Ctx) -> Self
+    where
+        Ctx: Clone + Send + Sync + 'static,
+    {
+        Arc::make_mut(&mut self.config).contexts.insert(context);
+        self
+    }
+
+    /// Limit the size of request bodies read by the extractors, answering 413 Content Too Large to bodies over `bytes`.
+    ///
+    /// It replaces the [DefaultBodyLimit](axum::extract::DefaultBodyLimit) of the routes. Bodies declaring a larger `Content-Length` are rejected
+    /// before being read, and bodies without one, such as chunked uploads, are rejected as soon as they stream past the limit.
+    pub fn max_request_size(mut self, bytes: usize) -> Self {
+        Arc::make_mut(&mut self.config).max_request_size = Some(bytes);
+        self
+    }
+
+    /// Size limit effectively applied while reading request bodies.
+    pub fn request_body_limit(&self) -> RequestBodyLimit {
+        match self.config.max_request_size {
+            Some(bytes) => RequestBodyLimit::Bytes(bytes),
+            None => RequestBodyLimit::Axum,
+        }
+    }
+
+    /// Limit the size of serialized response bodies, answering 507 Insufficient Storage instead of sending payloads over `bytes`.
+    ///
+    /// Responses are fully serialized before being sent, so a payload failing to serialize or going over the limit never produces a partial body.
+    pub fn max_response_size(mut self, bytes: usize) -> Self {
+        Arc::make_mut(&mut self.config).max_response_size = Some(bytes);
+        self
+    }
+
+    /// Negotiate only the encodings, among the enabled ones, rejecting the others as disabled on this service.
+    ///
+    /// Features are global to the binary, so routes can be restricted with a [route_layer](axum::Router::route_layer),
+    /// such as internal routes accepting only CBOR while the rest of the router keeps every format.
+    /// Outer layers still negotiate first, so the encodings must be allowed by them too.
+    /// The default encoding is replaced by the first allowed one when it is not allowed.
+    ///
+    /// ```rust
+    /// use axum::{routing::post, Router};
+    /// use axum_content_negotiation::{Encoding, NegotiateLayer};
+    ///
+    /// # #[cfg(feature = "cbor")]
+    /// let router: Router<()> = Router::new()
+    ///     .route("/internal/sync", post(|| async {}))
+    ///     .route_layer(NegotiateLayer::new().only_encodings([Encoding::Cbor]))
+    ///     .route("/orders", post(|| async {}))
+    ///     .layer(NegotiateLayer::new());
+    /// ```
+    pub fn only_encodings(mut self, encodings: impl IntoIterator<Item = Encoding>) -> Self {
+        Arc::make_mut(&mut self.config).allowed_encodings = Some(encodings.into_iter().collect());
+        self
+    }
+
+    /// Encodings tried in order when the negotiated one fails to serialize the response, instead of answering 500 Internal Server Error.
+    ///
+    /// Types can support only some formats by failing to serialize on the others, such as with [SerializeFor] returning an error.
+    /// The response informs the `Content-Type` of the encoding that succeeded, even if the request didn't accept it.
+    pub fn fallback_encodings(mut self, encodings: impl IntoIterator<Item = Encoding>) -> Self {
+        Arc::make_mut(&mut self.config).fallback_encodings = encodings.into_iter().collect();
+        self
+    }
+
+    /// Break ties between media ranges of the same quality with the server preference, listing encodings from the most preferred,
+    /// such as CBOR over JSON to save bandwidth.
+    ///
+    /// Without it, ties go to the most specific range, then to the first one listed, so `application/json, application/cbor` answers JSON.
+    /// Encodings missing from the list are ranked after the listed ones.
+    pub fn prefer_encodings(mut self, encodings: impl IntoIterator<Item = Encoding>) -> Self {
+        Arc::make_mut(&mut self.config).matcher.preference = encodings.into_iter().collect();
+        self
+    }
+
+    /// Serialize [Negotiate] responses of up to `bytes` on the stack first, copying them to the body with a single allocation.
+    ///
+    /// Useful on ping-like routes answering tiny payloads at a high rate, such as with a [route_layer](axum::Router::route_layer).
+    /// Larger responses are serialized again on the heap, so the threshold should be close to the expected size. It is capped at 1 KiB.
+    /// Only JSON and CBOR use the stack buffer.
+    pub fn inline_response_size(mut self, bytes: usize) -> Self {
+        Arc::make_mut(&mut self.config).inline_response_size = bytes.min(INLINE_RESPONSE_CAPACITY);
+        self
+    }
+
+    /// Decode request bodies of extra media types using [BodyDecoders], which can keep being registered after the router is built.
+    ///
+    /// Keep a clone of the decoders, such as on the router `State`, to register new media types at runtime.
+    pub fn body_decoders(mut self, decoders: BodyDecoders) -> Self {
+        Arc::make_mut(&mut self.config).body_decoders = Some(decoders);
+        self
+    }
+
+    /// Pin the layout of CBOR responses, such as with [CborProfile::Deterministic] for clients comparing digests of cached responses.
+    ///
+    /// Defaults to [CborProfile::Backend]. Deterministic responses are re-encoded after being serialized, which takes extra time.
+    #[cfg(feature = "cbor")]
+    pub fn cbor_profile(mut self, profile: CborProfile) -> Self {
+        Arc::make_mut(&mut self.config).cbor_profile = profile;
+        self
+    }
+
+    /// Read `text/csv` request bodies with another delimiter, or without a header line.
+    ///
+    /// Defaults to [CsvOptions::new], reading comma-separated records by the field names on their first line.
+    #[cfg(feature = "csv")]
+    pub fn csv_options(mut self, options: CsvOptions) -> Self {
+        Arc::make_mut(&mut self.config).decode_options.csv = options;
+        self
+    }
+
+    /// Check `application/geo+json` payloads are GeoJSON objects, such as a `Feature` with its `geometry` and `properties`.
+    ///
+    /// Invalid request bodies are rejected as undecodable, and invalid responses fail to serialize, so map clients never get a layer they can't render.
+    #[cfg(feature = "geojson")]
+    pub fn validate_geojson(mut self) -> Self {
+        Arc::make_mut(&mut self.config)
+            .decode_options
+            .validate_geojson = true;
+        self
+    }
+
+    /// Inform an `ETag` on successful negotiated responses, answering 304 Not Modified to `GET` and `HEAD` requests whose `If-None-Match` matches it.
+    ///
+    /// [ETagPolicy::Weak] validators are shared by every representation of the same payload, while [ETagPolicy::Strong] ones are unique to each.
+    /// Responses already informing an `ETag` keep theirs.
+    pub fn etag(mut self, policy: ETagPolicy) -> Self {
+        Arc::make_mut(&mut self.config).etag = Some(policy);
+        self
+    }
+
+    /// Parse the `Content-Type` parameters of requests, such as `application/json; schema=2024-06`, into a value stored on the request extensions.
+    ///
+    /// Handlers can read it with an [Extension] extractor to decode multiple versions of a body,
+    /// or [BodySeed]s can be built from it. Parameter names are provided as sent, so they should be compared case-insensitively.
+    ///
+    /// ```rust
+    /// use axum::{routing::post, Extension, Router};
+    /// use axum_content_negotiation::{Negotiate, NegotiateLayer};
+    ///
+    /// #[derive(Clone)]
+    /// struct SchemaVersion(String);
+    ///
+    /// #[derive(serde::Deserialize)]
+    /// struct Order {
+    ///     id: u32,
+    /// }
+    ///
+    /// async fn handler(
+    ///     Extension(SchemaVersion(version)): Extension<SchemaVersion>,
+    ///     Negotiate(order): Negotiate<Order>,
+    /// ) -> String {
+    ///     format!("order {} on schema {version}", order.id)
+    /// }
+    ///
+    /// let router: Router = Router::new().route("/", post(handler)).layer(
+    ///     NegotiateLayer::new().content_type_parameters(|parameters| {
+    ///         let version = parameters
+    ///             .iter()
+    ///             .find(|(name, _)| name.eq_ignore_ascii_case("schema"))
+    ///             .map_or("2024-01", |(_, version)| version);
+    ///         Some(SchemaVersion(version.to_string()))
+    ///     }),
+    /// );
+    /// ```
+    pub fn content_type_parameters<F, V>(mut self, parse: F) -> Self
+    where
+        F: Fn(&[(&str, &str)]) -> Option<V> + Send + Sync + 'static,
+        V: Clone + Send + Sync + 'static,
+    {
+        let hook = move |parameters: &[(&str, &str)], extensions: &mut axum::http::Extensions| {
+            if let Some(value) = parse(parameters) {
+                extensions.insert(value);
+            }
+        };
+        Arc::make_mut(&mut self.config).parameter_hook =
+            Some(media_type::ParameterHook(Arc::new(hook)));
+        self
+    }
+
+    /// Hash the canonical form of request payloads, storing the result as a [PayloadHash] on the request extensions.
+    ///
+    /// The same logical payload hashes the same whether it is sent as JSON or CBOR, or with its map keys in another order,
+    /// so idempotency-key middleware under the layer can tell retries from conflicting requests before the handler runs.
+    /// The layer buffers the body to hash it, within [max_request_size](NegotiateLayer::max_request_size) or the default limit of axum.
+    /// Payloads that can't be decoded are not hashed, and are rejected by the extractors as usual.
+    ///
+    /// The wrapped service is called once the body is hashed, so it must be `Clone + Send + 'static`, as explained on [DeferredCall].
+    ///
+    /// ```rust
+    /// use std::hash::{DefaultHasher, Hasher};
+    /// use axum::{routing::post, Extension, Router};
+    /// use axum_content_negotiation::{NegotiateLayer, PayloadHash};
+    ///
+    /// async fn handler(Extension(PayloadHash(hash)): Extension<PayloadHash<u64>>) -> String {
+    ///     format!("{hash:x}")
+    /// }
+    ///
+    /// let router: Router = Router::new().route("/", post(handler)).layer(
+    ///     // Usually a cryptographic hash, such as SHA-256
+    ///     NegotiateLayer::new().payload_hash(|canonical| {
+    ///         let mut hasher = DefaultHasher::new();
+    ///         hasher.write(canonical);
+    ///         hasher.finish()
+    ///     }),
+    /// );
+    /// ```
+    pub fn payload_hash<F, H>(mut self, hasher: F) -> NegotiateLayer<DeferredCall>
+    where
+        F: Fn(&[u8]) -> H + Send + Sync + 'static,
+        H: Clone + Send + Sync + 'static,
+    {
+        let hook = move |canonical: &[u8], extensions: &mut axum::http::Extensions| {
+            extensions.insert(PayloadHash(hasher(canonical)));
+        };
+        Arc::make_mut(&mut self.config).payload_hash =
+            Some(payload_hash::PayloadHasher(Arc::new(hook)));
+        self.deferred()
+    }
+
+    /// Never answer some encodings to the clients registered on [ClientQuirks], even if their `Accept` header claims support,
+    /// centralizing the compatibility matrix of old SDKs and partners.
+    pub fn client_quirks(mut self, quirks: ClientQuirks) -> Self {
+        Arc::make_mut(&mut self.config).client_quirks = Some(quirks);
+        self
+    }
+
+    /// Answer `application/javascript` requests with JSONP, wrapping the JSON body in the callback named by the query `parameter`,
+    /// such as `callback` for `/orders?callback=render`, for legacy clients unable to use CORS.
+    ///
+    /// Requests negotiating JSONP without a valid callback are answered 400 Bad Request.
+    /// Without this option, `application/javascript` is never negotiated.
+    ///
+    /// Requires the `jsonp` feature.
+    #[cfg(feature = "jsonp")]
+    pub fn jsonp(mut self, parameter: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.config).jsonp_callback = Some(parameter.into());
+        self
+    }
+
+    /// Answer 500 Internal Server Error to responses with a body which bypassed the negotiation, such as an `axum::Json` returned by mistake.
+    ///
+    /// Meant for development and test builds, to catch handlers of negotiated routes which don't use [Negotiate].
+    /// Responses with a media type from `allowed`, such as `text/event-stream`, and the rejections of the crate are let through.
+    ///
+    /// ```rust
+    /// use axum_content_negotiation::NegotiateLayer;
+    ///
+    /// let layer = if cfg!(debug_assertions) {
+    ///     NegotiateLayer::new().strict_negotiation(["text/html"])
+    /// } else {
+    ///     NegotiateLayer::new()
+    /// };
+    /// ```
+    pub fn strict_negotiation<I>(mut self, allowed: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        Arc::make_mut(&mut self.config).strict =
+            Some(allowed.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Serialize a [Negotiate] response produced outside of this layer, such as the challenge of an authentication layer wrapping it.
+    ///
+    /// The format is negotiated with the request headers, and the response status and headers, such as `WWW-Authenticate`, are kept.
+    /// When the request accepts none of the enabled formats, the default encoding is used, or the first enabled one without a default.
+    ///
+    /// ```rust
+    /// use axum::{
+    ///     extract::Request,
+    ///     http::{header::WWW_AUTHENTICATE, StatusCode},
+    ///     middleware::Next,
+    ///     response::{IntoResponse, Response},
+    /// };
+    /// use axum_content_negotiation::{Negotiate, NegotiateLayer};
+    ///
+    /// #[derive(serde::Serialize)]
+    /// struct Denied {
+    ///     reason: &'static str,
+    /// }
+    ///
+    /// async fn auth(request: Request, next: Next) -> Response {
+    ///     if request.headers().contains_key("authorization") {
+    ///         return next.run(request).await;
+    ///     }
+    ///
+    ///     let challenge = (
+    ///         StatusCode::UNAUTHORIZED,
+    ///         [(WWW_AUTHENTICATE, "Bearer")],
+    ///         Negotiate(Denied { reason: "missing credentials" }),
+    ///     );
+    ///     NegotiateLayer::new().negotiate_response(request.headers(), challenge.into_response())
+    /// }
+    /// ```
+    pub fn negotiate_response(&self, request: &HeaderMap, response: Response) -> Response {
+        let selection = request.negotiate(&self.config).ok();
+        // The callback of JSONP is on the query string, which is not available here
+        #[cfg(feature = "jsonp")]
+        let selection = selection.filter(|selection| selection.encoding != Encoding::Jsonp);
+        let selection = selection.or_else(|| {
+            self.config
+                .matcher
+                .default_encoding
+                .or_else(|| Encoding::ALL.first().copied())
+                .map(Selection::from)
+        });
+        let Some(selection) = selection else {
+            return response;
+        };
+
+        let request_id = error::request_id(request);
+        self.config.encode_response(
+            response,
+            selection,
+            Some(request),
+            request_id.as_deref(),
+            None,
+            None,
+            None,
+        )
+    }
+
+    /// Layer rewriting the `Accept` header of requests sent to upstream services to the media types this layer can decode,
+    /// for services proxying and transcoding their payloads.
+    ///
+    /// The media types follow the configuration of this layer, including its aliases and vendor types.
+    ///
+    /// ```rust
+    /// use axum_content_negotiation::NegotiateLayer;
+    ///
+    /// let negotiate = NegotiateLayer::new();
+    /// // Wrap the tower service of the upstream client with it
+    /// let downgrade = negotiate.downgrade_accept();
+    /// ```
+    pub fn downgrade_accept(&self) -> AcceptDowngradeLayer {
+        AcceptDowngradeLayer::new(self.config.clone())
+    }
+
+    /// Serve the format named by the query parameter, such as `?format=cbor`, over the `Accept` header,
+    /// so browsers and webhook configuration UIs can force a format without setting headers.
+    ///
+    /// Formats are named by their [Encoding::extension], and unknown names are rejected with 406 Not Acceptable.
+    /// Requests without the parameter are negotiated as usual.
+    pub fn format_parameter(mut self, parameter: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.config).format_parameter = Some(parameter.into());
+        self
+    }
+
+    /// Layer serving the format named by an extension on the request path, such as `/orders.json` or `/orders.cbor`, over the `Accept` header,
+    /// for clients unable to set headers but able to change URLs.
+    ///
+    /// The extension is stripped from the path, so it must wrap the router for the requests to be routed without it.
+    /// Extensions follow [Encoding::extension], picking the first encoding answered by this layer when several share one.
+    ///
+    /// ```rust
+    /// use axum::{extract::Request, routing::get, Router, ServiceExt};
+    /// use axum_content_negotiation::NegotiateLayer;
+    /// use tower::Layer;
+    ///
+    /// let negotiate = NegotiateLayer::new();
+    /// let router: Router<()> = Router::new()
+    ///     .route("/orders", get(|| async { "orders" }))
+    ///     .layer(negotiate.clone());
+    /// let app = negotiate.path_extensions().layer(router);
+    /// # let _ = ServiceExt::<Request>::into_make_service(app);
+    /// ```
+    pub fn path_extensions(&self) -> PathExtensionLayer {
+        PathExtensionLayer::new(self.config.clone())
+    }
+
+    /// Build the body of every rejection produced by the crate, instead of the default plain text messages.
+    ///
+    /// The returned value is serialized using the negotiated encoding, falling back to the default encoding when the `Accept` header is not supported.
+    ///
+    /// ```rust
+    /// use axum_content_negotiation::NegotiateLayer;
+    ///
+    /// #[derive(serde::Serialize)]
+    /// struct ApiError {
+    ///     code: &'static str,
+    ///     message: &'static str,
+    ///     request_id: Option<String>,
+    /// }
+    ///
+    /// let layer = NegotiateLayer::new().error_body(|details| ApiError {
+    ///     code: details.code,
+    ///     message: details.message,
+    ///     request_id: details.request_id.map(str::to_string),
+    /// });
+    /// ```
+    pub fn error_body<F, B>(mut self, template: F) -> Self
+    where
+        F: Fn(&ErrorDetails<'_>) -> B + Send + Sync + 'static,
+        B: serde::Serialize + Send + Sync + 'static,
+    {
+        Arc::make_mut(&mut self.config).error_template = Some(error::ErrorTemplate {
+            build: Arc::new(move |details| template(details).into()),
+            media_type: Encoding::media_type,
+        });
+        self
+    }
+
+    /// Build the body of every rejection produced by the crate as the problem details of RFC 9457,
+    /// informed as `application/problem+json` or `application/problem+cbor`.
+    ///
+    /// The rejection code, such as `malformed_body`, and the `x-request-id` of the request are informed as the `code` and `request_id` members.
+    /// Handlers can answer their own errors with [ProblemDetails].
+    pub fn problem_details(mut self) -> Self {
+        Arc::make_mut(&mut self.config).error_template =
+            Some(error::ErrorTemplate::problem_details());
+        self
+    }
+
+    /// Answer [ApiError] responses with the status mapped to their code, the same way in every encoding.
+    ///
+    /// Without a table, every [ApiError] is answered 500 Internal Server Error.
+    ///
+    /// ```rust
+    /// use axum::http::StatusCode;
+    /// use axum_content_negotiation::{ErrorCodes, NegotiateLayer};
+    ///
+    /// let layer = NegotiateLayer::new().error_codes(
+    ///     ErrorCodes::new()
+    ///         .code("not_found", StatusCode::NOT_FOUND)
+    ///         .code("conflict", StatusCode::CONFLICT),
+    /// );
+    /// ```
+    pub fn error_codes(mut self, codes: ErrorCodes) -> Self {
+        Arc::make_mut(&mut self.config).error_codes = Some(codes);
+        self
+    }
+}
+
+impl<S, C> tower::Layer<S> for NegotiateLayer<C> {
+    type Service = NegotiateService<S, C>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        NegotiateService {
+            inner,
+            config: self.config.clone(),
+            call: PhantomData,
+        }
+    }
+}
+
+trait AcceptExt {
+    fn negotiate(&self, config: &Config) -> Result<Selection, Rejection> {
+        self.negotiate_excluding(config, &[])
+    }
+
+    /// Negotiate without some of the enabled encodings, such as the ones a response payload can't be encoded into.
+    fn negotiate_excluding(
+        &self,
+        config: &Config,
+        unrepresentable: &[Encoding],
+    ) -> Result<Selection, Rejection>;
+}
+
+impl AcceptExt for HeaderMap {
+    fn negotiate_excluding(
+        &self,
+        config: &Config,
+        unrepresentable: &[Encoding],
+    ) -> Result<Selection, Rejection> {
+        let mut excluded = config
+            .client_quirks
+            .as_ref()
+            .map(|quirks| quirks.excluded(self))
+            .unwrap_or_default();
+        excluded.extend(config.disabled_encodings());
+        excluded.extend_from_slice(unrepresentable);
+        let inferred = config
+            .client_quirks
+            .as_ref()
+            .and_then(|quirks| quirks.inferred(self));
+        let default = || {
+            config
+                .matcher
+                .default_excluding(&excluded, inferred)
+                .map(Selection::from)
+                .ok_or(Rejection::NotAcceptable(Mismatch::Invalid))
+        };
+
+        let Some(accept) = self.get(ACCEPT) else {
+            return default();
+        };
+        let Ok(accept) = accept.to_str() else {
+            return if config.lenient {
+                default()
+            } else {
+                Err(Rejection::NotAcceptable(Mismatch::Invalid))
+            };
+        };
+
+        if accept.trim().is_empty() {
+            return match config.empty_accept {
+                EmptyAccept::Default => default(),
+                EmptyAccept::BadRequest => Err(Rejection::EmptyAccept),
+                EmptyAccept::NotAcceptable => Err(Rejection::NotAcceptable(Mismatch::Invalid)),
+            };
+        }
+
+        match config.matcher.negotiate(accept, &excluded, inferred) {
+            Some(selection) => Ok(selection),
+            None if config.lenient => default(),
+            None => Err(Rejection::NotAcceptable(
+                config.matcher.mismatch_accept(accept),
+            )),
+        }
+    }
+}
+
+/// How to treat a request with an empty or whitespace-only `Accept` header, as emitted by some proxies.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum EmptyAccept {
+    /// Treat it as if the header was missing, using the default encoding.
+    #[default]
+    Default,
+    /// Reject the request with a 400 Bad Request.
+    BadRequest,
+    /// Reject the request with a 406 Not Acceptable, as no media type is acceptable.
+    NotAcceptable,
+}
+
+/// Representation served by the [NegotiateLayer], available on the extensions of the responses it serialized.
+///
+/// Outer layers, such as the `on_response` hook of an access log, can read it without parsing the `Content-Type` back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct NegotiationOutcome {
+    /// Encoding used to serialize the response
+    pub encoding: Encoding,
+    /// Media type informed on the `Content-Type`, such as a vendor media type of the encoding
+    pub media_type: HeaderValue,
+    /// Whether the encoding is one of the [fallback encodings](NegotiateLayer::fallback_encodings), used because the negotiated one failed
+    pub fallback: bool,
+}
+
+/// Serialize the stored [Extension] struct defined by a [Negotiate] into the right serialization format based on the `Accept` header.
+///
+/// Services of a [DeferredCall] layer only implement [Service] when the wrapped service is `Clone + Send + 'static`.
+pub struct NegotiateService<S, C = DirectCall> {
+    inner: S,
+    config: Arc<Config>,
+    call: PhantomData<C>,
+}
+
+impl<S: Clone, C> Clone for NegotiateService<S, C> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            config: self.config.clone(),
+            call: PhantomData,
+        }
+    }
+}
+
+type ResponseFuture<E> = Pin<Box<dyn Future<Output = Result<Response, E>> + Send + 'static>>;
+
+/// What the layer needs to serialize the response of the inner service, gathered from the request before calling it.
+struct Pending {
+    config: Arc<Config>,
+    selection: Selection,
+    headers: Option<HeaderMap>,
+    request_id: Option<String>,
+    location: Option<String>,
+    if_none_match: Option<HeaderValue>,
+    callback: Option<String>,
+    #[cfg(feature = "test-util")]
+    injected: Option<test_util::InjectedFailure>,
+}
+
+impl Pending {
+    fn encode(self, response: Response) -> Response {
+        #[cfg(feature = "test-util")]
+        if self.injected == Some(test_util::InjectedFailure::Encode)
+            && (response.extensions().get::<ErasedNegotiate>().is_some()
+                || response
+                    .extensions()
+                    .get::<context::ContextualNegotiate>()
+                    .is_some())
+        {
+            return self.config.reject(
+                Rejection::SerializationFailed,
+                Some(self.selection.encoding),
+                self.request_id.as_deref(),
+            );
+        }
+        self.config.encode_response(
+            response,
+            self.selection,
+            self.headers.as_ref(),
+            self.request_id.as_deref(),
+            self.location.as_deref(),
+            self.if_none_match.as_ref(),
+            self.callback.as_deref(),
+        )
+    }
+}
+
+// Rejections are answered as they are, without running the wrapped service
+#[allow(clippy::result_large_err)]
+impl<S, C> NegotiateService<S, C> {
+    /// Negotiate the encoding of the response from the request headers, answering the rejections right away.
+    fn negotiate(&self, request: &mut Request) -> Result<Selection, Response> {
+        #[cfg(feature = "test-util")]
+        if request.extensions().get::<test_util::InjectedFailure>()
+            == Some(&test_util::InjectedFailure::NotAcceptable)
+        {
+            let request_id = error::request_id(request.headers());
+            let rejection = Rejection::NotAcceptable(Mismatch::Unsupported);
+            return Err(self.config.reject(rejection, None, request_id.as_deref()));
+        }
+
+        // The requested format replaces the header, so extractors rejecting the body answer it too
+        let format = self.config.format_parameter.as_ref().and_then(|parameter| {
+            path_extension::query_format(&self.config, request.uri().query(), parameter)
+        });
+        match format {
+            Some(Ok(encoding)) => {
+                request
+                    .headers_mut()
+                    .insert(ACCEPT, HeaderValue::from_static(encoding.media_type()));
+            }
+            Some(Err(())) => {
+                let request_id = error::request_id(request.headers());
+                let rejection = Rejection::NotAcceptable(Mismatch::Unsupported);
+                return Err(self.config.reject(rejection, None, request_id.as_deref()));
+            }
+            None => {}
+        }
+
+        request
+            .headers()
+            .negotiate(&self.config)
+            .map_err(|rejection| {
+                let request_id = error::request_id(request.headers());
+                self.config.reject(rejection, None, request_id.as_deref())
+            })
+    }
+
+    /// Gather what is needed to serialize the response, and hand the configuration over to the extractors.
+    fn prepare(&self, request: &mut Request, selection: Selection) -> Result<Pending, Response> {
+        let config = self.config.clone();
+        // Only some payloads can be encoded into some encodings, such as dedicated response types, so the others negotiate again
+        let headers = (!selection.encoding.encodes() || selection.encoding.shaped())
+            .then(|| request.headers().clone());
+        let request_id = error::request_id(request.headers());
+        #[cfg(feature = "jsonp")]
+        let callback = match (&config.jsonp_callback, selection.encoding) {
+            (Some(parameter), Encoding::Jsonp) => {
+                match jsonp::callback(request.uri().query(), parameter) {
+                    Some(callback) => Some(callback.to_string()),
+                    None => {
+                        let rejection = Rejection::InvalidCallback;
+                        return Err(config.reject(rejection, None, request_id.as_deref()));
+                    }
+                }
+            }
+            _ => None,
+        };
+        #[cfg(not(feature = "jsonp"))]
+        let callback: Option<String> = None;
+        let location = config
+            .link_alternates
+            .then(|| request.uri().path_and_query().map(|p| p.to_string()))
+            .flatten();
+        // Only safe methods answer 304 Not Modified
+        let if_none_match = (config.etag.is_some()
+            && matches!(*request.method(), Method::GET | Method::HEAD))
+        .then(|| request.headers().get(IF_NONE_MATCH).cloned())
+        .flatten();
+        if let Some(media_type::ParameterHook(hook)) = &config.parameter_hook {
+            let content_type = request
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|content_type| content_type.to_str().ok())
+                .map(str::to_owned);
+            if let Some(content_type) = content_type {
+                let (_, parameters) = media_type::parse_content_type(&content_type);
+                hook(&parameters, request.extensions_mut());
+            }
+        }
+        request.extensions_mut().insert(config.clone());
+
+        Ok(Pending {
+            #[cfg(feature = "test-util")]
+            injected: request
+                .extensions()
+                .get::<test_util::InjectedFailure>()
+                .copied(),
+            config,
+            selection,
+            headers,
+            request_id,
+            location,
+            if_none_match,
+            callback,
+        })
+    }
+}
+
+impl<T> Service<Request> for NegotiateService<T, DirectCall>
+where
+    T: Service<Request>,
+    T::Response: IntoResponse,
+    T::Future: Send + 'static,
+{
+    type Response = axum::response::Response;
+    type Error = T::Error;
+    type Future = ResponseFuture<T::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request) -> Self::Future {
+        let selection = match self.negotiate(&mut request) {
+            Ok(selection) => selection,
+            Err(response) => return Box::pin(async move { Ok(response) }),
+        };
+        let pending = match self.prepare(&mut request, selection) {
+            Ok(pending) => pending,
+            Err(response) => return Box::pin(async move { Ok(response) }),
+        };
+
+        let future = self.inner.call(request);
+        Box::pin(async move { Ok(pending.encode(future.await?.into_response())) })
+    }
+}
+
+impl<T> Service<Request> for NegotiateService<T, DeferredCall>
+where
+    T: Service<Request> + Clone + Send + 'static,
+    T::Response: IntoResponse,
+    T::Future: Send + 'static,
+{
+    type Response = axum::response::Response;
+    type Error = T::Error;
+    type Future = ResponseFuture<T::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request) -> Self::Future {
+        let selection = match self.negotiate(&mut request) {
+            Ok(selection) => selection,
+            Err(response) => return Box::pin(async move { Ok(response) }),
+        };
+
+        #[cfg(feature = "tokio")]
+        let leader = match self.config.coalescer.as_ref().and_then(|coalescer| {
+            let key = coalesce::Key::of(
+                request.method(),
+                request.uri().to_string(),
+                request.headers(),
+                &selection.media_type,
+            )?;
+            Some(coalescer.join(key))
+        }) {
+            Some(coalesce::Role::Leader(leader)) => Some(leader),
+            Some(coalesce::Role::Follower(follower)) => {
+                // Waiting requests only run the handler if the leader doesn't share its response
+                let ready = self.inner.clone();
+                let mut service = NegotiateService::<_, DeferredCall> {
+                    inner: std::mem::replace(&mut self.inner, ready),
+                    config: self.config.clone(),
+                    call: PhantomData,
+                };
+                return Box::pin(async move {
+                    match follower.wait().await {
+                        Some(response) => Ok(response),
+                        None => service.call(request).await,
+                    }
+                });
+            }
+            None => None,
+        };
+
+        let pending = match self.prepare(&mut request, selection) {
+            Ok(pending) => pending,
+            Err(response) => return Box::pin(async move { Ok(response) }),
+        };
+        let future: ResponseFuture<T::Error> = match &pending.config.payload_hash {
+            None => {
+                let future = self.inner.call(request);
+                Box::pin(async move { future.await.map(IntoResponse::into_response) })
+            }
+            Some(_) => {
+                // The body is hashed before calling the service, so the one polled ready goes along with the request
+                let ready = self.inner.clone();
+                let mut inner = std::mem::replace(&mut self.inner, ready);
+                let config = pending.config.clone();
+                let accepted = Some(pending.selection.encoding);
+                let request_id = pending.request_id.clone();
+                Box::pin(async move {
+                    let reject =
+                        |rejection| config.reject(rejection, accepted, request_id.as_deref());
+                    match payload_hash::hash_request(request, &config, reject).await {
+                        Ok(request) => inner.call(request).await.map(IntoResponse::into_response),
+                        Err(response) => Ok(response),
+                    }
+                })
+            }
+        };
+
+        Box::pin(async move {
+            let response = pending.encode(future.await?);
+            #[cfg(feature = "tokio")]
+            if let Some(leader) = leader {
+                return Ok(leader.share(response).await);
+            }
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Negotiate;
+
+    use axum::{
+        body::Body,
+        http::{
+            header::{ACCEPT, CONTENT_LENGTH, CONTENT_TYPE},
+            Request, StatusCode,
+        },
+        response::IntoResponse,
+        routing::post,
+        Router,
+    };
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    use crate::{EmptyAccept, NegotiateLayer};
+
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+    struct Example {
+        message: String,
+    }
+
+    fn content_length(headers: &axum::http::HeaderMap) -> usize {
+        headers
+            .get(CONTENT_LENGTH)
+            .map(|v| v.to_str().unwrap().parse::<usize>().unwrap())
+            .unwrap()
+    }
+
+    /// Message of a plain text rejection, without the supported media types listed after it.
+    async fn rejection_message(response: axum::response::Response) -> String {
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        body.lines().next().unwrap_or_default().to_string()
+    }
+
+    mod general {
+        use super::*;
+
+        mod input {
+            use super::*;
+
+            #[tokio::test]
+            async fn test_does_not_process_handler_if_content_type_is_not_supported() {
+                #[axum::debug_handler]
+                async fn handler(_: Negotiate<Example>) -> impl IntoResponse {
+                    unimplemented!("This should not be called");
+                    #[allow(unreachable_code)]
+                    ()
+                }
+
+                let app = Router::new()
+                    .route("/", post(handler))
+                    .layer(NegotiateLayer::new());
+
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .header(CONTENT_TYPE, "non-supported")
+                            .method("POST")
+                            .body(Body::from("really-cool-format"))
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), 415);
+                assert_eq!(
+                    rejection_message(response).await,
+                    "Invalid content type on request"
+                );
+            }
+
+            #[cfg(any(feature = "default-json", feature = "default-cbor"))]
+            #[tokio::test]
+            async fn test_use_already_decoded_value_from_extensions() {
+                #[axum::debug_handler]
+                async fn handler(Negotiate(input): Negotiate<Example>) -> impl IntoResponse {
+                    format!("Hello, {}!", input.message)
+                }
+
+                let app = Router::new()
+                    .route("/", post(handler))
+                    .layer(NegotiateLayer::new());
+
+                let mut request = Request::builder()
+                    .uri("/")
+                    .header(CONTENT_TYPE, "non-supported")
+                    .method("POST")
+                    .body(Body::from("really-cool-format"))
+                    .unwrap();
+                request.extensions_mut().insert(Negotiate(Example {
+                    message: "test".to_string(),
+                }));
+
+                let response = app.oneshot(request).await.unwrap();
+
+                assert_eq!(response.status(), 200);
+                assert_eq!(
+                    response.into_body().collect().await.unwrap().to_bytes(),
+                    "Hello, test!"
+                );
+            }
+            #[cfg(any(feature = "default-json", feature = "default-cbor"))]
+            #[tokio::test]
+            async fn test_distinguish_valid_but_unsupported_content_type() {
+                #[axum::debug_handler]
+                async fn handler(_: Negotiate<Example>) -> impl IntoResponse {
+                    unimplemented!("This should not be called");
+                    #[allow(unreachable_code)]
+                    ()
+                }
+
+                let app = Router::new()
+                    .route("/", post(handler))
+                    .layer(NegotiateLayer::new());
+
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .header(CONTENT_TYPE, "application/xml")
+                            .method("POST")
+                            .body(Body::from("payload"))
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), 415);
+                assert_eq!(
+                    rejection_message(response).await,
+                    "Unsupported content type on request"
+                );
+            }
+
+            #[cfg(all(feature = "default-json", not(feature = "cbor")))]
+            #[tokio::test]
+            async fn test_distinguish_disabled_content_type() {
+                #[axum::debug_handler]
+                async fn handler(_: Negotiate<Example>) -> impl IntoResponse {
+                    unimplemented!("This should not be called");
+                    #[allow(unreachable_code)]
+                    ()
+                }
+
+                let app = Router::new()
+                    .route("/", post(handler))
+                    .layer(NegotiateLayer::new());
+
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .header(CONTENT_TYPE, "application/cbor")
+                            .method("POST")
+                            .body(Body::from("payload"))
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), 415);
+                assert_eq!(
+                    rejection_message(response).await,
+                    "Content type disabled on this service"
+                );
+            }
+
+            #[tokio::test]
+            async fn test_require_explicit_content_type_without_default() {
+                #[axum::debug_handler]
+                async fn handler(_: Negotiate<Example>) -> impl IntoResponse {
+                    unimplemented!("This should not be called");
+                    #[allow(unreachable_code)]
+                    ()
+                }
+
+                let app = Router::new()
+                    .route("/", post(handler))
+                    .layer(NegotiateLayer::new().no_default_encoding());
+
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .method("POST")
+                            .body(Body::from("{}"))
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), 406);
+                assert_eq!(
+                    rejection_message(response).await,
+                    "Invalid content type on request"
+                );
+            }
+
+            #[tokio::test]
+            async fn test_require_content_type_on_bodies_when_configured() {
+                #[axum::debug_handler]
+                async fn handler(_: Negotiate<Example>) -> impl IntoResponse {
+                    unimplemented!("This should not be called");
+                    #[allow(unreachable_code)]
+                    ()
+                }
+
+                let app = Router::new()
+                    .route("/", post(handler))
+                    .layer(NegotiateLayer::new().require_content_type());
+
+                let response = app
+                    .clone()
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .method("POST")
+                            .header(ACCEPT, crate::Encoding::ALL[0].media_type())
+                            .body(Body::from("{}"))
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), 415);
+                assert_eq!(
+                    rejection_message(response).await,
+                    "Missing content type on request"
+                );
+
+                // Without a body there is nothing to confuse, so the usual rejections apply
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .method("POST")
+                            .header(ACCEPT, crate::Encoding::ALL[0].media_type())
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_ne!(response.status(), 415);
+            }
+        }
+
+        mod output {
+            use super::*;
+
+            #[tokio::test]
+            async fn test_inform_error_when_misconfigured() {
+                #[axum::debug_handler]
+                async fn handler() -> impl IntoResponse {
+                    Negotiate(Example {
+                        message: "Hello, test!".to_string(),
+                    })
+                }
+
+                let app = Router::new().route("/", post(handler));
+
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .method("POST")
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), 415);
+                assert_eq!(
+                    response.into_body().collect().await.unwrap().to_bytes(),
+                    "Misconfigured service layer"
+                );
+            }
+
+            #[cfg(any(feature = "default-json", feature = "default-cbor"))]
+            #[tokio::test]
+            async fn test_treat_empty_accept_as_missing_by_default() {
+                #[axum::debug_handler]
+                async fn handler() -> impl IntoResponse {
+                    Negotiate(Example {
+                        message: "Hello, test!".to_string(),
+                    })
+                }
+
+                let app = Router::new()
+                    .route("/", post(handler))
+                    .layer(NegotiateLayer::new());
+
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .header(ACCEPT, " ")
+                            .method("POST")
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), 200);
+                assert_eq!(
+                    response.headers().get(CONTENT_TYPE).unwrap(),
+                    crate::Encoding::compiled_default().unwrap().media_type()
+                );
+            }
+
+            #[tokio::test]
+            async fn test_reject_empty_accept_when_configured() {
+                #[axum::debug_handler]
+                async fn handler() -> impl IntoResponse {
+                    unimplemented!("This should not be called");
+                    #[allow(unreachable_code)]
+                    ()
+                }
+
+                let app = Router::new()
+                    .route("/", post(handler))
+                    .layer(NegotiateLayer::new().empty_accept(EmptyAccept::BadRequest));
+
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .header(ACCEPT, "")
+                            .method("POST")
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), 400);
+                assert_eq!(
+                    rejection_message(response).await,
+                    "Empty accept header on request"
+                );
+            }
+
+            #[tokio::test]
+            async fn test_require_explicit_accept_without_default() {
+                #[axum::debug_handler]
+                async fn handler() -> impl IntoResponse {
+                    unimplemented!("This should not be called");
+                    #[allow(unreachable_code)]
+                    ()
+                }
+
+                let app = Router::new()
+                    .route("/", post(handler))
+                    .layer(NegotiateLayer::new().no_default_encoding());
+
+                for accept in [None, Some("*/*")] {
+                    let mut request = Request::builder().uri("/").method("POST");
+                    if let Some(accept) = accept {
+                        request = request.header(ACCEPT, accept);
+                    }
+
+                    let response = app
+                        .clone()
+                        .oneshot(request.body(Body::empty()).unwrap())
+                        .await
+                        .unwrap();
+
+                    assert_eq!(response.status(), 406);
+                }
+            }
+
+            #[cfg(any(feature = "simd-json", feature = "json"))]
+            #[tokio::test]
+            async fn test_answer_default_encoding_when_lenient() {
+                use crate::Encoding;
+
+                #[axum::debug_handler]
+                async fn handler() -> impl IntoResponse {
+                    Negotiate(Example {
+                        message: "Hello, test!".to_string(),
+                    })
+                }
+
+                for (layer, status) in [
+                    (NegotiateLayer::new().default_encoding(Encoding::Json), 406),
+                    (
+                        NegotiateLayer::new()
+                            .default_encoding(Encoding::Json)
+                            .lenient(),
+                        200,
+                    ),
+                    (NegotiateLayer::new().no_default_encoding().lenient(), 406),
+                ] {
+                    let response = Router::new()
+                        .route("/", post(handler))
+                        .layer(layer)
+                        .oneshot(
+                            Request::builder()
+                                .uri("/")
+                                .method("POST")
+                                .header(ACCEPT, "application/xml, image/*")
+                                .body(Body::empty())
+                                .unwrap(),
+                        )
+                        .await
+                        .unwrap();
+
+                    assert_eq!(response.status(), status);
+                    if status == 200 {
+                        assert_eq!(
+                            response.headers().get(CONTENT_TYPE).unwrap(),
+                            "application/json"
+                        );
+                    }
+                }
+            }
+
+            #[tokio::test]
+            async fn test_does_not_process_handler_if_accept_is_not_supported() {
+                #[axum::debug_handler]
+                async fn handler() -> impl IntoResponse {
+                    unimplemented!("This should not be called");
+                    #[allow(unreachable_code)]
+                    ()
+                }
+
+                let app = Router::new()
+                    .route("/", post(handler))
+                    .layer(NegotiateLayer::new());
+
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .header(ACCEPT, "non-supported")
+                            .method("POST")
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), 406);
+                assert_eq!(
+                    rejection_message(response).await,
+                    "Invalid content type on request"
+                );
+            }
+        }
+    }
+
+    mod introspection {
+        use super::*;
+        use crate::Encoding;
+
+        #[test]
+        fn test_share_codecs_with_clients() {
+            let example = Example {
+                message: "Hello, test!".to_string(),
+            };
+
+            let encodings = [
+                #[cfg(any(feature = "simd-json", feature = "json"))]
+                Encoding::Json,
+                #[cfg(feature = "cbor")]
+                Encoding::Cbor,
+            ];
+            for encoding in encodings {
+                let body = encoding.to_vec(&example).unwrap();
+                let decoded: Example = encoding.from_slice(&body).unwrap();
+                assert_eq!(decoded.message, example.message, "{encoding}");
+            }
+        }
+
+        #[test]
+        fn test_report_supported_media_types() {
+            let layer = NegotiateLayer::new();
+
+            let media_types = layer.supported_media_types().collect::<Vec<_>>();
+            #[cfg(any(feature = "simd-json", feature = "json"))]
+            assert!(media_types.contains(&"application/json"));
+            #[cfg(feature = "cbor")]
+            assert!(media_types.contains(&"application/cbor"));
+            // JSONP is only negotiated once its callback parameter is configured
+            assert_eq!(
+                media_types.len(),
+                Encoding::ALL.len() - usize::from(cfg!(feature = "jsonp"))
+            );
+
+            assert_eq!(
+                layer.default_media_type(),
+                Encoding::compiled_default().map(Encoding::media_type)
+            );
+        }
+
+        #[cfg(any(feature = "simd-json", feature = "json"))]
+        #[test]
+        fn test_report_media_types_of_the_layer_configuration() {
+            let layer = NegotiateLayer::new().only_encodings([Encoding::Json]);
+
+            assert_eq!(layer.supported_encodings(), vec![Encoding::Json]);
+            assert_eq!(
+                layer.supported_media_types().collect::<Vec<_>>(),
+                vec!["application/json"]
+            );
+
+            #[cfg(feature = "jsonp")]
+            assert!(NegotiateLayer::new()
+                .jsonp("callback")
+                .supported_encodings()
+                .contains(&Encoding::Jsonp));
+        }
+
+        #[cfg(any(feature = "simd-json", feature = "json"))]
+        #[test]
+        fn test_describe_negotiated_media_types() {
+            let layer = NegotiateLayer::new()
+                .suffix_family(
+                    Encoding::Json,
+                    crate::SuffixFamily::only(["application/vnd.example+json"]),
+                )
+                .fallback_encodings([Encoding::Json]);
+
+            let metadata = layer.metadata();
+            for media_type in [
+                "application/json",
+                "text/json",
+                "application/vnd.example+json",
+            ] {
+                assert!(metadata
+                    .request_media_types
+                    .iter()
+                    .any(|described| described == media_type));
+                assert!(metadata
+                    .response_media_types
+                    .iter()
+                    .any(|described| described == media_type));
+            }
+            assert_eq!(metadata.default_media_type, layer.default_media_type());
+            assert_eq!(metadata.fallback_media_types, ["application/json"]);
+        }
+
+        #[cfg(feature = "cbor")]
+        #[test]
+        fn test_report_configured_default() {
+            let layer = NegotiateLayer::new().default_encoding(Encoding::Cbor);
+
+            assert_eq!(layer.default_media_type(), Some("application/cbor"));
+        }
+    }
+
+    mod matrix {
+        use super::*;
+        use crate::{feature_matrix, Encoding};
+
+        #[test]
+        fn test_feature_set_is_coherent() {
+            let matrix = feature_matrix();
+            assert!(matrix.problems().is_empty(), "{:?}", matrix.problems());
+            assert_eq!(matrix.encodings.len(), Encoding::ALL.len());
+        }
+
+        #[tokio::test]
+        async fn test_roundtrip_every_enabled_encoding() {
+            #[axum::debug_handler]
+            async fn handler(Negotiate(input): Negotiate<Vec<Example>>) -> impl IntoResponse {
+                Negotiate(input)
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new());
+
+            let value = vec![Example {
+                message: "Hello, test!".to_string(),
+            }];
+            let encodings = feature_matrix().encodings.into_iter();
+            for support in encodings.filter(|s| s.decodes_requests && s.encodes_responses) {
+                let media_type = support.encoding.media_type();
+                let body = support.encoding.encode(&value).unwrap();
+
+                let response = app
+                    .clone()
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .method("POST")
+                            .header(CONTENT_TYPE, media_type)
+                            .header(ACCEPT, media_type)
+                            .body(Body::from(body.clone()))
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), 200, "{media_type}");
+                assert_eq!(response.headers().get(CONTENT_TYPE).unwrap(), media_type);
+                assert_eq!(
+                    response.into_body().collect().await.unwrap().to_bytes(),
+                    body,
+                    "{media_type}"
+                );
+            }
+        }
+
+        #[tokio::test]
+        async fn test_compiled_default_is_served() {
+            let Some(default) = feature_matrix().default_encoding else {
+                return;
+            };
+
+            #[axum::debug_handler]
+            async fn handler() -> impl IntoResponse {
+                Negotiate(Example {
+                    message: "Hello, test!".to_string(),
+                })
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new());
+
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .uri("/")
+                        .method("POST")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), 200);
+            assert_eq!(
+                response.headers().get(CONTENT_TYPE).unwrap(),
+                default.media_type()
+            );
+        }
+    }
+
+    #[cfg(any(feature = "simd-json", feature = "json"))]
+    mod matching {
+        use super::*;
+        use crate::{Encoding, SuffixFamily};
+
+        async fn negotiated_content_type(layer: NegotiateLayer, accept: &str) -> Option<String> {
+            #[axum::debug_handler]
+            async fn handler() -> impl IntoResponse {
+                Negotiate(Example {
+                    message: "Hello, test!".to_string(),
+                })
+            }
+
+            let app = Router::new().route("/", post(handler)).layer(layer);
+
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .uri("/")
+                        .method("POST")
+                        .header(ACCEPT, accept)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            if response.status() == StatusCode::NOT_ACCEPTABLE {
+                return None;
+            }
+            response
+                .headers()
+                .get(CONTENT_TYPE)
+                .map(|content_type| content_type.to_str().unwrap().to_string())
+        }
+
+        #[cfg(feature = "cbor")]
+        #[tokio::test]
+        async fn test_prefer_highest_quality() {
+            assert_eq!(
+                negotiated_content_type(
+                    NegotiateLayer::new(),
+                    "image/webp, application/json;q=0.5, application/cbor;q=0.8"
+                )
+                .await
+                .as_deref(),
+                Some("application/cbor")
+            );
+        }
+
+        #[cfg(all(feature = "cbor", any(feature = "simd-json", feature = "json")))]
+        #[tokio::test]
+        async fn test_rank_ranges_by_specificity() {
+            let layer = NegotiateLayer::new().default_encoding(Encoding::Json);
+            for (accept, expected) in [
+                // The most specific range decides the quality of a media type
+                ("application/json;q=0, */*", "application/cbor"),
+                (
+                    "application/json;q=0.2, application/*;q=0.5",
+                    "application/cbor",
+                ),
+                ("*/*;q=0.1, application/cbor", "application/cbor"),
+                // Ties are broken by specificity, then by the order of the header
+                ("*/*, application/cbor", "application/cbor"),
+                ("application/*, application/cbor", "application/cbor"),
+                ("application/cbor, application/json", "application/cbor"),
+                ("application/json, application/cbor", "application/json"),
+                // Quoted parameters may contain separators
+                (
+                    r#"application/cbor;profile="a,b";q=0.9, application/json;q=0.8"#,
+                    "application/cbor",
+                ),
+            ] {
+                assert_eq!(
+                    negotiated_content_type(layer.clone(), accept)
+                        .await
+                        .as_deref(),
+                    Some(expected),
+                    "{accept}"
+                );
+            }
+        }
+
+        #[cfg(all(feature = "cbor", any(feature = "simd-json", feature = "json")))]
+        #[tokio::test]
+        async fn test_match_subtype_wildcards() {
+            for (layer, expected) in [
+                (
+                    NegotiateLayer::new().default_encoding(Encoding::Cbor),
+                    "application/cbor",
+                ),
+                (
+                    NegotiateLayer::new().default_encoding(Encoding::Json),
+                    "application/json",
+                ),
+                // Without a default, the first enabled codec of the type is used
+                (
+                    NegotiateLayer::new()
+                        .no_default_encoding()
+                        .prefer_encodings([Encoding::Cbor]),
+                    "application/cbor",
+                ),
+                // A default excluded from the route doesn't apply
+                (
+                    NegotiateLayer::new()
+                        .default_encoding(Encoding::Json)
+                        .only_encodings([Encoding::Cbor]),
+                    "application/cbor",
+                ),
+            ] {
+                assert_eq!(
+                    negotiated_content_type(layer, "application/*")
+                        .await
+                        .as_deref(),
+                    Some(expected)
+                );
+            }
+
+            assert_eq!(
+                negotiated_content_type(NegotiateLayer::new().no_default_encoding(), "*/*").await,
+                None
+            );
+            #[cfg(not(any(feature = "csv", feature = "plain-text", feature = "debug-html")))]
+            assert_eq!(
+                negotiated_content_type(NegotiateLayer::new(), "text/*").await,
+                None
+            );
+        }
+
+        #[cfg(all(feature = "cbor", any(feature = "simd-json", feature = "json")))]
+        #[tokio::test]
+        async fn test_break_ties_with_server_preference() {
+            let layer = NegotiateLayer::new()
+                .default_encoding(Encoding::Json)
+                .prefer_encodings([Encoding::Cbor]);
+            for (accept, expected) in [
+                ("application/json, application/cbor", "application/cbor"),
+                ("application/cbor, application/json", "application/cbor"),
+                ("application/json, application/*", "application/cbor"),
+                // Quality still comes first
+                (
+                    "application/json, application/cbor;q=0.9",
+                    "application/json",
+                ),
+                ("*/*", "application/json"),
+            ] {
+                assert_eq!(
+                    negotiated_content_type(layer.clone(), accept)
+                        .await
+                        .as_deref(),
+                    Some(expected),
+                    "{accept}"
+                );
+            }
+        }
+
+        #[tokio::test]
+        async fn test_ignore_unacceptable_types() {
+            assert_eq!(
+                negotiated_content_type(NegotiateLayer::new(), "application/json;q=0").await,
+                None
+            );
+        }
+
+        #[tokio::test]
+        async fn test_respond_canonical_media_type_for_aliases() {
+            assert_eq!(
+                negotiated_content_type(NegotiateLayer::new(), "text/json")
+                    .await
+                    .as_deref(),
+                Some("application/json")
+            );
+        }
+
+        #[tokio::test]
+        async fn test_register_custom_aliases() {
+            let layer =
+                NegotiateLayer::new().media_type_alias("application/x-legacy", Encoding::Json);
+
+            assert_eq!(
+                negotiated_content_type(layer, "application/X-Legacy")
+                    .await
+                    .as_deref(),
+                Some("application/json")
+            );
+        }
+
+        #[tokio::test]
+        async fn test_echo_registered_media_types() {
+            #[axum::debug_handler]
+            async fn echo(Negotiate(example): Negotiate<Example>) -> impl IntoResponse {
+                Negotiate(example)
+            }
+
+            let layer = NegotiateLayer::new()
+                .register_media_type("application/vnd.example.order.v2", Encoding::Json);
+            assert_eq!(
+                negotiated_content_type(
+                    layer.clone(),
+                    "application/json;q=0.5, application/vnd.example.Order.v2"
+                )
+                .await
+                .as_deref(),
+                Some("application/vnd.example.order.v2")
+            );
+            assert!(layer
+                .metadata()
+                .response_media_types
+                .contains(&"application/vnd.example.order.v2".to_string()));
+
+            let response = Router::new()
+                .route("/", post(echo))
+                .layer(layer)
+                .oneshot(
+                    Request::builder()
+                        .uri("/")
+                        .method("POST")
+                        .header(CONTENT_TYPE, "application/vnd.example.order.v2")
+                        .header(ACCEPT, "application/vnd.example.order.v2")
+                        .body(Body::from(r#"{"message":"Hello, test!"}"#))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), 200);
+            assert_eq!(
+                response.headers().get(CONTENT_TYPE).unwrap(),
+                "application/vnd.example.order.v2"
+            );
+            assert_eq!(
+                response.into_body().collect().await.unwrap().to_bytes(),
+                r#"{"message":"Hello, test!"}"#
+            );
+        }
+
+        #[test]
+        #[should_panic(expected = "invalid media type")]
+        fn test_reject_invalid_registered_media_types() {
+            let _ = NegotiateLayer::new().register_media_type("vnd.example.order", Encoding::Json);
+        }
+
+        #[tokio::test]
+        async fn test_negotiate_allowed_vendor_types_of_suffix_family() {
+            let layer = NegotiateLayer::new().suffix_family(
+                Encoding::Json,
+                SuffixFamily::only(["application/vnd.example.order+json"]),
+            );
+
+            assert_eq!(
+                negotiated_content_type(layer.clone(), "application/vnd.example.order+json")
+                    .await
+                    .as_deref(),
+                Some("application/vnd.example.order+json")
+            );
+            assert_eq!(
+                negotiated_content_type(layer, "application/vnd.example.other+json").await,
+                None
+            );
+        }
+
+        #[tokio::test]
+        async fn test_negotiate_any_vendor_type_with_a_known_suffix() {
+            #[axum::debug_handler]
+            async fn echo(Negotiate(example): Negotiate<Example>) -> impl IntoResponse {
+                Negotiate(example)
+            }
+
+            let layer = NegotiateLayer::new().vendor_media_types();
+            assert_eq!(
+                negotiated_content_type(layer.clone(), "application/vnd.example.thing+json")
+                    .await
+                    .as_deref(),
+                Some("application/vnd.example.thing+json")
+            );
+            #[cfg(feature = "cbor")]
+            assert_eq!(
+                negotiated_content_type(layer.clone(), "application/vnd.example.thing+cbor")
+                    .await
+                    .as_deref(),
+                Some("application/vnd.example.thing+cbor")
+            );
+            assert_eq!(
+                negotiated_content_type(layer.clone(), "application/vnd.example.thing+xml").await,
+                None
+            );
+
+            let response = Router::new()
+                .route("/", post(echo))
+                .layer(layer)
+                .oneshot(
+                    Request::builder()
+                        .uri("/")
+                        .method("POST")
+                        .header(CONTENT_TYPE, "application/vnd.example.thing+json")
+                        .header(ACCEPT, "application/vnd.example.thing+json")
+                        .body(Body::from(r#"{"message":"Hello, test!"}"#))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), 200);
+            assert_eq!(
+                response.headers().get(CONTENT_TYPE).unwrap(),
+                "application/vnd.example.thing+json"
+            );
+            assert_eq!(
+                response.into_body().collect().await.unwrap().to_bytes(),
+                r#"{"message":"Hello, test!"}"#
+            );
+        }
+
+        #[tokio::test]
+        async fn test_ignore_vendor_types_by_default() {
+            assert_eq!(
+                negotiated_content_type(NegotiateLayer::new(), "application/vnd.example+json")
+                    .await,
+                None
+            );
+        }
+    }
+
+    #[cfg(all(feature = "tokio", any(feature = "simd-json", feature = "json")))]
+    mod limits {
+        use std::{
+            convert::Infallible,
+            future::Future,
+            pin::Pin,
+            task::{Context, Poll},
+            time::Duration,
+        };
+
+        use axum::body::{Bytes, HttpBody};
+        use http_body_util::BodyExt;
+        use tokio::sync::oneshot;
+
+        use super::*;
+
+        /// Body only sending its content once the test releases it
+        struct PendingBody(Option<oneshot::Receiver<Bytes>>);
+
+        impl HttpBody for PendingBody {
+            type Data = Bytes;
+            type Error = Infallible;
+
+            fn poll_frame(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+            ) -> Poll<Option<Result<http_body::Frame<Bytes>, Infallible>>> {
+                let Some(receiver) = self.0.as_mut() else {
+                    return Poll::Ready(None);
+                };
+                let Poll::Ready(data) = Pin::new(receiver).poll(cx) else {
+                    return Poll::Pending;
+                };
+                self.0 = None;
+                Poll::Ready(data.ok().map(|data| Ok(http_body::Frame::data(data))))
+            }
+        }
+
+        fn request(body: Body, length: usize) -> Request<Body> {
+            Request::builder()
+                .uri("/")
+                .method("POST")
+                .header(CONTENT_TYPE, "application/json")
+                .header(CONTENT_LENGTH, length)
+                .body(body)
+                .unwrap()
+        }
+
+        #[tokio::test]
+        async fn test_limit_concurrent_large_decodes() {
+            #[axum::debug_handler]
+            async fn handler(Negotiate(input): Negotiate<Example>) -> impl IntoResponse {
+                input.message
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new().limit_large_decodes(20, 1));
+
+            let large = r#"{ "message": "large payload" }"#;
+            let (release, pending) = oneshot::channel();
+            let first = tokio::spawn(
+                app.clone()
+                    .oneshot(request(Body::new(PendingBody(Some(pending))), large.len())),
+            );
+            tokio::time::sleep(Duration::from_millis(10)).await;
+
+            let second = tokio::spawn(app.clone().oneshot(request(large.into(), large.len())));
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            assert!(!second.is_finished(), "waits for the large decode permit");
+
+            let small = r#"{ "message": "ok" }"#;
+            let response = app
+                .oneshot(request(small.into(), small.len()))
+                .await
+                .unwrap();
+            assert_eq!(
+                response.into_body().collect().await.unwrap().to_bytes(),
+                "ok"
+            );
+
+            release.send(Bytes::from(large)).unwrap();
+            for response in [first.await.unwrap(), second.await.unwrap()] {
+                let response = response.unwrap();
+                assert_eq!(response.status(), 200);
+                assert_eq!(
+                    response.into_body().collect().await.unwrap().to_bytes(),
+                    "large payload"
+                );
+            }
+        }
+
+        #[tokio::test]
+        async fn test_only_wait_for_bodies_of_unknown_size() {
+            #[axum::debug_handler]
+            async fn handler(Negotiate(input): Negotiate<Example>) -> impl IntoResponse {
+                input.message
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new().limit_large_decodes(20, 1));
+            let unsized_request = |body: Body| {
+                Request::builder()
+                    .uri("/")
+                    .method("POST")
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(body)
+                    .unwrap()
+            };
+
+            let (release, pending) = oneshot::channel();
+            let chunked = tokio::spawn(
+                app.clone()
+                    .oneshot(unsized_request(Body::new(PendingBody(Some(pending))))),
+            );
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            assert!(!chunked.is_finished(), "holds the large decode permit");
+
+            let small = r#"{ "message": "ok" }"#;
+            let response = tokio::time::timeout(
+                Duration::from_secs(1),
+                app.clone().oneshot(unsized_request(small.into())),
+            )
+            .await
+            .expect("bodies of a known size don't wait")
+            .unwrap();
+            assert_eq!(
+                response.into_body().collect().await.unwrap().to_bytes(),
+                "ok"
+            );
+            let response = tokio::time::timeout(
+                Duration::from_secs(1),
+                app.clone().oneshot(unsized_request(Body::empty())),
+            )
+            .await
+            .expect("requests without a body don't wait")
+            .unwrap();
+            assert_eq!(response.status(), 400);
+
+            release
+                .send(Bytes::from(r#"{ "message": "chunked" }"#))
+                .unwrap();
+            let response = chunked.await.unwrap().unwrap();
+            assert_eq!(
+                response.into_body().collect().await.unwrap().to_bytes(),
+                "chunked"
+            );
+        }
+
+        #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+        async fn test_hold_large_decode_permit_while_deserializing() {
+            use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+            static DECODING: AtomicUsize = AtomicUsize::new(0);
+            static RELEASED: AtomicBool = AtomicBool::new(false);
+
+            /// Payload blocking its deserialization until the test releases it
+            struct Blocking;
+
+            impl<'de> serde::Deserialize<'de> for Blocking {
+                fn deserialize<D: serde::Deserializer<'de>>(
+                    deserializer: D,
+                ) -> Result<Self, D::Error> {
+                    serde::de::IgnoredAny::deserialize(deserializer)?;
+                    DECODING.fetch_add(1, Ordering::SeqCst);
+                    while !RELEASED.load(Ordering::SeqCst) {
+                        std::thread::sleep(Duration::from_millis(1));
+                    }
+                    Ok(Blocking)
+                }
+            }
+
+            #[axum::debug_handler]
+            async fn handler(Negotiate(_): Negotiate<Blocking>) -> impl IntoResponse {
+                "decoded"
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new().limit_large_decodes(20, 1));
+
+            let large = r#"{ "message": "large payload" }"#;
+            let first = tokio::spawn(app.clone().oneshot(request(large.into(), large.len())));
+            while DECODING.load(Ordering::SeqCst) == 0 {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+
+            let second = tokio::spawn(app.oneshot(request(large.into(), large.len())));
+            // Both workers may be busy deserializing, so the test thread can't rely on the timer of the runtime
+            std::thread::sleep(Duration::from_millis(50));
+            let decoding = DECODING.load(Ordering::SeqCst);
+            RELEASED.store(true, Ordering::SeqCst);
+            assert_eq!(decoding, 1, "waits for the permit held while deserializing");
+
+            for response in [first.await.unwrap(), second.await.unwrap()] {
+                assert_eq!(response.unwrap().status(), 200);
+            }
+            assert_eq!(DECODING.load(Ordering::SeqCst), 2);
+        }
+
+        #[test]
+        #[should_panic(expected = "at least one permit")]
+        fn test_reject_large_decodes_without_permits() {
+            let _ = NegotiateLayer::new().limit_large_decodes(20, 0);
+        }
+
+        /// Body sending its content over many frames, like a chunked upload without a `Content-Length`
+        struct ChunkedBody(Vec<Bytes>);
+
+        impl HttpBody for ChunkedBody {
+            type Data = Bytes;
+            type Error = Infallible;
+
+            fn poll_frame(
+                mut self: Pin<&mut Self>,
+                _: &mut Context<'_>,
+            ) -> Poll<Option<Result<http_body::Frame<Bytes>, Infallible>>> {
+                match self.0.is_empty() {
+                    true => Poll::Ready(None),
+                    false => Poll::Ready(Some(Ok(http_body::Frame::data(self.0.remove(0))))),
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn test_limit_request_sizes_with_and_without_content_length() {
+            #[axum::debug_handler]
+            async fn handler(Negotiate(input): Negotiate<Example>) -> impl IntoResponse {
+                input.message
+            }
+
+            let layer = NegotiateLayer::new().max_request_size(24);
+            assert_eq!(
+                layer.request_body_limit(),
+                crate::RequestBodyLimit::Bytes(24)
+            );
+            let app = Router::new().route("/", post(handler)).layer(layer);
+
+            let chunked = |chunks: &[&'static str]| {
+                Request::builder()
+                    .uri("/")
+                    .method("POST")
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(Body::new(ChunkedBody(
+                        chunks.iter().copied().map(Bytes::from).collect(),
+                    )))
+                    .unwrap()
+            };
+
+            let response = app
+                .clone()
+                .oneshot(chunked(&[r#"{ "message": "#, r#""small" }"#]))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 200);
+
+            let response = app
+                .clone()
+                .oneshot(chunked(&[
+                    r#"{ "message": "#,
+                    r#""too large ""#,
+                    r#", "and": 1 }"#,
+                ]))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 413);
+            assert_eq!(
+                response.into_body().collect().await.unwrap().to_bytes(),
+                "Request body exceeds the size limit"
+            );
+
+            // The declared length is rejected without waiting for the body
+            let (_release, pending) = oneshot::channel();
+            let response = app
+                .oneshot(request(Body::new(PendingBody(Some(pending))), 1024))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 413);
+        }
+
+        #[tokio::test]
+        async fn test_time_out_slow_bodies() {
+            #[axum::debug_handler]
+            async fn handler(_: Negotiate<Example>) -> impl IntoResponse {
+                unimplemented!("This should not be called");
+                #[allow(unreachable_code)]
+                ()
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new().body_read_timeout(Duration::from_millis(10)));
+
+            let (_release, pending) = oneshot::channel();
+            let response = app
+                .oneshot(request(Body::new(PendingBody(Some(pending))), 10))
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), 408);
+            assert_eq!(
+                response.into_body().collect().await.unwrap().to_bytes(),
+                "Timed out reading request body"
+            );
+        }
+    }
+
+    #[cfg(all(feature = "tokio", any(feature = "simd-json", feature = "json")))]
+    mod coalescing {
+        use std::{
+            sync::{
+                atomic::{AtomicUsize, Ordering},
+                Arc,
+            },
+            time::Duration,
+        };
+
+        use axum::{http::header::AUTHORIZATION, routing::get};
+        use tokio::sync::Semaphore;
+
+        use super::*;
+
+        #[tokio::test]
+        async fn test_coalesce_concurrent_identical_gets() {
+            let calls = Arc::new(AtomicUsize::new(0));
+            let gate = Arc::new(Semaphore::new(0));
+            let handler = {
+                let (calls, gate) = (calls.clone(), gate.clone());
+                move || async move {
+                    let call = calls.fetch_add(1, Ordering::SeqCst);
+                    gate.acquire().await.unwrap().forget();
+                    Negotiate(Example {
+                        message: format!("call {call}"),
+                    })
+                }
+            };
+
+            let app = Router::new()
+                .route("/", get(handler))
+                .layer(NegotiateLayer::new().coalesce_gets());
+            let request = |authorization: Option<&'static str>| {
+                let mut request = Request::builder()
+                    .uri("/")
+                    .header(ACCEPT, "application/json");
+                if let Some(authorization) = authorization {
+                    request = request.header(AUTHORIZATION, authorization);
+                }
+                app.clone().oneshot(request.body(Body::empty()).unwrap())
+            };
+
+            let identical: Vec<_> = (0..3).map(|_| tokio::spawn(request(None))).collect();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            let other_user = tokio::spawn(request(Some("Bearer other")));
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+            gate.add_permits(2);
+            let mut bodies = Vec::new();
+            for response in identical {
+                let response = response.await.unwrap().unwrap();
+                assert_eq!(response.status(), 200);
+                assert_eq!(
+                    response.headers().get(CONTENT_TYPE).unwrap(),
+                    "application/json"
+                );
+                bodies.push(response.into_body().collect().await.unwrap().to_bytes());
+            }
+            assert!(
+                bodies.windows(2).all(|pair| pair[0] == pair[1]),
+                "{bodies:?}"
+            );
+            let response = other_user.await.unwrap().unwrap();
+            assert_eq!(response.status(), 200);
+
+            // Requests arriving after the response was shared run the handler again
+            gate.add_permits(1);
+            let response = request(None).await.unwrap();
+            assert_eq!(
+                response.into_body().collect().await.unwrap().to_bytes(),
+                r#"{"message":"call 2"}"#
+            );
+        }
+
+        #[tokio::test]
+        async fn test_never_share_private_responses() {
+            use axum::http::header::SET_COOKIE;
+
+            let calls = Arc::new(AtomicUsize::new(0));
+            let gate = Arc::new(Semaphore::new(0));
+            let handler = {
+                let (calls, gate) = (calls.clone(), gate.clone());
+                move || async move {
+                    let call = calls.fetch_add(1, Ordering::SeqCst);
+                    gate.acquire().await.unwrap().forget();
+                    (
+                        [(SET_COOKIE, format!("session={call}"))],
+                        Negotiate(Example {
+                            message: format!("call {call}"),
+                        }),
+                    )
+                }
+            };
+
+            let app = Router::new()
+                .route("/", get(handler))
+                .layer(NegotiateLayer::new().coalesce_gets());
+            let request = || {
+                app.clone().oneshot(
+                    Request::builder()
+                        .uri("/")
+                        .header(ACCEPT, "application/json")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+            };
+
+            let identical: Vec<_> = (0..3).map(|_| tokio::spawn(request())).collect();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+            gate.add_permits(3);
+            let mut cookies = Vec::new();
+            for response in identical {
+                let response = response.await.unwrap().unwrap();
+                assert_eq!(response.status(), 200);
+                cookies.push(response.headers().get(SET_COOKIE).unwrap().clone());
+            }
+            cookies.sort();
+            cookies.dedup();
+            assert_eq!(cookies.len(), 3, "{cookies:?}");
+            assert_eq!(calls.load(Ordering::SeqCst), 3);
+        }
+    }
+
+    #[cfg(all(feature = "tokio", any(feature = "simd-json", feature = "json")))]
+    mod long_poll {
+        use std::time::Duration;
+
+        use axum::{extract::State, response::Response, routing::get};
+        use serde_json::json;
+        use tokio::sync::watch;
+
+        use super::*;
+        use crate::LongPoll;
+
+        #[tokio::test]
+        async fn test_answer_the_item_or_the_pending_document() {
+            async fn handler(State(mut events): State<watch::Receiver<u32>>) -> Response {
+                LongPoll::new(Duration::from_millis(20))
+                    .or_document(StatusCode::ACCEPTED, json!({ "pending": true }))
+                    .wait(async move {
+                        events.changed().await.unwrap();
+                        let event = *events.borrow();
+                        Example {
+                            message: format!("event {event}"),
+                        }
+                    })
+                    .await
+            }
+
+            async fn without_document() -> Response {
+                LongPoll::new(Duration::from_millis(20))
+                    .wait(std::future::pending::<u32>())
+                    .await
+            }
+
+            let (sender, events) = watch::channel(0);
+            let app = Router::new()
+                .route("/", get(handler))
+                .route("/empty", get(without_document))
+                .with_state(events)
+                .layer(NegotiateLayer::new());
+            let request = |uri| {
+                Request::builder()
+                    .uri(uri)
+                    .header(ACCEPT, "application/json")
+                    .body(Body::empty())
+                    .unwrap()
+            };
+
+            let response = app.clone().oneshot(request("/")).await.unwrap();
+            assert_eq!(response.status(), StatusCode::ACCEPTED);
+            assert_eq!(
+                response.into_body().collect().await.unwrap().to_bytes(),
+                r#"{"pending":true}"#
+            );
+
+            let response = app.clone().oneshot(request("/empty")).await.unwrap();
+            assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+            let waiting = tokio::spawn(app.oneshot(request("/")));
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            sender.send(1).unwrap();
+            let response = waiting.await.unwrap().unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(
+                response.into_body().collect().await.unwrap().to_bytes(),
+                r#"{"message":"event 1"}"#
+            );
+        }
+    }
+
+    #[cfg(all(feature = "metrics", any(feature = "simd-json", feature = "json")))]
+    mod metrics {
+        use std::sync::{Arc, Mutex};
+
+        use super::*;
+        use crate::{metrics::CodecOperation, Encoding};
+
+        #[tokio::test]
+        async fn test_record_codec_time_per_operation() {
+            #[axum::debug_handler]
+            async fn handler(Negotiate(input): Negotiate<Example>) -> impl IntoResponse {
+                Negotiate(input)
+            }
+
+            let recorded = Arc::new(Mutex::new(Vec::new()));
+            let app = Router::new().route("/", post(handler)).layer({
+                let recorded = recorded.clone();
+                NegotiateLayer::new().record_codec_time(move |timing| {
+                    recorded.lock().unwrap().push(timing);
+                })
+            });
+
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .uri("/")
+                        .method("POST")
+                        .header(CONTENT_TYPE, "application/json")
+                        .header(ACCEPT, "application/json")
+                        .body(Body::from(r#"{"message":"test"}"#))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), 200);
+            let recorded = recorded.lock().unwrap();
+            assert_eq!(
+                recorded
+                    .iter()
+                    .map(|timing| (timing.encoding, timing.operation, timing.success))
+                    .collect::<Vec<_>>(),
+                vec![
+                    (Encoding::Json, CodecOperation::Decode, true),
+                    (Encoding::Json, CodecOperation::Encode, true),
+                ]
+            );
+        }
+    }
+
+    #[cfg(feature = "csv")]
+    mod csv {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_encode_collections_as_csv() {
+            #[axum::debug_handler]
+            async fn handler() -> impl IntoResponse {
+                Negotiate(vec![
+                    Example {
+                        message: "first".to_string(),
+                    },
+                    Example {
+                        message: "second, quoted".to_string(),
+                    },
+                ])
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new());
+
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .uri("/")
+                        .method("POST")
+                        .header(ACCEPT, "text/csv")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), 200);
+            assert_eq!(response.headers().get(CONTENT_TYPE).unwrap(), "text/csv");
+            assert_eq!(
+                response.into_body().collect().await.unwrap().to_bytes(),
+                "message\nfirst\n\"second, quoted\"\n"
+            );
+        }
+
+        #[tokio::test]
+        async fn test_decode_csv_imports() {
+            #[axum::debug_handler]
+            async fn handler(Negotiate(rows): Negotiate<Vec<Example>>) -> impl IntoResponse {
+                Negotiate(rows)
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new().csv_options(crate::CsvOptions::new().delimiter(b';')));
+
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .uri("/")
+                        .method("POST")
+                        .header(CONTENT_TYPE, "text/csv")
+                        .header(ACCEPT, "text/csv")
+                        .body(Body::from("message\nfirst\n\"second; quoted\"\n"))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), 200);
+            assert_eq!(
+                response.into_body().collect().await.unwrap().to_bytes(),
+                "message\nfirst\nsecond; quoted\n"
+            );
+        }
+
+        #[cfg(any(feature = "simd-json", feature = "json"))]
+        #[tokio::test]
+        async fn test_negotiate_another_encoding_for_non_collections() {
+            #[axum::debug_handler]
+            async fn handler() -> impl IntoResponse {
+                Negotiate(Example {
+                    message: "single".to_string(),
+                })
+            }
+
+            let app = Router::new().route("/", post(handler)).layer(
+                NegotiateLayer::new().only_encodings([crate::Encoding::Csv, crate::Encoding::Json]),
+            );
+
+            for (accept, expected) in [
+                (
+                    "text/csv;q=0.9, application/json;q=0.5",
+                    Some("application/json"),
+                ),
+                ("text/*, application/json;q=0.5", Some("application/json")),
+                ("text/csv", None),
+                ("text/*", None),
+            ] {
+                let response = app
+                    .clone()
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .method("POST")
+                            .header(ACCEPT, accept)
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                match expected {
+                    Some(expected) => {
+                        assert_eq!(response.status(), 200, "{accept}");
+                        assert_eq!(response.headers().get(CONTENT_TYPE).unwrap(), expected);
+                    }
+                    None => assert_eq!(response.status(), 406, "{accept}"),
+                }
+            }
+        }
+
+        #[cfg(any(feature = "simd-json", feature = "json"))]
+        #[tokio::test]
+        async fn test_negotiate_another_encoding_for_rows_with_other_fields() {
+            #[axum::debug_handler]
+            async fn handler() -> impl IntoResponse {
+                Negotiate(vec![
+                    std::collections::BTreeMap::from([("message", "first")]),
+                    std::collections::BTreeMap::from([("note", "second")]),
+                ])
+            }
+
+            let response = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new())
+                .oneshot(
+                    Request::builder()
+                        .uri("/")
+                        .method("POST")
+                        .header(ACCEPT, "text/csv;q=0.9, application/json;q=0.5")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), 200);
+            assert_eq!(
+                response.headers().get(CONTENT_TYPE).unwrap(),
+                "application/json"
+            );
+            assert_eq!(
+                response.into_body().collect().await.unwrap().to_bytes(),
+                r#"[{"message":"first"},{"note":"second"}]"#
+            );
+        }
+    }
+
+    #[cfg(feature = "ndjson")]
+    mod ndjson {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_encode_collections_one_document_per_line() {
+            #[axum::debug_handler]
+            async fn handler() -> impl IntoResponse {
+                Negotiate(vec![
+                    Example {
+                        message: "first".to_string(),
+                    },
+                    Example {
+                        message: "second".to_string(),
+                    },
+                ])
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new());
+
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .uri("/")
+                        .method("POST")
+                        .header(ACCEPT, "application/x-ndjson")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), 200);
+            assert_eq!(
+                response.headers().get(CONTENT_TYPE).unwrap(),
+                "application/x-ndjson"
+            );
+            assert_eq!(
+                response.into_body().collect().await.unwrap().to_bytes(),
+                "{\"message\":\"first\"}\n{\"message\":\"second\"}\n"
+            );
+        }
+
+        #[tokio::test]
+        async fn test_encode_single_values_on_one_line() {
+            #[axum::debug_handler]
+            async fn handler() -> impl IntoResponse {
+                Negotiate(Example {
+                    message: "single".to_string(),
+                })
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new());
+
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .uri("/")
+                        .method("POST")
+                        .header(ACCEPT, "application/x-ndjson")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), 200);
+            assert_eq!(
+                response.into_body().collect().await.unwrap().to_bytes(),
+                "{\"message\":\"single\"}\n"
+            );
+        }
+    }
+
+    #[cfg(feature = "cbor-seq")]
+    mod cbor_seq {
+        use super::*;
+        use crate::Encoding;
+
+        #[tokio::test]
+        async fn test_decode_and_encode_concatenated_items() {
+            #[axum::debug_handler]
+            async fn handler(Negotiate(items): Negotiate<Vec<Example>>) -> impl IntoResponse {
+                assert_eq!(items.len(), 2);
+                Negotiate(items)
+            }
+
+            let mut body = Vec::new();
+            for message in ["first", "second"] {
+                let item = Example {
+                    message: message.to_string(),
+                };
+                body.append(&mut Encoding::Cbor.encode(&item).unwrap());
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new());
+
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .uri("/")
+                        .method("POST")
+                        .header(CONTENT_TYPE, "application/cbor-seq")
+                        .header(ACCEPT, "application/cbor-seq")
+                        .body(Body::from(body.clone()))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), 200);
+            assert_eq!(
+                response.headers().get(CONTENT_TYPE).unwrap(),
+                "application/cbor-seq"
+            );
+            assert_eq!(
+                response.into_body().collect().await.unwrap().to_bytes(),
+                body
+            );
+        }
+    }
+
+    #[cfg(all(feature = "flatbuffers", any(feature = "simd-json", feature = "json")))]
+    mod flatbuffers {
+        use super::*;
+        use crate::{NegotiateFlatBuffer, ToFlatBuffer};
+
+        impl ToFlatBuffer for Example {
+            fn to_flatbuffer(&self) -> Vec<u8> {
+                self.message.as_bytes().to_vec()
+            }
+        }
+
+        #[tokio::test]
+        async fn test_mix_flatbuffers_and_serde_clients() {
+            #[axum::debug_handler]
+            async fn handler() -> impl IntoResponse {
+                NegotiateFlatBuffer(Example {
+                    message: "Hello, test!".to_string(),
+                })
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new());
+
+            for (accept, expected) in [
+                ("application/flatbuffers", "Hello, test!"),
+                ("application/json", r#"{"message":"Hello, test!"}"#),
+            ] {
+                let response = app
+                    .clone()
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .method("POST")
+                            .header(ACCEPT, accept)
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), 200);
+                assert_eq!(response.headers().get(CONTENT_TYPE).unwrap(), accept);
+                assert_eq!(
+                    response.into_body().collect().await.unwrap().to_bytes(),
+                    expected
+                );
+            }
+        }
+
+        #[tokio::test]
+        async fn test_negotiate_serde_payloads_without_flatbuffers() {
+            #[axum::debug_handler]
+            async fn handler() -> impl IntoResponse {
+                Negotiate(Example {
+                    message: "Hello, test!".to_string(),
+                })
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new());
+
+            for (accept, expected) in [
+                (
+                    "application/flatbuffers, application/json;q=0.5",
+                    Some("application/json"),
+                ),
+                ("application/flatbuffers", None),
+            ] {
+                let response = app
+                    .clone()
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .method("POST")
+                            .header(ACCEPT, accept)
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                match expected {
+                    Some(expected) => {
+                        assert_eq!(response.status(), 200, "{accept}");
+                        assert_eq!(response.headers().get(CONTENT_TYPE).unwrap(), expected);
+                    }
+                    None => assert_eq!(response.status(), 406, "{accept}"),
+                }
+            }
+        }
+    }
+
+    #[cfg(all(feature = "test-util", any(feature = "simd-json", feature = "json")))]
+    mod injected_failures {
+        use super::*;
+        use crate::test_util::InjectedFailure;
+
+        #[tokio::test]
+        async fn test_answer_injected_failures_as_real_ones() {
+            #[axum::debug_handler]
+            async fn handler(Negotiate(input): Negotiate<Example>) -> impl IntoResponse {
+                Negotiate(input)
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new());
+
+            for (failure, status, message) in [
+                (
+                    InjectedFailure::UnsupportedContentType,
+                    StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                    "Unsupported content type on request",
+                ),
+                (
+                    InjectedFailure::NotAcceptable,
+                    StatusCode::NOT_ACCEPTABLE,
+                    "Unsupported content type on request",
+                ),
+                (
+                    InjectedFailure::Decode,
+                    StatusCode::BAD_REQUEST,
+                    "Malformed request body",
+                ),
+                (
+                    InjectedFailure::Encode,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to serialize response",
+                ),
+            ] {
+                let response = app
+                    .clone()
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .method("POST")
+                            .header(CONTENT_TYPE, "application/json")
+                            .header(ACCEPT, "application/json")
+                            .extension(failure)
+                            .body(Body::from(r#"{ "message": "valid" }"#))
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), status, "{failure:?}");
+                assert_eq!(rejection_message(response).await, message, "{failure:?}");
+            }
+        }
+    }
+
+    #[cfg(all(feature = "octet-stream", feature = "cbor"))]
+    mod octet_stream {
+        use super::*;
+        use crate::NegotiateBytes;
+
+        #[tokio::test]
+        async fn test_inform_freshness_of_cached_blobs() {
+            use std::time::{Duration, SystemTime};
+
+            use axum::{
+                body::Bytes,
+                http::header::{AGE, CACHE_CONTROL},
+            };
+
+            use crate::NegotiateCached;
+
+            #[axum::debug_handler]
+            async fn cached() -> NegotiateCached<NegotiateBytes> {
+                NegotiateCached::new(
+                    NegotiateBytes(Bytes::from_static(b"blob")),
+                    SystemTime::now() - Duration::from_secs(10),
+                )
+            }
+
+            #[axum::debug_handler]
+            async fn private() -> impl IntoResponse {
+                ([(CACHE_CONTROL, "private")], NegotiateBytes(Bytes::new()))
+            }
+
+            let app = Router::new()
+                .route("/cached", post(cached))
+                .route("/private", post(private))
+                .layer(NegotiateLayer::new().max_age(Duration::from_secs(60)));
+            let request = |uri| {
+                Request::builder()
+                    .uri(uri)
+                    .method("POST")
+                    .header(ACCEPT, "application/octet-stream")
+                    .body(Body::empty())
+                    .unwrap()
+            };
+
+            let response = app.clone().oneshot(request("/cached")).await.unwrap();
+            assert_eq!(response.headers().get(CACHE_CONTROL).unwrap(), "max-age=60");
+            let age: u64 = response.headers()[AGE].to_str().unwrap().parse().unwrap();
+            assert!((10..60).contains(&age), "{age}");
+
+            let response = app.oneshot(request("/private")).await.unwrap();
+            assert_eq!(response.headers().get(CACHE_CONTROL).unwrap(), "private");
+            assert!(response.headers().get(AGE).is_none());
+        }
+
+        #[tokio::test]
+        async fn test_pass_blobs_through_or_wrap_them_on_negotiated_encodings() {
+            #[axum::debug_handler]
+            async fn handler(NegotiateBytes(blob): NegotiateBytes) -> impl IntoResponse {
+                NegotiateBytes(blob)
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new());
+
+            for (content_type, accept, body, expected, expected_type) in [
+                (
+                    "application/octet-stream",
+                    "application/octet-stream, application/cbor;q=0.5",
+                    &b"\x00blob"[..],
+                    &b"\x00blob"[..],
+                    "application/octet-stream",
+                ),
+                (
+                    "application/cbor",
+                    "application/cbor",
+                    &b"\x45\x00blob"[..],
+                    &b"\x45\x00blob"[..],
+                    "application/cbor",
+                ),
+                (
+                    "application/octet-stream",
+                    "application/cbor",
+                    &b"\x00blob"[..],
+                    &b"\x45\x00blob"[..],
+                    "application/cbor",
+                ),
+            ] {
+                let response = app
+                    .clone()
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .method("POST")
+                            .header(CONTENT_TYPE, content_type)
+                            .header(ACCEPT, accept)
+                            .body(Body::from(body))
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), 200);
+                assert_eq!(response.headers().get(CONTENT_TYPE).unwrap(), expected_type);
+                assert_eq!(
+                    response.into_body().collect().await.unwrap().to_bytes(),
+                    expected
+                );
+            }
+        }
+
+        #[tokio::test]
+        async fn test_negotiate_serde_payloads_without_octet_stream() {
+            #[axum::debug_handler]
+            async fn handler() -> impl IntoResponse {
+                Negotiate(Example {
+                    message: "Hello, test!".to_string(),
+                })
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new());
+
+            for (accept, expected) in [
+                (
+                    "application/octet-stream, application/cbor;q=0.5",
+                    Some("application/cbor"),
+                ),
+                ("application/octet-stream", None),
+            ] {
+                let response = app
+                    .clone()
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .method("POST")
+                            .header(ACCEPT, accept)
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                match expected {
+                    Some(expected) => {
+                        assert_eq!(response.status(), 200, "{accept}");
+                        assert_eq!(response.headers().get(CONTENT_TYPE).unwrap(), expected);
+                    }
+                    None => assert_eq!(response.status(), 406, "{accept}"),
+                }
+            }
+        }
+    }
+
+    #[cfg(all(feature = "plain-text", any(feature = "simd-json", feature = "json")))]
+    mod plain_text {
+        use super::*;
+        use crate::NegotiatePlainText;
+
+        impl std::fmt::Display for Example {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str(&self.message)
+            }
+        }
+
+        #[tokio::test]
+        async fn test_mix_plain_text_and_serde_clients() {
+            #[axum::debug_handler]
+            async fn handler() -> impl IntoResponse {
+                NegotiatePlainText(Example {
+                    message: "Hello, test!".to_string(),
+                })
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new());
+
+            for (accept, expected) in [
+                ("text/plain", "Hello, test!"),
+                ("application/json", r#"{"message":"Hello, test!"}"#),
+            ] {
+                let response = app
+                    .clone()
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .method("POST")
+                            .header(ACCEPT, accept)
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), 200);
+                assert_eq!(response.headers().get(CONTENT_TYPE).unwrap(), accept);
+                assert_eq!(
+                    response.into_body().collect().await.unwrap().to_bytes(),
+                    expected
+                );
+            }
+        }
+
+        #[tokio::test]
+        async fn test_negotiate_serde_payloads_without_plain_text() {
+            #[axum::debug_handler]
+            async fn handler() -> impl IntoResponse {
+                Negotiate(Example {
+                    message: "Hello, test!".to_string(),
+                })
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new());
+
+            for (accept, expected) in [
+                (
+                    "text/plain, application/json;q=0.5",
+                    Some("application/json"),
+                ),
+                ("text/plain, */*;q=0.1", Some("application/json")),
+                ("text/plain", None),
+            ] {
+                let response = app
+                    .clone()
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .method("POST")
+                            .header(ACCEPT, accept)
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                match expected {
+                    Some(expected) => {
+                        assert_eq!(response.status(), 200, "{accept}");
+                        assert_eq!(response.headers().get(CONTENT_TYPE).unwrap(), expected);
+                    }
+                    None => assert_eq!(response.status(), 406, "{accept}"),
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "hal")]
+    mod hal {
+        use super::*;
+        use crate::{HalLink, HalLinks, NegotiateHal};
+
+        impl HalLinks for Example {
+            fn links(&self) -> Vec<HalLink> {
+                vec![HalLink::new("self", "/")]
+            }
+        }
+
+        #[tokio::test]
+        async fn test_inject_links_when_negotiated() {
+            #[axum::debug_handler]
+            async fn handler() -> impl IntoResponse {
+                NegotiateHal(Example {
+                    message: "Hello, test!".to_string(),
+                })
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new());
+
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .uri("/")
+                        .method("POST")
+                        .header(ACCEPT, "application/hal+json")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), 200);
+            assert_eq!(
+                response.headers().get(CONTENT_TYPE).unwrap(),
+                "application/hal+json"
+            );
+            assert_eq!(
+                response.into_body().collect().await.unwrap().to_bytes(),
+                r#"{"message":"Hello, test!","_links":{"self":{"href":"/"}}}"#
+            );
+        }
+    }
+
+    #[cfg(feature = "feed")]
+    mod feed {
+        use super::*;
+        use crate::{Feed, FeedChannel, FeedEntry, NegotiateFeed};
+
+        impl Feed for Example {
+            fn feed(&self) -> FeedChannel {
+                FeedChannel::new("/", "Examples").entry(FeedEntry::new(
+                    "/1",
+                    &self.message,
+                    std::time::UNIX_EPOCH,
+                ))
+            }
+        }
+
+        #[tokio::test]
+        async fn test_answer_feeds_when_negotiated() {
+            #[axum::debug_handler]
+            async fn handler() -> impl IntoResponse {
+                NegotiateFeed(Example {
+                    message: "Hello, test!".to_string(),
+                })
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new());
+
+            for (accept, root) in [
+                ("application/atom+xml", "<feed "),
+                ("application/rss+xml, application/atom+xml;q=0.5", "<rss "),
+            ] {
+                let response = app
+                    .clone()
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .method("POST")
+                            .header(ACCEPT, accept)
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), 200);
+                let content_type = response.headers().get(CONTENT_TYPE).unwrap().clone();
+                assert_eq!(content_type, accept.split(',').next().unwrap());
+                let body = response.into_body().collect().await.unwrap().to_bytes();
+                let body = std::str::from_utf8(&body).unwrap();
+                assert!(body.contains(root), "{body}");
+                assert!(body.contains("<title>Hello, test!</title>"), "{body}");
+            }
+        }
+
+        #[tokio::test]
+        async fn test_reject_feed_readers_on_serde_routes() {
+            #[axum::debug_handler]
+            async fn handler() -> impl IntoResponse {
+                Negotiate(Example {
+                    message: "Hello, test!".to_string(),
+                })
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new());
+
+            for accept in [
+                "application/atom+xml",
+                "application/rss+xml, application/atom+xml;q=0.5",
+            ] {
+                let response = app
+                    .clone()
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .method("POST")
+                            .header(ACCEPT, accept)
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), 406, "{accept}");
+            }
+        }
+    }
+
+    #[cfg(feature = "geojson")]
+    mod geojson {
+        use super::*;
+
+        #[derive(serde::Serialize)]
+        struct Point {
+            r#type: &'static str,
+            coordinates: Vec<f64>,
+        }
+
+        #[tokio::test]
+        async fn test_echo_geojson_and_validate_payloads() {
+            #[axum::debug_handler]
+            async fn point() -> impl IntoResponse {
+                Negotiate(Point {
+                    r#type: "Point",
+                    coordinates: vec![1.5, 2.0],
+                })
+            }
+
+            #[axum::debug_handler]
+            async fn invalid() -> impl IntoResponse {
+                Negotiate(Example {
+                    message: "Hello, test!".to_string(),
+                })
+            }
+
+            let request = |uri| {
+                Request::builder()
+                    .uri(uri)
+                    .method("POST")
+                    .header(ACCEPT, "application/geo+json")
+                    .body(Body::empty())
+                    .unwrap()
+            };
+            let app = |layer| {
+                Router::new()
+                    .route("/point", post(point))
+                    .route("/invalid", post(invalid))
+                    .layer(layer)
+            };
+
+            let response = app(NegotiateLayer::new().validate_geojson())
+                .oneshot(request("/point"))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 200);
+            assert_eq!(
+                response.headers().get(CONTENT_TYPE).unwrap(),
+                "application/geo+json"
+            );
+            assert_eq!(
+                response.into_body().collect().await.unwrap().to_bytes(),
+                r#"{"type":"Point","coordinates":[1.5,2.0]}"#
+            );
+
+            let response = app(NegotiateLayer::new().validate_geojson())
+                .oneshot(request("/invalid"))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 500);
+
+            let response = app(NegotiateLayer::new())
+                .oneshot(request("/invalid"))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 200);
+        }
+
+        #[tokio::test]
+        async fn test_reject_invalid_geojson_requests() {
+            #[axum::debug_handler]
+            async fn handler(Negotiate(point): Negotiate<serde_json::Value>) -> impl IntoResponse {
+                Negotiate(point)
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new().validate_geojson());
+            let request = |body: &'static str| {
+                Request::builder()
+                    .uri("/")
+                    .method("POST")
+                    .header(CONTENT_TYPE, "application/geo+json")
+                    .header(ACCEPT, "application/json")
+                    .body(Body::from(body))
+                    .unwrap()
+            };
+
+            let response = app
+                .clone()
+                .oneshot(request(r#"{"type":"Point","coordinates":[0,0]}"#))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 200);
+
+            let response = app.oneshot(request(r#"{"type":"Point"}"#)).await.unwrap();
+            assert_eq!(response.status(), 400);
+        }
+    }
+
+    #[cfg(feature = "debug-html")]
+    mod html {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_render_html_for_browsers() {
+            #[axum::debug_handler]
+            async fn handler() -> impl IntoResponse {
+                Negotiate(Example {
+                    message: "Hello, <test>!".to_string(),
+                })
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new());
+
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .uri("/")
+                        .method("POST")
+                        .header(
+                            ACCEPT,
+                            "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8",
+                        )
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), 200);
+            assert_eq!(response.headers().get(CONTENT_TYPE).unwrap(), "text/html");
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            let body = std::str::from_utf8(&body).unwrap();
+            assert!(body.starts_with("<!DOCTYPE html>"), "{body}");
+            assert!(
+                body.contains(
+                    "<table><tr><th>message</th><td>Hello, &lt;test&gt;!</td></tr></table>"
+                ),
+                "{body}"
+            );
+        }
+    }
+
+    #[cfg(feature = "xlsx")]
+    mod xlsx {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_export_collections_as_workbooks() {
+            #[axum::debug_handler]
+            async fn handler() -> impl IntoResponse {
+                Negotiate(vec![Example {
+                    message: "Hello, test!".to_string(),
+                }])
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new());
+            let media_type = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet";
+
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .uri("/")
+                        .method("POST")
+                        .header(ACCEPT, media_type)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), 200);
+            assert_eq!(response.headers().get(CONTENT_TYPE).unwrap(), media_type);
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            assert!(body.starts_with(b"PK\x03\x04"));
+            // Entries are stored uncompressed, so the worksheet can be found as is
+            let body = String::from_utf8_lossy(&body);
+            assert!(
+                body.contains("<t xml:space=\"preserve\">message</t>"),
+                "{body}"
+            );
+            assert!(
+                body.contains("<t xml:space=\"preserve\">Hello, test!</t>"),
+                "{body}"
+            );
+        }
+
+        #[tokio::test]
+        async fn test_negotiate_another_encoding_for_non_collections() {
+            #[axum::debug_handler]
+            async fn handler() -> impl IntoResponse {
+                Negotiate(Example {
+                    message: "single".to_string(),
+                })
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new());
+            let media_type = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet";
+
+            for (accept, expected) in [
+                #[cfg(any(feature = "simd-json", feature = "json"))]
+                (
+                    format!("{media_type}, application/json;q=0.5"),
+                    Some("application/json"),
+                ),
+                (media_type.to_string(), None),
+            ] {
+                let response = app
+                    .clone()
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .method("POST")
+                            .header(ACCEPT, &accept)
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                match expected {
+                    Some(expected) => {
+                        assert_eq!(response.status(), 200, "{accept}");
+                        assert_eq!(response.headers().get(CONTENT_TYPE).unwrap(), expected);
+                    }
+                    None => assert_eq!(response.status(), 406, "{accept}"),
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "jsonp")]
+    mod jsonp {
+        use super::*;
+
+        async fn get(app: Router, uri: &str, accept: &str) -> (StatusCode, String, String) {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .uri(uri)
+                        .method("POST")
+                        .header(ACCEPT, accept)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let status = response.status();
+            let content_type = response
+                .headers()
+                .get(CONTENT_TYPE)
+                .map(|v| v.to_str().unwrap().to_string())
+                .unwrap_or_default();
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            (
+                status,
+                content_type,
+                String::from_utf8(body.to_vec()).unwrap(),
+            )
+        }
+
+        #[tokio::test]
+        async fn test_wrap_json_in_the_query_callback() {
+            #[axum::debug_handler]
+            async fn handler() -> impl IntoResponse {
+                Negotiate(Example {
+                    message: "Hello, test!".to_string(),
+                })
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new().jsonp("callback"));
+
+            let (status, content_type, body) =
+                get(app.clone(), "/?callback=render", "application/javascript").await;
+            assert_eq!(status, 200);
+            assert_eq!(content_type, "application/javascript");
+            assert_eq!(body, r#"/**/render({"message":"Hello, test!"});"#);
+
+            let (status, content_type, body) =
+                get(app.clone(), "/?callback=render", "application/json").await;
+            assert_eq!(status, 200);
+            assert_eq!(content_type, "application/json");
+            assert_eq!(body, r#"{"message":"Hello, test!"}"#);
+
+            let (status, _, body) = get(app, "/?callback=alert(1)", "text/javascript").await;
+            assert_eq!(status, 400);
+            assert_eq!(body, "Missing or invalid JSONP callback");
+        }
+
+        #[tokio::test]
+        async fn test_never_negotiate_jsonp_unless_configured() {
+            #[axum::debug_handler]
+            async fn handler() -> impl IntoResponse {
+                Negotiate(Example {
+                    message: "Hello, test!".to_string(),
+                })
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new());
+
+            let (status, _, body) = get(app, "/?callback=render", "application/javascript").await;
+            assert_eq!(status, 406);
+            assert_eq!(
+                body.lines().next(),
+                Some("Content type disabled on this service")
+            );
+        }
+    }
+
+    #[cfg(all(feature = "multipart", any(feature = "simd-json", feature = "json")))]
+    mod multipart {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_decode_form_fields() {
+            #[axum::debug_handler]
+            async fn handler(Negotiate(example): Negotiate<Example>) -> impl IntoResponse {
+                Negotiate(example)
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new());
+
+            let body = concat!(
+                "--boundary\r\n",
+                "Content-Disposition: form-data; name=\"message\"\r\n",
+                "\r\n",
+                "Hello, test!\r\n",
+                "--boundary--\r\n",
+            );
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .uri("/")
+                        .method("POST")
+                        .header(CONTENT_TYPE, "multipart/form-data; boundary=boundary")
+                        .header(ACCEPT, "application/json")
+                        .body(Body::from(body))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), 200);
+            assert_eq!(
+                response.into_body().collect().await.unwrap().to_bytes(),
+                r#"{"message":"Hello, test!"}"#
+            );
+        }
+
+        #[tokio::test]
+        async fn test_never_negotiate_multipart_responses() {
+            #[axum::debug_handler]
+            async fn handler() -> impl IntoResponse {
+                StatusCode::NO_CONTENT
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new());
+
+            for (accept, expected) in [
+                ("multipart/form-data, application/json;q=0.5", 204),
+                ("multipart/form-data", 406),
+            ] {
+                let response = app
+                    .clone()
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .method("POST")
+                            .header(ACCEPT, accept)
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), expected, "{accept}");
+            }
+        }
+    }
+
+    #[cfg(all(feature = "cbor", any(feature = "simd-json", feature = "json")))]
+    mod path_extensions {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_route_path_extensions_as_the_accepted_format() {
+            #[axum::debug_handler]
+            async fn handler() -> impl IntoResponse {
+                Negotiate(Example {
+                    message: "Hello, test!".to_string(),
+                })
+            }
+
+            let negotiate = NegotiateLayer::new();
+            let router = Router::new()
+                .route("/orders", post(handler))
+                .layer(negotiate.clone());
+            let app = tower::Layer::layer(&negotiate.path_extensions(), router);
+
+            for (uri, status, content_type) in [
+                ("/orders.cbor", 200, Some("application/cbor")),
+                ("/orders.json?page=2", 200, Some("application/json")),
+                ("/orders", 200, Some("application/json")),
+                ("/orders.xml", 404, None),
+            ] {
+                let response = app
+                    .clone()
+                    .oneshot(
+                        Request::builder()
+                            .uri(uri)
+                            .method("POST")
+                            .header(ACCEPT, "application/json")
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), status, "{uri}");
+                assert_eq!(
+                    response
+                        .headers()
+                        .get(CONTENT_TYPE)
+                        .map(|v| v.to_str().unwrap()),
+                    content_type,
+                    "{uri}"
+                );
+            }
+        }
+
+        #[tokio::test]
+        async fn test_serve_the_format_named_on_the_query() {
+            #[axum::debug_handler]
+            async fn handler() -> impl IntoResponse {
+                Negotiate(Example {
+                    message: "Hello, test!".to_string(),
+                })
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new().format_parameter("format"));
+
+            for (uri, status, content_type) in [
+                ("/?page=2&format=cbor", 200, Some("application/cbor")),
+                ("/?format=json", 200, Some("application/json")),
+                ("/", 200, Some("application/json")),
+                ("/?format=xml", 406, Some("text/plain; charset=utf-8")),
+            ] {
+                let response = app
+                    .clone()
+                    .oneshot(
+                        Request::builder()
+                            .uri(uri)
+                            .method("POST")
+                            .header(ACCEPT, "application/json")
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), status, "{uri}");
+                assert_eq!(
+                    response
+                        .headers()
+                        .get(CONTENT_TYPE)
+                        .map(|v| v.to_str().unwrap()),
+                    content_type,
+                    "{uri}"
+                );
+            }
+        }
+    }
+
+    #[cfg(any(feature = "simd-json", feature = "json"))]
+    mod composition {
+        use axum::{
+            http::{
+                header::{CONTENT_ENCODING, VARY},
+                HeaderValue,
+            },
+            middleware::map_response,
+            response::Response,
+        };
+
+        use super::*;
+
+        async fn outer_inspects_headers(mut response: Response) -> Response {
+            let seen = response.headers().get(CONTENT_LENGTH).cloned();
+            if let Some(seen) = seen {
+                response
+                    .headers_mut()
+                    .insert("x-outer-content-length", seen);
+            }
+            response
+        }
+
+        async fn inner_sets_headers(mut response: Response) -> Response {
+            response
+                .headers_mut()
+                .append(VARY, HeaderValue::from_static("origin"));
+            response
+                .headers_mut()
+                .insert("x-inner", HeaderValue::from_static("kept"));
+            response
+        }
+
+        #[tokio::test]
+        async fn test_outer_layer_reads_negotiation_outcome() {
+            use crate::{Encoding, NegotiationOutcome};
+
+            async fn access_log(mut response: Response) -> Response {
+                let outcome = response.extensions().get::<NegotiationOutcome>().cloned();
+                if let Some(outcome) = outcome {
+                    assert_eq!(outcome.encoding, Encoding::Json);
+                    assert!(!outcome.fallback);
+                    response
+                        .headers_mut()
+                        .insert("x-served", outcome.media_type);
+                }
+                response
+            }
+
+            #[axum::debug_handler]
+            async fn handler() -> impl IntoResponse {
+                Negotiate(Example {
+                    message: "Hello, test!".to_string(),
+                })
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new())
+                .layer(map_response(access_log));
+
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .uri("/")
+                        .method("POST")
+                        .header(ACCEPT, "application/json")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), 200);
+            assert_eq!(
+                response.headers().get("x-served").unwrap(),
+                "application/json"
+            );
+        }
+
+        #[cfg(any(feature = "simd-json", feature = "json"))]
+        #[tokio::test]
+        async fn test_pass_through_responses_of_nested_layers() {
+            #[axum::debug_handler]
+            async fn handler() -> impl IntoResponse {
+                Negotiate(Example {
+                    message: "Hello, test!".to_string(),
+                })
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new())
+                .layer(
+                    NegotiateLayer::new()
+                        .strict_negotiation(Vec::<String>::new())
+                        .etag(crate::ETagPolicy::Strong),
+                );
+
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .uri("/")
+                        .method("POST")
+                        .header(ACCEPT, "application/json")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), 200);
+            assert_eq!(
+                response.headers().get(CONTENT_TYPE).unwrap(),
+                "application/json"
+            );
+            assert_eq!(response.headers().get(CONTENT_LENGTH).unwrap(), "26");
+            assert!(response.headers().get(axum::http::header::ETAG).is_none());
+            assert_eq!(
+                response.into_body().collect().await.unwrap().to_bytes(),
+                r#"{"message":"Hello, test!"}"#
+            );
+        }
+
+        #[cfg(feature = "cbor")]
+        #[tokio::test]
+        async fn test_route_layer_overrides_default_encoding() {
+            use crate::Encoding;
+
+            #[axum::debug_handler]
+            async fn handler() -> impl IntoResponse {
+                Negotiate(Example {
+                    message: "Hello, test!".to_string(),
+                })
+            }
+
+            let app = Router::new()
+                .route(
+                    "/export",
+                    post(handler)
+                        .route_layer(NegotiateLayer::new().default_encoding(Encoding::Cbor)),
+                )
+                .route("/api", post(handler))
+                .layer(NegotiateLayer::new().default_encoding(Encoding::Json));
+
+            for (uri, expected) in [
+                ("/export", "application/cbor"),
+                ("/api", "application/json"),
+            ] {
+                let response = app
+                    .clone()
+                    .oneshot(
+                        Request::builder()
+                            .uri(uri)
+                            .method("POST")
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), 200);
+                assert_eq!(response.headers().get(CONTENT_TYPE).unwrap(), expected);
+            }
+        }
+
+        #[cfg(feature = "cbor")]
+        #[tokio::test]
+        async fn test_route_layer_restricts_encodings() {
+            use crate::Encoding;
+
+            #[axum::debug_handler]
+            async fn handler(Negotiate(input): Negotiate<Example>) -> impl IntoResponse {
+                Negotiate(input)
+            }
+
+            let app = Router::new()
+                .route(
+                    "/internal",
+                    post(handler)
+                        .route_layer(NegotiateLayer::new().only_encodings([Encoding::Cbor])),
+                )
+                .route("/api", post(handler))
+                .layer(NegotiateLayer::new().default_encoding(Encoding::Json));
+
+            let cbor = Encoding::Cbor
+                .encode(&Example {
+                    message: "Hello, test!".to_string(),
+                })
+                .unwrap();
+            let json = r#"{"message":"Hello, test!"}"#.as_bytes().to_vec();
+
+            for (uri, media_type, body, status) in [
+                ("/internal", "application/cbor", cbor.clone(), 200),
+                ("/internal", "application/json", json.clone(), 406),
+                ("/api", "application/cbor", cbor, 200),
+                ("/api", "application/json", json, 200),
+            ] {
+                let response = app
+                    .clone()
+                    .oneshot(
+                        Request::builder()
+                            .uri(uri)
+                            .method("POST")
+                            .header(CONTENT_TYPE, media_type)
+                            .header(ACCEPT, media_type)
+                            .body(Body::from(body))
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), status, "{uri} {media_type}");
+                if status == 406 {
+                    assert_eq!(
+                        rejection_message(response).await,
+                        "Content type disabled on this service"
+                    );
+                }
+            }
+        }
+
+        #[cfg(feature = "cbor")]
+        #[tokio::test]
+        async fn test_typed_route_restricts_declared_formats() {
+            use crate::{formats::Cbor, Encoding, NegotiatedRoute};
+
+            type InternalRoute = NegotiatedRoute<crate::Formats![Cbor]>;
+
+            let app = Router::new()
+                .route(
+                    "/internal",
+                    post(|| async {
+                        Negotiate(Example {
+                            message: "Hello, test!".to_string(),
+                        })
+                    }),
+                )
+                .route_layer(InternalRoute::from_layer(
+                    NegotiateLayer::new().default_encoding(Encoding::Json),
+                ));
+
+            assert_eq!(InternalRoute::ENCODINGS, [Encoding::Cbor]);
+            assert_eq!(
+                InternalRoute::new().metadata().response_media_types,
+                ["application/cbor", "application/x-cbor"]
+            );
+            for (accept, status) in [("application/json", 406), ("*/*", 200)] {
+                let response = app
+                    .clone()
+                    .oneshot(
+                        Request::builder()
+                            .uri("/internal")
+                            .method("POST")
+                            .header(ACCEPT, accept)
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), status, "{accept}");
+                if status == 200 {
+                    assert_eq!(
+                        response.headers().get(CONTENT_TYPE).unwrap(),
+                        "application/cbor"
+                    );
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn test_outer_auth_layer_negotiates_challenge() {
+            use axum::{extract::Request, http::header::WWW_AUTHENTICATE, middleware::Next};
+
+            async fn auth(request: Request, next: Next) -> Response {
+                if request.headers().contains_key("authorization") {
+                    return next.run(request).await;
+                }
+                let challenge = (
+                    StatusCode::UNAUTHORIZED,
+                    [(WWW_AUTHENTICATE, "Bearer")],
+                    Negotiate(Example {
+                        message: "missing credentials".to_string(),
+                    }),
+                );
+                NegotiateLayer::new()
+                    .negotiate_response(request.headers(), challenge.into_response())
+            }
+
+            #[axum::debug_handler]
+            async fn handler() -> impl IntoResponse {
+                unimplemented!("This should not be called");
+                #[allow(unreachable_code)]
+                ()
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new())
+                .layer(axum::middleware::from_fn(auth));
+
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .uri("/")
+                        .method("POST")
+                        .header(ACCEPT, "application/json")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), 401);
+            assert_eq!(response.headers().get(WWW_AUTHENTICATE).unwrap(), "Bearer");
+            assert_eq!(
+                response.headers().get(CONTENT_TYPE).unwrap(),
+                "application/json"
+            );
+            assert_eq!(
+                response.into_body().collect().await.unwrap().to_bytes(),
+                r#"{"message":"missing credentials"}"#
+            );
+        }
+
+        #[tokio::test]
+        async fn test_outer_layers_see_serialized_content_length() {
+            #[axum::debug_handler]
+            async fn handler() -> impl IntoResponse {
+                Negotiate(Example {
+                    message: "Hello, test!".to_string(),
+                })
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new())
+                .layer(map_response(outer_inspects_headers));
+
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .uri("/")
+                        .method("POST")
+                        .header(ACCEPT, "application/json")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            let expected_body = serde_json::json!({ "message": "Hello, test!" }).to_string();
+            assert_eq!(
+                response.headers().get("x-outer-content-length").unwrap(),
+                &expected_body.len().to_string()
+            );
+        }
+
+        #[tokio::test]
+        async fn test_inner_layers_headers_are_kept() {
+            #[axum::debug_handler]
+            async fn handler() -> impl IntoResponse {
+                Negotiate(Example {
+                    message: "Hello, test!".to_string(),
+                })
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(map_response(inner_sets_headers))
+                .layer(NegotiateLayer::new());
+
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .uri("/")
+                        .method("POST")
+                        .header(ACCEPT, "application/json")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.headers().get("x-inner").unwrap(), "kept");
+            assert_eq!(
+                response.headers().get_all(VARY).iter().collect::<Vec<_>>(),
+                vec!["origin, accept"]
+            );
+        }
+
+        #[tokio::test]
+        async fn test_merge_vary_of_inner_layers_into_one_header() {
+            // tower-http is not a dependency, so its layers are reproduced by the headers they append
+            async fn set_response_header(mut response: Response) -> Response {
+                response
+                    .headers_mut()
+                    .append(VARY, HeaderValue::from_static("origin"));
+                response
+            }
+
+            async fn compression(mut response: Response) -> Response {
+                response
+                    .headers_mut()
+                    .append(VARY, HeaderValue::from_static("accept-encoding"));
+                response
+            }
+
+            #[axum::debug_handler]
+            async fn handler() -> impl IntoResponse {
+                Negotiate(Example {
+                    message: "Hello, test!".to_string(),
+                })
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(map_response(set_response_header))
+                .layer(map_response(compression))
+                .layer(NegotiateLayer::new());
+
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .uri("/")
+                        .method("POST")
+                        .header(ACCEPT, "application/json")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(
+                response.headers().get_all(VARY).iter().collect::<Vec<_>>(),
+                vec!["origin, accept-encoding, accept"]
+            );
+        }
+
+        #[tokio::test]
+        async fn test_drop_content_encoding_of_inner_compression() {
+            // Like a `CompressionLayer` compressing the placeholder body of the handler, with `compress_when(Always)`
+            async fn compression(mut response: Response) -> Response {
+                let headers = response.headers_mut();
+                headers.insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+                headers.append(VARY, HeaderValue::from_static("accept-encoding"));
+                response
+            }
+
+            #[axum::debug_handler]
+            async fn handler() -> impl IntoResponse {
+                Negotiate(Example {
+                    message: "Hello, test!".to_string(),
+                })
+            }
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(map_response(compression))
+                .layer(NegotiateLayer::new());
+
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .uri("/")
+                        .method("POST")
+                        .header(ACCEPT, "application/json")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.headers().get(CONTENT_ENCODING), None);
+            assert_eq!(
+                response.headers().get_all(VARY).iter().collect::<Vec<_>>(),
+                vec!["accept-encoding, accept"]
+            );
+            assert_eq!(
+                response.into_body().collect().await.unwrap().to_bytes(),
+                r#"{"message":"Hello, test!"}"#
+            );
+        }
+
+        #[tokio::test]
+        async fn test_vary_on_accept_once() {
+            async fn varies_on_accept(mut response: Response) -> Response {
+                response
+                    .headers_mut()
+                    .append(VARY, HeaderValue::from_static("Origin, Accept"));
+                response
+            }
+
+            #[axum::debug_handler]
+            async fn handler() -> impl IntoResponse {
+                Negotiate(Example {
+                    message: "Hello, test!".to_string(),
+                })
+            }
+
+            let request = || {
+                Request::builder()
+                    .uri("/")
+                    .method("POST")
+                    .header(ACCEPT, "application/json")
+                    .body(Body::empty())
+                    .unwrap()
+            };
+            let vary = |response: Response| {
+                response
+                    .headers()
+                    .get_all(VARY)
+                    .iter()
+                    .map(|value| value.to_str().unwrap().to_string())
+                    .collect::<Vec<_>>()
+            };
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new());
+            let response = app.oneshot(request()).await.unwrap();
+            assert_eq!(vary(response), vec!["accept"]);
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(map_response(varies_on_accept))
+                .layer(NegotiateLayer::new());
+            let response = app.oneshot(request()).await.unwrap();
+            assert_eq!(vary(response), vec!["Origin, Accept"]);
+
+            let app = Router::new()
+                .route("/", post(handler))
+                .layer(NegotiateLayer::new().no_vary_accept());
+            let response = app.oneshot(request()).await.unwrap();
+            assert!(vary(response).is_empty());
+        }
+    }
+
+    #[cfg(any(feature = "simd-json", feature = "json"))]
+    mod json {
+        use serde_json::json;
+
+        use super::*;
+
+        mod input {
+            use super::*;
+
+            #[cfg(feature = "default-json")]
+            #[tokio::test]
+            async fn test_can_read_input_without_content_type_by_default() {
+                #[axum::debug_handler]
+                async fn handler(Negotiate(input): Negotiate<Example>) -> impl IntoResponse {
+                    format!("Hello, {}!", input.message)
+                }
+
+                let app = Router::new().route("/", post(handler));
+
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .method("POST")
+                            .body(json!({ "message": "test" }).to_string())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), 200);
+                assert_eq!(
+                    response.into_body().collect().await.unwrap().to_bytes(),
+                    "Hello, test!"
+                );
+            }
+
+            #[tokio::test]
+            async fn test_accept_content_type_with_parameters() {
+                #[axum::debug_handler]
+                async fn handler(Negotiate(input): Negotiate<Example>) -> impl IntoResponse {
+                    format!("Hello, {}!", input.message)
+                }
+
+                let app = Router::new()
+                    .route("/", post(handler))
+                    .layer(NegotiateLayer::new());
+
+                for (content_type, status) in [
+                    ("application/json; charset=utf-8", StatusCode::OK),
+                    ("Application/JSON;charset=\"UTF-8\"", StatusCode::OK),
+                    (
+                        "application/json; charset=us-ascii; foo=bar",
+                        StatusCode::OK,
+                    ),
+                    (
+                        "application/json; charset=iso-8859-1",
+                        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                    ),
+                ] {
+                    let response = app
+                        .clone()
+                        .oneshot(
+                            Request::builder()
+                                .uri("/")
+                                .method("POST")
+                                .header(CONTENT_TYPE, content_type)
+                                .header(ACCEPT, "application/json")
+                                .body(json!({ "message": "test" }).to_string())
+                                .unwrap(),
+                        )
+                        .await
+                        .unwrap();
+
+                    assert_eq!(response.status(), status, "{content_type}");
+                }
+            }
+
+            #[tokio::test]
+            async fn test_read_content_type_parameters() {
+                use axum::Extension;
+
+                #[derive(Clone)]
+                struct SchemaVersion(String);
+
+                #[axum::debug_handler]
+                async fn handler(
+                    Extension(SchemaVersion(version)): Extension<SchemaVersion>,
+                    Negotiate(input): Negotiate<Example>,
+                ) -> impl IntoResponse {
+                    format!("Hello, {} on {version}!", input.message)
+                }
+
+                let app = Router::new().route("/", post(handler)).layer(
+                    NegotiateLayer::new().content_type_parameters(|parameters| {
+                        parameters
+                            .iter()
+                            .find(|(name, _)| name.eq_ignore_ascii_case("schema"))
+                            .map(|(_, version)| SchemaVersion(version.to_string()))
+                    }),
+                );
+
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .header(CONTENT_TYPE, "application/json; Schema=2024-06")
+                            .header(ACCEPT, "application/json")
+                            .method("POST")
+                            .body(json!({ "message": "test" }).to_string())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), 200);
+                assert_eq!(
+                    response.into_body().collect().await.unwrap().to_bytes(),
+                    "Hello, test on 2024-06!"
+                );
+            }
+
+            #[tokio::test]
+            async fn test_read_input_with_runtime_decoder() {
+                use crate::{BodyDecoders, Encoding};
+
+                #[axum::debug_handler]
+                async fn handler(Negotiate(input): Negotiate<String>) -> impl IntoResponse {
+                    format!("Hello, {input}!")
+                }
+
+                let decoders = BodyDecoders::new();
+                let app = Router::new()
+                    .route("/", post(handler))
+                    .layer(NegotiateLayer::new().body_decoders(decoders.clone()));
+
+                let request = || {
+                    Request::builder()
+                        .uri("/")
+                        .header(CONTENT_TYPE, "text/plain; charset=utf-8")
+                        .header(ACCEPT, "application/json")
+                        .method("POST")
+                        .body(Body::from("test"))
+                        .unwrap()
+                };
+
+                let response = app.clone().oneshot(request()).await.unwrap();
+                assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+                decoders.register("text/plain", Encoding::Json, |body| {
+                    let text = std::str::from_utf8(&body)?;
+                    Ok(serde_json::to_vec(text)?.into())
+                });
+
+                let response = app.clone().oneshot(request()).await.unwrap();
+                assert_eq!(response.status(), 200);
+                assert_eq!(
+                    response.into_body().collect().await.unwrap().to_bytes(),
+                    "Hello, test!"
+                );
+
+                assert!(decoders.unregister("text/plain"));
+                let response = app.oneshot(request()).await.unwrap();
+                assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+            }
+
+            #[tokio::test]
+            async fn test_can_read_input_with_specified_header() {
+                #[axum::debug_handler]
+                async fn handler(Negotiate(input): Negotiate<Example>) -> impl IntoResponse {
+                    format!("Hello, {}!", input.message)
+                }
+
+                let app = Router::new().route("/", post(handler));
+
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .header(CONTENT_TYPE, "application/json")
+                            .method("POST")
+                            .body(json!({ "message": "test" }).to_string())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), 200);
+                assert_eq!(
+                    response.into_body().collect().await.unwrap().to_bytes(),
+                    "Hello, test!"
+                );
+            }
+
+            #[tokio::test]
+            async fn test_can_read_input_with_seed_from_state() {
+                use axum::extract::FromRef;
+                use serde::de::{Deserialize, DeserializeSeed, Deserializer};
+
+                use crate::NegotiateSeed;
+
+                #[derive(Clone)]
+                struct Greeting(&'static str);
+
+                impl<'de> DeserializeSeed<'de> for Greeting {
+                    type Value = String;
+
+                    fn deserialize<D: Deserializer<'de>>(
+                        self,
+                        deserializer: D,
+                    ) -> Result<String, D::Error> {
+                        let input = Example::deserialize(deserializer)?;
+                        Ok(format!("{}, {}!", self.0, input.message))
+                    }
+                }
+
+                #[derive(Clone)]
+                struct AppState {
+                    greeting: Greeting,
+                }
+
+                impl FromRef<AppState> for Greeting {
+                    fn from_ref(state: &AppState) -> Self {
+                        state.greeting.clone()
+                    }
+                }
+
+                #[axum::debug_handler(state = AppState)]
+                async fn handler(
+                    NegotiateSeed(message): NegotiateSeed<Greeting>,
+                ) -> impl IntoResponse {
+                    message
+                }
+
+                let app = Router::new()
+                    .route("/", post(handler))
+                    .layer(NegotiateLayer::new())
+                    .with_state(AppState {
+                        greeting: Greeting("Welcome"),
+                    });
+
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .header(ACCEPT, "application/json")
+                            .header(CONTENT_TYPE, "application/json")
+                            .method("POST")
+                            .body(json!({ "message": "test" }).to_string())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), 200);
+                assert_eq!(
+                    response.into_body().collect().await.unwrap().to_bytes(),
+                    "Welcome, test!"
+                );
+            }
+
+            #[cfg(feature = "default-json")]
+            #[tokio::test]
+            async fn test_does_not_accept_invalid_inputs() {
+                #[axum::debug_handler]
+                async fn handler(_: Negotiate<Example>) -> impl IntoResponse {
+                    unimplemented!("This should not be called");
+                    #[allow(unreachable_code)]
+                    ()
+                }
+
+                let app = Router::new()
+                    .route("/", post(handler))
+                    .layer(NegotiateLayer::new());
+
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .method("POST")
+                            .header(CONTENT_TYPE, "application/json")
+                            .body(json!({ "not": true }).to_string())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), 400);
+                assert_eq!(
+                    response.into_body().collect().await.unwrap().to_bytes(),
+                    "Malformed request body"
+                );
+            }
+
+            #[cfg(feature = "json-patch")]
+            #[tokio::test]
+            async fn test_apply_json_patch_bodies() {
+                #[axum::debug_handler]
+                async fn handler(patch: crate::JsonPatch) -> impl IntoResponse {
+                    let current = Example {
+                        message: "Hello".to_string(),
+                    };
+                    Negotiate(patch.apply(&current).unwrap())
+                }
+
+                let app = Router::new()
+                    .route("/", axum::routing::patch(handler))
+                    .layer(NegotiateLayer::new());
+
+                for (content_type, status) in [
+                    ("application/json-patch+json", StatusCode::OK),
+                    ("application/json", StatusCode::UNSUPPORTED_MEDIA_TYPE),
+                ] {
+                    let response = app
+                        .clone()
+                        .oneshot(
+                            Request::builder()
+                                .uri("/")
+                                .method("PATCH")
+                                .header(CONTENT_TYPE, content_type)
+                                .header(ACCEPT, "application/json")
+                                .body(
+                                    json!([{ "op": "replace", "path": "/message", "value": "Hi" }])
+                                        .to_string(),
+                                )
+                                .unwrap(),
+                        )
+                        .await
+                        .unwrap();
+
+                    assert_eq!(response.status(), status);
+                    if status == StatusCode::OK {
+                        assert_eq!(
+                            response.into_body().collect().await.unwrap().to_bytes(),
+                            r#"{"message":"Hi"}"#
+                        );
+                    } else {
+                        assert_eq!(
+                            response.headers().get("accept-patch").unwrap(),
+                            "application/json-patch+json"
+                        );
+                    }
+                }
+            }
+
+            #[tokio::test]
+            async fn test_decode_lazy_body_only_when_accessed() {
+                #[axum::debug_handler]
+                async fn handler(
+                    headers: axum::http::HeaderMap,
+                    input: crate::LazyNegotiate<Example>,
+                ) -> Result<String, axum::response::Response> {
+                    if !headers.contains_key("x-allowed") {
+                        return Ok("denied".to_string());
+                    }
+                    let message = &input.get()?.message;
+                    Ok(format!("Hello, {message}!"))
+                }
+
+                let app = Router::new()
+                    .route("/", post(handler))
+                    .layer(NegotiateLayer::new());
+
+                for (allowed, body, status, expected) in [
+                    (false, "{", StatusCode::OK, "denied"),
+                    (true, "{", StatusCode::BAD_REQUEST, "Malformed request body"),
+                    (
+                        true,
+                        r#"{"message":"test"}"#,
+                        StatusCode::OK,
+                        "Hello, test!",
+                    ),
+                ] {
+                    let mut request = Request::builder()
+                        .uri("/")
+                        .method("POST")
+                        .header(CONTENT_TYPE, "application/json")
+                        .header(ACCEPT, "application/json");
+                    if allowed {
+                        request = request.header("x-allowed", "1");
+                    }
+
+                    let response = app
+                        .clone()
+                        .oneshot(request.body(Body::from(body)).unwrap())
+                        .await
+                        .unwrap();
+
+                    assert_eq!(response.status(), status);
+                    assert_eq!(
+                        response.into_body().collect().await.unwrap().to_bytes(),
+                        expected
+                    );
+                }
+            }
+
+            #[tokio::test]
+            async fn test_use_problem_details_for_rejections() {
+                #[axum::debug_handler]
+                async fn handler(_: Negotiate<Example>) -> impl IntoResponse {
+                    unimplemented!("This should not be called");
+                    #[allow(unreachable_code)]
+                    ()
+                }
+
+                let app = Router::new()
+                    .route("/", post(handler))
+                    .layer(NegotiateLayer::new().problem_details());
+
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .method("POST")
+                            .header(CONTENT_TYPE, "application/json")
+                            .header(ACCEPT, "application/json")
+                            .header("x-request-id", "abc-123")
+                            .body(json!({ "not": true }).to_string())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), 400);
+                assert_eq!(
+                    response.headers().get(CONTENT_TYPE).unwrap(),
+                    "application/problem+json"
+                );
+                assert_eq!(
+                    response.into_body().collect().await.unwrap().to_bytes(),
+                    r#"{"type":"about:blank","title":"Bad Request","status":400,"detail":"Malformed request body","code":"malformed_body","request_id":"abc-123"}"#
+                );
+            }
+
+            #[tokio::test]
+            async fn test_list_supported_media_types_on_rejections() {
+                use crate::Encoding;
+
+                #[axum::debug_handler]
+                async fn handler(_: Negotiate<Example>) -> impl IntoResponse {
+                    unimplemented!("This should not be called");
+                    #[allow(unreachable_code)]
+                    ()
+                }
+
+                let decoded = Encoding::ALL
+                    .iter()
+                    .filter(|encoding| encoding.decodes())
+                    .map(|encoding| encoding.media_type())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let response = Router::new()
+                    .route("/", post(handler))
+                    .layer(NegotiateLayer::new())
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .method("POST")
+                            .header(CONTENT_TYPE, "application/xml")
+                            .header(ACCEPT, "application/json")
+                            .body(Body::from("<not/>"))
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(
+                    response.headers().get("accept-post").unwrap(),
+                    decoded.as_str()
+                );
+                assert!(response.headers().get(ACCEPT).is_none());
+                assert_eq!(
+                    response.into_body().collect().await.unwrap().to_bytes(),
+                    format!(
+                        "Unsupported content type on request\nSupported media types: {decoded}"
+                    )
+                );
+
+                let response = Router::new()
+                    .route("/", post(handler))
+                    .layer(NegotiateLayer::new().problem_details())
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .method("POST")
+                            .header(CONTENT_TYPE, "application/json")
+                            .header(ACCEPT, "application/xml")
+                            .body(json!({ "message": "valid" }).to_string())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), 406);
+                assert!(response.headers().get(ACCEPT).is_none());
+                let body: serde_json::Value = serde_json::from_slice(
+                    &response.into_body().collect().await.unwrap().to_bytes(),
+                )
+                .unwrap();
+                assert!(body["supported"]
+                    .as_array()
+                    .unwrap()
+                    .contains(&json!("application/json")));
+            }
+
+            #[tokio::test]
+            async fn test_use_error_template_for_rejections() {
+                #[axum::debug_handler]
+                async fn handler(_: Negotiate<Example>) -> impl IntoResponse {
+                    unimplemented!("This should not be called");
+                    #[allow(unreachable_code)]
+                    ()
+                }
+
+                #[derive(serde::Serialize)]
+                struct ApiError {
+                    status: u16,
+                    code: &'static str,
+                    request_id: Option<String>,
+                }
+
+                let app = Router::new().route("/", post(handler)).layer(
+                    NegotiateLayer::new().error_body(|details| ApiError {
+                        status: details.status.as_u16(),
+                        code: details.code,
+                        request_id: details.request_id.map(str::to_string),
+                    }),
+                );
+
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .method("POST")
+                            .header(CONTENT_TYPE, "application/json")
+                            .header(ACCEPT, "application/json")
+                            .header("x-request-id", "abc-123")
+                            .body(json!({ "not": true }).to_string())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), 400);
+                assert_eq!(
+                    response.headers().get(CONTENT_TYPE).unwrap(),
+                    "application/json"
+                );
+                assert_eq!(
+                    response.into_body().collect().await.unwrap().to_bytes(),
+                    r#"{"status":400,"code":"malformed_body","request_id":"abc-123"}"#
+                );
+            }
+        }
+
+        mod output {
+            use super::*;
+
+            #[derive(Clone)]
+            struct Currency(&'static str);
+
+            struct Price(u64);
+
+            impl crate::SerializeWith<Currency> for Price {
+                fn serialize_with<S: serde::Serializer>(
+                    &self,
+                    context: &Currency,
+                    serializer: S,
+                ) -> Result<S::Ok, S::Error> {
+                    serializer.collect_str(&format_args!("{} {}", self.0, context.0))
+                }
+            }
+
+            #[axum::debug_handler]
+            async fn price_handler() -> crate::NegotiateWith<Currency, Price> {
+                crate::NegotiateWith::new(Price(42))
+            }
+
+            #[tokio::test]
+            async fn test_answer_problem_details_with_their_status() {
+                #[axum::debug_handler]
+                async fn handler() -> crate::ProblemDetails {
+                    crate::ProblemDetails::new(StatusCode::UNSUPPORTED_MEDIA_TYPE)
+                        .type_uri("https://example.com/probs/unsupported")
+                        .extension("accepted", ["application/json"])
+                }
+
+                let app = Router::new()
+                    .route("/", post(handler))
+                    .layer(NegotiateLayer::new());
+
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .method("POST")
+                            .header(ACCEPT, "application/json")
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+                assert_eq!(
+                    response.headers().get(CONTENT_TYPE).unwrap(),
+                    "application/problem+json"
+                );
+                assert_eq!(
+                    response.into_body().collect().await.unwrap().to_bytes(),
+                    r#"{"type":"https://example.com/probs/unsupported","title":"Unsupported Media Type","status":415,"accepted":["application/json"]}"#
+                );
+            }
+
+            #[tokio::test]
+            async fn test_answer_api_errors_with_the_status_of_their_code() {
+                #[axum::debug_handler]
+                async fn handler(
+                    axum::extract::Path(code): axum::extract::Path<String>,
+                ) -> crate::ApiError {
+                    crate::ApiError::new(code, "Something failed").detail("retry", false)
+                }
+
+                let codes = crate::ErrorCodes::new()
+                    .code("not_found", StatusCode::NOT_FOUND)
+                    .unknown(StatusCode::BAD_REQUEST);
+                let app = Router::new()
+                    .route("/{code}", post(handler))
+                    .layer(NegotiateLayer::new().error_codes(codes));
+
+                for (code, status) in [
+                    ("not_found", StatusCode::NOT_FOUND),
+                    ("other", StatusCode::BAD_REQUEST),
+                ] {
+                    let response = app
+                        .clone()
+                        .oneshot(
+                            Request::builder()
+                                .uri(format!("/{code}"))
+                                .method("POST")
+                                .header(ACCEPT, "application/json")
+                                .body(Body::empty())
+                                .unwrap(),
+                        )
+                        .await
+                        .unwrap();
+
+                    assert_eq!(response.status(), status);
+                    assert_eq!(
+                        response.headers().get(CONTENT_TYPE).unwrap(),
+                        "application/json"
+                    );
+                    assert_eq!(
+                        response.into_body().collect().await.unwrap().to_bytes(),
+                        format!(
+                            r#"{{"code":"{code}","message":"Something failed","details":{{"retry":false}}}}"#
+                        )
+                    );
+                }
+            }
+
+            #[tokio::test]
+            async fn test_strict_layer_rejects_bypassed_negotiation() {
+                async fn bypassed() -> impl IntoResponse {
+                    axum::Json(Example {
+                        message: "Hello, test!".to_string(),
+                    })
+                }
+
+                async fn negotiated(Negotiate(example): Negotiate<Example>) -> impl IntoResponse {
+                    Negotiate(example)
+                }
+
+                async fn page() -> impl IntoResponse {
+                    axum::response::Html("<p>Hello, test!</p>")
+                }
+
+                let app = Router::new()
+                    .route("/bypassed", post(bypassed))
+                    .route("/negotiated", post(negotiated))
+                    .route("/page", post(page))
+                    .layer(NegotiateLayer::new().strict_negotiation(["text/html"]));
+
+                for (uri, body, status) in [
+                    ("/bypassed", "", StatusCode::INTERNAL_SERVER_ERROR),
+                    (
+                        "/negotiated",
+                        r#"{"message":"Hello, test!"}"#,
+                        StatusCode::OK,
+                    ),
+                    ("/negotiated", "{", StatusCode::BAD_REQUEST),
+                    ("/page", "", StatusCode::OK),
+                ] {
+                    let response = app
+                        .clone()
+                        .oneshot(
+                            Request::builder()
+                                .uri(uri)
+                                .method("POST")
+                                .header(CONTENT_TYPE, "application/json")
+                                .header(ACCEPT, "application/json")
+                                .body(Body::from(body))
+                                .unwrap(),
+                        )
+                        .await
+                        .unwrap();
+
+                    assert_eq!(response.status(), status, "{uri} {body}");
+                }
+            }
+
+            #[tokio::test]
+            async fn test_download_named_after_encoding() {
+                #[axum::debug_handler]
+                async fn handler() -> crate::NegotiateDownload<Example> {
+                    crate::NegotiateDownload::new(
+                        "report",
+                        Example {
+                            message: "Hello, test!".to_string(),
+                        },
+                    )
+                }
+
+                let app = Router::new()
+                    .route("/", post(handler))
+                    .layer(NegotiateLayer::new());
+
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .method("POST")
+                            .header(ACCEPT, "application/json")
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), 200);
+                assert_eq!(
+                    response
+                        .headers()
+                        .get(axum::http::header::CONTENT_DISPOSITION)
+                        .unwrap(),
+                    "attachment; filename=\"report.json\""
+                );
+                assert_eq!(
+                    response.into_body().collect().await.unwrap().to_bytes(),
+                    r#"{"message":"Hello, test!"}"#
+                );
+            }
+
+            #[tokio::test]
+            async fn test_encode_inline_and_spill_over_threshold() {
+                #[axum::debug_handler]
+                async fn handler() -> impl IntoResponse {
+                    Negotiate(Example {
+                        message: "Hello, test!".to_string(),
+                    })
+                }
+
+                // The body is 26 bytes long, fitting only the first threshold
+                for threshold in [64, 8] {
+                    let app = Router::new()
+                        .route("/", post(handler))
+                        .layer(NegotiateLayer::new().inline_response_size(threshold));
+
+                    let response = app
+                        .oneshot(
+                            Request::builder()
+                                .uri("/")
+                                .method("POST")
+                                .header(ACCEPT, "application/json")
+                                .body(Body::empty())
+                                .unwrap(),
+                        )
+                        .await
+                        .unwrap();
+
+                    assert_eq!(response.status(), 200);
+                    assert_eq!(content_length(response.headers()), 26);
+                    assert_eq!(
+                        response.into_body().collect().await.unwrap().to_bytes(),
+                        r#"{"message":"Hello, test!"}"#
+                    );
+                }
+            }
+
+            #[tokio::test]
+            async fn test_encode_with_layer_context() {
+                let app = Router::new()
+                    .route("/", post(price_handler))
+                    .layer(NegotiateLayer::new().serialization_context(Currency("EUR")));
+
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .method("POST")
+                            .header(ACCEPT, "application/json")
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), 200);
+                assert_eq!(
+                    response.headers().get(CONTENT_TYPE).unwrap(),
+                    "application/json"
+                );
+                assert_eq!(
+                    response.into_body().collect().await.unwrap().to_bytes(),
+                    r#""42 EUR""#
+                );
+            }
+
+            #[tokio::test]
+            async fn test_reject_responses_over_size_limit() {
+                #[axum::debug_handler]
+                async fn handler() -> impl IntoResponse {
+                    Negotiate(Example {
+                        message: "Hello, test!".to_string(),
+                    })
+                }
+
+                #[derive(serde::Serialize)]
+                struct ApiError {
+                    code: &'static str,
+                }
+
+                let app = Router::new().route("/", post(handler)).layer(
+                    NegotiateLayer::new()
+                        .max_response_size(16)
+                        .error_body(|details| ApiError { code: details.code }),
+                );
+
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .method("POST")
+                            .header(ACCEPT, "application/json")
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), 507);
+                assert_eq!(
+                    response.headers().get(CONTENT_TYPE).unwrap(),
+                    "application/json"
+                );
+                assert_eq!(
+                    response.into_body().collect().await.unwrap().to_bytes(),
+                    r#"{"code":"response_too_large"}"#
+                );
+            }
+
+            #[tokio::test]
+            async fn test_fail_to_encode_without_layer_context() {
+                let app = Router::new()
+                    .route("/", post(price_handler))
+                    .layer(NegotiateLayer::new());
+
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .method("POST")
+                            .header(ACCEPT, "application/json")
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), 500);
+                assert_eq!(
+                    response.into_body().collect().await.unwrap().to_bytes(),
+                    "Failed to serialize response"
+                );
+            }
+
+            #[tokio::test]
+            async fn test_encode_as_requested() {
+                #[axum::debug_handler]
+                async fn handler() -> impl IntoResponse {
+                    Negotiate(Example {
+                        message: "Hello, test!".to_string(),
+                    })
+                }
+
+                let app = Router::new()
+                    .route("/", post(handler))
+                    .layer(NegotiateLayer::new());
+
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .method("POST")
+                            .header(ACCEPT, "application/json")
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                let expected_body = json!({ "message": "Hello, test!" }).to_string();
+
+                assert_eq!(response.status(), 200);
+                assert_eq!(
+                    response.headers().get(CONTENT_TYPE).unwrap(),
+                    "application/json"
+                );
+                assert_eq!(content_length(response.headers()), expected_body.len());
+                assert_eq!(
+                    response.into_body().collect().await.unwrap().to_bytes(),
+                    expected_body,
+                );
+            }
+
+            #[cfg(feature = "default-json")]
+            #[tokio::test]
+            async fn test_use_default_encoding_without_headers() {
+                #[axum::debug_handler]
+                async fn handler() -> impl IntoResponse {
+                    Negotiate(Example {
+                        message: "Hello, test!".to_string(),
+                    })
+                }
+
+                let app = Router::new()
+                    .route("/", post(handler))
+                    .layer(NegotiateLayer::new());
+
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .method("POST")
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), 200);
+                assert_eq!(
+                    response.headers().get(CONTENT_TYPE).unwrap(),
+                    "application/json"
+                );
+                assert_eq!(
+                    response.into_body().collect().await.unwrap().to_bytes(),
+                    json!({ "message": "Hello, test!" }).to_string()
+                );
+            }
+
+            #[cfg(feature = "default-json")]
+            #[tokio::test]
+            async fn test_retain_handler_status_code() {
+                #[axum::debug_handler]
+                async fn handler() -> impl IntoResponse {
+                    (
+                        StatusCode::CREATED,
+                        Negotiate(Example {
+                            message: "Hello, test!".to_string(),
+                        }),
+                    )
+                }
+
+                let app = Router::new()
+                    .route("/", post(handler))
+                    .layer(NegotiateLayer::new());
+
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .method("POST")
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
                     .unwrap();
 
-                assert_eq!(response.status(), 406);
+                assert_eq!(response.status(), StatusCode::CREATED);
+                assert_eq!(
+                    response.headers().get(CONTENT_TYPE).unwrap(),
+                    "application/json"
+                );
                 assert_eq!(
                     response.into_body().collect().await.unwrap().to_bytes(),
-                    "Invalid content type on request"
+                    json!({ "message": "Hello, test!" }).to_string()
                 );
             }
         }
+    }
 
-        mod output {
+    #[cfg(feature = "cbor")]
+    mod cbor {
+        use cbor4ii::core::{enc::Encode, utils::BufWriter, Value};
+
+        use super::*;
+        use crate::Encoding;
+
+        mod input {
             use super::*;
 
+            #[cfg(all(feature = "default-cbor", not(feature = "default-json")))]
             #[tokio::test]
-            async fn test_inform_error_when_misconfigured() {
+            async fn test_can_read_input_without_content_type_by_default() {
                 #[axum::debug_handler]
-                async fn handler() -> impl IntoResponse {
-                    Negotiate(Example {
-                        message: "Hello, test!".to_string(),
-                    })
+                async fn handler(Negotiate(input): Negotiate<Example>) -> impl IntoResponse {
+                    format!("Hello, {}!", input.message)
                 }
 
                 let app = Router::new().route("/", post(handler));
+                let body = {
+                    let mut writer = BufWriter::new(Vec::new());
+                    Value::Map(vec![(
+                        Value::Text("message".to_string()),
+                        Value::Text("test".to_string()),
+                    )])
+                    .encode(&mut writer)
+                    .unwrap();
+                    writer.into_inner()
+                };
 
                 let response = app
                     .oneshot(
                         Request::builder()
                             .uri("/")
                             .method("POST")
+                            .body(Body::from(body))
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), 200);
+                assert_eq!(
+                    response.into_body().collect().await.unwrap().to_bytes(),
+                    "Hello, test!"
+                );
+            }
+
+            #[cfg(any(feature = "simd-json", feature = "json"))]
+            #[tokio::test]
+            async fn test_wrap_services_without_clone() {
+                use tower::Layer;
+
+                // Not `Clone`, as services holding a connection or a mutex may be
+                let calls = std::sync::Mutex::new(0);
+                let service = tower::service_fn(move |_: Request<Body>| {
+                    *calls.lock().unwrap() += 1;
+                    async {
+                        Ok::<_, std::convert::Infallible>(Negotiate(Example {
+                            message: "Hello, test!".to_string(),
+                        }))
+                    }
+                });
+
+                let response = NegotiateLayer::new()
+                    .layer(service)
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .header(ACCEPT, "application/json")
                             .body(Body::empty())
                             .unwrap(),
                     )
                     .await
                     .unwrap();
 
-                assert_eq!(response.status(), 415);
                 assert_eq!(
                     response.into_body().collect().await.unwrap().to_bytes(),
-                    "Misconfigured service layer"
+                    r#"{"message":"Hello, test!"}"#
                 );
             }
 
+            #[cfg(any(feature = "simd-json", feature = "json"))]
             #[tokio::test]
-            async fn test_does_not_process_handler_if_accept_is_not_supported() {
+            async fn test_hash_the_same_payload_identically_across_encodings() {
+                use std::hash::{DefaultHasher, Hasher};
+
+                use axum::Extension;
+
+                use crate::PayloadHash;
+
                 #[axum::debug_handler]
-                async fn handler() -> impl IntoResponse {
-                    unimplemented!("This should not be called");
-                    #[allow(unreachable_code)]
-                    ()
+                async fn handler(
+                    Extension(PayloadHash(hash)): Extension<PayloadHash<u64>>,
+                ) -> impl IntoResponse {
+                    hash.to_string()
+                }
+
+                let app = Router::new().route("/", post(handler)).layer(
+                    NegotiateLayer::new().payload_hash(|canonical| {
+                        let mut hasher = DefaultHasher::new();
+                        hasher.write(canonical);
+                        hasher.finish()
+                    }),
+                );
+                let cbor = {
+                    let mut writer = BufWriter::new(Vec::new());
+                    Value::Map(vec![
+                        (Value::Text("count".to_string()), Value::Integer(1)),
+                        (
+                            Value::Text("message".to_string()),
+                            Value::Text("test".to_string()),
+                        ),
+                    ])
+                    .encode(&mut writer)
+                    .unwrap();
+                    writer.into_inner()
+                };
+
+                let mut hashes = Vec::new();
+                for (content_type, body) in [
+                    ("application/cbor", cbor),
+                    (
+                        "application/json",
+                        br#"{ "message": "test", "count": 1 }"#.to_vec(),
+                    ),
+                    (
+                        "application/json",
+                        br#"{ "message": "test", "count": 2 }"#.to_vec(),
+                    ),
+                ] {
+                    let response = app
+                        .clone()
+                        .oneshot(
+                            Request::builder()
+                                .uri("/")
+                                .method("POST")
+                                .header(CONTENT_TYPE, content_type)
+                                .header(ACCEPT, "application/json")
+                                .body(Body::from(body))
+                                .unwrap(),
+                        )
+                        .await
+                        .unwrap();
+
+                    assert_eq!(response.status(), 200);
+                    hashes.push(response.into_body().collect().await.unwrap().to_bytes());
+                }
+
+                assert_eq!(hashes[0], hashes[1]);
+                assert_ne!(hashes[1], hashes[2]);
+            }
+
+            #[cfg(any(feature = "simd-json", feature = "json"))]
+            #[tokio::test]
+            async fn test_hash_payloads_as_the_extractors_read_them() {
+                use std::hash::{DefaultHasher, Hasher};
+
+                use axum::{extract::DefaultBodyLimit, Extension};
+
+                use crate::{BodyDecoders, PayloadHash};
+
+                #[axum::debug_handler]
+                async fn handler(
+                    Extension(PayloadHash(hash)): Extension<PayloadHash<u64>>,
+                ) -> impl IntoResponse {
+                    hash.to_string()
+                }
+
+                let decoders = BodyDecoders::new();
+                decoders.register("application/x-plugin", Encoding::Json, Ok);
+                let app = Router::new()
+                    .route("/", post(handler))
+                    .layer(NegotiateLayer::new().body_decoders(decoders).payload_hash(
+                        |canonical| {
+                            let mut hasher = DefaultHasher::new();
+                            hasher.write(canonical);
+                            hasher.finish()
+                        },
+                    ))
+                    .layer(DefaultBodyLimit::max(64));
+
+                let mut hashes = Vec::new();
+                for (content_type, body, status) in [
+                    (
+                        "application/json",
+                        r#"{ "message": "test" }"#.to_string(),
+                        200,
+                    ),
+                    (
+                        "application/x-plugin",
+                        r#"{"message":"test"}"#.to_string(),
+                        200,
+                    ),
+                    (
+                        "application/json",
+                        format!(r#"{{ "message": "{}" }}"#, "a".repeat(64)),
+                        413,
+                    ),
+                ] {
+                    let response = app
+                        .clone()
+                        .oneshot(
+                            Request::builder()
+                                .uri("/")
+                                .method("POST")
+                                .header(CONTENT_TYPE, content_type)
+                                .header(ACCEPT, "application/json")
+                                .body(Body::from(body))
+                                .unwrap(),
+                        )
+                        .await
+                        .unwrap();
+
+                    assert_eq!(response.status(), status, "{content_type}");
+                    hashes.push(response.into_body().collect().await.unwrap().to_bytes());
+                }
+
+                assert_eq!(hashes[0], hashes[1]);
+            }
+
+            #[cfg(any(feature = "simd-json", feature = "json"))]
+            #[tokio::test]
+            async fn test_sample_payload_fields_per_route() {
+                use std::sync::{Arc, Mutex};
+
+                use crate::audit::{FieldType, SchemaSample};
+
+                #[axum::debug_handler]
+                async fn handler(Negotiate(input): Negotiate<Example>) -> impl IntoResponse {
+                    input.message
+                }
+
+                let samples = Arc::new(Mutex::new(Vec::new()));
+                let recorded = samples.clone();
+                let app = Router::new().route("/users/{id}", post(handler)).layer(
+                    NegotiateLayer::new().audit_schemas(2, move |sample: SchemaSample| {
+                        recorded.lock().unwrap().push(sample);
+                    }),
+                );
+
+                for body in [
+                    r#"{ "message": "first", "legacy": 1 }"#,
+                    r#"{ "message": "second", "legacy": 2 }"#,
+                    r#"{ "message": "third" }"#,
+                ] {
+                    let response = app
+                        .clone()
+                        .oneshot(
+                            Request::builder()
+                                .uri("/users/42")
+                                .method("POST")
+                                .header(CONTENT_TYPE, "application/json")
+                                .header(ACCEPT, "application/json")
+                                .body(Body::from(body))
+                                .unwrap(),
+                        )
+                        .await
+                        .unwrap();
+                    assert_eq!(response.status(), 200);
+                }
+
+                let samples = samples.lock().unwrap();
+                assert_eq!(samples.len(), 2);
+                assert_eq!(samples[0].route.as_deref(), Some("/users/{id}"));
+                assert_eq!(samples[0].encoding, crate::Encoding::Json);
+                assert_eq!(
+                    samples[0].fields,
+                    vec![
+                        ("message".to_string(), FieldType::Text),
+                        ("legacy".to_string(), FieldType::Integer),
+                    ]
+                );
+                assert_eq!(
+                    samples[1].fields,
+                    vec![("message".to_string(), FieldType::Text)]
+                );
+            }
+
+            #[tokio::test]
+            async fn test_can_read_input_with_specified_header() {
+                #[axum::debug_handler]
+                async fn handler(Negotiate(input): Negotiate<Example>) -> impl IntoResponse {
+                    format!("Hello, {}!", input.message)
+                }
+
+                let app = Router::new().route("/", post(handler));
+                let body = {
+                    let mut writer = BufWriter::new(Vec::new());
+                    Value::Map(vec![(
+                        Value::Text("message".to_string()),
+                        Value::Text("test".to_string()),
+                    )])
+                    .encode(&mut writer)
+                    .unwrap();
+                    writer.into_inner()
+                };
+
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .uri("/")
+                            .header(CONTENT_TYPE, "application/cbor")
+                            .method("POST")
+                            .body(Body::from(body))
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(response.status(), 200);
+                assert_eq!(
+                    response.into_body().collect().await.unwrap().to_bytes(),
+                    "Hello, test!"
+                );
+            }
+            #[tokio::test]
+            async fn test_can_read_input_with_configured_default() {
+                #[axum::debug_handler]
+                async fn handler(Negotiate(input): Negotiate<Example>) -> impl IntoResponse {
+                    format!("Hello, {}!", input.message)
                 }
 
                 let app = Router::new()
                     .route("/", post(handler))
-                    .layer(NegotiateLayer);
+                    .layer(NegotiateLayer::new().default_encoding(Encoding::Cbor));
+                let body = {
+                    let mut writer = BufWriter::new(Vec::new());
+                    Value::Map(vec![(
+                        Value::Text("message".to_string()),
+                        Value::Text("test".to_string()),
+                    )])
+                    .encode(&mut writer)
+                    .unwrap();
+                    writer.into_inner()
+                };
 
                 let response = app
                     .oneshot(
                         Request::builder()
                             .uri("/")
-                            .header(ACCEPT, "non-supported")
+                            .header(ACCEPT, "application/cbor")
                             .method("POST")
-                            .body(Body::empty())
+                            .body(Body::from(body))
                             .unwrap(),
                     )
                     .await
                     .unwrap();
 
-                assert_eq!(response.status(), 406);
+                assert_eq!(response.status(), 200);
                 assert_eq!(
                     response.into_body().collect().await.unwrap().to_bytes(),
-                    "Invalid content type on request"
+                    "Hello, test!"
                 );
             }
         }
-    }
 
-    #[cfg(any(feature = "simd-json", feature = "json"))]
-    mod json {
-        use serde_json::json;
+        mod output {
+            use super::*;
 
-        use super::*;
+            #[cfg(any(feature = "simd-json", feature = "json"))]
+            #[tokio::test]
+            async fn test_exclude_encodings_for_quirky_clients() {
+                #[axum::debug_handler]
+                async fn handler() -> impl IntoResponse {
+                    Negotiate(Example {
+                        message: "Hello, test!".to_string(),
+                    })
+                }
 
-        mod input {
-            use super::*;
+                let quirks = crate::ClientQuirks::new().user_agent("acme-sdk/1.", [Encoding::Cbor]);
+                let app = Router::new()
+                    .route("/", post(handler))
+                    .layer(NegotiateLayer::new().client_quirks(quirks));
+
+                for (user_agent, expected) in [
+                    ("acme-sdk/1.4", "application/json"),
+                    ("acme-sdk/2.0", "application/cbor"),
+                ] {
+                    let response = app
+                        .clone()
+                        .oneshot(
+                            Request::builder()
+                                .uri("/")
+                                .method("POST")
+                                .header(ACCEPT, "application/cbor, application/json;q=0.5")
+                                .header(axum::http::header::USER_AGENT, user_agent)
+                                .body(Body::empty())
+                                .unwrap(),
+                        )
+                        .await
+                        .unwrap();
+
+                    assert_eq!(response.status(), 200);
+                    assert_eq!(response.headers().get(CONTENT_TYPE).unwrap(), expected);
+                }
+            }
 
-            #[cfg(feature = "default-json")]
             #[tokio::test]
-            async fn test_can_read_input_without_content_type_by_default() {
+            async fn test_infer_default_encoding_for_known_clients() {
                 #[axum::debug_handler]
-                async fn handler(Negotiate(input): Negotiate<Example>) -> impl IntoResponse {
-                    format!("Hello, {}!", input.message)
+                async fn handler() -> impl IntoResponse {
+                    Negotiate(Example {
+                        message: "Hello, test!".to_string(),
+                    })
                 }
 
-                let app = Router::new().route("/", post(handler));
+                let quirks =
+                    crate::ClientQuirks::new().default_for_user_agent("curl/", Encoding::Cbor);
+                let app = Router::new()
+                    .route("/", post(handler))
+                    .layer(NegotiateLayer::new().client_quirks(quirks));
+
+                for accept in [None, Some("*/*"), Some("application/json;q=0.1, */*")] {
+                    let mut request = Request::builder()
+                        .uri("/")
+                        .method("POST")
+                        .header(axum::http::header::USER_AGENT, "curl/8.5.0");
+                    if let Some(accept) = accept {
+                        request = request.header(ACCEPT, accept);
+                    }
+                    let response = app
+                        .clone()
+                        .oneshot(request.body(Body::empty()).unwrap())
+                        .await
+                        .unwrap();
+
+                    assert_eq!(response.status(), 200, "{accept:?}");
+                    assert_eq!(
+                        response.headers().get(CONTENT_TYPE).unwrap(),
+                        "application/cbor",
+                        "{accept:?}"
+                    );
+                }
 
                 let response = app
                     .oneshot(
                         Request::builder()
                             .uri("/")
                             .method("POST")
-                            .body(json!({ "message": "test" }).to_string())
+                            .header(ACCEPT, "*/*")
+                            .header(axum::http::header::USER_AGENT, "acme-sdk/1.4")
+                            .body(Body::empty())
                             .unwrap(),
                     )
                     .await
                     .unwrap();
 
-                assert_eq!(response.status(), 200);
-                assert_eq!(
-                    response.into_body().collect().await.unwrap().to_bytes(),
-                    "Hello, test!"
-                );
+                match Encoding::compiled_default() {
+                    Some(default) => assert_eq!(
+                        response.headers().get(CONTENT_TYPE).unwrap(),
+                        default.media_type()
+                    ),
+                    None => assert_eq!(response.status(), 406),
+                }
             }
 
+            #[cfg(any(feature = "simd-json", feature = "json"))]
             #[tokio::test]
-            async fn test_can_read_input_with_specified_header() {
+            async fn test_encode_representation_for_encoding() {
+                use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+                use crate::{DeserializeFor, NegotiateFor, SerializeFor};
+
+                #[derive(Serialize, Deserialize)]
+                #[serde(tag = "type")]
+                enum Shape {
+                    Circle { radius: u32 },
+                }
+
+                #[derive(Serialize, Deserialize)]
+                struct CompactShape(u8, u32);
+
+                impl SerializeFor for Shape {
+                    fn serialize_for<S: Serializer>(
+                        &self,
+                        encoding: Encoding,
+                        serializer: S,
+                    ) -> Result<S::Ok, S::Error> {
+                        match (encoding, self) {
+                            (Encoding::Cbor, Shape::Circle { radius }) => {
+                                CompactShape(0, *radius).serialize(serializer)
+                            }
+                            _ => self.serialize(serializer),
+                        }
+                    }
+                }
+
+                impl DeserializeFor for Shape {
+                    fn deserialize_for<'de, D: Deserializer<'de>>(
+                        encoding: Encoding,
+                        deserializer: D,
+                    ) -> Result<Self, D::Error> {
+                        match encoding {
+                            Encoding::Cbor => {
+                                let CompactShape(_, radius) =
+                                    CompactShape::deserialize(deserializer)?;
+                                Ok(Shape::Circle { radius })
+                            }
+                            _ => Shape::deserialize(deserializer),
+                        }
+                    }
+                }
+
                 #[axum::debug_handler]
-                async fn handler(Negotiate(input): Negotiate<Example>) -> impl IntoResponse {
-                    format!("Hello, {}!", input.message)
+                async fn handler(NegotiateFor(shape): NegotiateFor<Shape>) -> impl IntoResponse {
+                    NegotiateFor(shape)
                 }
 
-                let app = Router::new().route("/", post(handler));
+                let app = Router::new()
+                    .route("/", post(handler))
+                    .layer(NegotiateLayer::new());
 
                 let response = app
                     .oneshot(
                         Request::builder()
                             .uri("/")
-                            .header(CONTENT_TYPE, "application/json")
                             .method("POST")
-                            .body(json!({ "message": "test" }).to_string())
+                            .header(CONTENT_TYPE, "application/json")
+                            .header(ACCEPT, "application/cbor")
+                            .body(Body::from(r#"{"type":"Circle","radius":2}"#))
                             .unwrap(),
                     )
                     .await
                     .unwrap();
 
+                let expected_body = {
+                    let mut writer = BufWriter::new(Vec::new());
+                    Value::Array(vec![Value::Integer(0), Value::Integer(2)])
+                        .encode(&mut writer)
+                        .unwrap();
+                    writer.into_inner()
+                };
+
                 assert_eq!(response.status(), 200);
                 assert_eq!(
                     response.into_body().collect().await.unwrap().to_bytes(),
-                    "Hello, test!"
+                    expected_body,
                 );
             }
 
+            #[cfg(any(feature = "simd-json", feature = "json"))]
             #[tokio::test]
-            async fn test_does_not_accept_invalid_inputs() {
+            async fn test_etag_policies_across_representations() {
+                use axum::http::header::{ETAG, IF_NONE_MATCH};
+
+                use crate::ETagPolicy;
+
                 #[axum::debug_handler]
-                async fn handler(_: Negotiate<Example>) -> impl IntoResponse {
-                    unimplemented!("This should not be called");
-                    #[allow(unreachable_code)]
-                    ()
+                async fn handler() -> impl IntoResponse {
+                    Negotiate(Example {
+                        message: "Hello, test!".to_string(),
+                    })
                 }
 
-                let app = Router::new()
-                    .route("/", post(handler))
-                    .layer(NegotiateLayer);
+                async fn etag(policy: ETagPolicy, accept: &str) -> String {
+                    let app = Router::new()
+                        .route("/", axum::routing::get(handler))
+                        .layer(NegotiateLayer::new().etag(policy));
+
+                    let response = app
+                        .oneshot(
+                            Request::builder()
+                                .uri("/")
+                                .header(ACCEPT, accept)
+                                .body(Body::empty())
+                                .unwrap(),
+                        )
+                        .await
+                        .unwrap();
+                    assert_eq!(response.status(), 200);
+                    response
+                        .headers()
+                        .get(ETAG)
+                        .unwrap()
+                        .to_str()
+                        .unwrap()
+                        .to_string()
+                }
+
+                let weak = etag(ETagPolicy::Weak, "application/json").await;
+                assert!(weak.starts_with("W/"));
+                assert_eq!(weak, etag(ETagPolicy::Weak, "application/cbor").await);
+                assert_ne!(
+                    etag(ETagPolicy::Strong, "application/json").await,
+                    etag(ETagPolicy::Strong, "application/cbor").await
+                );
 
+                let app = Router::new()
+                    .route("/", axum::routing::get(handler))
+                    .layer(NegotiateLayer::new().etag(ETagPolicy::Weak));
                 let response = app
                     .oneshot(
                         Request::builder()
                             .uri("/")
-                            .method("POST")
-                            .header(CONTENT_TYPE, "application/json")
-                            .body(json!({ "not": true }).to_string())
+                            .header(ACCEPT, "application/cbor")
+                            .header(IF_NONE_MATCH, &weak)
+                            .body(Body::empty())
                             .unwrap(),
                     )
                     .await
                     .unwrap();
 
-                assert_eq!(response.status(), 400);
-                assert_eq!(
-                    response.into_body().collect().await.unwrap().to_bytes(),
-                    "Malformed request body"
-                );
-            }
-        }
+                assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+                assert_eq!(response.headers().get(ETAG).unwrap(), weak.as_str());
+                assert!(response
+                    .into_body()
+                    .collect()
+                    .await
+                    .unwrap()
+                    .to_bytes()
+                    .is_empty());
 
-        mod output {
-            use super::*;
+                // Only safe methods are answered with 304 Not Modified
+                let strong = etag(ETagPolicy::Strong, "application/json").await;
+                let app = Router::new()
+                    .route("/", axum::routing::get(handler).post(handler))
+                    .layer(NegotiateLayer::new().etag(ETagPolicy::Strong));
+                for (method, status) in [
+                    ("GET", StatusCode::NOT_MODIFIED),
+                    ("HEAD", StatusCode::NOT_MODIFIED),
+                    ("POST", StatusCode::OK),
+                ] {
+                    let response = app
+                        .clone()
+                        .oneshot(
+                            Request::builder()
+                                .uri("/")
+                                .method(method)
+                                .header(ACCEPT, "application/json")
+                                .header(IF_NONE_MATCH, format!("\"stale\", {strong}"))
+                                .body(Body::empty())
+                                .unwrap(),
+                        )
+                        .await
+                        .unwrap();
+
+                    assert_eq!(response.status(), status, "{method}");
+                }
+            }
 
             #[tokio::test]
-            async fn test_encode_as_requested() {
+            async fn test_encode_deterministic_cbor() {
+                use serde::ser::{SerializeSeq, Serializer};
+
+                use crate::CborProfile;
+
+                /// Sequence of unknown length, which the backend encodes with an indefinite length
+                #[derive(Clone)]
+                struct Unsized;
+
+                impl serde::Serialize for Unsized {
+                    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                        let mut seq = serializer.serialize_seq(None)?;
+                        seq.serialize_element(&1)?;
+                        seq.end()
+                    }
+                }
+
+                #[derive(Clone, serde::Serialize)]
+                struct Unsorted {
+                    b: u8,
+                    a: Unsized,
+                }
+
                 #[axum::debug_handler]
                 async fn handler() -> impl IntoResponse {
-                    Negotiate(Example {
-                        message: "Hello, test!".to_string(),
-                    })
+                    Negotiate(Unsorted { b: 2, a: Unsized })
                 }
 
                 let app = Router::new()
                     .route("/", post(handler))
-                    .layer(NegotiateLayer);
+                    .layer(NegotiateLayer::new().cbor_profile(CborProfile::Deterministic));
 
                 let response = app
                     .oneshot(
                         Request::builder()
                             .uri("/")
                             .method("POST")
-                            .header(ACCEPT, "application/json")
+                            .header(ACCEPT, "application/cbor")
                             .body(Body::empty())
                             .unwrap(),
                     )
                     .await
                     .unwrap();
 
-                let expected_body = json!({ "message": "Hello, test!" }).to_string();
+                let expected_body = {
+                    let mut writer = BufWriter::new(Vec::new());
+                    Value::Map(vec![
+                        (
+                            Value::Text("a".to_string()),
+                            Value::Array(vec![Value::Integer(1)]),
+                        ),
+                        (Value::Text("b".to_string()), Value::Integer(2)),
+                    ])
+                    .encode(&mut writer)
+                    .unwrap();
+                    writer.into_inner()
+                };
 
                 assert_eq!(response.status(), 200);
-                assert_eq!(
-                    response.headers().get(CONTENT_TYPE).unwrap(),
-                    "application/json"
-                );
-                assert_eq!(content_length(response.headers()), expected_body.len());
                 assert_eq!(
                     response.into_body().collect().await.unwrap().to_bytes(),
                     expected_body,
                 );
             }
 
-            #[cfg(feature = "default-json")]
+            #[cfg(any(feature = "simd-json", feature = "json"))]
             #[tokio::test]
-            async fn test_use_default_encoding_without_headers() {
+            async fn test_fallback_when_type_opts_out_of_encoding() {
+                use serde::{ser::Error, Serialize, Serializer};
+
+                use crate::{NegotiateFor, SerializeFor};
+
+                #[derive(Serialize)]
+                struct JsonOnly {
+                    message: String,
+                }
+
+                impl SerializeFor for JsonOnly {
+                    fn serialize_for<S: Serializer>(
+                        &self,
+                        encoding: Encoding,
+                        serializer: S,
+                    ) -> Result<S::Ok, S::Error> {
+                        match encoding {
+                            Encoding::Cbor => Err(S::Error::custom("no CBOR representation")),
+                            _ => self.serialize(serializer),
+                        }
+                    }
+                }
+
                 #[axum::debug_handler]
                 async fn handler() -> impl IntoResponse {
-                    Negotiate(Example {
+                    NegotiateFor(JsonOnly {
                         message: "Hello, test!".to_string(),
                     })
                 }
 
                 let app = Router::new()
                     .route("/", post(handler))
-                    .layer(NegotiateLayer);
+                    .layer(NegotiateLayer::new().fallback_encodings([Encoding::Json]));
 
                 let response = app
                     .oneshot(
                         Request::builder()
                             .uri("/")
                             .method("POST")
+                            .header(ACCEPT, "application/cbor")
                             .body(Body::empty())
                             .unwrap(),
                     )
@@ -623,123 +7462,81 @@ mod test {
                 );
                 assert_eq!(
                     response.into_body().collect().await.unwrap().to_bytes(),
-                    json!({ "message": "Hello, test!" }).to_string()
+                    r#"{"message":"Hello, test!"}"#
                 );
             }
 
             #[tokio::test]
-            async fn test_retain_handler_status_code() {
+            async fn test_encode_as_requested() {
                 #[axum::debug_handler]
                 async fn handler() -> impl IntoResponse {
-                    (
-                        StatusCode::CREATED,
-                        Negotiate(Example {
-                            message: "Hello, test!".to_string(),
-                        }),
-                    )
+                    Negotiate(Example {
+                        message: "Hello, test!".to_string(),
+                    })
                 }
 
                 let app = Router::new()
                     .route("/", post(handler))
-                    .layer(NegotiateLayer);
+                    .layer(NegotiateLayer::new());
 
                 let response = app
                     .oneshot(
                         Request::builder()
                             .uri("/")
                             .method("POST")
+                            .header(ACCEPT, "application/cbor")
                             .body(Body::empty())
                             .unwrap(),
                     )
                     .await
                     .unwrap();
 
-                assert_eq!(response.status(), StatusCode::CREATED);
-                assert_eq!(
-                    response.headers().get(CONTENT_TYPE).unwrap(),
-                    "application/json"
-                );
-                assert_eq!(
-                    response.into_body().collect().await.unwrap().to_bytes(),
-                    json!({ "message": "Hello, test!" }).to_string()
-                );
-            }
-        }
-    }
-
-    #[cfg(feature = "cbor")]
-    mod cbor {
-        use cbor4ii::core::{enc::Encode, utils::BufWriter, Value};
-
-        use super::*;
-
-        mod input {
-            use super::*;
-
-            #[cfg(feature = "default-cbor")]
-            #[tokio::test]
-            async fn test_can_read_input_without_content_type_by_default() {
-                #[axum::debug_handler]
-                async fn handler(Negotiate(input): Negotiate<Example>) -> impl IntoResponse {
-                    format!("Hello, {}!", input.message)
-                }
-
-                let app = Router::new().route("/", post(handler));
-                let body = {
+                let expected_body = {
                     let mut writer = BufWriter::new(Vec::new());
                     Value::Map(vec![(
                         Value::Text("message".to_string()),
-                        Value::Text("test".to_string()),
+                        Value::Text("Hello, test!".to_string()),
                     )])
                     .encode(&mut writer)
                     .unwrap();
                     writer.into_inner()
                 };
 
-                let response = app
-                    .oneshot(
-                        Request::builder()
-                            .uri("/")
-                            .method("POST")
-                            .body(Body::from(body))
-                            .unwrap(),
-                    )
-                    .await
-                    .unwrap();
-
                 assert_eq!(response.status(), 200);
+                assert_eq!(
+                    response.headers().get(CONTENT_TYPE).unwrap(),
+                    "application/cbor"
+                );
+                assert_eq!(content_length(response.headers()), expected_body.len());
                 assert_eq!(
                     response.into_body().collect().await.unwrap().to_bytes(),
-                    "Hello, test!"
+                    expected_body,
                 );
             }
 
+            #[cfg(any(feature = "simd-json", feature = "json"))]
             #[tokio::test]
-            async fn test_can_read_input_with_specified_header() {
+            async fn test_link_alternate_representations() {
                 #[axum::debug_handler]
-                async fn handler(Negotiate(input): Negotiate<Example>) -> impl IntoResponse {
-                    format!("Hello, {}!", input.message)
+                async fn handler() -> impl IntoResponse {
+                    Negotiate(Example {
+                        message: "Hello, test!".to_string(),
+                    })
                 }
 
-                let app = Router::new().route("/", post(handler));
-                let body = {
-                    let mut writer = BufWriter::new(Vec::new());
-                    Value::Map(vec![(
-                        Value::Text("message".to_string()),
-                        Value::Text("test".to_string()),
-                    )])
-                    .encode(&mut writer)
-                    .unwrap();
-                    writer.into_inner()
-                };
+                let app = Router::new().route("/items", post(handler)).layer(
+                    NegotiateLayer::new()
+                        .link_alternates()
+                        .only_encodings([Encoding::Json, Encoding::Cbor]),
+                );
 
                 let response = app
                     .oneshot(
                         Request::builder()
-                            .uri("/")
-                            .header(CONTENT_TYPE, "application/cbor")
+                            .uri("/items?page=2")
                             .method("POST")
-                            .body(Body::from(body))
+                            .header(ACCEPT, "application/cbor")
+                            .body(Body::empty())
                             .unwrap(),
                     )
                     .await
@@ -747,27 +7544,31 @@ mod test {
 
                 assert_eq!(response.status(), 200);
                 assert_eq!(
-                    response.into_body().collect().await.unwrap().to_bytes(),
-                    "Hello, test!"
+                    response
+                        .headers()
+                        .get_all(axum::http::header::LINK)
+                        .iter()
+                        .map(|link| link.to_str().unwrap().to_string())
+                        .collect::<Vec<_>>(),
+                    [r#"</items?page=2>; rel="alternate"; type="application/json""#]
                 );
             }
-        }
-
-        mod output {
-            use super::*;
 
             #[tokio::test]
-            async fn test_encode_as_requested() {
+            async fn test_retain_status_code() {
                 #[axum::debug_handler]
                 async fn handler() -> impl IntoResponse {
-                    Negotiate(Example {
-                        message: "Hello, test!".to_string(),
-                    })
+                    (
+                        StatusCode::CREATED,
+                        Negotiate(Example {
+                            message: "Hello, test!".to_string(),
+                        }),
+                    )
                 }
 
                 let app = Router::new()
                     .route("/", post(handler))
-                    .layer(NegotiateLayer);
+                    .layer(NegotiateLayer::new());
 
                 let response = app
                     .oneshot(
@@ -781,7 +7582,12 @@ mod test {
                     .await
                     .unwrap();
 
-                let expected_body = {
+                assert_eq!(response.status(), StatusCode::CREATED);
+                assert_eq!(
+                    response.headers().get(CONTENT_TYPE).unwrap(),
+                    "application/cbor"
+                );
+                assert_eq!(response.into_body().collect().await.unwrap().to_bytes(), {
                     let mut writer = BufWriter::new(Vec::new());
                     Value::Map(vec![(
                         Value::Text("message".to_string()),
@@ -790,49 +7596,35 @@ mod test {
                     .encode(&mut writer)
                     .unwrap();
                     writer.into_inner()
-                };
-
-                assert_eq!(response.status(), 200);
-                assert_eq!(
-                    response.headers().get(CONTENT_TYPE).unwrap(),
-                    "application/cbor"
-                );
-                assert_eq!(content_length(response.headers()), expected_body.len());
-                assert_eq!(
-                    response.into_body().collect().await.unwrap().to_bytes(),
-                    expected_body,
-                );
+                });
             }
 
             #[tokio::test]
-            async fn test_retain_status_code() {
+            async fn test_use_configured_default_encoding() {
                 #[axum::debug_handler]
                 async fn handler() -> impl IntoResponse {
-                    (
-                        StatusCode::CREATED,
-                        Negotiate(Example {
-                            message: "Hello, test!".to_string(),
-                        }),
-                    )
+                    Negotiate(Example {
+                        message: "Hello, test!".to_string(),
+                    })
                 }
 
                 let app = Router::new()
                     .route("/", post(handler))
-                    .layer(NegotiateLayer);
+                    .layer(NegotiateLayer::new().default_encoding(Encoding::Cbor));
 
                 let response = app
                     .oneshot(
                         Request::builder()
                             .uri("/")
                             .method("POST")
-                            .header(ACCEPT, "application/cbor")
+                            .header(ACCEPT, "*/*")
                             .body(Body::empty())
                             .unwrap(),
                     )
                     .await
                     .unwrap();
 
-                assert_eq!(response.status(), StatusCode::CREATED);
+                assert_eq!(response.status(), StatusCode::OK);
                 assert_eq!(
                     response.headers().get(CONTENT_TYPE).unwrap(),
                     "application/cbor"
@@ -849,7 +7641,7 @@ mod test {
                 });
             }
 
-            #[cfg(feature = "default-cbor")]
+            #[cfg(all(feature = "default-cbor", not(feature = "default-json")))]
             #[tokio::test]
             async fn test_default_encoding_without_header() {
                 #[axum::debug_handler]
@@ -864,7 +7656,7 @@ mod test {
 
                 let app = Router::new()
                     .route("/", post(handler))
-                    .layer(NegotiateLayer);
+                    .layer(NegotiateLayer::new());
 
                 let response = app
                     .oneshot(
@@ -894,7 +7686,7 @@ mod test {
                 });
             }
 
-            #[cfg(feature = "default-cbor")]
+            #[cfg(all(feature = "default-cbor", not(feature = "default-json")))]
             #[tokio::test]
             async fn test_default_encoding_with_star() {
                 #[axum::debug_handler]
@@ -909,7 +7701,7 @@ mod test {
 
                 let app = Router::new()
                     .route("/", post(handler))
-                    .layer(NegotiateLayer);
+                    .layer(NegotiateLayer::new());
 
                 let response = app
                     .oneshot(
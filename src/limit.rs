@@ -0,0 +1,108 @@
+//! Guards protecting the service resources while decoding request bodies.
+
+use std::pin::Pin;
+#[cfg(feature = "tokio")]
+use std::sync::Arc;
+
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    http::{header::CONTENT_LENGTH, HeaderMap},
+};
+#[cfg(feature = "tokio")]
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Size limit applied while reading request bodies, with or without a `Content-Length` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RequestBodyLimit {
+    /// The limit of axum, configured on the routes with [DefaultBodyLimit](axum::extract::DefaultBodyLimit), which is 2 MB by default.
+    Axum,
+    /// Bodies over this many bytes are answered with 413 Content Too Large.
+    ///
+    /// A larger `Content-Length` is rejected before reading the body, and chunked uploads are counted while they are streamed,
+    /// so they are never buffered past the limit.
+    Bytes(usize),
+}
+
+/// Why a request body could not be read within its limit.
+#[derive(Debug)]
+pub(crate) enum ReadError {
+    TooLarge,
+    Body(axum::Error),
+}
+
+/// The `Content-Length` declared by the request, if valid.
+pub(crate) fn content_length(headers: &HeaderMap) -> Option<usize> {
+    headers
+        .get(CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok())
+        .and_then(|length| length.parse::<usize>().ok())
+}
+
+/// Buffer the body, failing as soon as more than `limit` bytes are received.
+pub(crate) async fn read_body(mut body: Body, limit: usize) -> Result<Bytes, ReadError> {
+    let mut buffer = Vec::new();
+    while let Some(frame) = std::future::poll_fn(|cx| Pin::new(&mut body).poll_frame(cx)).await {
+        let Ok(data) = frame.map_err(ReadError::Body)?.into_data() else {
+            continue;
+        };
+        if buffer.len() + data.len() > limit {
+            return Err(ReadError::TooLarge);
+        }
+        buffer.extend_from_slice(&data);
+    }
+    Ok(buffer.into())
+}
+
+/// Permit of a [DecodeGuard] for a large request body, held until the body is decoded.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DecodePermit {
+    #[cfg(feature = "tokio")]
+    _permit: Option<Arc<OwnedSemaphorePermit>>,
+}
+
+/// Limit how many large request bodies are buffered and deserialized at the same time.
+#[cfg(feature = "tokio")]
+#[derive(Debug, Clone)]
+pub(crate) struct DecodeGuard {
+    /// Bodies declaring a `Content-Length` of at least this many bytes are considered large
+    threshold: usize,
+    permits: Arc<Semaphore>,
+}
+
+#[cfg(feature = "tokio")]
+impl DecodeGuard {
+    pub(crate) fn new(threshold: usize, permits: usize) -> Self {
+        Self {
+            threshold,
+            permits: Arc::new(Semaphore::new(permits)),
+        }
+    }
+
+    /// Check if the request body is large, needing a permit to be decoded.
+    ///
+    /// Bodies are sized by their `Content-Length`, or by their exact size when known, so requests without a body are never large.
+    /// Other bodies, such as chunked uploads or HTTP/2 requests without a length, can't be sized ahead of time and are considered large.
+    pub(crate) fn is_large(&self, headers: &HeaderMap, body: &Body) -> bool {
+        let length = content_length(headers).or_else(|| {
+            body.size_hint()
+                .exact()
+                .and_then(|length| usize::try_from(length).ok())
+        });
+        !body.is_end_stream() && !matches!(length, Some(length) if length < self.threshold)
+    }
+
+    /// Wait for a permit, holding it until the permit is dropped.
+    pub(crate) async fn acquire(&self) -> DecodePermit {
+        // The semaphore is never closed, so acquiring only fails if the guard is gone
+        DecodePermit {
+            _permit: self
+                .permits
+                .clone()
+                .acquire_owned()
+                .await
+                .ok()
+                .map(Arc::new),
+        }
+    }
+}
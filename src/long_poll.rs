@@ -0,0 +1,90 @@
+//! Long-poll endpoints, waiting for an item up to a deadline before answering that there is nothing yet.
+
+use std::{future::Future, time::Duration};
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension,
+};
+
+use crate::{ErasedNegotiate, Negotiate};
+
+/// Wait for the item of a long-poll request, answering it negotiated like [Negotiate] if it arrives before the deadline.
+///
+/// When the deadline elapses first, the response is 204 No Content, or the document set with [LongPoll::or_document],
+/// negotiated like the item so clients parse both with the same format.
+///
+/// ```rust
+/// use std::time::Duration;
+/// use axum::{http::StatusCode, response::Response};
+/// use axum_content_negotiation::LongPoll;
+/// use tokio::sync::watch;
+///
+/// #[derive(serde::Serialize)]
+/// struct Pending {
+///     retry_in_seconds: u32,
+/// }
+///
+/// async fn handler(mut events: watch::Receiver<u32>) -> Response {
+///     LongPoll::new(Duration::from_secs(30))
+///         .or_document(StatusCode::ACCEPTED, Pending { retry_in_seconds: 1 })
+///         .wait(async move {
+///             events.changed().await.ok();
+///             *events.borrow()
+///         })
+///         .await
+/// }
+/// ```
+#[derive(Clone)]
+pub struct LongPoll {
+    deadline: Duration,
+    pending: Option<(StatusCode, ErasedNegotiate)>,
+}
+
+impl LongPoll {
+    /// Wait up to `deadline` for the item, answering 204 No Content when it elapses.
+    pub fn new(deadline: Duration) -> Self {
+        Self {
+            deadline,
+            pending: None,
+        }
+    }
+
+    /// Answer the document with the status when the deadline elapses, such as a pending status with a retry hint.
+    pub fn or_document<P>(mut self, status: StatusCode, document: P) -> Self
+    where
+        P: serde::Serialize + Send + Sync + 'static,
+    {
+        self.pending = Some((status, document.into()));
+        self
+    }
+
+    /// Wait for the future, answering its output or the pending response, whichever comes first.
+    ///
+    /// The future is dropped once the deadline elapses.
+    pub async fn wait<F, T>(self, future: F) -> Response
+    where
+        F: Future<Output = T>,
+        T: serde::Serialize + Send + Sync + 'static,
+    {
+        match tokio::time::timeout(self.deadline, future).await {
+            Ok(item) => Negotiate(item).into_response(),
+            Err(_) => match self.pending {
+                Some((status, document)) => {
+                    (status, Extension(document), "Misconfigured service layer").into_response()
+                }
+                None => StatusCode::NO_CONTENT.into_response(),
+            },
+        }
+    }
+}
+
+impl std::fmt::Debug for LongPoll {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LongPoll")
+            .field("deadline", &self.deadline)
+            .field("pending", &self.pending.as_ref().map(|(status, _)| status))
+            .finish()
+    }
+}
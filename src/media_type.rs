@@ -0,0 +1,712 @@
+//! Parsing of media types, and matching them against the enabled encodings.
+
+use std::{fmt, sync::Arc};
+
+use axum::http::{Extensions, HeaderValue};
+
+use crate::{error::Mismatch, Encoding};
+
+/// Which vendor media types using a structured syntax suffix, such as `application/vnd.example+json`, an encoding claims.
+///
+/// Every encoding claims the family of its suffix (`+json` for JSON, `+cbor` for CBOR), and the family decides which vendor types are allowed.
+///
+/// ```rust
+/// use axum_content_negotiation::SuffixFamily;
+///
+/// let family = SuffixFamily::only(["application/vnd.example.order+json"]);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SuffixFamily {
+    allowed: Allowed,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+enum Allowed {
+    #[default]
+    None,
+    Any,
+    Only(Vec<String>),
+}
+
+impl SuffixFamily {
+    /// Don't negotiate any vendor type, only the base media type of the encoding.
+    pub fn none() -> Self {
+        Self {
+            allowed: Allowed::None,
+        }
+    }
+
+    /// Negotiate any vendor type using the encoding suffix.
+    pub fn any() -> Self {
+        Self {
+            allowed: Allowed::Any,
+        }
+    }
+
+    /// Negotiate only the listed vendor types, compared case-insensitively.
+    pub fn only<I, M>(media_types: I) -> Self
+    where
+        I: IntoIterator<Item = M>,
+        M: Into<String>,
+    {
+        Self {
+            allowed: Allowed::Only(
+                media_types
+                    .into_iter()
+                    .map(|media_type| media_type.into().to_ascii_lowercase())
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Vendor types of the family, described as `application/*+suffix` when any is allowed.
+    pub(crate) fn describe(&self, suffix: &str) -> Vec<String> {
+        match &self.allowed {
+            Allowed::None => Vec::new(),
+            Allowed::Any => vec![format!("application/*+{suffix}")],
+            Allowed::Only(allowed) => allowed.clone(),
+        }
+    }
+
+    fn allows(&self, media_type: &str) -> bool {
+        match &self.allowed {
+            Allowed::None => false,
+            Allowed::Any => true,
+            Allowed::Only(allowed) => allowed
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(media_type)),
+        }
+    }
+}
+
+/// Encoding selected for a media type, and the media type informed on the `Content-Type` of responses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Selection {
+    pub(crate) encoding: Encoding,
+    pub(crate) media_type: HeaderValue,
+}
+
+impl From<Encoding> for Selection {
+    fn from(encoding: Encoding) -> Self {
+        Self {
+            encoding,
+            media_type: HeaderValue::from_static(encoding.media_type()),
+        }
+    }
+}
+
+/// Alternative spellings of the encodings media types found in the wild.
+///
+/// They are negotiated as the encoding, but responses always use its canonical media type.
+pub(crate) static ALIASES: &[(&str, Encoding)] = &[
+    #[cfg(any(feature = "simd-json", feature = "json"))]
+    ("text/json", Encoding::Json),
+    #[cfg(any(feature = "simd-json", feature = "json"))]
+    ("application/x-json", Encoding::Json),
+    #[cfg(feature = "cbor")]
+    ("application/x-cbor", Encoding::Cbor),
+    #[cfg(feature = "jsonp")]
+    ("text/javascript", Encoding::Jsonp),
+];
+
+/// Media types of every encoding known by the crate, regardless of the enabled features.
+static KNOWN: &[&str] = &[
+    "application/json",
+    "text/json",
+    "application/x-json",
+    "application/cbor",
+    "application/x-cbor",
+    "text/csv",
+    "application/x-ndjson",
+    "application/cbor-seq",
+    "application/flatbuffers",
+    "multipart/form-data",
+    "text/plain",
+    "application/hal+json",
+    "application/json-patch+json",
+    "application/octet-stream",
+    "application/atom+xml",
+    "application/rss+xml",
+    "application/javascript",
+    "text/javascript",
+    "text/html",
+    "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    "application/geo+json",
+];
+
+/// Check if the media type is syntactically valid, as `type/subtype` tokens.
+pub(crate) fn is_valid(media_type: &str) -> bool {
+    let is_token = |token: &str| {
+        !token.is_empty()
+            && token
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+    };
+    media_type
+        .split_once('/')
+        .is_some_and(|(kind, subtype)| is_token(kind) && is_token(subtype))
+}
+
+/// Rules used to match media types against the enabled encodings.
+#[derive(Debug, Clone)]
+pub(crate) struct Matcher {
+    /// Encoding used for `*/*`
+    pub(crate) default_encoding: Option<Encoding>,
+    /// Alternative spellings of the encodings media types
+    pub(crate) aliases: Vec<(String, Encoding)>,
+    /// Media types registered as first-class, echoed on the `Content-Type` of responses
+    pub(crate) media_types: Vec<(String, Encoding)>,
+    /// Vendor types claimed by each encoding suffix
+    pub(crate) families: Vec<(Encoding, SuffixFamily)>,
+    /// Encodings preferred by the server, in order, when media ranges tie on quality
+    pub(crate) preference: Vec<Encoding>,
+}
+
+impl Matcher {
+    /// Find the encoding for a single media type, without parameters.
+    pub(crate) fn select(&self, media_type: &str) -> Option<Selection> {
+        if let Some(encoding) = Encoding::ALL
+            .iter()
+            .find(|encoding| encoding.media_type().eq_ignore_ascii_case(media_type))
+        {
+            return Some((*encoding).into());
+        }
+
+        if let Some((registered, encoding)) = self
+            .media_types
+            .iter()
+            .find(|(registered, _)| registered.eq_ignore_ascii_case(media_type))
+        {
+            return Some(Selection {
+                encoding: *encoding,
+                media_type: HeaderValue::from_str(registered).ok()?,
+            });
+        }
+
+        if let Some((_, encoding)) = self
+            .aliases
+            .iter()
+            .find(|(alias, _)| alias.eq_ignore_ascii_case(media_type))
+        {
+            return Some((*encoding).into());
+        }
+
+        // Vendor types are echoed on responses, so only concrete `type/name+suffix` media types are matched
+        let (_, subtype) = media_type.split_once('/')?;
+        let (name, suffix) = subtype.rsplit_once('+')?;
+        if name.is_empty() || media_type.contains('*') || !is_valid(media_type) {
+            return None;
+        }
+        let (encoding, _) = self.families.iter().find(|(encoding, family)| {
+            encoding.suffix().eq_ignore_ascii_case(suffix) && family.allows(media_type)
+        })?;
+
+        Some(Selection {
+            encoding: *encoding,
+            media_type: HeaderValue::from_str(media_type).ok()?,
+        })
+    }
+
+    /// Explain why a single media type, without parameters, has no matching encoding.
+    pub(crate) fn mismatch(&self, media_type: &str) -> Mismatch {
+        if !is_valid(media_type) {
+            Mismatch::Invalid
+        } else if KNOWN
+            .iter()
+            .any(|known| known.eq_ignore_ascii_case(media_type))
+        {
+            Mismatch::Disabled
+        } else {
+            Mismatch::Unsupported
+        }
+    }
+
+    /// Explain why an `Accept` header has no acceptable encoding, using its most specific media range.
+    pub(crate) fn mismatch_accept(&self, accept: &str) -> Mismatch {
+        parse_accept(accept)
+            .map(|range| self.mismatch(range.media_type))
+            .max()
+            .unwrap_or(Mismatch::Invalid)
+    }
+
+    /// Encoding used for `*/*` when some encodings are excluded for the client, replacing an excluded default with the first allowed encoding.
+    ///
+    /// The default inferred for the client, if any, is preferred over the one of the layer.
+    pub(crate) fn default_excluding(
+        &self,
+        excluded: &[Encoding],
+        inferred: Option<Encoding>,
+    ) -> Option<Encoding> {
+        self.default_where(inferred, false, |encoding| !excluded.contains(&encoding))
+    }
+
+    /// Encoding used for a wildcard, trying the inferred and default encodings before the first allowed one.
+    ///
+    /// Without a default encoding, only subtype wildcards such as `application/*` match, as they still narrow the encodings down.
+    fn default_where(
+        &self,
+        inferred: Option<Encoding>,
+        subtypes: bool,
+        allowed: impl Fn(Encoding) -> bool,
+    ) -> Option<Encoding> {
+        if let Some(inferred) = inferred.filter(|&inferred| allowed(inferred)) {
+            return Some(inferred);
+        }
+        match self.default_encoding {
+            Some(default) if allowed(default) => return Some(default),
+            None if !subtypes => return None,
+            _ => {}
+        }
+        self.preference
+            .iter()
+            .chain(Encoding::ALL)
+            .copied()
+            .find(|&encoding| encoding.encodes() && allowed(encoding))
+    }
+
+    /// Position of the encoding on the server preference, after every listed encoding if it is missing.
+    fn rank(&self, encoding: Encoding) -> usize {
+        self.preference
+            .iter()
+            .position(|&preferred| preferred == encoding)
+            .unwrap_or(self.preference.len())
+    }
+
+    /// Check if the media range applies to the canonical media type of the encoding, or one of its aliases.
+    fn covers(&self, range: &MediaRange<'_>, encoding: Encoding) -> bool {
+        match range.media_type.split_once('/') {
+            Some(("*", "*")) => true,
+            Some((kind, "*")) => encoding
+                .media_type()
+                .split_once('/')
+                .is_some_and(|(encoding_kind, _)| encoding_kind.eq_ignore_ascii_case(kind)),
+            _ => self.select(range.media_type).is_some_and(|selection| {
+                selection.encoding == encoding && selection.media_type == encoding.media_type()
+            }),
+        }
+    }
+
+    /// Find the acceptable encoding with the highest quality on an `Accept` header, skipping the excluded encodings.
+    ///
+    /// Following RFC 9110, the quality of an encoding is given by the most specific range applying to it, so `application/json;q=0, */*`
+    /// never answers JSON. Ties are broken by the server preference, then by the most specific range, then by the first one listed.
+    pub(crate) fn negotiate(
+        &self,
+        accept: &str,
+        excluded: &[Encoding],
+        inferred: Option<Encoding>,
+    ) -> Option<Selection> {
+        let ranges: Vec<_> = parse_accept(accept).collect();
+        let most_specific = |encoding: Encoding| {
+            ranges
+                .iter()
+                .enumerate()
+                .filter(|(_, range)| self.covers(range, encoding))
+                .max_by(|(a, range_a), (b, range_b)| {
+                    range_a.specificity.cmp(&range_b.specificity).then(b.cmp(a))
+                })
+                .map(|(index, _)| index)
+        };
+
+        ranges
+            .iter()
+            .enumerate()
+            .filter(|(_, range)| range.quality > 0.0)
+            .filter_map(|(index, range)| {
+                let selection = if range.specificity < MediaRange::EXACT {
+                    let subtypes = range.specificity == MediaRange::SUBTYPES;
+                    self.default_where(inferred, subtypes, |encoding| {
+                        !excluded.contains(&encoding)
+                            && self.covers(range, encoding)
+                            && most_specific(encoding) == Some(index)
+                    })
+                    .map(Selection::from)
+                } else {
+                    self.select(range.media_type).filter(|selection| {
+                        selection.encoding.answers() && !excluded.contains(&selection.encoding)
+                    })
+                };
+                Some((range.quality, range.specificity, index, selection?))
+            })
+            .max_by(
+                |(quality_a, specificity_a, a, selection_a),
+                 (quality_b, specificity_b, b, selection_b)| {
+                    quality_a
+                        .total_cmp(quality_b)
+                        .then(
+                            self.rank(selection_b.encoding)
+                                .cmp(&self.rank(selection_a.encoding)),
+                        )
+                        .then(specificity_a.cmp(specificity_b))
+                        .then(b.cmp(a))
+                },
+            )
+            .map(|(.., selection)| selection)
+    }
+}
+
+/// One of the media ranges listed on an `Accept` header.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct MediaRange<'a> {
+    pub(crate) media_type: &'a str,
+    pub(crate) quality: f32,
+    /// Precedence of the range over the others applying to the same media type, from `*/*` to a media type with parameters
+    pub(crate) specificity: u8,
+}
+
+impl MediaRange<'_> {
+    /// Specificity of `*/*`
+    pub(crate) const ANY: u8 = 0;
+    /// Specificity of a subtype wildcard, such as `application/*`
+    pub(crate) const SUBTYPES: u8 = 1;
+    /// Specificity of a media type without parameters
+    pub(crate) const EXACT: u8 = 2;
+    /// Specificity of a media type with parameters, such as `text/plain;format=flowed`
+    pub(crate) const PARAMETERS: u8 = 3;
+}
+
+/// Split a header on the separator, ignoring the ones inside quoted strings such as `a;p="x,y"`.
+pub(crate) fn split_unquoted(header: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut quoted, mut escaped, mut start) = (false, false, 0);
+    for (at, c) in header.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if quoted && c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            quoted = !quoted;
+        } else if c == separator && !quoted {
+            parts.push(&header[start..at]);
+            start = at + c.len_utf8();
+        }
+    }
+    parts.push(&header[start..]);
+    parts
+}
+
+/// Split an `Accept` header into its media ranges, reading their `q=` quality values.
+///
+/// Parameters before the quality are part of the range, making it more specific, while the ones after it are ignored.
+/// Invalid or out of range quality values are treated as `q=0`, so a malformed range never outranks the valid ones.
+pub(crate) fn parse_accept(accept: &str) -> impl Iterator<Item = MediaRange<'_>> {
+    split_unquoted(accept, ',')
+        .into_iter()
+        .map(str::trim)
+        .filter(|range| !range.is_empty())
+        .map(|range| {
+            let (media_type, parameters, quality) = parse_range(range);
+            let specificity = match media_type.split_once('/') {
+                Some(("*", "*")) => MediaRange::ANY,
+                Some((_, "*")) => MediaRange::SUBTYPES,
+                _ if !parameters.is_empty() => MediaRange::PARAMETERS,
+                _ => MediaRange::EXACT,
+            };
+
+            MediaRange {
+                media_type,
+                quality,
+                specificity,
+            }
+        })
+}
+
+/// Split a media range into its media type, the parameters before its quality, and its quality.
+///
+/// The parameters after the quality are accept extensions, which are dropped as the negotiation ignores them.
+fn parse_range(range: &str) -> (&str, Vec<(&str, &str)>, f32) {
+    let mut parameters = split_unquoted(range, ';').into_iter().map(str::trim);
+    let media_type = parameters.next().unwrap_or_default();
+    let mut before_quality = Vec::new();
+    for (name, value) in parameters.filter_map(|parameter| parameter.split_once('=')) {
+        let (name, value) = (name.trim(), value.trim());
+        if name.eq_ignore_ascii_case("q") {
+            return (
+                media_type,
+                before_quality,
+                parse_quality(value).unwrap_or(0.0),
+            );
+        }
+        before_quality.push((name, value));
+    }
+    (media_type, before_quality, 1.0)
+}
+
+/// Split a `Content-Type` header into its media type and parameters, unquoting their values.
+pub(crate) fn parse_content_type(content_type: &str) -> (&str, Vec<(&str, &str)>) {
+    let mut parts = content_type.split(';').map(str::trim);
+    let media_type = parts.next().unwrap_or_default();
+    let parameters = parts
+        .filter_map(|parameter| parameter.split_once('='))
+        .map(|(name, value)| {
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+                .unwrap_or(value);
+            (name.trim(), value)
+        })
+        .collect();
+    (media_type, parameters)
+}
+
+/// Check if the `charset` parameter of a `Content-Type`, when informed, is UTF-8 or its US-ASCII subset.
+pub(crate) fn is_utf8(parameters: &[(&str, &str)]) -> bool {
+    parameters
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("charset"))
+        .all(|(_, charset)| {
+            ["utf-8", "utf8", "us-ascii"]
+                .iter()
+                .any(|utf8| utf8.eq_ignore_ascii_case(charset))
+        })
+}
+
+type Parameters = dyn Fn(&[(&str, &str)], &mut Extensions) + Send + Sync;
+
+/// Callback reading the `Content-Type` parameters of requests into their extensions.
+#[derive(Clone)]
+pub(crate) struct ParameterHook(pub(crate) Arc<Parameters>);
+
+impl fmt::Debug for ParameterHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ParameterHook")
+    }
+}
+
+/// Read a `q=` quality value, which must be between 0 and 1.
+fn parse_quality(value: &str) -> Option<f32> {
+    value
+        .trim()
+        .parse::<f32>()
+        .ok()
+        .filter(|quality| (0.0..=1.0).contains(quality))
+}
+
+/// Normalize an `Accept` header, useful to build cache keys for responses varying on it.
+///
+/// Media ranges and their parameters are lowercased, duplicated ranges keep their highest quality,
+/// and ranges are sorted by quality, keeping the header order between ranges of the same quality.
+/// The default quality of 1 is omitted, and ranges are read as the negotiation does:
+/// invalid qualities become `q=0`, and the parameters after the quality are dropped.
+///
+/// ```rust
+/// use axum_content_negotiation::canonicalize_accept;
+///
+/// assert_eq!(
+///     canonicalize_accept("Application/CBOR;q=0.5, application/json, application/cbor;Q=0.8"),
+///     "application/json, application/cbor;q=0.8"
+/// );
+/// ```
+pub fn canonicalize_accept(accept: &str) -> String {
+    let mut ranges: Vec<(String, f32)> = Vec::new();
+
+    for range in split_unquoted(accept, ',')
+        .into_iter()
+        .map(str::trim)
+        .filter(|r| !r.is_empty())
+    {
+        let (media_type, parameters, quality) = parse_range(range);
+        let mut canonical = media_type.to_ascii_lowercase();
+        for (name, value) in parameters {
+            canonical.push_str(&format!(
+                ";{}={}",
+                name.to_ascii_lowercase(),
+                value.to_ascii_lowercase()
+            ));
+        }
+
+        match ranges
+            .iter_mut()
+            .find(|(existing, _)| *existing == canonical)
+        {
+            Some((_, existing)) => *existing = existing.max(quality),
+            None => ranges.push((canonical, quality)),
+        }
+    }
+
+    // Stable sort keeps the header order for ties
+    ranges.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    ranges
+        .into_iter()
+        .map(|(range, quality)| match quality {
+            1.0 => range,
+            quality => format!("{range};q={quality}"),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        canonicalize_accept, is_valid, parse_accept, parse_content_type, split_unquoted, MediaRange,
+    };
+    #[cfg(any(feature = "simd-json", feature = "json"))]
+    use super::{Matcher, SuffixFamily};
+    #[cfg(any(feature = "simd-json", feature = "json"))]
+    use crate::Encoding;
+
+    #[test]
+    fn test_parse_quality_values() {
+        let ranges =
+            parse_accept("text/html, application/cbor;q=0.5 , */*; Q=0.1;level=1, a/b;q=nope")
+                .collect::<Vec<_>>();
+
+        assert_eq!(
+            ranges,
+            vec![
+                MediaRange {
+                    media_type: "text/html",
+                    quality: 1.0,
+                    specificity: MediaRange::EXACT,
+                },
+                MediaRange {
+                    media_type: "application/cbor",
+                    quality: 0.5,
+                    specificity: MediaRange::EXACT,
+                },
+                MediaRange {
+                    media_type: "*/*",
+                    quality: 0.1,
+                    specificity: MediaRange::ANY,
+                },
+                MediaRange {
+                    media_type: "a/b",
+                    quality: 0.0,
+                    specificity: MediaRange::EXACT,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_specificity_and_quoted_parameters() {
+        let ranges = parse_accept(r#"text/*;q=0.3, text/plain;format="a,b;c";q=0.7, text/plain"#)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            ranges,
+            vec![
+                MediaRange {
+                    media_type: "text/*",
+                    quality: 0.3,
+                    specificity: MediaRange::SUBTYPES,
+                },
+                MediaRange {
+                    media_type: "text/plain",
+                    quality: 0.7,
+                    specificity: MediaRange::PARAMETERS,
+                },
+                MediaRange {
+                    media_type: "text/plain",
+                    quality: 1.0,
+                    specificity: MediaRange::EXACT,
+                },
+            ]
+        );
+        assert_eq!(
+            split_unquoted(r#"a;p="x\",y", b"#, ','),
+            vec![r#"a;p="x\",y""#, " b"]
+        );
+    }
+
+    #[test]
+    fn test_validate_media_type_syntax() {
+        assert!(is_valid("application/vnd.example+json"));
+        assert!(is_valid("*/*"));
+        assert!(!is_valid("non-supported"));
+        assert!(!is_valid("application/"));
+        assert!(!is_valid("application/json; charset=utf-8"));
+    }
+
+    #[cfg(any(feature = "simd-json", feature = "json"))]
+    #[test]
+    fn test_select_only_concrete_vendor_types() {
+        let matcher = Matcher {
+            default_encoding: None,
+            aliases: Vec::new(),
+            media_types: Vec::new(),
+            families: vec![(Encoding::Json, SuffixFamily::any())],
+            preference: Vec::new(),
+        };
+
+        assert_eq!(
+            matcher
+                .select("application/vnd.example+json")
+                .map(|selection| selection.media_type),
+            Some(axum::http::HeaderValue::from_static(
+                "application/vnd.example+json"
+            ))
+        );
+        for invalid in [
+            "+json",
+            "foo+json",
+            "application/+json",
+            "application/*+json",
+            "*/vnd.example+json",
+            "application/vnd example+json",
+        ] {
+            assert_eq!(matcher.select(invalid), None, "{invalid}");
+        }
+    }
+
+    #[cfg(all(feature = "csv", any(feature = "simd-json", feature = "json")))]
+    #[test]
+    fn test_never_prefer_ranges_with_invalid_quality() {
+        let matcher = Matcher {
+            default_encoding: None,
+            aliases: Vec::new(),
+            media_types: Vec::new(),
+            families: Vec::new(),
+            preference: Vec::new(),
+        };
+
+        for accept in [
+            "text/csv;q=garbage, application/json;q=0.9",
+            "text/csv;q=5, application/json;q=0.9",
+            "text/csv;q=-1, application/json;q=0.9",
+        ] {
+            assert_eq!(
+                matcher
+                    .negotiate(accept, &[], None)
+                    .map(|selection| selection.encoding),
+                Some(Encoding::Json),
+                "{accept}"
+            );
+        }
+        for accept in ["text/csv;q=garbage", "text/csv;q=5"] {
+            assert_eq!(matcher.negotiate(accept, &[], None), None, "{accept}");
+        }
+    }
+
+    #[test]
+    fn test_canonicalize_accept() {
+        assert_eq!(
+            canonicalize_accept(
+                "*/*;q=0.1, Text/HTML; Level=1 ,text/html;level=1;q=0.2, application/json;q=nope"
+            ),
+            "text/html;level=1, */*;q=0.1, application/json;q=0"
+        );
+        assert_eq!(
+            canonicalize_accept("text/csv;q=0.5;header=present, text/plain;q=5, text/csv;q=0.4"),
+            "text/csv;q=0.5, text/plain;q=0"
+        );
+        assert_eq!(canonicalize_accept(" , "), "");
+    }
+
+    #[test]
+    fn test_parse_content_type_parameters() {
+        assert_eq!(
+            parse_content_type("application/json"),
+            ("application/json", vec![])
+        );
+        assert_eq!(
+            parse_content_type("application/json; schema=2024-06 ;charset=\"utf-8\""),
+            (
+                "application/json",
+                vec![("schema", "2024-06"), ("charset", "utf-8")]
+            )
+        );
+    }
+}
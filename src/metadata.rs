@@ -0,0 +1,88 @@
+//! Structured description of what a layer negotiates, for documentation and client code generation.
+
+use serde::ser::SerializeMap;
+
+use crate::{Config, Encoding};
+
+/// Media types negotiated by a [NegotiateLayer](crate::NegotiateLayer), returned by [NegotiateLayer::metadata](crate::NegotiateLayer::metadata).
+///
+/// It is serializable, so it can be merged into an OpenAPI document or served to client generators,
+/// keeping them in sync with the actual configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct NegotiationMetadata {
+    /// Media types of the request bodies decoded, including aliases, registered and vendor types, and [BodyDecoders](crate::BodyDecoders)
+    ///
+    /// Vendor families accepting any type are described with a wildcard, such as `application/*+json`.
+    pub request_media_types: Vec<String>,
+    /// Media types [Negotiate](crate::Negotiate) responses can be answered with, including registered and vendor types
+    pub response_media_types: Vec<String>,
+    /// Media type used when the request has no `Content-Type`/`Accept` header, or accepts `*/*`
+    pub default_media_type: Option<&'static str>,
+    /// Media types tried when the negotiated one fails to serialize the response
+    pub fallback_media_types: Vec<&'static str>,
+}
+
+impl NegotiationMetadata {
+    pub(crate) fn new(config: &Config) -> Self {
+        let matcher = &config.matcher;
+        let media_types = |enabled: fn(Encoding) -> bool| {
+            let mut media_types = Vec::new();
+            for &encoding in Encoding::ALL
+                .iter()
+                .filter(|&&encoding| enabled(encoding) && config.negotiable(encoding))
+            {
+                media_types.push(encoding.media_type().to_string());
+                media_types.extend(
+                    matcher
+                        .aliases
+                        .iter()
+                        .filter(|(_, aliased)| *aliased == encoding)
+                        .map(|(alias, _)| alias.clone()),
+                );
+                media_types.extend(
+                    matcher
+                        .media_types
+                        .iter()
+                        .filter(|(_, registered)| *registered == encoding)
+                        .map(|(media_type, _)| media_type.clone()),
+                );
+                for (_, family) in matcher
+                    .families
+                    .iter()
+                    .filter(|(claimed, _)| *claimed == encoding)
+                {
+                    media_types.extend(family.describe(encoding.suffix()));
+                }
+            }
+            media_types
+        };
+
+        let mut request_media_types = media_types(Encoding::decodes);
+        if let Some(decoders) = &config.body_decoders {
+            request_media_types.extend(decoders.media_types());
+        }
+
+        Self {
+            request_media_types,
+            response_media_types: media_types(Encoding::encodes),
+            default_media_type: matcher.default_encoding.map(Encoding::media_type),
+            fallback_media_types: config
+                .fallback_encodings
+                .iter()
+                .map(|encoding| encoding.media_type())
+                .collect(),
+        }
+    }
+}
+
+impl serde::Serialize for NegotiationMetadata {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(4))?;
+        map.serialize_entry("request_media_types", &self.request_media_types)?;
+        map.serialize_entry("response_media_types", &self.response_media_types)?;
+        map.serialize_entry("default_media_type", &self.default_media_type)?;
+        map.serialize_entry("fallback_media_types", &self.fallback_media_types)?;
+        map.end()
+    }
+}
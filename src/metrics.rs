@@ -0,0 +1,65 @@
+//! Measurements of the time spent encoding and decoding payloads, to compare the cost of each encoding on real traffic.
+
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::Encoding;
+
+/// Which side of the codec was measured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum CodecOperation {
+    /// Deserialization of a request body by the [Negotiate](crate::Negotiate) extractor
+    Decode,
+    /// Serialization of a response payload by the [NegotiateLayer](crate::NegotiateLayer)
+    Encode,
+}
+
+/// Time spent on a single encoding or decoding of a payload, provided to [NegotiateLayer::record_codec_time](crate::NegotiateLayer::record_codec_time).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CodecTiming {
+    /// Encoding used
+    pub encoding: Encoding,
+    /// Whether the payload was decoded or encoded
+    pub operation: CodecOperation,
+    /// Time spent on the codec, excluding reading the request body
+    pub elapsed: Duration,
+    /// Whether the codec succeeded
+    pub success: bool,
+}
+
+/// Callback receiving every [CodecTiming], usually forwarding them to a metrics or OpenTelemetry histogram.
+#[derive(Clone)]
+pub(crate) struct Recorder(pub(crate) Arc<dyn Fn(CodecTiming) + Send + Sync>);
+
+impl fmt::Debug for Recorder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Recorder")
+    }
+}
+
+/// Run the codec, recording how long it took when a recorder is configured.
+pub(crate) fn time<T, E>(
+    recorder: Option<&Recorder>,
+    encoding: Encoding,
+    operation: CodecOperation,
+    codec: impl FnOnce() -> Result<T, E>,
+) -> Result<T, E> {
+    let Some(Recorder(record)) = recorder else {
+        return codec();
+    };
+
+    let start = Instant::now();
+    let result = codec();
+    record(CodecTiming {
+        encoding,
+        operation,
+        elapsed: start.elapsed(),
+        success: result.is_ok(),
+    });
+    result
+}
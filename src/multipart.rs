@@ -0,0 +1,120 @@
+//! Decoding of `multipart/form-data` bodies, as sent by browser forms and `curl -F`, from their text fields.
+
+use crate::{encoding::CodecError, media_type::parse_content_type};
+
+/// Deserialize the text fields of a `multipart/form-data` body, as if they were a `application/x-www-form-urlencoded` one.
+///
+/// The boundary is read from the first delimiter of the body. File fields are skipped.
+pub(crate) fn decode_seed<D, T>(seed: D, body: &[u8]) -> Result<T, CodecError>
+where
+    D: for<'de> serde::de::DeserializeSeed<'de, Value = T>,
+{
+    let fields = parse_fields(body)?;
+    let form = form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(fields)
+        .finish();
+    let deserializer = serde_urlencoded::Deserializer::new(form_urlencoded::parse(form.as_bytes()));
+    Ok(seed.deserialize(deserializer)?)
+}
+
+/// Split the body into the names and values of its text fields.
+fn parse_fields(body: &[u8]) -> Result<Vec<(String, String)>, CodecError> {
+    let first_line = find(body, b"\r\n").ok_or("missing multipart boundary")?;
+    let boundary = body[..first_line]
+        .strip_prefix(b"--")
+        .filter(|boundary| !boundary.is_empty())
+        .ok_or("missing multipart boundary")?;
+    let delimiter = [b"\r\n--", boundary].concat();
+
+    let mut fields = Vec::new();
+    let mut rest = &body[first_line + 2..];
+    loop {
+        let headers_end = find(rest, b"\r\n\r\n").ok_or("unterminated multipart headers")?;
+        let headers = std::str::from_utf8(&rest[..headers_end])?;
+        rest = &rest[headers_end + 4..];
+
+        let content_end = find(rest, &delimiter).ok_or("unterminated multipart body")?;
+        let content = &rest[..content_end];
+        rest = &rest[content_end + delimiter.len()..];
+
+        if let Some(name) = field_name(headers)? {
+            fields.push((name, String::from_utf8(content.to_vec())?));
+        }
+
+        match rest {
+            [b'-', b'-', ..] => return Ok(fields),
+            [b'\r', b'\n', tail @ ..] => rest = tail,
+            _ => return Err("malformed multipart delimiter".into()),
+        }
+    }
+}
+
+/// Name of a text field from the part headers, or `None` for file fields.
+fn field_name(headers: &str) -> Result<Option<String>, CodecError> {
+    let disposition = headers
+        .split("\r\n")
+        .filter_map(|header| header.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-disposition"))
+        .map(|(_, value)| value)
+        .ok_or("multipart part without a Content-Disposition")?;
+
+    let (_, parameters) = parse_content_type(disposition);
+    if parameters
+        .iter()
+        .any(|(name, _)| name.eq_ignore_ascii_case("filename"))
+    {
+        return Ok(None);
+    }
+    let name = parameters
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("name"))
+        .map(|(_, value)| value.to_string())
+        .ok_or("multipart part without a field name")?;
+    Ok(Some(name))
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_fields;
+
+    #[test]
+    fn test_parse_text_fields_skipping_files() {
+        let body = concat!(
+            "--XyZ\r\n",
+            "Content-Disposition: form-data; name=\"message\"\r\n",
+            "\r\n",
+            "Hello, test!\r\n",
+            "--XyZ\r\n",
+            "Content-Disposition: form-data; name=\"upload\"; filename=\"a.txt\"\r\n",
+            "Content-Type: text/plain\r\n",
+            "\r\n",
+            "ignored\r\n",
+            "--XyZ\r\n",
+            "content-disposition: form-data; name=count\r\n",
+            "\r\n",
+            "2\r\n",
+            "--XyZ--\r\n",
+        );
+
+        assert_eq!(
+            parse_fields(body.as_bytes()).unwrap(),
+            vec![
+                ("message".to_string(), "Hello, test!".to_string()),
+                ("count".to_string(), "2".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reject_unterminated_bodies() {
+        let body = "--XyZ\r\nContent-Disposition: form-data; name=\"message\"\r\n\r\nHello";
+
+        assert!(parse_fields(body.as_bytes()).is_err());
+    }
+}
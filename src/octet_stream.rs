@@ -0,0 +1,135 @@
+//! Raw `application/octet-stream` bodies, for pre-encoded blobs served alongside structured payloads.
+
+use std::{fmt, sync::Arc};
+
+use axum::{
+    body::Bytes,
+    extract::{FromRequest, Request},
+    http::{Extensions, StatusCode},
+    response::{IntoResponse, Response},
+    Extension,
+};
+
+use crate::{
+    context::{ContextualNegotiate, ContextualPayload},
+    encoding::CodecError,
+    Encoding,
+};
+
+/// Used either as an [Extract](axum::extract::FromRequest) or [Response](axum::response::IntoResponse) for raw bytes,
+/// passed through as is on [Encoding::OctetStream] and as a byte string on the other encodings.
+///
+/// It still takes part in the negotiation, so a client accepting `application/octet-stream, application/cbor;q=0.5`
+/// gets the blob itself, while a CBOR-only client gets it wrapped in a CBOR byte string.
+///
+/// ```rust
+/// use axum::body::Bytes;
+/// use axum_content_negotiation::NegotiateBytes;
+///
+/// async fn thumbnail() -> NegotiateBytes {
+///     NegotiateBytes(Bytes::from_static(b"\x89PNG..."))
+/// }
+///
+/// async fn upload(NegotiateBytes(blob): NegotiateBytes) -> String {
+///     format!("received {} bytes", blob.len())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct NegotiateBytes(
+    /// The raw content of the body
+    pub Bytes,
+);
+
+/// Serializes the blob as a byte string, such as a CBOR byte string or a JSON array of numbers.
+struct Blob<'a>(&'a [u8]);
+
+impl serde::Serialize for Blob<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+/// Deserializes a byte string, or a sequence of bytes for encodings without one.
+struct BlobVisitor;
+
+impl<'de> serde::de::Visitor<'de> for BlobVisitor {
+    type Value = Bytes;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a byte string")
+    }
+
+    fn visit_bytes<E: serde::de::Error>(self, value: &[u8]) -> Result<Bytes, E> {
+        Ok(Bytes::copy_from_slice(value))
+    }
+
+    fn visit_byte_buf<E: serde::de::Error>(self, value: Vec<u8>) -> Result<Bytes, E> {
+        Ok(value.into())
+    }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Bytes, A::Error> {
+        // The declared length is read from the untrusted payload, so only a bounded part is preallocated
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or_default().min(64 * 1024));
+        while let Some(byte) = seq.next_element::<u8>()? {
+            bytes.push(byte);
+        }
+        Ok(bytes.into())
+    }
+}
+
+struct BlobSeed;
+
+impl<'de> serde::de::DeserializeSeed<'de> for BlobSeed {
+    type Value = Bytes;
+
+    fn deserialize<D: serde::Deserializer<'de>>(self, deserializer: D) -> Result<Bytes, D::Error> {
+        deserializer.deserialize_byte_buf(BlobVisitor)
+    }
+}
+
+/// [NegotiateBytes] implements [FromRequest] for any decodable `Content-Type`.
+///
+/// `application/octet-stream` bodies are taken as is, without being copied, and the other encodings must hold a byte string.
+/// Unsupported media types and malformed bodies are answered with the same rejections as [Negotiate](crate::Negotiate).
+impl<S> FromRequest<S> for NegotiateBytes
+where
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        crate::decode_request(req, state, |encoding, body, options| match encoding {
+            Encoding::OctetStream => Ok(body),
+            _ => encoding.decode_seed(BlobSeed, body, options),
+        })
+        .await
+        .map(Self)
+    }
+}
+
+/// [NegotiateBytes] implements [IntoResponse], converted to the right response by the [NegotiateLayer](crate::NegotiateLayer)
+/// just like [Negotiate](crate::Negotiate).
+impl IntoResponse for NegotiateBytes {
+    fn into_response(self) -> Response {
+        let payload: Arc<dyn ContextualPayload> = Arc::new(self);
+        (
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Extension(ContextualNegotiate(payload)),
+            "Misconfigured service layer",
+        )
+            .into_response()
+    }
+}
+
+impl ContextualPayload for NegotiateBytes {
+    fn encode(&self, encoding: Encoding, _: &Extensions) -> Result<Vec<u8>, CodecError> {
+        match encoding {
+            Encoding::OctetStream => Ok(self.0.to_vec()),
+            _ => encoding.encode(&Blob(&self.0)),
+        }
+    }
+
+    fn encodes(&self, encoding: Encoding) -> bool {
+        encoding == Encoding::OctetStream || encoding.encodes()
+    }
+}
@@ -0,0 +1,403 @@
+//! JSON Patch request bodies of RFC 6902, sent as `application/json-patch+json` to partially update resources.
+
+use std::fmt;
+
+use axum::{
+    extract::{FromRequest, Request},
+    http::header::CONTENT_TYPE,
+    response::Response,
+};
+use serde_json::Value;
+
+use crate::{
+    error::{Mismatch, Rejection},
+    media_type, AcceptExt, Config,
+};
+
+static MEDIA_TYPE: &str = "application/json-patch+json";
+
+/// One of the operations of a [JsonPatch].
+///
+/// Paths are JSON Pointers of RFC 6901, such as `/items/0/name`.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum PatchOperation {
+    /// Add the value, inserting it into arrays, or appending it with the `-` index
+    Add {
+        /// Location of the value
+        path: String,
+        /// Value added
+        value: Value,
+    },
+    /// Remove the existing value
+    Remove {
+        /// Location of the value
+        path: String,
+    },
+    /// Replace the existing value
+    Replace {
+        /// Location of the value
+        path: String,
+        /// New value
+        value: Value,
+    },
+    /// Remove the value at `from` and add it to `path`
+    Move {
+        /// Location of the value moved
+        from: String,
+        /// Location it is moved to
+        path: String,
+    },
+    /// Add a copy of the value at `from` to `path`
+    Copy {
+        /// Location of the value copied
+        from: String,
+        /// Location of the copy
+        path: String,
+    },
+    /// Check the value is equal, failing the whole patch otherwise
+    Test {
+        /// Location of the value
+        path: String,
+        /// Expected value
+        value: Value,
+    },
+}
+
+/// Extractor reading a JSON Patch from an `application/json-patch+json` request body.
+///
+/// Other media types are rejected like unsupported formats of [Negotiate](crate::Negotiate), and the response is still negotiated as usual.
+///
+/// ```rust
+/// use axum_content_negotiation::{JsonPatch, Negotiate};
+///
+/// #[derive(serde::Serialize, serde::Deserialize)]
+/// struct Profile {
+///     name: String,
+/// }
+///
+/// async fn handler(patch: JsonPatch) -> Result<Negotiate<Profile>, String> {
+///     let current = Profile { name: "Ada".to_string() };
+///     patch.apply(&current).map(Negotiate).map_err(|e| e.to_string())
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonPatch(
+    /// The operations, applied in order
+    pub Vec<PatchOperation>,
+);
+
+/// Why a [JsonPatch] could not be applied, leaving the value untouched.
+#[derive(Debug)]
+pub struct PatchError {
+    operation: usize,
+    reason: String,
+}
+
+impl PatchError {
+    /// Position of the operation which failed, or the number of operations if the patched value didn't deserialize back.
+    pub fn operation(&self) -> usize {
+        self.operation
+    }
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "JSON Patch operation {} failed: {}",
+            self.operation, self.reason
+        )
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+impl JsonPatch {
+    /// Apply the operations to a copy of the value, returning the patched value only if all of them succeed.
+    pub fn apply<T>(&self, value: &T) -> Result<T, PatchError>
+    where
+        T: serde::Serialize + serde::de::DeserializeOwned,
+    {
+        let failure = |operation, reason: String| PatchError { operation, reason };
+
+        let mut document =
+            serde_json::to_value(value).map_err(|e| failure(self.0.len(), e.to_string()))?;
+        self.apply_to_value(&mut document)?;
+        serde_json::from_value(document).map_err(|e| failure(self.0.len(), e.to_string()))
+    }
+
+    /// Apply the operations to a JSON value, leaving it untouched if any of them fails.
+    pub fn apply_to_value(&self, document: &mut Value) -> Result<(), PatchError> {
+        let mut patched = document.clone();
+        for (index, operation) in self.0.iter().enumerate() {
+            apply(&mut patched, operation).map_err(|reason| PatchError {
+                operation: index,
+                reason,
+            })?;
+        }
+        *document = patched;
+        Ok(())
+    }
+}
+
+fn apply(document: &mut Value, operation: &PatchOperation) -> Result<(), String> {
+    match operation {
+        PatchOperation::Add { path, value } => add(document, &pointer(path)?, value.clone()),
+        PatchOperation::Remove { path } => remove(document, &pointer(path)?).map(drop),
+        PatchOperation::Replace { path, value } => {
+            let target = get_mut(document, &pointer(path)?).ok_or("path does not exist")?;
+            *target = value.clone();
+            Ok(())
+        }
+        PatchOperation::Move { from, path } => {
+            let (from, path) = (pointer(from)?, pointer(path)?);
+            if path.len() > from.len() && path.starts_with(&from) {
+                return Err("a value can't be moved into itself".to_string());
+            }
+            let value = remove(document, &from)?;
+            add(document, &path, value)
+        }
+        PatchOperation::Copy { from, path } => {
+            let value = get_mut(document, &pointer(from)?)
+                .ok_or("from does not exist")?
+                .clone();
+            add(document, &pointer(path)?, value)
+        }
+        PatchOperation::Test { path, value } => match get_mut(document, &pointer(path)?) {
+            Some(current) if current == value => Ok(()),
+            _ => Err("test failed".to_string()),
+        },
+    }
+}
+
+/// Split a JSON Pointer into its unescaped reference tokens.
+fn pointer(path: &str) -> Result<Vec<String>, String> {
+    if path.is_empty() {
+        return Ok(Vec::new());
+    }
+    let tokens = path
+        .strip_prefix('/')
+        .ok_or_else(|| format!("invalid JSON Pointer {path:?}"))?;
+    Ok(tokens
+        .split('/')
+        .map(|token| token.replace("~1", "/").replace("~0", "~"))
+        .collect())
+}
+
+/// Position of an existing array element, as a token without leading zeros.
+fn index(token: &str, len: usize) -> Option<usize> {
+    if token.is_empty() || (token.len() > 1 && token.starts_with('0')) {
+        return None;
+    }
+    token.parse().ok().filter(|&index| index < len)
+}
+
+fn get_mut<'a>(document: &'a mut Value, tokens: &[String]) -> Option<&'a mut Value> {
+    tokens
+        .iter()
+        .try_fold(document, |current, token| match current {
+            Value::Object(map) => map.get_mut(token),
+            Value::Array(items) => {
+                let index = index(token, items.len())?;
+                items.get_mut(index)
+            }
+            _ => None,
+        })
+}
+
+fn add(document: &mut Value, tokens: &[String], value: Value) -> Result<(), String> {
+    let Some((last, parent)) = tokens.split_last() else {
+        *document = value;
+        return Ok(());
+    };
+    match get_mut(document, parent).ok_or("parent does not exist")? {
+        Value::Object(map) => {
+            map.insert(last.clone(), value);
+            Ok(())
+        }
+        Value::Array(items) if last == "-" => {
+            items.push(value);
+            Ok(())
+        }
+        Value::Array(items) => {
+            let index = index(last, items.len() + 1).ok_or("array index is out of bounds")?;
+            items.insert(index, value);
+            Ok(())
+        }
+        _ => Err("parent is not a container".to_string()),
+    }
+}
+
+fn remove(document: &mut Value, tokens: &[String]) -> Result<Value, String> {
+    let (last, parent) = tokens
+        .split_last()
+        .ok_or("the whole document can't be removed")?;
+    match get_mut(document, parent).ok_or("parent does not exist")? {
+        Value::Object(map) => map
+            .remove(last)
+            .ok_or_else(|| "path does not exist".to_string()),
+        Value::Array(items) => {
+            let index = index(last, items.len()).ok_or("array index is out of bounds")?;
+            Ok(items.remove(index))
+        }
+        _ => Err("parent is not a container".to_string()),
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for PatchOperation {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let Value::Object(mut operation) = Value::deserialize(deserializer)? else {
+            return Err(D::Error::custom("JSON Patch operations must be objects"));
+        };
+        fn member<E: Error>(
+            operation: &mut serde_json::Map<String, Value>,
+            name: &'static str,
+        ) -> Result<Value, E> {
+            operation.remove(name).ok_or_else(|| E::missing_field(name))
+        }
+        fn text<E: Error>(
+            operation: &mut serde_json::Map<String, Value>,
+            name: &'static str,
+        ) -> Result<String, E> {
+            match member(operation, name)? {
+                Value::String(text) => Ok(text),
+                _ => Err(E::custom(format!("{name} must be a string"))),
+            }
+        }
+
+        let op = text(&mut operation, "op")?;
+        let path = text(&mut operation, "path")?;
+        let operation = match op.as_str() {
+            "add" => Self::Add {
+                path,
+                value: member(&mut operation, "value")?,
+            },
+            "remove" => Self::Remove { path },
+            "replace" => Self::Replace {
+                path,
+                value: member(&mut operation, "value")?,
+            },
+            "move" => Self::Move {
+                from: text(&mut operation, "from")?,
+                path,
+            },
+            "copy" => Self::Copy {
+                from: text(&mut operation, "from")?,
+                path,
+            },
+            "test" => Self::Test {
+                path,
+                value: member(&mut operation, "value")?,
+            },
+            _ => {
+                return Err(D::Error::unknown_variant(
+                    &op,
+                    &["add", "remove", "replace", "move", "copy", "test"],
+                ))
+            }
+        };
+        Ok(operation)
+    }
+}
+
+/// [JsonPatch] implements [FromRequest] for `application/json-patch+json` bodies.
+///
+/// Failures are answered with the same rejections as the [Negotiate](crate::Negotiate) extractor.
+impl<S> FromRequest<S> for JsonPatch
+where
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let config = Config::from_extensions(req.extensions());
+        let accepted = req
+            .headers()
+            .negotiate(&config)
+            .ok()
+            .map(|selection| selection.encoding);
+        let request_id = crate::error::request_id(req.headers());
+        let reject = |rejection| config.reject(rejection, accepted, request_id.as_deref());
+        // Only JSON Patch documents are decoded, whatever the encodings of the layer
+        let unsupported = |mismatch| {
+            config.respond(
+                Rejection::UnsupportedContentType(mismatch),
+                accepted,
+                request_id.as_deref(),
+                &[MEDIA_TYPE],
+                Some(crate::error::accept_header(req.method())),
+            )
+        };
+
+        let content_type = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .map(|content_type| media_type::parse_content_type(content_type).0);
+        match content_type {
+            Some(content_type) if content_type.eq_ignore_ascii_case(MEDIA_TYPE) => {}
+            Some(content_type) if media_type::is_valid(content_type) => {
+                return Err(unsupported(Mismatch::Unsupported));
+            }
+            _ => return Err(unsupported(Mismatch::Invalid)),
+        }
+
+        let (body, _permit) = crate::read_body(req, state, &config, &reject).await?;
+
+        serde_json::from_slice(&body).map(Self).map_err(|e| {
+            tracing::error!(error = %e, "failed to deserialize JSON Patch body");
+            reject(Rejection::MalformedBody)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::JsonPatch;
+
+    fn patch(operations: serde_json::Value) -> JsonPatch {
+        JsonPatch(serde_json::from_value(operations).unwrap())
+    }
+
+    #[test]
+    fn test_apply_operations_in_order() {
+        let mut document = json!({ "name": "Ada", "tags": ["a", "b"], "old": 1 });
+
+        patch(json!([
+            { "op": "test", "path": "/name", "value": "Ada" },
+            { "op": "replace", "path": "/name", "value": "Grace" },
+            { "op": "add", "path": "/tags/1", "value": "c" },
+            { "op": "add", "path": "/tags/-", "value": "d" },
+            { "op": "move", "from": "/old", "path": "/new" },
+            { "op": "copy", "from": "/new", "path": "/a~1b" },
+            { "op": "remove", "path": "/tags/0" },
+        ]))
+        .apply_to_value(&mut document)
+        .unwrap();
+
+        assert_eq!(
+            document,
+            json!({ "name": "Grace", "tags": ["c", "b", "d"], "new": 1, "a/b": 1 })
+        );
+    }
+
+    #[test]
+    fn test_failed_patches_leave_the_value_untouched() {
+        let mut document = json!({ "name": "Ada" });
+
+        let error = patch(json!([
+            { "op": "replace", "path": "/name", "value": "Grace" },
+            { "op": "test", "path": "/name", "value": "Ada" },
+        ]))
+        .apply_to_value(&mut document)
+        .unwrap_err();
+
+        assert_eq!(error.operation(), 1);
+        assert_eq!(document, json!({ "name": "Ada" }));
+    }
+}
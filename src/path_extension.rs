@@ -0,0 +1,154 @@
+//! Selection of the response format from the URL, for clients unable to set `Accept` headers,
+//! with an extension on the request path such as `/orders.json`, or a query parameter such as `?format=json`.
+
+use std::{
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use axum::http::{header::ACCEPT, uri::PathAndQuery, HeaderValue, Request, Uri};
+use tower::{Layer, Service};
+
+use crate::{Config, Encoding};
+
+/// Strips a format extension from the path of requests, replacing their `Accept` header with the media type of the encoding,
+/// built by [NegotiateLayer::path_extensions](crate::NegotiateLayer::path_extensions).
+///
+/// Routing happens before the layers of a [Router](axum::Router) run, so it must wrap the router itself for `/orders.json`
+/// to be routed to `/orders`. Extensions not negotiated by the [NegotiateLayer](crate::NegotiateLayer) are left untouched.
+#[derive(Debug, Clone)]
+pub struct PathExtensionLayer {
+    config: Arc<Config>,
+}
+
+impl PathExtensionLayer {
+    pub(crate) fn new(config: Arc<Config>) -> Self {
+        Self { config }
+    }
+}
+
+impl<S> Layer<S> for PathExtensionLayer {
+    type Service = PathExtension<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        PathExtension {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+/// Strips a format extension from the path of requests, created by the [PathExtensionLayer].
+#[derive(Debug, Clone)]
+pub struct PathExtension<S> {
+    inner: S,
+    config: Arc<Config>,
+}
+
+impl<S, B> Service<Request<B>> for PathExtension<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<B>) -> Self::Future {
+        if let Some((uri, encoding)) = strip(&self.config, request.uri()) {
+            *request.uri_mut() = uri;
+            request
+                .headers_mut()
+                .insert(ACCEPT, HeaderValue::from_static(encoding.media_type()));
+        }
+        self.inner.call(request)
+    }
+}
+
+/// Remove the extension of the last path segment when it names an encoding answered by the layer, keeping the query.
+fn strip(config: &Config, uri: &Uri) -> Option<(Uri, Encoding)> {
+    let path = uri.path();
+    let (stem, extension) = path.rsplit_once('.')?;
+    if stem.ends_with('/') || extension.contains('/') {
+        return None;
+    }
+    let encoding = encoding(config, extension)?;
+
+    let path_and_query = match uri.query() {
+        Some(query) => format!("{stem}?{query}"),
+        None => stem.to_string(),
+    };
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(PathAndQuery::try_from(path_and_query).ok()?);
+    Some((Uri::from_parts(parts).ok()?, encoding))
+}
+
+/// Find the format named by the query parameter, if any, as an encoding answered by the layer or `Err` for unknown names.
+pub(crate) fn query_format(
+    config: &Config,
+    query: Option<&str>,
+    parameter: &str,
+) -> Option<Result<Encoding, ()>> {
+    let (_, format) = query?
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(name, format)| *name == parameter && !format.is_empty())?;
+    Some(encoding(config, format).ok_or(()))
+}
+
+/// First encoding answered by the layer with the file extension, so `.json` is JSON even with HAL enabled.
+fn encoding(config: &Config, extension: &str) -> Option<Encoding> {
+    Encoding::ALL.iter().copied().find(|&encoding| {
+        encoding.encodes()
+            && config.negotiable(encoding)
+            && encoding
+                .extension()
+                .is_some_and(|known| known.eq_ignore_ascii_case(extension))
+    })
+}
+
+#[cfg(all(test, any(feature = "simd-json", feature = "json")))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_strip_known_extensions_only() {
+        let config = Config::default();
+        let strip = |uri: &str| {
+            strip(&config, &uri.parse().unwrap()).map(|(uri, encoding)| (uri.to_string(), encoding))
+        };
+
+        assert_eq!(
+            strip("/orders.json?page=2"),
+            Some(("/orders?page=2".to_string(), Encoding::Json))
+        );
+        assert_eq!(
+            strip("http://example.com/v1.2/orders.JSON"),
+            Some(("http://example.com/v1.2/orders".to_string(), Encoding::Json))
+        );
+        assert_eq!(strip("/orders"), None);
+        assert_eq!(strip("/v1.2/orders"), None);
+        assert_eq!(strip("/orders.xml"), None);
+        assert_eq!(strip("/.json"), None);
+    }
+
+    #[test]
+    fn test_read_format_from_the_query() {
+        let config = Config::default();
+
+        assert_eq!(
+            query_format(&config, Some("page=2&format=json"), "format"),
+            Some(Ok(Encoding::Json))
+        );
+        assert_eq!(
+            query_format(&config, Some("format=xml"), "format"),
+            Some(Err(()))
+        );
+        assert_eq!(query_format(&config, Some("format="), "format"), None);
+        assert_eq!(query_format(&config, Some("f=json"), "format"), None);
+        assert_eq!(query_format(&config, None, "format"), None);
+    }
+}
@@ -0,0 +1,121 @@
+//! Stable hashes of request payloads, identical for the same logical payload sent in any encoding, such as for idempotency keys.
+
+use std::{fmt, sync::Arc};
+
+use axum::{body::Body, extract::Request, http::Extensions, response::Response};
+
+use crate::{error::Rejection, value::Value, Config};
+
+type Hasher = dyn Fn(&[u8], &mut Extensions) + Send + Sync;
+
+/// Callback hashing the canonical form of request payloads into their extensions.
+#[derive(Clone)]
+pub(crate) struct PayloadHasher(pub(crate) Arc<Hasher>);
+
+impl fmt::Debug for PayloadHasher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PayloadHasher")
+    }
+}
+
+/// Hash of the canonical form of the request payload, stored on the request extensions by
+/// [NegotiateLayer::payload_hash](crate::NegotiateLayer::payload_hash).
+///
+/// Middleware under the layer, and handlers with an [Extension](axum::Extension) extractor, can read it before the body is decoded.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PayloadHash<H>(
+    /// Value returned by the hasher
+    pub H,
+);
+
+/// Buffer the request body, storing the hash of its canonical form on the extensions when it can be decoded.
+///
+/// Bodies failing to decode are left for the extractors to reject, while bodies failing to be read are rejected right away.
+pub(crate) async fn hash_request(
+    request: Request,
+    config: &Config,
+    reject: impl Fn(Rejection) -> Response,
+) -> Result<Request, Response> {
+    let Some(PayloadHasher(hasher)) = &config.payload_hash else {
+        return Ok(request);
+    };
+    // Bodies are selected and read as the extractors do, keeping the extensions such as `DefaultBodyLimit`
+    let Ok((encoding, translate)) = crate::request_encoding(&request, config) else {
+        return Ok(request);
+    };
+
+    let (mut parts, body) = request.into_parts();
+    let buffered = Request::from_parts(parts.clone(), body);
+    let (body, _permit) = crate::read_body(buffered, &(), config, &reject).await?;
+
+    if !body.is_empty() {
+        let translated = match &translate {
+            Some(translate) => translate(body.clone()),
+            None => Ok(body.clone()),
+        };
+        match translated
+            .and_then(|translated| encoding.decode::<Value>(translated, &config.decode_options))
+        {
+            Ok(value) => {
+                let mut canonical = Vec::new();
+                write_canonical(&value, &mut canonical);
+                hasher(&canonical, &mut parts.extensions);
+            }
+            Err(e) => tracing::debug!(error = %e, %encoding, "skipped hashing undecodable payload"),
+        }
+    }
+    Ok(Request::from_parts(parts, Body::from(body)))
+}
+
+/// Write the value with a layout independent of the encoding it was decoded from: integers are widened,
+/// and map entries are sorted by their own canonical form, as formats differ on key order.
+pub(crate) fn write_canonical(value: &Value, out: &mut Vec<u8>) {
+    let write_len =
+        |len: usize, out: &mut Vec<u8>| out.extend_from_slice(&(len as u64).to_be_bytes());
+    match value {
+        Value::Null => out.push(0),
+        Value::Bool(v) => out.extend_from_slice(&[1, u8::from(*v)]),
+        Value::Integer(v) => {
+            out.push(2);
+            out.extend_from_slice(&v.to_be_bytes());
+        }
+        Value::Float(v) => {
+            out.push(3);
+            out.extend_from_slice(&v.to_bits().to_be_bytes());
+        }
+        Value::Text(v) => {
+            out.push(4);
+            write_len(v.len(), out);
+            out.extend_from_slice(v.as_bytes());
+        }
+        Value::Bytes(v) => {
+            out.push(5);
+            write_len(v.len(), out);
+            out.extend_from_slice(v);
+        }
+        Value::Array(values) => {
+            out.push(6);
+            write_len(values.len(), out);
+            for value in values {
+                write_canonical(value, out);
+            }
+        }
+        Value::Map(entries) => {
+            let mut entries: Vec<_> = entries
+                .iter()
+                .map(|(key, value)| {
+                    let mut entry = Vec::new();
+                    write_canonical(key, &mut entry);
+                    write_canonical(value, &mut entry);
+                    entry
+                })
+                .collect();
+            entries.sort();
+            out.push(7);
+            write_len(entries.len(), out);
+            for entry in entries {
+                out.extend_from_slice(&entry);
+            }
+        }
+    }
+}
@@ -0,0 +1,91 @@
+//! `text/plain` responses for types with a human-readable form, such as for curl users and health endpoints.
+
+use std::{fmt, sync::Arc};
+
+use axum::{
+    http::{Extensions, StatusCode},
+    response::{IntoResponse, Response},
+    Extension,
+};
+
+use crate::{
+    context::{ContextualNegotiate, ContextualPayload},
+    encoding::CodecError,
+    Encoding,
+};
+
+/// Build the `text/plain` representation of a value.
+///
+/// Every [Display](fmt::Display) type implements it, so usually implementing [Display](fmt::Display) is enough.
+///
+/// ```rust
+/// use std::fmt;
+///
+/// #[derive(serde::Serialize)]
+/// struct Health {
+///     healthy: bool,
+/// }
+///
+/// impl fmt::Display for Health {
+///     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+///         f.write_str(if self.healthy { "ok" } else { "degraded" })
+///     }
+/// }
+/// ```
+pub trait ToPlainText {
+    /// Text of the value, encoded as UTF-8 on the body.
+    fn to_plain_text(&self) -> String;
+}
+
+impl<T> ToPlainText for T
+where
+    T: fmt::Display + ?Sized,
+{
+    fn to_plain_text(&self) -> String {
+        self.to_string()
+    }
+}
+
+/// Used as a [Response](axum::response::IntoResponse), like [Negotiate](crate::Negotiate),
+/// but answering [Encoding::PlainText] with [ToPlainText] and the other encodings with serde.
+///
+/// Clients only get `text/plain` when asking for it, such as with `curl -H 'Accept: text/plain'`, while machine clients keep their format.
+#[derive(Debug, Clone)]
+pub struct NegotiatePlainText<T>(
+    /// The stored content to be serialized
+    pub T,
+);
+
+/// [NegotiatePlainText] implements [IntoResponse] if the internal content is serializable by both serde and [ToPlainText].
+///
+/// Just like [Negotiate](crate::Negotiate), it is converted to the right response by the [NegotiateLayer](crate::NegotiateLayer).
+impl<T> IntoResponse for NegotiatePlainText<T>
+where
+    T: serde::Serialize + ToPlainText + Send + Sync + 'static,
+{
+    fn into_response(self) -> Response {
+        let payload: Arc<dyn ContextualPayload> = Arc::new(self);
+        (
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Extension(ContextualNegotiate(payload)),
+            "Misconfigured service layer",
+        )
+            .into_response()
+    }
+}
+
+impl<T> ContextualPayload for NegotiatePlainText<T>
+where
+    T: serde::Serialize + ToPlainText + Send + Sync,
+{
+    fn encode(&self, encoding: Encoding, _: &Extensions) -> Result<Vec<u8>, CodecError> {
+        match encoding {
+            Encoding::PlainText => Ok(self.0.to_plain_text().into_bytes()),
+            _ => encoding.encode(&self.0),
+        }
+    }
+
+    fn encodes(&self, encoding: Encoding) -> bool {
+        encoding == Encoding::PlainText || encoding.encodes()
+    }
+}
@@ -0,0 +1,162 @@
+//! Problem details of RFC 9457, negotiated as `application/problem+json` or `application/problem+cbor`.
+
+use std::fmt;
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::ser::SerializeMap;
+
+use crate::{Encoding, ErasedNegotiate, Negotiate};
+
+/// Used as a [Response](axum::response::IntoResponse) describing an error with the problem details of RFC 9457.
+///
+/// The [NegotiateLayer](crate::NegotiateLayer) serializes it like [Negotiate], informing the problem media type of the encoding,
+/// such as `application/problem+json`. Use [NegotiateLayer::problem_details](crate::NegotiateLayer::problem_details)
+/// for the crate rejections to use them as well.
+///
+/// ```rust
+/// use axum::http::StatusCode;
+/// use axum_content_negotiation::ProblemDetails;
+///
+/// async fn handler() -> ProblemDetails {
+///     ProblemDetails::new(StatusCode::FORBIDDEN)
+///         .type_uri("https://example.com/probs/out-of-credit")
+///         .detail("Your current balance is 30, but that costs 50.")
+///         .extension("balance", 30)
+/// }
+/// ```
+#[derive(Clone)]
+pub struct ProblemDetails {
+    status: StatusCode,
+    type_uri: Option<String>,
+    title: Option<String>,
+    detail: Option<String>,
+    instance: Option<String>,
+    extensions: Vec<(String, ErasedNegotiate)>,
+}
+
+impl ProblemDetails {
+    /// Describe a problem answered with the status, titled after its canonical reason and typed `about:blank` by default.
+    pub fn new(status: StatusCode) -> Self {
+        Self {
+            status,
+            type_uri: None,
+            title: None,
+            detail: None,
+            instance: None,
+            extensions: Vec::new(),
+        }
+    }
+
+    /// URI reference identifying the problem type.
+    pub fn type_uri(mut self, type_uri: impl Into<String>) -> Self {
+        self.type_uri = Some(type_uri.into());
+        self
+    }
+
+    /// Short summary of the problem type, which shouldn't change between occurrences.
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    /// Explanation specific to this occurrence of the problem.
+    pub fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    /// URI reference identifying this occurrence of the problem.
+    pub fn instance(mut self, instance: impl Into<String>) -> Self {
+        self.instance = Some(instance.into());
+        self
+    }
+
+    /// Extra member of the problem, such as a machine-readable code.
+    pub fn extension<T>(mut self, name: impl Into<String>, value: T) -> Self
+    where
+        T: serde::Serialize + Send + Sync + 'static,
+    {
+        self.extensions.push((name.into(), value.into()));
+        self
+    }
+
+    /// Status code of the response.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+}
+
+impl fmt::Debug for ProblemDetails {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProblemDetails")
+            .field("status", &self.status)
+            .field("type_uri", &self.type_uri)
+            .field("title", &self.title)
+            .field("detail", &self.detail)
+            .field("instance", &self.instance)
+            .field(
+                "extensions",
+                &self
+                    .extensions
+                    .iter()
+                    .map(|(name, _)| name)
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl serde::Serialize for ProblemDetails {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let title = self
+            .title
+            .as_deref()
+            .or_else(|| self.status.canonical_reason());
+
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("type", self.type_uri.as_deref().unwrap_or("about:blank"))?;
+        if let Some(title) = title {
+            map.serialize_entry("title", title)?;
+        }
+        map.serialize_entry("status", &self.status.as_u16())?;
+        if let Some(detail) = &self.detail {
+            map.serialize_entry("detail", detail)?;
+        }
+        if let Some(instance) = &self.instance {
+            map.serialize_entry("instance", instance)?;
+        }
+        for (name, ErasedNegotiate(value)) in &self.extensions {
+            map.serialize_entry(name, value.as_ref())?;
+        }
+        map.end()
+    }
+}
+
+/// [ProblemDetails] implements [IntoResponse], answering its status.
+impl IntoResponse for ProblemDetails {
+    fn into_response(self) -> Response {
+        let status = self.status;
+        let mut response = Negotiate(self).into_response();
+        response.extensions_mut().insert(Problem(status));
+        response
+    }
+}
+
+/// Marks [ProblemDetails] responses, waiting for the negotiated encoding to apply their status and media type.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Problem(pub(crate) StatusCode);
+
+/// Problem media type of the encoding, or its own media type when it has none.
+pub(crate) fn media_type(encoding: Encoding) -> &'static str {
+    match encoding {
+        #[cfg(any(feature = "simd-json", feature = "json"))]
+        Encoding::Json => "application/problem+json",
+        #[cfg(feature = "cbor")]
+        Encoding::Cbor => "application/problem+cbor",
+        #[allow(unreachable_patterns)]
+        _ => encoding.media_type(),
+    }
+}
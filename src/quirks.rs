@@ -0,0 +1,128 @@
+//! Registry of clients with known format quirks, adjusting the negotiation regardless of what their `Accept` header claims.
+
+use std::{fmt, sync::Arc};
+
+use axum::http::{header::USER_AGENT, HeaderMap};
+
+use crate::Encoding;
+
+type Matches = dyn Fn(&HeaderMap) -> bool + Send + Sync;
+
+/// Clients which must not be answered with some encodings, such as old SDKs claiming a format they fail to parse.
+///
+/// Rules are checked in order against the request headers, and the encodings of every matching rule are excluded from the negotiation,
+/// including the default encoding used for `*/*` or a missing `Accept` header.
+///
+/// Clients known to omit the `Accept` header can also be given their own default encoding,
+/// instead of the single default of the [NegotiateLayer](crate::NegotiateLayer). The first matching rule with a default wins.
+///
+/// ```rust
+/// use axum_content_negotiation::{ClientQuirks, Encoding, NegotiateLayer};
+///
+/// # #[cfg(feature = "cbor")]
+/// let quirks = ClientQuirks::new()
+///     // SDKs before 2.0 had a broken CBOR parser
+///     .user_agent("acme-sdk/1.", [Encoding::Cbor])
+///     .matching(
+///         |headers| headers.get("x-api-key").is_some_and(|key| key == "legacy-partner"),
+///         [Encoding::Cbor],
+///     )
+///     // Embedded devices never send an Accept header
+///     .default_for_user_agent("acme-firmware/", Encoding::Cbor);
+/// # #[cfg(feature = "cbor")]
+/// let layer = NegotiateLayer::new().client_quirks(quirks);
+/// ```
+#[derive(Clone, Default)]
+pub struct ClientQuirks(Vec<Rule>);
+
+#[derive(Clone)]
+struct Rule {
+    matches: Arc<Matches>,
+    excluded: Vec<Encoding>,
+    default: Option<Encoding>,
+}
+
+/// Match the `User-Agent` starting with `prefix`, compared case-insensitively.
+fn user_agent(prefix: String) -> impl Fn(&HeaderMap) -> bool + Send + Sync + 'static {
+    move |headers| {
+        headers
+            .get(USER_AGENT)
+            .and_then(|user_agent| user_agent.to_str().ok())
+            .and_then(|user_agent| user_agent.get(..prefix.len()))
+            .is_some_and(|start| start.eq_ignore_ascii_case(&prefix))
+    }
+}
+
+impl ClientQuirks {
+    /// Create a registry without clients.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Never answer the encodings to clients whose `User-Agent` starts with `prefix`, compared case-insensitively.
+    pub fn user_agent(
+        self,
+        prefix: impl Into<String>,
+        excluded: impl IntoIterator<Item = Encoding>,
+    ) -> Self {
+        self.matching(user_agent(prefix.into()), excluded)
+    }
+
+    /// Never answer the encodings to clients whose request headers match, such as by API key or version header.
+    pub fn matching<F>(mut self, matches: F, excluded: impl IntoIterator<Item = Encoding>) -> Self
+    where
+        F: Fn(&HeaderMap) -> bool + Send + Sync + 'static,
+    {
+        self.0.push(Rule {
+            matches: Arc::new(matches),
+            excluded: excluded.into_iter().collect(),
+            default: None,
+        });
+        self
+    }
+
+    /// Answer the encoding to clients whose `User-Agent` starts with `prefix`, compared case-insensitively,
+    /// when they omit the `Accept` header or accept `*/*`.
+    pub fn default_for_user_agent(self, prefix: impl Into<String>, encoding: Encoding) -> Self {
+        self.default_matching(user_agent(prefix.into()), encoding)
+    }
+
+    /// Answer the encoding to clients whose request headers match when they omit the `Accept` header or accept `*/*`.
+    pub fn default_matching<F>(mut self, matches: F, encoding: Encoding) -> Self
+    where
+        F: Fn(&HeaderMap) -> bool + Send + Sync + 'static,
+    {
+        self.0.push(Rule {
+            matches: Arc::new(matches),
+            excluded: Vec::new(),
+            default: Some(encoding),
+        });
+        self
+    }
+
+    /// Default encoding inferred for the client sending the headers, if any.
+    pub(crate) fn inferred(&self, headers: &HeaderMap) -> Option<Encoding> {
+        self.0
+            .iter()
+            .filter(|rule| rule.default.is_some())
+            .find(|rule| (rule.matches)(headers))
+            .and_then(|rule| rule.default)
+    }
+
+    /// Encodings excluded for the client sending the headers.
+    pub(crate) fn excluded(&self, headers: &HeaderMap) -> Vec<Encoding> {
+        self.0
+            .iter()
+            .filter(|rule| (rule.matches)(headers))
+            .flat_map(|rule| rule.excluded.iter().copied())
+            .collect()
+    }
+}
+
+impl fmt::Debug for ClientQuirks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.0.iter().map(|rule| (&rule.excluded, rule.default)))
+            .finish()
+    }
+}
@@ -0,0 +1,140 @@
+//! Representations chosen per encoding, such as enums tagged differently on each format.
+
+use std::{marker::PhantomData, sync::Arc};
+
+use axum::{
+    extract::{FromRequest, Request},
+    http::{Extensions, StatusCode},
+    response::{IntoResponse, Response},
+    Extension,
+};
+use serde::{de::DeserializeSeed, Deserializer, Serializer};
+
+use crate::{
+    context::{ContextualNegotiate, ContextualPayload},
+    encoding::CodecError,
+    Encoding,
+};
+
+/// Serialize a value with a representation depending on the negotiated encoding.
+///
+/// Useful when the best practices of each format differ, such as internally tagged enums on JSON but integer-keyed on CBOR.
+///
+/// ```rust
+/// use axum_content_negotiation::{Encoding, SerializeFor};
+/// use serde::{Serialize, Serializer};
+///
+/// #[derive(Serialize)]
+/// #[serde(tag = "type")]
+/// enum Shape {
+///     Circle { radius: f64 },
+/// }
+///
+/// #[derive(Serialize)]
+/// struct CompactShape(u8, f64);
+///
+/// impl SerializeFor for Shape {
+///     fn serialize_for<S: Serializer>(&self, encoding: Encoding, serializer: S) -> Result<S::Ok, S::Error> {
+///         match (encoding, self) {
+///             #[cfg(feature = "cbor")]
+///             (Encoding::Cbor, Shape::Circle { radius }) => CompactShape(0, *radius).serialize(serializer),
+///             _ => self.serialize(serializer),
+///         }
+///     }
+/// }
+/// ```
+pub trait SerializeFor {
+    /// Serialize the value for the encoding.
+    fn serialize_for<S>(&self, encoding: Encoding, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer;
+}
+
+/// Deserialize a value with a representation depending on the encoding informed by the request.
+pub trait DeserializeFor: Sized {
+    /// Deserialize the value for the encoding.
+    fn deserialize_for<'de, D>(encoding: Encoding, deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>;
+}
+
+/// Used either as an [Extract](axum::extract::FromRequest) or [Response](axum::response::IntoResponse), like [Negotiate](crate::Negotiate),
+/// but using the [SerializeFor] and [DeserializeFor] representations of the negotiated encoding.
+#[derive(Debug, Clone)]
+pub struct NegotiateFor<T>(
+    /// The stored content to be serialized/deserialized
+    pub T,
+);
+
+/// [NegotiateFor] implements [FromRequest] if the target type is deserializable for every encoding.
+impl<T, S> FromRequest<S> for NegotiateFor<T>
+where
+    T: DeserializeFor + Send,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        crate::decode_request(req, state, |encoding, body, options| {
+            encoding.decode_seed(ForEncoding(encoding, PhantomData::<T>), body, options)
+        })
+        .await
+        .map(Self)
+    }
+}
+
+/// [NegotiateFor] implements [IntoResponse] if the internal content is serializable for every encoding.
+///
+/// Just like [Negotiate](crate::Negotiate), it is converted to the right response by the [NegotiateLayer](crate::NegotiateLayer).
+impl<T> IntoResponse for NegotiateFor<T>
+where
+    T: SerializeFor + Send + Sync + 'static,
+{
+    fn into_response(self) -> Response {
+        let payload: Arc<dyn ContextualPayload> = Arc::new(self);
+        (
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Extension(ContextualNegotiate(payload)),
+            "Misconfigured service layer",
+        )
+            .into_response()
+    }
+}
+
+impl<T> ContextualPayload for NegotiateFor<T>
+where
+    T: SerializeFor + Send + Sync,
+{
+    fn encode(&self, encoding: Encoding, _: &Extensions) -> Result<Vec<u8>, CodecError> {
+        encoding.encode(&ForEncoding(encoding, &self.0))
+    }
+}
+
+/// Adapter carrying the encoding into [SerializeFor] and [DeserializeFor].
+struct ForEncoding<T>(Encoding, T);
+
+impl<T> serde::Serialize for ForEncoding<&T>
+where
+    T: SerializeFor,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.1.serialize_for(self.0, serializer)
+    }
+}
+
+impl<'de, T> DeserializeSeed<'de> for ForEncoding<PhantomData<T>>
+where
+    T: DeserializeFor,
+{
+    type Value = T;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<T, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        T::deserialize_for(self.0, deserializer)
+    }
+}
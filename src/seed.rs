@@ -0,0 +1,104 @@
+//! Stateful deserialization of request bodies using a [DeserializeSeed] taken from the application state.
+
+use axum::{
+    extract::{FromRef, FromRequest, Request},
+    response::Response,
+};
+use serde::de::DeserializeSeed;
+
+/// A [DeserializeSeed] usable with any input lifetime, naming the value it produces.
+///
+/// It is implemented for every seed that does not borrow from the request body, and only exists to be used as a bound of [NegotiateSeed].
+pub trait BodySeed: for<'de> DeserializeSeed<'de, Value = <Self as BodySeed>::Output> {
+    /// Value produced by the seed
+    type Output;
+}
+
+impl<D, V> BodySeed for D
+where
+    D: for<'de> DeserializeSeed<'de, Value = V>,
+{
+    type Output = V;
+}
+
+/// Extractor deserializing the request body with a seed taken from the application state.
+///
+/// It negotiates the request format just like [Negotiate](crate::Negotiate), but the seed can carry state into decoding,
+/// such as an interner, an arena or a schema describing the payload.
+///
+/// ```rust
+/// use axum::{extract::FromRef, routing::post, Router};
+/// use axum_content_negotiation::{NegotiateLayer, NegotiateSeed};
+/// use serde::de::{Deserialize, DeserializeSeed, Deserializer};
+///
+/// /// Rejects names longer than the configured limit while decoding.
+/// #[derive(Clone, Copy)]
+/// struct MaxLength(usize);
+///
+/// impl<'de> DeserializeSeed<'de> for MaxLength {
+///     type Value = String;
+///
+///     fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<String, D::Error> {
+///         let name = String::deserialize(deserializer)?;
+///         if name.len() > self.0 {
+///             return Err(serde::de::Error::custom("name is too long"));
+///         }
+///         Ok(name)
+///     }
+/// }
+///
+/// #[derive(Clone)]
+/// struct AppState {
+///     max_length: MaxLength,
+/// }
+///
+/// impl FromRef<AppState> for MaxLength {
+///     fn from_ref(state: &AppState) -> Self {
+///         state.max_length
+///     }
+/// }
+///
+/// async fn handler(NegotiateSeed(name): NegotiateSeed<MaxLength>) -> String {
+///     name
+/// }
+///
+/// let router: Router = Router::new()
+///     .route("/", post(handler))
+///     .layer(NegotiateLayer::new())
+///     .with_state(AppState { max_length: MaxLength(32) });
+/// ```
+pub struct NegotiateSeed<D: BodySeed>(
+    /// The value produced by the seed
+    pub D::Output,
+);
+
+impl<D> std::fmt::Debug for NegotiateSeed<D>
+where
+    D: BodySeed,
+    D::Output: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("NegotiateSeed").field(&self.0).finish()
+    }
+}
+
+/// [NegotiateSeed] implements [FromRequest] when the seed can be taken from the application state.
+///
+/// Failures are answered with the same rejections as the [Negotiate](crate::Negotiate) extractor.
+impl<D, S> FromRequest<S> for NegotiateSeed<D>
+where
+    D: BodySeed + FromRef<S> + Send,
+    D::Output: Send,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let seed = D::from_ref(state);
+        crate::decode_request(req, state, |encoding, body, options| {
+            encoding.decode_seed(seed, body, options)
+        })
+        .await
+        .map(Self)
+    }
+}
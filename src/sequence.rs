@@ -0,0 +1,254 @@
+//! Splitting of sequence payloads into their items, and joining of items into sequences, for the formats streaming one document per item.
+
+use std::fmt::{self, Display};
+
+use serde::ser::{self, Impossible, Serialize};
+
+use crate::encoding::CodecError;
+
+/// Error while visiting the items, or the marker of a payload that is not a sequence.
+#[derive(Debug)]
+enum Error {
+    NotSequence,
+    Item(CodecError),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotSequence => f.write_str("payload is not a sequence"),
+            Self::Item(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self::Item(msg.to_string().into())
+    }
+}
+
+#[cfg(feature = "cbor-seq")]
+impl serde::de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self::Item(msg.to_string().into())
+    }
+}
+
+/// Call `item` with every element of a sequence payload, such as a `Vec<T>`.
+///
+/// Returns `false` without calling `item` when the payload is not a sequence.
+pub(crate) fn for_each_item<F>(
+    payload: &dyn erased_serde::Serialize,
+    item: F,
+) -> Result<bool, CodecError>
+where
+    F: FnMut(&dyn erased_serde::Serialize) -> Result<(), CodecError>,
+{
+    match payload.serialize(Items(item)) {
+        Ok(()) => Ok(true),
+        Err(Error::NotSequence) => Ok(false),
+        Err(Error::Item(e)) => Err(e),
+    }
+}
+
+struct Items<F>(F);
+
+macro_rules! not_sequence {
+    ($($method:ident($($arg:ty),*)),* $(,)?) => {
+        $(
+            fn $method(self, $(_: $arg),*) -> Result<(), Error> {
+                Err(Error::NotSequence)
+            }
+        )*
+    };
+}
+
+impl<F> ser::Serializer for Items<F>
+where
+    F: FnMut(&dyn erased_serde::Serialize) -> Result<(), CodecError>,
+{
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Impossible<(), Error>;
+    type SerializeTupleVariant = Impossible<(), Error>;
+    type SerializeMap = Impossible<(), Error>;
+    type SerializeStruct = Impossible<(), Error>;
+    type SerializeStructVariant = Impossible<(), Error>;
+
+    not_sequence!(
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str),
+        serialize_bytes(&[u8]),
+        serialize_none(),
+        serialize_unit(),
+        serialize_unit_struct(&'static str),
+        serialize_unit_variant(&'static str, u32, &'static str),
+    );
+
+    fn serialize_some<T: ?Sized + Serialize>(self, _: &T) -> Result<(), Error> {
+        Err(Error::NotSequence)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<(), Error> {
+        Err(Error::NotSequence)
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self, Error> {
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Self, Error> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        Err(Error::NotSequence)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        Err(Error::NotSequence)
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        Err(Error::NotSequence)
+    }
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self::SerializeStruct, Error> {
+        Err(Error::NotSequence)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        Err(Error::NotSequence)
+    }
+}
+
+impl<F> ser::SerializeSeq for Items<F>
+where
+    F: FnMut(&dyn erased_serde::Serialize) -> Result<(), CodecError>,
+{
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        (self.0)(&value).map_err(Error::Item)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<F> ser::SerializeTuple for Items<F>
+where
+    F: FnMut(&dyn erased_serde::Serialize) -> Result<(), CodecError>,
+{
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Deserialize a body of concatenated CBOR items, as `application/cbor-seq`, as if it was a sequence of them.
+#[cfg(feature = "cbor-seq")]
+pub(crate) fn decode_cbor_items<D, T>(seed: D, body: &[u8]) -> Result<T, CodecError>
+where
+    D: for<'de> serde::de::DeserializeSeed<'de, Value = T>,
+{
+    match seed.deserialize(CborItems(cbor4ii::core::utils::SliceReader::new(body))) {
+        Ok(value) => Ok(value),
+        Err(Error::Item(e)) => Err(e),
+        Err(e @ Error::NotSequence) => Err(e.into()),
+    }
+}
+
+#[cfg(feature = "cbor-seq")]
+struct CborItems<'de>(cbor4ii::core::utils::SliceReader<'de>);
+
+#[cfg(feature = "cbor-seq")]
+impl<'de> serde::de::Deserializer<'de> for CborItems<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: serde::de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_seq(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+#[cfg(feature = "cbor-seq")]
+impl<'de> serde::de::SeqAccess<'de> for CborItems<'de> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Error>
+    where
+        T: serde::de::DeserializeSeed<'de>,
+    {
+        use cbor4ii::core::dec::{Read, Reference};
+
+        let Ok(Reference::Long(remaining) | Reference::Short(remaining)) = self.0.fill(1);
+        if remaining.is_empty() {
+            return Ok(None);
+        }
+
+        let mut deserializer = cbor4ii::serde::Deserializer::new(&mut self.0);
+        seed.deserialize(&mut deserializer)
+            .map(Some)
+            .map_err(|e| Error::Item(e.into()))
+    }
+}
@@ -0,0 +1,407 @@
+//! Helpers to test types against every compiled encoding, and applications against negotiation failures.
+
+use std::fmt::{self, Debug};
+
+use crate::{value::Value, Encoding};
+
+/// Encode and decode the value through every compiled encoding, asserting the result is equal to the original value.
+///
+/// Useful to catch types that serialize fine in one format but break in another before they hit production.
+///
+/// ```rust
+/// #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+/// struct Example {
+///     message: String,
+/// }
+///
+/// axum_content_negotiation::test_util::roundtrip_all_encodings(&Example {
+///     message: "Hello, test!".to_string(),
+/// });
+/// ```
+///
+/// # Panics
+///
+/// Panics if the value fails to be encoded or decoded, or if the decoded value is different from the original one.
+pub fn roundtrip_all_encodings<T>(value: &T)
+where
+    T: serde::Serialize + serde::de::DeserializeOwned + PartialEq + Debug,
+{
+    for encoding in Encoding::ALL {
+        // Some encodings only work on one side, such as NDJSON responses, or need a specific shape, such as CSV
+        if !encoding.decodes() || !encoding.encodes() {
+            continue;
+        }
+        #[cfg(feature = "csv")]
+        if *encoding == Encoding::Csv {
+            continue;
+        }
+        #[cfg(feature = "cbor-seq")]
+        if *encoding == Encoding::CborSeq {
+            continue;
+        }
+
+        let body = encoding
+            .encode(value)
+            .unwrap_or_else(|e| panic!("failed to encode {value:?} as {encoding}: {e}"));
+        let decoded: T = encoding
+            .decode(body.into(), &Default::default())
+            .unwrap_or_else(|e| panic!("failed to decode {value:?} as {encoding}: {e}"));
+
+        assert_eq!(&decoded, value, "{encoding} roundtrip changed the value");
+    }
+}
+
+/// Semantic difference between two representations of a payload, reported by [diff_encodings] and [diff_versions].
+///
+/// Paths are JSON Pointers into the payload, such as `/items/0/name`, and values are rendered in a JSON-like notation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Difference {
+    /// The value is only present on the left representation.
+    Removed {
+        /// Where the value is in the payload
+        path: String,
+        /// Value of the left representation
+        left: String,
+    },
+    /// The value is only present on the right representation.
+    Added {
+        /// Where the value is in the payload
+        path: String,
+        /// Value of the right representation
+        right: String,
+    },
+    /// The value is present on both representations, but different.
+    Changed {
+        /// Where the value is in the payload
+        path: String,
+        /// Value of the left representation
+        left: String,
+        /// Value of the right representation
+        right: String,
+    },
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Difference::Removed { path, left } => write!(f, "{path}: removed {left}"),
+            Difference::Added { path, right } => write!(f, "{path}: added {right}"),
+            Difference::Changed { path, left, right } => write!(f, "{path}: {left} != {right}"),
+        }
+    }
+}
+
+/// Serialize the value with both encodings and report where their decoded forms differ, such as fields skipped or renamed
+/// by a [SerializeFor](crate::SerializeFor) on only one format.
+///
+/// Map entries are compared by key regardless of their order, integers are equal to floats of the same number,
+/// and byte strings are equal to sequences of the same bytes, as JSON has no byte string type.
+///
+/// ```rust
+/// use axum_content_negotiation::{test_util::diff_encodings, Encoding};
+///
+/// #[derive(Debug, serde::Serialize)]
+/// struct Example {
+///     message: String,
+/// }
+///
+/// # #[cfg(all(feature = "cbor", any(feature = "simd-json", feature = "json")))]
+/// assert!(diff_encodings(&Example { message: "Hello, test!".to_string() }, Encoding::Json, Encoding::Cbor).is_empty());
+/// ```
+///
+/// # Panics
+///
+/// Panics if the value fails to be encoded or decoded with either encoding.
+pub fn diff_encodings<T>(value: &T, left: Encoding, right: Encoding) -> Vec<Difference>
+where
+    T: serde::Serialize + Debug,
+{
+    diff_values(&decoded(value, left), &decoded(value, right))
+}
+
+/// Serialize two versions of a representation with the same encoding and report where their decoded forms differ,
+/// such as to validate that a migrated struct keeps the payload of its previous version.
+///
+/// Values are compared like on [diff_encodings].
+///
+/// # Panics
+///
+/// Panics if either value fails to be encoded or decoded with the encoding.
+pub fn diff_versions<A, B>(old: &A, new: &B, encoding: Encoding) -> Vec<Difference>
+where
+    A: serde::Serialize + Debug,
+    B: serde::Serialize + Debug,
+{
+    diff_values(&decoded(old, encoding), &decoded(new, encoding))
+}
+
+/// Encode and decode the value back as a schemaless [Value].
+fn decoded<T>(value: &T, encoding: Encoding) -> Value
+where
+    T: serde::Serialize + Debug,
+{
+    let body = encoding
+        .encode(value)
+        .unwrap_or_else(|e| panic!("failed to encode {value:?} as {encoding}: {e}"));
+    encoding
+        .decode(body.into(), &Default::default())
+        .unwrap_or_else(|e| panic!("failed to decode {value:?} as {encoding}: {e}"))
+}
+
+fn diff_values(left: &Value, right: &Value) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    diff_at(String::new(), left, right, &mut differences);
+    differences
+}
+
+fn diff_at(path: String, left: &Value, right: &Value, differences: &mut Vec<Difference>) {
+    match (left, right) {
+        (Value::Array(left), Value::Array(right)) => {
+            for index in 0..left.len().max(right.len()) {
+                let path = format!("{path}/{index}");
+                match (left.get(index), right.get(index)) {
+                    (Some(left), Some(right)) => diff_at(path, left, right, differences),
+                    (Some(left), None) => differences.push(Difference::Removed {
+                        path,
+                        left: render(left),
+                    }),
+                    (None, Some(right)) => differences.push(Difference::Added {
+                        path,
+                        right: render(right),
+                    }),
+                    (None, None) => unreachable!("index is within one of the sequences"),
+                }
+            }
+        }
+        (Value::Map(left), Value::Map(right)) => {
+            for (key, left) in left {
+                let path = format!("{path}/{}", pointer_token(key));
+                match right.iter().find(|(other, _)| other == key) {
+                    Some((_, right)) => diff_at(path, left, right, differences),
+                    None => differences.push(Difference::Removed {
+                        path,
+                        left: render(left),
+                    }),
+                }
+            }
+            for (key, right) in right {
+                if !left.iter().any(|(other, _)| other == key) {
+                    differences.push(Difference::Added {
+                        path: format!("{path}/{}", pointer_token(key)),
+                        right: render(right),
+                    });
+                }
+            }
+        }
+        (Value::Bytes(bytes), other) | (other, Value::Bytes(bytes))
+            if matches!(other, Value::Array(_)) =>
+        {
+            let sequence =
+                Value::Array(bytes.iter().map(|b| Value::Integer((*b).into())).collect());
+            match left {
+                Value::Bytes(_) => diff_at(path, &sequence, right, differences),
+                _ => diff_at(path, left, &sequence, differences),
+            }
+        }
+        _ if equivalent(left, right) => {}
+        _ => differences.push(Difference::Changed {
+            path,
+            left: render(left),
+            right: render(right),
+        }),
+    }
+}
+
+/// Compare scalars, allowing integers and floats of the same number.
+fn equivalent(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Integer(i), Value::Float(f)) | (Value::Float(f), Value::Integer(i)) => {
+            *i as f64 == *f
+        }
+        _ => left == right,
+    }
+}
+
+/// Escape a map key as a JSON Pointer reference token, following RFC 6901.
+fn pointer_token(key: &Value) -> String {
+    let key = match key {
+        Value::Text(key) => key.clone(),
+        key => render(key),
+    };
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+/// Render the value in a JSON-like notation, for the reports.
+fn render(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Bool(v) => v.to_string(),
+        Value::Integer(v) => v.to_string(),
+        Value::Float(v) => format!("{v:?}"),
+        Value::Text(v) => format!("{v:?}"),
+        Value::Bytes(v) => format!("bytes{v:?}"),
+        Value::Array(values) => {
+            let values: Vec<_> = values.iter().map(render).collect();
+            format!("[{}]", values.join(", "))
+        }
+        Value::Map(entries) => {
+            let entries: Vec<_> = entries
+                .iter()
+                .map(|(key, value)| format!("{}: {}", render(key), render(value)))
+                .collect();
+            format!("{{{}}}", entries.join(", "))
+        }
+    }
+}
+
+/// Failure forced on the negotiation of a request, to exercise how the application handles it without crafting invalid payloads.
+///
+/// Insert it on the request extensions, such as with [Request::builder().extension()](axum::http::request::Builder::extension),
+/// or on every request with an [Extension](axum::Extension) layer added after the [NegotiateLayer](crate::NegotiateLayer).
+/// The crate then answers the same rejection as for a real failure, using the configured error bodies.
+///
+/// ```rust
+/// use axum::{body::Body, http::Request};
+/// use axum_content_negotiation::test_util::InjectedFailure;
+///
+/// let request = Request::builder()
+///     .uri("/orders")
+///     .method("POST")
+///     .extension(InjectedFailure::Decode)
+///     .body(Body::from(r#"{ "valid": "payload" }"#))
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum InjectedFailure {
+    /// Reject the request `Content-Type` as unsupported, answering 415 Unsupported Media Type from the extractors.
+    UnsupportedContentType,
+    /// Reject the request `Accept` header, answering 406 Not Acceptable from the layer without running the handler.
+    NotAcceptable,
+    /// Fail to deserialize the request body, answering 400 Bad Request from the extractors once they decode it.
+    Decode,
+    /// Fail to serialize negotiated responses, answering 500 Internal Server Error from the layer.
+    Encode,
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn test_roundtrip_supported_types() {
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        enum Kind {
+            Unit,
+            Tuple(u8, String),
+        }
+
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct Example {
+            message: String,
+            count: i64,
+            kinds: Vec<Kind>,
+            tags: HashMap<String, Option<bool>>,
+        }
+
+        roundtrip_all_encodings(&Example {
+            message: "Hello, test!".to_string(),
+            count: -1,
+            kinds: vec![Kind::Unit, Kind::Tuple(1, "one".to_string())],
+            tags: HashMap::from([("key".to_string(), None)]),
+        });
+    }
+
+    #[cfg(any(feature = "simd-json", feature = "json"))]
+    #[test]
+    #[should_panic(expected = "failed to encode")]
+    fn test_catch_types_unsupported_by_an_encoding() {
+        // JSON can't represent maps with non-string keys
+        roundtrip_all_encodings(&HashMap::from([((1, 2), "tuple key".to_string())]));
+    }
+
+    #[cfg(all(feature = "cbor", any(feature = "simd-json", feature = "json")))]
+    #[test]
+    fn test_diff_representations_across_encodings_and_versions() {
+        #[derive(Debug, serde::Serialize)]
+        struct Order {
+            id: u32,
+            total: f64,
+            #[serde(with = "bytes")]
+            digest: Vec<u8>,
+            notes: Option<String>,
+        }
+
+        mod bytes {
+            pub fn serialize<S: serde::Serializer>(v: &[u8], s: S) -> Result<S::Ok, S::Error> {
+                s.serialize_bytes(v)
+            }
+        }
+
+        let order = Order {
+            id: 1,
+            total: 2.0,
+            digest: vec![1, 2],
+            notes: None,
+        };
+        assert_eq!(diff_encodings(&order, Encoding::Json, Encoding::Cbor), []);
+
+        // Representation drifting on binary formats, which forget to serialize the notes
+        #[derive(Debug)]
+        struct Drifted(Option<String>);
+
+        impl serde::Serialize for Drifted {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                use serde::ser::SerializeMap;
+
+                let human_readable = serializer.is_human_readable();
+                let mut map = serializer.serialize_map(None)?;
+                if human_readable {
+                    map.serialize_entry("notes", &self.0)?;
+                }
+                map.end()
+            }
+        }
+        assert_eq!(
+            diff_encodings(&Drifted(None), Encoding::Json, Encoding::Cbor),
+            [Difference::Removed {
+                path: "/notes".to_string(),
+                left: "null".to_string()
+            }]
+        );
+
+        #[derive(Debug, serde::Serialize)]
+        struct OrderV2 {
+            id: u32,
+            total: f64,
+            #[serde(rename = "memo")]
+            notes: Option<String>,
+            tags: Vec<String>,
+        }
+
+        let differences = diff_versions(
+            &order,
+            &OrderV2 {
+                id: 1,
+                total: 2.5,
+                notes: None,
+                tags: vec!["rush".to_string()],
+            },
+            Encoding::Json,
+        );
+        let differences: Vec<_> = differences.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            differences,
+            [
+                "/total: 2.0 != 2.5",
+                "/digest: removed [1, 2]",
+                "/notes: removed null",
+                "/memo: added null",
+                "/tags: added [\"rush\"]",
+            ]
+        );
+    }
+}
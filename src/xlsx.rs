@@ -0,0 +1,331 @@
+//! Excel workbooks of sequences of records, written as a single worksheet of an uncompressed Office Open XML package.
+//!
+//! Like CSV, only flat payloads can be represented: the top level must be a sequence, and every row a map, a sequence or a scalar
+//! with scalar fields. Rows of maps get a header with the keys in the order they first appear.
+
+use std::fmt::Write;
+
+use crate::{encoding::CodecError, value::Value, Encoding};
+
+const CONTENT_TYPES: &str = concat!(
+    r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+    r#"<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">"#,
+    r#"<Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/>"#,
+    r#"<Default Extension="xml" ContentType="application/xml"/>"#,
+    r#"<Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/>"#,
+    r#"<Override PartName="/xl/worksheets/sheet1.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml"/>"#,
+    r#"</Types>"#,
+);
+
+const ROOT_RELATIONSHIPS: &str = concat!(
+    r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+    r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">"#,
+    r#"<Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/>"#,
+    r#"</Relationships>"#,
+);
+
+const WORKBOOK: &str = concat!(
+    r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+    r#"<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships">"#,
+    r#"<sheets><sheet name="Sheet1" sheetId="1" r:id="rId1"/></sheets>"#,
+    r#"</workbook>"#,
+);
+
+const WORKBOOK_RELATIONSHIPS: &str = concat!(
+    r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+    r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">"#,
+    r#"<Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet1.xml"/>"#,
+    r#"</Relationships>"#,
+);
+
+/// Largest integer stored exactly by the floating point cells of spreadsheets, as wider ones are written as text.
+const MAX_EXACT_INTEGER: i128 = 1 << 53;
+
+/// Serialize a sequence of records into an Excel workbook, reading it back through a self-describing codec.
+pub(crate) fn to_vec(payload: &dyn erased_serde::Serialize) -> Result<Vec<u8>, CodecError> {
+    #[cfg(feature = "cbor")]
+    let codec = Encoding::Cbor;
+    #[cfg(not(feature = "cbor"))]
+    let codec = Encoding::Json;
+    let value: Value = codec.decode(codec.encode(payload)?.into(), &Default::default())?;
+
+    let sheet = worksheet(&value)?;
+    Ok(package(&[
+        ("[Content_Types].xml", CONTENT_TYPES.as_bytes()),
+        ("_rels/.rels", ROOT_RELATIONSHIPS.as_bytes()),
+        ("xl/workbook.xml", WORKBOOK.as_bytes()),
+        (
+            "xl/_rels/workbook.xml.rels",
+            WORKBOOK_RELATIONSHIPS.as_bytes(),
+        ),
+        ("xl/worksheets/sheet1.xml", sheet.as_bytes()),
+    ]))
+}
+
+/// Write the rows of the worksheet, with a header when they are maps.
+fn worksheet(value: &Value) -> Result<String, CodecError> {
+    let Value::Array(rows) = value else {
+        return Err("a payload that is not a sequence can't be represented as XLSX".into());
+    };
+
+    let mut sheet = String::from(r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#);
+    sheet.push_str(
+        r#"<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetData>"#,
+    );
+    let mut line = 0;
+    let mut write_row = |cells: &mut dyn Iterator<Item = Option<&Value>>| {
+        line += 1;
+        let _ = write!(sheet, r#"<row r="{line}">"#);
+        for (column, cell) in cells.enumerate() {
+            if let Some(cell) = cell {
+                write_cell(&mut sheet, &format!("{}{line}", column_name(column)), cell)?;
+            }
+        }
+        sheet.push_str("</row>");
+        Ok::<_, CodecError>(())
+    };
+
+    match columns(rows) {
+        Some(columns) => {
+            write_row(&mut columns.iter().map(|column| Some(*column)))?;
+            for row in rows {
+                write_row(&mut columns.iter().map(|column| field(row, column)))?;
+            }
+        }
+        None => {
+            for row in rows {
+                match row {
+                    Value::Array(cells) => write_row(&mut cells.iter().map(Some))?,
+                    Value::Map(_) => {
+                        return Err(
+                            "rows mixing maps and other values can't be represented as XLSX".into(),
+                        )
+                    }
+                    cell => write_row(&mut std::iter::once(Some(cell)))?,
+                }
+            }
+        }
+    }
+
+    sheet.push_str("</sheetData></worksheet>");
+    Ok(sheet)
+}
+
+/// Keys of a sequence of maps in the order they first appear, so records are written as the rows of a single table.
+fn columns(rows: &[Value]) -> Option<Vec<&Value>> {
+    let mut columns: Vec<&Value> = Vec::new();
+    for row in rows {
+        let Value::Map(entries) = row else {
+            return None;
+        };
+        for (key, _) in entries {
+            if !columns.contains(&key) {
+                columns.push(key);
+            }
+        }
+    }
+    (!rows.is_empty()).then_some(columns)
+}
+
+fn field<'a>(row: &'a Value, column: &Value) -> Option<&'a Value> {
+    let Value::Map(entries) = row else {
+        return None;
+    };
+    entries
+        .iter()
+        .find(|(key, _)| key == column)
+        .map(|(_, value)| value)
+}
+
+/// Write a cell, as a number, a boolean or an inline string. Missing and null values are left as empty cells.
+fn write_cell(sheet: &mut String, reference: &str, value: &Value) -> Result<(), CodecError> {
+    let text = match value {
+        Value::Null => return Ok(()),
+        Value::Bool(v) => {
+            let _ = write!(
+                sheet,
+                r#"<c r="{reference}" t="b"><v>{}</v></c>"#,
+                u8::from(*v)
+            );
+            return Ok(());
+        }
+        Value::Integer(v) if v.abs() <= MAX_EXACT_INTEGER => {
+            let _ = write!(sheet, r#"<c r="{reference}"><v>{v}</v></c>"#);
+            return Ok(());
+        }
+        Value::Float(v) if v.is_finite() => {
+            let _ = write!(sheet, r#"<c r="{reference}"><v>{v}</v></c>"#);
+            return Ok(());
+        }
+        Value::Integer(v) => v.to_string(),
+        Value::Float(v) => v.to_string(),
+        Value::Text(v) => v.clone(),
+        Value::Bytes(v) => v.iter().fold(String::new(), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        }),
+        Value::Array(_) | Value::Map(_) => {
+            return Err("nested values can't be represented as XLSX cells".into())
+        }
+    };
+    let _ = write!(
+        sheet,
+        r#"<c r="{reference}" t="inlineStr"><is><t xml:space="preserve">"#
+    );
+    escape(sheet, &text);
+    sheet.push_str("</t></is></c>");
+    Ok(())
+}
+
+/// Name of the column in the A1 notation: A to Z, then AA, AB and so on.
+fn column_name(mut index: usize) -> String {
+    let mut name = Vec::new();
+    loop {
+        name.push(b'A' + (index % 26) as u8);
+        if index < 26 {
+            break;
+        }
+        index = index / 26 - 1;
+    }
+    name.reverse();
+    String::from_utf8(name).expect("column names are ASCII")
+}
+
+/// Escape the text for XML, dropping the control characters it can't represent.
+fn escape(xml: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => xml.push_str("&amp;"),
+            '<' => xml.push_str("&lt;"),
+            '>' => xml.push_str("&gt;"),
+            '\t' | '\n' | '\r' => xml.push(c),
+            c if c.is_control() && c < ' ' => {}
+            c => xml.push(c),
+        }
+    }
+}
+
+/// Write the files into a ZIP archive, stored without compression so no deflate implementation is needed.
+fn package(files: &[(&str, &[u8])]) -> Vec<u8> {
+    // DOS date of 1980-01-01 at midnight, keeping the archive reproducible
+    const DATE: u16 = 0x0021;
+
+    let mut archive = Vec::new();
+    let mut directory = Vec::new();
+    for (name, data) in files {
+        let offset = archive.len() as u32;
+        let crc = crc32(data);
+        let size = data.len() as u32;
+        let name_len = name.len() as u16;
+
+        archive.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+        for field in [20, 0, 0, 0, DATE] {
+            archive.extend_from_slice(&u16::to_le_bytes(field));
+        }
+        for field in [crc, size, size] {
+            archive.extend_from_slice(&field.to_le_bytes());
+        }
+        for field in [name_len, 0] {
+            archive.extend_from_slice(&field.to_le_bytes());
+        }
+        archive.extend_from_slice(name.as_bytes());
+        archive.extend_from_slice(data);
+
+        directory.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+        for field in [20, 20, 0, 0, 0, DATE] {
+            directory.extend_from_slice(&u16::to_le_bytes(field));
+        }
+        for field in [crc, size, size] {
+            directory.extend_from_slice(&field.to_le_bytes());
+        }
+        for field in [name_len, 0, 0, 0, 0] {
+            directory.extend_from_slice(&field.to_le_bytes());
+        }
+        for field in [0, offset] {
+            directory.extend_from_slice(&u32::to_le_bytes(field));
+        }
+        directory.extend_from_slice(name.as_bytes());
+    }
+
+    let directory_offset = archive.len() as u32;
+    let directory_size = directory.len() as u32;
+    let entries = files.len() as u16;
+    archive.append(&mut directory);
+    archive.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+    for field in [0, 0, entries, entries] {
+        archive.extend_from_slice(&u16::to_le_bytes(field));
+    }
+    for field in [directory_size, directory_offset] {
+        archive.extend_from_slice(&field.to_le_bytes());
+    }
+    archive.extend_from_slice(&0u16.to_le_bytes());
+    archive
+}
+
+/// CRC-32 of the ZIP entries, with the reflected 0xEDB88320 polynomial.
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, byte| {
+        (0..8).fold(crc ^ u32::from(*byte), |crc, _| {
+            (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg())
+        })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_write_flat_records() {
+        let rows = Value::Array(vec![
+            Value::Map(vec![
+                (Value::Text("name".into()), Value::Text("<Ada>".into())),
+                (Value::Text("age".into()), Value::Integer(36)),
+            ]),
+            Value::Map(vec![
+                (Value::Text("name".into()), Value::Text("Grace".into())),
+                (Value::Text("active".into()), Value::Bool(true)),
+                (Value::Text("age".into()), Value::Null),
+            ]),
+        ]);
+
+        let sheet = worksheet(&rows).unwrap();
+        assert!(
+            sheet.contains(concat!(
+                r#"<row r="2">"#,
+                r#"<c r="A2" t="inlineStr"><is><t xml:space="preserve">&lt;Ada&gt;</t></is></c>"#,
+                r#"<c r="B2"><v>36</v></c>"#,
+                r#"</row><row r="3">"#,
+                r#"<c r="A3" t="inlineStr"><is><t xml:space="preserve">Grace</t></is></c>"#,
+                r#"<c r="C3" t="b"><v>1</v></c>"#,
+                r#"</row>"#,
+            )),
+            "{sheet}"
+        );
+        assert!(sheet.contains(r#"<c r="C1" t="inlineStr"><is><t xml:space="preserve">active</t>"#));
+    }
+
+    #[test]
+    fn test_reject_payloads_that_are_not_flat() {
+        assert!(worksheet(&Value::Integer(1)).is_err());
+        assert!(worksheet(&Value::Array(vec![Value::Array(vec![Value::Array(
+            vec![]
+        )])]))
+        .is_err());
+        assert!(worksheet(&Value::Array(vec![
+            Value::Integer(1),
+            Value::Map(Vec::new())
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn test_name_columns_and_checksum_entries() {
+        assert_eq!(column_name(0), "A");
+        assert_eq!(column_name(25), "Z");
+        assert_eq!(column_name(26), "AA");
+        assert_eq!(column_name(701), "ZZ");
+        assert_eq!(column_name(702), "AAA");
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+}